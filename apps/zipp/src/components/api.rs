@@ -0,0 +1,96 @@
+use database::{DatabaseError, DatabasePool};
+use fire_http::{
+	header::{RequestHeader, StatusCode},
+	FireBuilder,
+};
+use fire_http_api::{api, error, ApiError, Method, Request};
+use graphql::execute;
+use serde::{Deserialize, Serialize};
+
+use super::{subscriptions::GraphqlWs, Components};
+use crate::users::api::{self as users, Actors, ACTOR_HEADER, TENANT_HEADER};
+
+/// Only errors before the query is executed, the errors of the query are
+/// part of the response
+#[derive(Debug, Serialize, Deserialize, thiserror::Error)]
+pub enum Error {
+	#[error("internal error {0}")]
+	Internal(String),
+
+	#[error("request error {0}")]
+	Request(String),
+}
+
+impl ApiError for Error {
+	fn from_error(e: error::Error) -> Self {
+		use error::Error::*;
+
+		match e {
+			HeadersMissing(_) | Deserialize(_) => Self::Request(e.to_string()),
+			ExtractionError(e) => e
+				.downcast()
+				.map(|e| *e)
+				.unwrap_or_else(|e| Self::Internal(e.to_string())),
+			e => Self::Internal(e.to_string()),
+		}
+	}
+
+	fn status_code(&self) -> StatusCode {
+		match self {
+			Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+			Self::Request(_) => StatusCode::BAD_REQUEST,
+		}
+	}
+}
+
+impl From<DatabaseError> for Error {
+	fn from(e: DatabaseError) -> Self {
+		Self::Internal(e.to_string())
+	}
+}
+
+/// The actor header is parsed by the users api
+impl From<users::Error> for Error {
+	fn from(e: users::Error) -> Self {
+		match e {
+			users::Error::Internal(e) => Self::Internal(e),
+			users::Error::Request(e) => Self::Request(e),
+		}
+	}
+}
+
+/// A graphql request, the errors of the query are part of the response
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct GraphqlReq(pub execute::Request);
+
+impl Request for GraphqlReq {
+	type Response = execute::Response;
+	type Error = Error;
+
+	const PATH: &'static str = "/graphql";
+	const METHOD: Method = Method::POST;
+	const SIZE_LIMIT: usize = 64 * 1024;
+}
+
+#[api(GraphqlReq)]
+pub async fn execute_graphql(
+	req: GraphqlReq,
+	header: &RequestHeader,
+	actors: &Actors,
+	components: &Components,
+	db: &DatabasePool,
+) -> Result<execute::Response, Error> {
+	let mut db = db.get_with_deadline(db.request_deadline()).await?;
+	db.set_actor(actors.actor(header.value(ACTOR_HEADER))?);
+	db.set_tenant(actors.tenant(header.value(TENANT_HEADER))?)
+		.await
+		.map_err(|e| Error::Internal(e.to_string()))?;
+
+	Ok(components.execute(db.connection(), &req.0).await)
+}
+
+pub fn register(fire: &mut FireBuilder) {
+	fire.add_route(execute_graphql);
+	fire.add_raw_route(GraphqlWs);
+}
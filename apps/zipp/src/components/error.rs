@@ -1,2 +1,15 @@
+use graphql::SchemaError;
+
+use super::{instances, schemas::PersistentError};
+
 #[derive(Debug, thiserror::Error)]
-pub enum Error {}
+pub enum Error {
+	#[error("the component schemas could not be loaded: {0}")]
+	Schemas(#[from] PersistentError),
+
+	#[error("the instances failed {0}")]
+	Instances(#[from] instances::Error),
+
+	#[error("the graphql schema could not be created: {0}")]
+	Schema(#[from] SchemaError),
+}
@@ -1,11 +1,28 @@
+use database::{
+	batch::BatchError, deadline::DeadlineExceeded, id::Id, kv,
+	query::QueryError,
+};
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
-	#[error("a user with the email {email} already exists!")]
-	AlreadyExists { email: String },
-
-	#[error("a connection error occured!")]
-	Connection(String),
-
 	#[error("a postgres error occured!")]
 	Postgres(#[from] database::Error),
+
+	#[error("the batch failed {0}")]
+	Batch(#[from] BatchError),
+
+	#[error("the query failed {0}")]
+	Query(#[from] QueryError),
+
+	#[error("{0}")]
+	Deadline(#[from] DeadlineExceeded),
+
+	#[error("a key-value store error occured!")]
+	KeyValue(#[from] kv::Error),
+
+	#[error("the instance {0} already exists")]
+	AlreadyExists(Id),
+
+	#[error("the access could not be audited {0}")]
+	Audit(#[from] database::audit::AuditError),
 }
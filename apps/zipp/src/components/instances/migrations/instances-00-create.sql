@@ -0,0 +1,7 @@
+-- the fields of an instance are stored as json
+CREATE TABLE component_instances (
+    id text PRIMARY KEY,
+    component text NOT NULL,
+    data jsonb NOT NULL
+);
+CREATE INDEX ON component_instances (component);
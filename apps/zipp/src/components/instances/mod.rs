@@ -1,3 +1,302 @@
+//! Instances
+//!
+//! An instance is the content of a component, the value of every field is
+//! stored as json.
+
 mod error;
 mod persistent;
-use error::Error;
+
+pub use error::Error;
+pub use persistent::{ColumnKinds, Instance};
+
+use database::{
+	audit::Operation,
+	dump::{DumpError, Dumpable},
+	id::{Id, Kind},
+	query::{Page, Query},
+	tenant::Tenant,
+	Connection, Database, DatabaseKind,
+};
+use tokio::sync::broadcast;
+
+use self::persistent::{
+	kv::KvBuilder, memory::MemoryBuilder, postgres::PostgresBuilder,
+	InstancesPersistent, InstancesPersistentBuilder,
+};
+
+pub const KIND: Kind = Kind::new(false, 4);
+
+/// The table name, used to cache queries
+const TABLE: &str = "component_instances";
+
+/// How many instances a list returns if the query has no limit
+const DEFAULT_LIMIT: u32 = 100;
+
+/// The most instances a list returns, larger limits are lowered
+pub const MAX_LIMIT: u32 = 1000;
+
+/// How many changes a slow subscriber can miss before it lags behind
+const CHANGES_CAPACITY: usize = 64;
+
+/// An instance of the component was created, updated or deleted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+	pub tenant: Option<Tenant>,
+	pub component: String,
+}
+
+#[derive(Debug)]
+pub struct Instances {
+	inner: Box<dyn InstancesPersistentBuilder>,
+	changes: broadcast::Sender<Change>,
+}
+
+impl Instances {
+	pub async fn new(conn: &mut Database) -> Result<Self, Error> {
+		let persistent: Box<dyn InstancesPersistentBuilder> = match conn.kind()
+		{
+			DatabaseKind::Memory => Box::new(MemoryBuilder::new()),
+			DatabaseKind::Postgres => {
+				Box::new(PostgresBuilder::new(conn).await?)
+			}
+			DatabaseKind::KeyValue => Box::new(KvBuilder::new()),
+		};
+
+		Ok(Self {
+			inner: persistent,
+			changes: broadcast::channel(CHANGES_CAPACITY).0,
+		})
+	}
+
+	pub fn with_conn<'a>(
+		&'a self,
+		conn: Connection<'a>,
+	) -> InstancesWithConn<'a> {
+		InstancesWithConn {
+			builder: &*self.inner,
+			inner: self.inner.with_conn(conn),
+			conn,
+			changes: &self.changes,
+		}
+	}
+
+	/// Receives every change after this call
+	pub fn subscribe(&self) -> broadcast::Receiver<Change> {
+		self.changes.subscribe()
+	}
+}
+
+#[derive(Debug)]
+pub struct InstancesWithConn<'a> {
+	/// Creates persistents for transactions
+	builder: &'a dyn InstancesPersistentBuilder,
+	inner: Box<dyn InstancesPersistent + 'a>,
+	conn: Connection<'a>,
+	changes: &'a broadcast::Sender<Change>,
+}
+
+impl InstancesWithConn<'_> {
+	/// Creates an instance of the component, the data is expected to be
+	/// validated
+	pub async fn create(
+		&self,
+		component: &str,
+		data: serde_json::Map<String, serde_json::Value>,
+	) -> Result<Instance, Error> {
+		let instance = Instance {
+			id: self.conn.new_id(KIND),
+			component: component.to_string(),
+			data,
+		};
+
+		let res = self
+			.conn
+			.atomic(|conn| async move {
+				self.builder.with_conn(conn).insert(&instance).await?;
+				conn.audit(Operation::Insert, TABLE, &[instance.id]).await?;
+
+				Ok::<_, Error>(instance)
+			})
+			.await;
+		self.conn.invalidate(TABLE);
+		let instance = res?;
+		self.changed(component);
+
+		Ok(instance)
+	}
+
+	/// Returns the instance of the component with the id
+	pub async fn by_id(
+		&self,
+		component: &str,
+		id: &Id,
+	) -> Result<Option<Instance>, Error> {
+		let instance = self
+			.conn
+			.cached(TABLE, format!("by_id {id}"), || self.inner.by_id(id))
+			.await?
+			.filter(|i| i.component == component);
+
+		if let Some(instance) = &instance {
+			self.conn
+				.audit(Operation::Read, TABLE, &[instance.id])
+				.await?;
+		}
+
+		Ok(instance)
+	}
+
+	/// Replaces the data of an instance, returns None if the component has no
+	/// instance with the id
+	///
+	/// The data is expected to be validated. The instance is not read before,
+	/// the backend checks the component while writing.
+	pub async fn update(
+		&self,
+		component: &str,
+		id: &Id,
+		data: serde_json::Map<String, serde_json::Value>,
+	) -> Result<Option<Instance>, Error> {
+		let instance = Instance {
+			id: *id,
+			component: component.to_string(),
+			data,
+		};
+
+		let res = self
+			.conn
+			.atomic(|conn| async move {
+				let updated =
+					self.builder.with_conn(conn).update(&instance).await?;
+				if !updated {
+					return Ok(None);
+				}
+				conn.audit(Operation::Update, TABLE, &[instance.id]).await?;
+
+				Ok::<_, Error>(Some(instance))
+			})
+			.await;
+		self.conn.invalidate(TABLE);
+		let instance = res?;
+		if instance.is_some() {
+			self.changed(component);
+		}
+
+		Ok(instance)
+	}
+
+	/// Deletes an instance, returns None if the component has no instance
+	/// with the id
+	pub async fn delete(
+		&self,
+		component: &str,
+		id: &Id,
+	) -> Result<Option<Instance>, Error> {
+		let res = self
+			.conn
+			.atomic(|conn| async move {
+				let instance =
+					self.builder.with_conn(conn).delete(component, id).await?;
+				if instance.is_some() {
+					conn.audit(Operation::Delete, TABLE, &[*id]).await?;
+				}
+
+				Ok::<_, Error>(instance)
+			})
+			.await;
+		self.conn.invalidate(TABLE);
+		let instance = res?;
+		if instance.is_some() {
+			self.changed(component);
+		}
+
+		Ok(instance)
+	}
+
+	/// Returns a page of the instances of a component, the kinds of the
+	/// fields are used to query the data
+	///
+	/// Without a limit [`DEFAULT_LIMIT`] instances are returned, at most
+	/// [`MAX_LIMIT`]. The page has a cursor if more instances exist.
+	pub async fn list(
+		&self,
+		component: &str,
+		kinds: &ColumnKinds,
+		query: &Query,
+	) -> Result<Page<Instance>, Error> {
+		let limit = query.limit.map_or(DEFAULT_LIMIT, |l| l.min(MAX_LIMIT));
+		let query = &query.clone().limit(limit);
+
+		let page = self
+			.conn
+			.cached(
+				TABLE,
+				format!("list {component} {}", query.cache_key()),
+				|| self.inner.list(component, kinds, query),
+			)
+			.await?;
+		let ids = page.items.iter().map(|i| i.id).collect::<Vec<Id>>();
+		self.conn.audit(Operation::Read, TABLE, &ids).await?;
+
+		Ok(page)
+	}
+
+	/// Notifies the subscribers, there might be none
+	fn changed(&self, component: &str) {
+		let _ = self.changes.send(Change {
+			tenant: self.conn.tenant(),
+			component: component.to_string(),
+		});
+	}
+}
+
+#[async_trait::async_trait]
+impl Dumpable for Instances {
+	fn name(&self) -> &str {
+		TABLE
+	}
+
+	async fn dump(
+		&self,
+		conn: Connection<'_>,
+	) -> Result<Vec<serde_json::Value>, DumpError> {
+		let instances = self
+			.inner
+			.with_conn(conn)
+			.all()
+			.await
+			.map_err(|e| DumpError::table(TABLE, e))?;
+
+		instances
+			.iter()
+			.map(serde_json::to_value)
+			.collect::<Result<_, _>>()
+			.map_err(Into::into)
+	}
+
+	async fn restore(
+		&self,
+		conn: Connection<'_>,
+		rows: Vec<serde_json::Value>,
+	) -> Result<(), DumpError> {
+		let instances = rows
+			.into_iter()
+			.map(serde_json::from_value)
+			.collect::<Result<Vec<Instance>, _>>()?;
+
+		self.inner
+			.with_conn(conn)
+			.restore(&instances)
+			.await
+			.map_err(|e| DumpError::table(TABLE, e))
+	}
+}
+
+impl Clone for Instances {
+	fn clone(&self) -> Self {
+		Self {
+			inner: self.inner.clone_box(),
+			changes: self.changes.clone(),
+		}
+	}
+}
@@ -0,0 +1,151 @@
+use database::{
+	batch::BatchMode,
+	id::Id,
+	kv::{Index, Table, TableWithConn},
+	query::{Page, Query},
+	trace::{Statement, ToSql},
+	Connection,
+};
+
+use crate::components::instances::TABLE;
+
+use super::{
+	select_page, ColumnKinds, Error, Instance, InstancesPersistent,
+	InstancesPersistentBuilder,
+};
+
+// not the value of the record, the data could contain a component field
+const BY_COMPONENT: Index<Instance> =
+	Index::new("component", |i| i.component.as_str().into());
+const INSTANCES: Table<Instance> = Table::with_indexes(TABLE, &[BY_COMPONENT]);
+
+#[derive(Debug, Clone)]
+pub struct KvBuilder;
+
+impl KvBuilder {
+	pub fn new() -> Self {
+		Self
+	}
+}
+
+impl InstancesPersistentBuilder for KvBuilder {
+	fn with_conn<'a>(
+		&'a self,
+		conn: Connection<'a>,
+	) -> Box<dyn InstancesPersistent + 'a> {
+		Box::new(Kv {
+			table: INSTANCES.with_conn(conn.into_kv()),
+			conn,
+		})
+	}
+
+	fn clone_box(&self) -> Box<dyn InstancesPersistentBuilder> {
+		Box::new(self.clone())
+	}
+}
+
+#[derive(Debug)]
+pub struct Kv<'a> {
+	table: TableWithConn<'a, Instance>,
+	conn: Connection<'a>,
+}
+
+#[async_trait::async_trait]
+impl InstancesPersistent for Kv<'_> {
+	async fn insert(&self, instance: &Instance) -> Result<(), Error> {
+		let params: [&(dyn ToSql + Sync); 1] = [&instance.id];
+		let stmt = Statement::new(TABLE, "insert").params(&params);
+
+		self.conn
+			.traced(stmt, async {
+				self.table.insert(instance).await.map_err(Into::into)
+			})
+			.await
+	}
+
+	async fn by_id(&self, id: &Id) -> Result<Option<Instance>, Error> {
+		let params: [&(dyn ToSql + Sync); 1] = [id];
+		let stmt = Statement::new(TABLE, "by_id").params(&params);
+
+		self.conn
+			.traced(stmt, async {
+				self.table.get(id).await.map_err(Into::into)
+			})
+			.await
+	}
+
+	async fn update(&self, instance: &Instance) -> Result<bool, Error> {
+		let params: [&(dyn ToSql + Sync); 1] = [&instance.id];
+		let stmt = Statement::new(TABLE, "update").params(&params);
+
+		let component = instance.component.clone();
+		self.conn
+			.traced(stmt, async {
+				self.table
+					.update_if(instance, move |i| i.component == component)
+					.await
+					.map_err(Into::into)
+			})
+			.await
+	}
+
+	async fn delete(
+		&self,
+		component: &str,
+		id: &Id,
+	) -> Result<Option<Instance>, Error> {
+		let params: [&(dyn ToSql + Sync); 1] = [id];
+		let stmt = Statement::new(TABLE, "delete").params(&params);
+
+		let component = component.to_string();
+		self.conn
+			.traced(stmt, async {
+				self.table
+					.delete_if(id, move |i| i.component == component)
+					.await
+					.map_err(Into::into)
+			})
+			.await
+	}
+
+	async fn list(
+		&self,
+		component: &str,
+		kinds: &ColumnKinds,
+		query: &Query,
+	) -> Result<Page<Instance>, Error> {
+		let params: [&(dyn ToSql + Sync); 1] = [&component];
+		let stmt = Statement::new(TABLE, "list").params(&params);
+
+		self.conn
+			.traced(stmt, async {
+				let rows =
+					self.table.find_by(BY_COMPONENT.name(), component).await?;
+
+				select_page(&rows, kinds, query).map_err(Into::into)
+			})
+			.await
+	}
+
+	async fn all(&self) -> Result<Vec<Instance>, Error> {
+		let stmt = Statement::new(TABLE, "all");
+
+		self.conn
+			.traced(stmt, async {
+				self.table.values().await.map_err(Into::into)
+			})
+			.await
+	}
+
+	async fn restore(&self, instances: &[Instance]) -> Result<(), Error> {
+		let stmt = Statement::new(TABLE, "restore");
+
+		self.conn
+			.traced(stmt, async {
+				self.table.insert_many(instances, BatchMode::Abort).await?;
+
+				Ok(())
+			})
+			.await
+	}
+}
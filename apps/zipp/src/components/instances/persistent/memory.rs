@@ -0,0 +1,159 @@
+use database::{
+	batch::BatchMode,
+	id::Id,
+	memory::{Partitioned, ReadWrite, Table},
+	query::{Page, Query},
+	trace::{Statement, ToSql},
+	Connection,
+};
+
+use crate::components::instances::TABLE;
+
+use super::{
+	select_page, ColumnKinds, Error, Instance, InstancesPersistent,
+	InstancesPersistentBuilder,
+};
+
+#[derive(Debug, Clone)]
+pub struct MemoryBuilder {
+	tables: Partitioned<Table<Id, Instance>>,
+}
+
+impl MemoryBuilder {
+	pub fn new() -> Self {
+		Self {
+			tables: Partitioned::new(),
+		}
+	}
+}
+
+impl InstancesPersistentBuilder for MemoryBuilder {
+	fn with_conn<'a>(
+		&'a self,
+		conn: Connection<'a>,
+	) -> Box<dyn InstancesPersistent + 'a> {
+		Box::new(Memory {
+			inner: self.tables.get(conn.tenant()),
+			conn,
+		})
+	}
+
+	fn clone_box(&self) -> Box<dyn InstancesPersistentBuilder> {
+		Box::new(self.clone())
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Memory<'a> {
+	inner: ReadWrite<Table<Id, Instance>>,
+	conn: Connection<'a>,
+}
+
+#[async_trait::async_trait]
+impl InstancesPersistent for Memory<'_> {
+	async fn insert(&self, instance: &Instance) -> Result<(), Error> {
+		let params: [&(dyn ToSql + Sync); 1] = [&instance.id];
+		let stmt = Statement::new(TABLE, "insert").params(&params);
+
+		self.conn
+			.traced(stmt, async {
+				self.inner
+					.write()
+					.insert(instance.id, instance.clone())
+					.map_err(|_| Error::AlreadyExists(instance.id))
+			})
+			.await
+	}
+
+	async fn by_id(&self, id: &Id) -> Result<Option<Instance>, Error> {
+		let params: [&(dyn ToSql + Sync); 1] = [id];
+		let stmt = Statement::new(TABLE, "by_id").params(&params);
+
+		self.conn
+			.traced(stmt, async { Ok(self.inner.read().get(id).cloned()) })
+			.await
+	}
+
+	async fn update(&self, instance: &Instance) -> Result<bool, Error> {
+		let params: [&(dyn ToSql + Sync); 1] = [&instance.id];
+		let stmt = Statement::new(TABLE, "update").params(&params);
+
+		self.conn
+			.traced(stmt, async {
+				let mut table = self.inner.write();
+				let exists = table
+					.get(&instance.id)
+					.is_some_and(|i| i.component == instance.component);
+				if exists {
+					table.upsert_many([(instance.id, instance.clone())]);
+				}
+
+				Ok(exists)
+			})
+			.await
+	}
+
+	async fn delete(
+		&self,
+		component: &str,
+		id: &Id,
+	) -> Result<Option<Instance>, Error> {
+		let params: [&(dyn ToSql + Sync); 1] = [id];
+		let stmt = Statement::new(TABLE, "delete").params(&params);
+
+		self.conn
+			.traced(stmt, async {
+				let mut table = self.inner.write();
+				let instance =
+					table.get(id).filter(|i| i.component == component).cloned();
+				if instance.is_some() {
+					table.delete_many([*id], BatchMode::Abort)?;
+				}
+
+				Ok(instance)
+			})
+			.await
+	}
+
+	async fn list(
+		&self,
+		component: &str,
+		kinds: &ColumnKinds,
+		query: &Query,
+	) -> Result<Page<Instance>, Error> {
+		let params: [&(dyn ToSql + Sync); 1] = [&component];
+		let stmt = Statement::new(TABLE, "list").params(&params);
+
+		self.conn
+			.traced(stmt, async {
+				let table = self.inner.read();
+				let rows = table.values().filter(|i| i.component == component);
+
+				select_page(rows, kinds, query).map_err(Into::into)
+			})
+			.await
+	}
+
+	async fn all(&self) -> Result<Vec<Instance>, Error> {
+		let stmt = Statement::new(TABLE, "all");
+
+		self.conn
+			.traced(stmt, async {
+				Ok(self.inner.read().values().cloned().collect())
+			})
+			.await
+	}
+
+	async fn restore(&self, instances: &[Instance]) -> Result<(), Error> {
+		let stmt = Statement::new(TABLE, "restore");
+
+		self.conn
+			.traced(stmt, async {
+				let rows = instances.iter().map(|i| (i.id, i.clone()));
+				self.inner.write().insert_many(rows, BatchMode::Abort)?;
+
+				Ok(())
+			})
+			.await
+	}
+}
@@ -1,52 +1,146 @@
+pub mod kv;
+pub mod memory;
+pub mod postgres;
+
+use std::collections::BTreeMap;
 use std::fmt;
 
-use database::{id::Id, Connection};
-use serde::Deserialize;
+use database::{
+	id::Id,
+	query::{self, Page, Query, QueryError, Record, Value},
+	trace::RowCount,
+	types::component::FieldKind,
+	Connection,
+};
+use serde::{Deserialize, Serialize};
 
 use super::Error;
 
-#[derive(Debug, Clone)]
-pub struct ComponentSchema<'a> {
-	pub name: &'a str,
-	pub fields: Vec<SchemaField<'a>>,
+/// The kind of every field of a component, backends which query the data
+/// themselves need to know how to compare the values
+pub type ColumnKinds = BTreeMap<String, FieldKind>;
+
+/// The content of a component
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Instance {
+	pub id: Id,
+	/// The handle of the component schema
+	pub component: String,
+	/// The value of every field
+	pub data: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Record for Instance {
+	fn id(&self) -> Id {
+		self.id
+	}
+
+	/// A missing field is null, lists and objects cannot be queried
+	fn value(&self, column: &str) -> Option<Value> {
+		if column == "id" {
+			return Some(self.id.into());
+		}
+
+		Some(match self.data.get(column) {
+			Some(serde_json::Value::Bool(b)) => (*b).into(),
+			Some(serde_json::Value::Number(n)) => match n.as_i64() {
+				Some(i) => i.into(),
+				None => n.as_f64().map_or(Value::Null, Into::into),
+			},
+			Some(serde_json::Value::String(s)) => s.as_str().into(),
+			_ => Value::Null,
+		})
+	}
+}
+
+/// An instance which returns the values with the kind of their field, a date
+/// time is stored as text but compared as a date
+#[derive(Debug, Clone, Copy)]
+struct Typed<'a> {
+	instance: &'a Instance,
+	kinds: &'a ColumnKinds,
+}
+
+impl Record for Typed<'_> {
+	fn id(&self) -> Id {
+		self.instance.id
+	}
+
+	fn value(&self, column: &str) -> Option<Value> {
+		let value = self.instance.value(column)?;
+
+		Some(match (self.kinds.get(column), value) {
+			(Some(FieldKind::DateTime), Value::Text(s)) => {
+				s.parse().map_or(Value::Null, Value::DateTime)
+			}
+			(_, value) => value,
+		})
+	}
 }
 
-#[derive(Debug, Clone)]
-pub struct SchemaField<'a> {
-	pub name: &'a str,
-	pub kind: FieldKind<'a>,
-	pub related: Option<&'a str>,
-	pub primary: bool,
-	pub index: bool,
+/// Returns a page of the instances, used by the backends which filter the
+/// instances in memory
+fn select_page<'a>(
+	instances: impl IntoIterator<Item = &'a Instance>,
+	kinds: &ColumnKinds,
+	query: &Query,
+) -> Result<Page<Instance>, QueryError> {
+	let rows: Vec<_> = instances
+		.into_iter()
+		.map(|instance| Typed { instance, kinds })
+		.collect();
+
+	let page = query::select_page(&rows, query)?;
+	Ok(page.map(|typed| typed.instance.clone()))
 }
 
-#[derive(Debug, Clone)]
-pub enum FieldKind<'a> {
-	Id,
-	ComponentId,
-	Component { name: &'a str },
-	Boolean,
-	Int,
-	Float,
-	Text,
-	Json,
-	DateTime,
+impl RowCount for Instance {
+	fn row_count(&self) -> Option<usize> {
+		Some(1)
+	}
 }
 
 #[async_trait::async_trait]
-pub trait ComponentsPersistentBuilder: fmt::Debug + Send + Sync {
+pub trait InstancesPersistentBuilder: fmt::Debug + Send + Sync {
 	fn with_conn<'a>(
 		&'a self,
 		conn: Connection<'a>,
-	) -> Box<dyn ComponentsPersistent + 'a>;
+	) -> Box<dyn InstancesPersistent + 'a>;
 
-	fn clone_box(&self) -> Box<dyn ComponentsPersistentBuilder>;
+	fn clone_box(&self) -> Box<dyn InstancesPersistentBuilder>;
 }
 
 #[async_trait::async_trait]
-pub trait ComponentsPersistent: fmt::Debug + Send + Sync {
-	async fn update_schema(
+pub trait InstancesPersistent: fmt::Debug + Send + Sync {
+	async fn insert(&self, instance: &Instance) -> Result<(), Error>;
+
+	async fn by_id(&self, id: &Id) -> Result<Option<Instance>, Error>;
+
+	/// Replaces the data of an existing instance of the same component,
+	/// returns false if there is none
+	///
+	/// The check and the write need to be atomic.
+	async fn update(&self, instance: &Instance) -> Result<bool, Error>;
+
+	/// Deletes the instance if it belongs to the component, returns the
+	/// deleted instance
+	async fn delete(
+		&self,
+		component: &str,
+		id: &Id,
+	) -> Result<Option<Instance>, Error>;
+
+	/// Returns the instances of a component
+	async fn list(
 		&self,
-		user: ComponentSchema<'_>,
-	) -> Result<(), Error>;
+		component: &str,
+		kinds: &ColumnKinds,
+		query: &Query,
+	) -> Result<Page<Instance>, Error>;
+
+	/// Returns the instances of all components
+	async fn all(&self) -> Result<Vec<Instance>, Error>;
+
+	/// Inserts the instances of a dump, fails if an id already exists
+	async fn restore(&self, instances: &[Instance]) -> Result<(), Error>;
 }
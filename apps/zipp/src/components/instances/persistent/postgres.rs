@@ -0,0 +1,282 @@
+use database::{
+	batch::{self, BatchMode},
+	id::Id,
+	migration_files,
+	query::{
+		self,
+		postgres::{Columns, Quoted},
+		Filter, Page, Query, QueryError, Record, Value,
+	},
+	tenant,
+	trace::{Statement, ToSql},
+	types::component::FieldKind,
+	Connection, Database,
+};
+use fire_postgres::{row::NamedColumns, FromRow, ToRow};
+
+use crate::components::instances::TABLE;
+
+use super::{
+	ColumnKinds, Error, Instance, InstancesPersistent,
+	InstancesPersistentBuilder,
+};
+
+const MIGRATIONS: &[(&str, &str)] = migration_files!["instances-00-create"];
+
+#[derive(Debug, Clone)]
+pub struct PostgresBuilder;
+
+impl PostgresBuilder {
+	pub async fn new(db: &mut Database) -> Result<Self, Error> {
+		let migrations = db.migrations().unwrap();
+
+		for (name, sql) in MIGRATIONS {
+			migrations.add(db.connection_owned(), name, sql).await?;
+		}
+
+		let scope_sql = tenant::scope_table_sql(TABLE);
+		migrations
+			.add(db.connection_owned(), "instances-01-tenant", &scope_sql)
+			.await?;
+
+		Ok(Self)
+	}
+}
+
+impl InstancesPersistentBuilder for PostgresBuilder {
+	fn with_conn<'a>(
+		&'a self,
+		conn: Connection<'a>,
+	) -> Box<dyn InstancesPersistent + 'a> {
+		Box::new(Postgres { conn })
+	}
+
+	fn clone_box(&self) -> Box<dyn InstancesPersistentBuilder> {
+		Box::new(self.clone())
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Postgres<'a> {
+	conn: Connection<'a>,
+}
+
+// match this with migrations
+#[derive(Debug, Clone, FromRow, ToRow)]
+struct InstanceTable {
+	id: Id,
+	component: String,
+	data: serde_json::Value,
+}
+
+impl Record for InstanceTable {
+	fn id(&self) -> Id {
+		self.id
+	}
+
+	fn value(&self, column: &str) -> Option<Value> {
+		match column {
+			"id" => Some(self.id.into()),
+			"component" => Some(self.component.as_str().into()),
+			_ => None,
+		}
+	}
+}
+
+/// The id and component are columns, the fields are read from the data and
+/// cast by their kind
+///
+/// Lists and objects cannot be queried, like in memory a missing field is
+/// null.
+struct DataColumns<'a>(&'a ColumnKinds);
+
+impl Columns for DataColumns<'_> {
+	fn expr(&self, column: &str) -> Result<String, QueryError> {
+		if matches!(column, "id" | "component") {
+			return Quoted.expr(column);
+		}
+
+		let cast = match self.0.get(column) {
+			Some(FieldKind::Boolean) => "boolean",
+			Some(FieldKind::Int) => "bigint",
+			Some(FieldKind::Float) => "double precision",
+			Some(FieldKind::Text | FieldKind::Id) => "text",
+			Some(FieldKind::DateTime) => "timestamptz",
+			_ => return Err(QueryError::InvalidColumn(column.into())),
+		};
+		// the field is a string literal, quotes are escaped by doubling them
+		let field = column.replace('\'', "''");
+
+		Ok(format!("(data->>'{field}')::{cast}"))
+	}
+}
+
+#[async_trait::async_trait]
+impl InstancesPersistent for Postgres<'_> {
+	async fn insert(&self, instance: &Instance) -> Result<(), Error> {
+		let row = InstanceTable {
+			id: instance.id,
+			component: instance.component.clone(),
+			data: instance.data.clone().into(),
+		};
+
+		query::postgres::insert(&self.conn, TABLE, &row)
+			.await
+			.map_err(Into::into)
+	}
+
+	async fn by_id(&self, id: &Id) -> Result<Option<Instance>, Error> {
+		let rows = query::postgres::select_where::<InstanceTable>(
+			&self.conn,
+			TABLE,
+			&[("id", id)],
+		)
+		.await?;
+
+		Ok(rows.into_iter().next().map(Into::into))
+	}
+
+	/// Only updates the instance if the component matches
+	async fn update(&self, instance: &Instance) -> Result<bool, Error> {
+		let sql = format!(
+			"UPDATE \"{TABLE}\" SET data = $3 \
+			WHERE id = $1 AND component = $2 AND {}",
+			tenant::CURRENT_CONDITION
+		);
+		let data = serde_json::Value::from(instance.data.clone());
+		let params: [&(dyn ToSql + Sync); 3] =
+			[&instance.id, &instance.component, &data];
+		let pg = self.conn.into_postgres();
+
+		let updated = self
+			.conn
+			.traced(Statement::new(TABLE, &sql).params(&params), async {
+				let stmt = pg.prepare_cached(&sql).await?;
+				pg.execute(&stmt, &params).await
+			})
+			.await?;
+
+		Ok(updated > 0)
+	}
+
+	/// Only deletes the instance if the component matches
+	async fn delete(
+		&self,
+		component: &str,
+		id: &Id,
+	) -> Result<Option<Instance>, Error> {
+		let sql = format!(
+			"DELETE FROM \"{TABLE}\" \
+			WHERE id = $1 AND component = $2 AND {} RETURNING {}",
+			tenant::CURRENT_CONDITION,
+			InstanceTable::select_columns()
+		);
+		let params: [&(dyn ToSql + Sync); 2] = [id, &component];
+		let pg = self.conn.into_postgres();
+
+		let row = self
+			.conn
+			.traced(Statement::new(TABLE, &sql).params(&params), async {
+				let stmt = pg.prepare_cached(&sql).await?;
+				pg.query_opt::<InstanceTable, _>(&stmt, &params).await
+			})
+			.await?;
+
+		Ok(row.map(Into::into))
+	}
+
+	/// The fields are read from the data column, see [`DataColumns`]
+	async fn list(
+		&self,
+		component: &str,
+		kinds: &ColumnKinds,
+		query: &Query,
+	) -> Result<Page<Instance>, Error> {
+		let of_component = Filter::eq("component", component);
+		let filter = match query.filter.clone() {
+			Some(filter) => Filter::And(vec![of_component, filter]),
+			None => of_component,
+		};
+		let query = query.clone().filter(filter);
+
+		query::postgres::select_page_with::<InstanceTable, Instance>(
+			&self.conn,
+			TABLE,
+			&query,
+			&DataColumns(kinds),
+		)
+		.await
+		.map_err(Into::into)
+	}
+
+	async fn all(&self) -> Result<Vec<Instance>, Error> {
+		let rows = query::postgres::select_where::<InstanceTable>(
+			&self.conn,
+			TABLE,
+			&[],
+		)
+		.await?;
+
+		Ok(rows.into_iter().map(Into::into).collect())
+	}
+
+	async fn restore(&self, instances: &[Instance]) -> Result<(), Error> {
+		let rows = instances
+			.iter()
+			.map(|i| InstanceTable {
+				id: i.id,
+				component: i.component.clone(),
+				data: i.data.clone().into(),
+			})
+			.collect::<Vec<_>>();
+
+		batch::postgres::insert_many(
+			&self.conn,
+			TABLE,
+			&rows,
+			BatchMode::Abort,
+		)
+		.await?;
+
+		Ok(())
+	}
+}
+
+impl From<InstanceTable> for Instance {
+	fn from(row: InstanceTable) -> Self {
+		Self {
+			id: row.id,
+			component: row.component,
+			data: match row.data {
+				serde_json::Value::Object(data) => data,
+				_ => serde_json::Map::new(),
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn data_columns() {
+		let kinds = ColumnKinds::from([
+			("count".into(), FieldKind::Int),
+			("link target".into(), FieldKind::Text),
+			("it's".into(), FieldKind::Text),
+			("links".into(), FieldKind::Json),
+		]);
+		let columns = DataColumns(&kinds);
+
+		assert_eq!(columns.expr("id").unwrap(), "\"id\"");
+		assert_eq!(columns.expr("count").unwrap(), "(data->>'count')::bigint");
+		assert_eq!(
+			columns.expr("link target").unwrap(),
+			"(data->>'link target')::text"
+		);
+		assert_eq!(columns.expr("it's").unwrap(), "(data->>'it''s')::text");
+		assert!(columns.expr("links").is_err());
+		assert!(columns.expr("missing").is_err());
+	}
+}
@@ -3,36 +3,583 @@
 //! This module contains everything about components. Which components
 //! exists, what fields they haven or what data or instances exist.
 
+pub mod api;
 mod error;
 mod instances;
 mod schemas;
+pub mod subscriptions;
 
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
 
 pub use error::Error;
 
 use database::{
-	id::{Id, Kind},
-	Database,
+	dump::{DumpError, Dumpable},
+	id::Id,
+	query::{Filter, Page, Query},
+	Connection, Database,
 };
-use tokio::sync::RwLock;
+use fire_http::Resource;
+use graphql::{
+	execute::{
+		Executor, FieldError, GraphQLError, JsonMap, JsonValue, Mutation,
+		MutationError, Plan, Request, ResolveError, Resolver, Response,
+		Selection,
+	},
+	mutation::Action,
+	names::NameMap,
+	Schema, SchemaError, Valid,
+};
+use tokio::sync::{broadcast, RwLock};
+
+use self::instances::{Change, Instance, Instances, InstancesWithConn};
+use self::schemas::{ComponentSchema, ComponentSchemas, SchemaComponentDto};
+use crate::fields::{defaults::ComponentField, Fields};
+
+/// The name of the schemas in a dump
+const SCHEMAS_TABLE: &str = "component_schemas";
 
-use self::schemas::ComponentSchemas;
+type Data = serde_json::Map<String, serde_json::Value>;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Resource)]
 pub struct Components {
-	schemas: Arc<RwLock<ComponentSchemas>>,
+	schemas: Arc<RwLock<Schemas>>,
+	instances: Instances,
+}
+
+/// The component schemas and the executor created from them
+#[derive(Debug)]
+struct Schemas {
+	schemas: ComponentSchemas,
+	/// The graphql type name of every component by it's handle, empty if
+	/// the names are invalid
+	names: NameMap,
+	/// Needs to be recreated every time the schemas change
+	executor: Result<Executor, SchemaError>,
+}
+
+impl Schemas {
+	fn new(schemas: ComponentSchemas) -> Self {
+		let (names, executor) = executor(&schemas);
+		Self {
+			schemas,
+			names,
+			executor,
+		}
+	}
+
+	/// Recreates the names and the executor after the schemas changed
+	fn changed(&mut self) {
+		(self.names, self.executor) = executor(&self.schemas);
+	}
 }
 
 impl Components {
-	pub async fn new(conn: &mut Database) -> Result<Self, Error> {
-		todo!()
+	/// Loads the schemas from the json file, without a file the schemas are
+	/// only kept in memory
+	pub async fn new(
+		conn: &mut Database,
+		fields: Fields,
+		file_name: Option<&str>,
+	) -> Result<Self, Error> {
+		let schemas = match file_name {
+			Some(file_name) => {
+				ComponentSchemas::load_file(fields, file_name).await?
+			}
+			None => ComponentSchemas::new_memory(fields),
+		};
+
+		Ok(Self {
+			schemas: Arc::new(RwLock::new(Schemas::new(schemas))),
+			instances: Instances::new(conn).await?,
+		})
+	}
+
+	pub fn instances<'a>(
+		&'a self,
+		conn: Connection<'a>,
+	) -> InstancesWithConn<'a> {
+		self.instances.with_conn(conn)
+	}
+
+	/// The instances of all components, these are dumped separately from the
+	/// schemas
+	pub fn instances_table(&self) -> Instances {
+		self.instances.clone()
+	}
+
+	/// Returns the graphql schema of the current component schemas
+	pub async fn graphql_schema(&self) -> Result<Valid<Schema>, Error> {
+		self.schemas
+			.read()
+			.await
+			.schemas
+			.graphql_schema()
+			.map_err(Into::into)
+	}
+
+	/// Receives the changes of all instances
+	pub fn subscribe_changes(&self) -> broadcast::Receiver<Change> {
+		self.instances.subscribe()
+	}
+
+	/// Returns the handles of the components a subscription lists, empty if
+	/// the request is not a subscription
+	///
+	/// Fails if the component schemas are not a valid graphql schema.
+	pub async fn subscription(
+		&self,
+		request: &Request,
+	) -> Result<Vec<String>, SchemaError> {
+		let schemas = self.schemas.read().await;
+		let executor = schemas.executor.as_ref().map_err(Clone::clone)?;
+
+		let handles = executor
+			.subscribed_types(request)
+			.iter()
+			.filter_map(|ty| {
+				schemas.schemas.get_by_graphql_name(&schemas.names, ty)
+			})
+			.map(|schema| schema.handle.clone())
+			.collect();
+
+		Ok(handles)
+	}
+
+	/// Executes a graphql request, the instances are loaded with the
+	/// connection
+	pub async fn execute(
+		&self,
+		conn: Connection<'_>,
+		request: &Request,
+	) -> Response {
+		let schemas = self.schemas.read().await;
+		let executor = match &schemas.executor {
+			Ok(executor) => executor,
+			Err(e) => {
+				let sources = Default::default();
+				let error = GraphQLError::new(e.to_string(), None, &sources);
+				return Response::from_request_error(error);
+			}
+		};
+		let resolver = InstancesResolver {
+			schemas: &schemas.schemas,
+			names: &schemas.names,
+			instances: self.instances.with_conn(conn),
+		};
+
+		executor.execute(&resolver, request).await
+	}
+}
+
+/// Creates the graphql names and the executor of the component schemas
+fn executor(
+	schemas: &ComponentSchemas,
+) -> (NameMap, Result<Executor, SchemaError>) {
+	let names = match schemas.graphql_names() {
+		Ok(names) => names,
+		Err(e) => return (NameMap::new(), Err(e.into())),
+	};
+	let executor = schemas.graphql_properties(&names).and_then(|props| {
+		Executor::new(props, schemas.graphql_mutations(&names)?)
+	});
+
+	(names, executor)
+}
+
+/// Loads the instances of the component with the graphql type name
+struct InstancesResolver<'a> {
+	schemas: &'a ComponentSchemas,
+	/// The graphql type name of every component by it's handle
+	names: &'a NameMap,
+	instances: InstancesWithConn<'a>,
+}
+
+impl InstancesResolver<'_> {
+	fn schema(
+		&self,
+		type_name: &str,
+	) -> Result<&ComponentSchema, ResolveError> {
+		let schema = self.schemas.get_by_graphql_name(self.names, type_name);
+		schema.ok_or_else(|| {
+			ResolveError(format!("the component {type_name} does not exist"))
+		})
+	}
+
+	/// Returns the graphql name of every field of the component
+	fn fields(
+		&self,
+		schema: &ComponentSchema,
+	) -> Result<NameMap, ResolveError> {
+		schema
+			.graphql_fields()
+			.map_err(|e| ResolveError(e.to_string()))
+	}
+
+	/// Returns the selected fields of the instances with their `__typename`,
+	/// selected references are replaced by the referenced objects
+	///
+	/// The referenced instances of a component are loaded with one query for
+	/// all instances, a reference to an instance which does not exist is
+	/// null.
+	fn objects<'b>(
+		&'b self,
+		instances: Vec<Instance>,
+		selection: &'b Selection,
+	) -> Pin<
+		Box<
+			dyn Future<Output = Result<Vec<JsonMap>, ResolveError>> + Send + 'b,
+		>,
+	> {
+		Box::pin(async move {
+			if instances.is_empty() {
+				return Ok(vec![]);
+			}
+
+			let mut schemas = Vec::with_capacity(instances.len());
+			for instance in &instances {
+				let schema = self
+					.schemas
+					.get_by_handle(&instance.component)
+					.ok_or_else(|| {
+						ResolveError(format!(
+							"the component {} does not exist",
+							instance.component
+						))
+					})?;
+				schemas.push((schema, self.fields(schema)?));
+			}
+
+			// the ids of the referenced instances by their component
+			let mut ids = BTreeMap::<&str, Vec<Id>>::new();
+			let mut nested = Selection::default();
+			for (instance, (schema, names)) in instances.iter().zip(&schemas) {
+				for (name, field, selection) in
+					references(schema, names, selection)
+				{
+					let Some(value) = instance.data.get(name) else {
+						continue;
+					};
+					nested.merge(selection.clone());
+
+					for (handle, id) in field.references(value) {
+						let Ok(id) = id.parse::<Id>() else {
+							continue;
+						};
+						let ids = ids.entry(handle).or_default();
+						if !ids.contains(&id) {
+							ids.push(id);
+						}
+					}
+				}
+			}
+
+			let mut referenced = vec![];
+			for (handle, ids) in ids {
+				referenced.extend(self.load(handle, ids).await?);
+			}
+			let referenced = self
+				.objects(referenced, &nested)
+				.await?
+				.into_iter()
+				.filter_map(|object| {
+					let id = object.get("id")?.as_str()?.to_string();
+					Some((id, JsonValue::Object(object)))
+				})
+				.collect::<HashMap<_, _>>();
+
+			let mut objects = Vec::with_capacity(instances.len());
+			for (instance, (schema, names)) in instances.iter().zip(&schemas) {
+				let mut object = instance_to_object(instance, selection, names);
+				for (name, field, _) in references(schema, names, selection) {
+					let Some(value) = instance.data.get(name) else {
+						continue;
+					};
+					let object_of = |r: &serde_json::Value| {
+						let (_, id) = field.references(r).next()?;
+						referenced.get(id).cloned()
+					};
+
+					let value = match value {
+						serde_json::Value::Array(refs) => JsonValue::Array(
+							refs.iter()
+								.map(|r| object_of(r).unwrap_or_default())
+								.collect(),
+						),
+						r => object_of(r).unwrap_or_default(),
+					};
+					let graphql_name = names.graphql_name(name).unwrap_or(name);
+					object.insert(graphql_name, value);
+				}
+
+				// the id is needed to find referenced objects
+				let type_name = self
+					.names
+					.graphql_name(&schema.handle)
+					.ok_or_else(|| {
+						let handle = &schema.handle;
+						ResolveError(format!(
+							"the component {handle} has no name"
+						))
+					})?;
+				object.insert("id", instance.id.to_string().into());
+				object.insert("__typename", type_name.into());
+				objects.push(object);
+			}
+
+			Ok(objects)
+		})
+	}
+
+	/// Loads the instances of the component with the ids, missing instances
+	/// are left out
+	async fn load(
+		&self,
+		handle: &str,
+		ids: Vec<Id>,
+	) -> Result<Vec<Instance>, ResolveError> {
+		let Some(schema) = self.schemas.get_by_handle(handle) else {
+			return Ok(vec![]);
+		};
+		let kinds = schema.field_kinds();
+
+		let mut instances = vec![];
+		for ids in ids.chunks(instances::MAX_LIMIT as usize) {
+			let ids = ids.iter().map(|id| (*id).into()).collect();
+			let query = Query::new()
+				.filter(Filter::is_in("id", ids))
+				.limit(instances::MAX_LIMIT);
+			let page = self
+				.instances
+				.list(handle, &kinds, &query)
+				.await
+				.map_err(|e| ResolveError(e.to_string()))?;
+			instances.extend(page.items);
+		}
+
+		Ok(instances)
+	}
+
+	/// Replaces the type names of reference inputs with the handles of the
+	/// components, unknown names are left for the validation to report
+	fn reference_handles(&self, value: &mut serde_json::Value) {
+		let refs = match value {
+			serde_json::Value::Array(refs) => refs.iter_mut().collect(),
+			value => vec![value],
+		};
+
+		for r in refs {
+			let Some(component) = r.get_mut("component") else {
+				continue;
+			};
+			let schema = component.as_str().and_then(|name| {
+				self.schemas.get_by_graphql_name(self.names, name)
+			});
+			if let Some(schema) = schema {
+				*component = schema.handle.clone().into();
+			}
+		}
+	}
+}
+
+/// Returns the component fields of the schema which are selected, with the
+/// selection of the referenced objects
+fn references<'a>(
+	schema: &'a ComponentSchema,
+	names: &'a NameMap,
+	selection: &'a Selection,
+) -> impl Iterator<Item = (&'a str, &'a ComponentField, &'a Selection)> {
+	schema.fields.iter().filter_map(|(name, field)| {
+		let field = field.downcast_ref::<ComponentField>()?;
+		let graphql_name = names.graphql_name(name).unwrap_or(name);
+
+		Some((name.as_str(), field, selection.field(graphql_name)?))
+	})
+}
+
+#[async_trait::async_trait]
+impl Resolver for InstancesResolver<'_> {
+	async fn list(&self, plan: &Plan) -> Result<Page<JsonMap>, ResolveError> {
+		let schema = self.schema(&plan.type_name)?;
+		let names = self.fields(schema)?;
+
+		// the query uses the graphql names of the fields
+		let query = plan.query.clone().map_columns(|column| {
+			names.name(column).unwrap_or(column).to_string()
+		});
+		let page = self
+			.instances
+			.list(&schema.handle, &schema.field_kinds(), &query)
+			.await
+			.map_err(|e| ResolveError(e.to_string()))?;
+
+		Ok(Page {
+			items: self.objects(page.items, &plan.selection).await?,
+			next: page.next,
+		})
+	}
+
+	/// The data is validated by the fields of the component
+	async fn mutate(
+		&self,
+		mutation: &Mutation,
+	) -> Result<Option<JsonMap>, MutationError> {
+		let schema = self.schema(&mutation.type_name)?;
+		let names = self.fields(schema)?;
+		let data = match serde_json::to_value(&mutation.data) {
+			Ok(serde_json::Value::Object(data)) => data,
+			_ => serde_json::Map::new(),
+		};
+		// the data is stored with the names of the fields
+		let mut data = data
+			.into_iter()
+			.map(|(name, value)| {
+				(names.name(&name).map_or(name, Into::into), value)
+			})
+			.collect::<Data>();
+		for (name, field) in &schema.fields {
+			if field.downcast_ref::<ComponentField>().is_none() {
+				continue;
+			}
+			if let Some(value) = data.get_mut(name) {
+				self.reference_handles(value);
+			}
+		}
+
+		if mutation.action != Action::Delete {
+			let errors = schema.validate(&data);
+			if !errors.is_empty() {
+				return Err(MutationError::Invalid(
+					errors
+						.into_iter()
+						.map(|(field, e)| FieldError {
+							field: names
+								.graphql_name(field)
+								.unwrap_or(field)
+								.into(),
+							message: e.to_string(),
+						})
+						.collect(),
+				));
+			}
+		}
+
+		let id = match &mutation.id {
+			Some(id) => Some(id.parse::<Id>().map_err(|_| {
+				ResolveError(format!("the id {id} is invalid"))
+			})?),
+			None => None,
+		};
+
+		let handle = &schema.handle;
+		let instance = match (mutation.action, id) {
+			(Action::Create, _) => {
+				self.instances.create(handle, data).await.map(Some)
+			}
+			(Action::Update, Some(id)) => {
+				self.instances.update(handle, &id, data).await
+			}
+			(Action::Delete, Some(id)) => {
+				self.instances.delete(handle, &id).await
+			}
+			(_, None) => {
+				return Err(ResolveError("the id is missing".into()).into())
+			}
+		}
+		.map_err(|e| ResolveError(e.to_string()))?;
+
+		match instance {
+			Some(instance) => {
+				let selection = &mutation.selection;
+				let mut objects =
+					self.objects(vec![instance], selection).await?;
+				Ok(objects.pop())
+			}
+			None => Ok(None),
+		}
+	}
+}
+
+/// Returns the selected fields of the instance, the columns are the graphql
+/// names of the fields
+fn instance_to_object(
+	instance: &Instance,
+	selection: &Selection,
+	names: &NameMap,
+) -> JsonMap {
+	selection
+		.names()
+		.map(|column| {
+			let value = match column {
+				"id" => instance.id.to_string().into(),
+				column => instance
+					.data
+					.get(names.name(column).unwrap_or(column))
+					.cloned()
+					.map_or(JsonValue::Null, Into::into),
+			};
+
+			(column.into(), value)
+		})
+		.collect()
+}
+
+/// Schemas are not stored in the database but are still part of a dump
+#[async_trait::async_trait]
+impl Dumpable for Components {
+	fn name(&self) -> &str {
+		SCHEMAS_TABLE
+	}
+
+	async fn dump(
+		&self,
+		_conn: Connection<'_>,
+	) -> Result<Vec<serde_json::Value>, DumpError> {
+		let schemas = self.schemas.read().await;
+
+		schemas
+			.schemas
+			.to_dtos()
+			.iter()
+			.map(serde_json::to_value)
+			.collect::<Result<_, _>>()
+			.map_err(Into::into)
+	}
+
+	async fn restore(
+		&self,
+		_conn: Connection<'_>,
+		rows: Vec<serde_json::Value>,
+	) -> Result<(), DumpError> {
+		let dtos = rows
+			.into_iter()
+			.map(serde_json::from_value)
+			.collect::<Result<Vec<SchemaComponentDto>, _>>()?;
+
+		let mut schemas = self.schemas.write().await;
+		schemas
+			.schemas
+			.insert_dtos(dtos)
+			.map_err(|e| DumpError::table(SCHEMAS_TABLE, e))?;
+		schemas.changed();
+
+		schemas
+			.schemas
+			.save()
+			.await
+			.map_err(|e| DumpError::table(SCHEMAS_TABLE, e))
 	}
 }
 
 #[cfg(test)]
 mod tests {
-	use super::Components;
+	use database::{dump::Dump, testing::TestPool};
+	use serde_json::json;
+
+	use super::*;
 
 	fn is_send<T: Send>() {}
 
@@ -41,4 +588,476 @@ mod tests {
 		is_send::<Components>();
 		is_send::<&Components>();
 	}
+
+	#[tokio::test]
+	async fn test_execute() {
+		for pool in TestPool::backends().await {
+			execute(pool).await;
+		}
+	}
+
+	async fn execute(pool: TestPool) {
+		let mut db = pool.isolated().await;
+		let components = Components::new(
+			&mut db,
+			Fields::default(),
+			Some("testfiles/components/test_load.json"),
+		)
+		.await
+		.unwrap();
+
+		let instances = components.instances(db.connection());
+		for label in ["Save", "Cancel"] {
+			let data = json!({ "label": label });
+			let data = serde_json::from_value(data).unwrap();
+			instances.create("button", data).await.unwrap();
+		}
+
+		let request = Request {
+			query: r#"{
+				button(filter: { label: { eq: "Save" } }) { label }
+				counter { id }
+			}"#
+			.into(),
+			operation_name: None,
+			variables: JsonMap::new(),
+		};
+		let res = components.execute(db.connection(), &request).await;
+		let res = serde_json::to_value(res).unwrap();
+
+		assert_eq!(
+			res,
+			json!({ "data": { "button": [{ "label": "Save" }], "counter": [] } })
+		);
+
+		// the next page is requested with the reported cursor
+		let query = "query ($after: String) {
+			button(order: { field: label }, limit: 1, after: $after) { label }
+		}";
+		let execute = |after: serde_json::Value| {
+			let request = Request {
+				query: query.into(),
+				operation_name: None,
+				variables: serde_json::from_value(json!({ "after": after }))
+					.unwrap(),
+			};
+			let components = &components;
+			let conn = db.connection();
+
+			async move {
+				let res = components.execute(conn, &request).await;
+				serde_json::to_value(res).unwrap()
+			}
+		};
+
+		let res = execute(json!(null)).await;
+		assert_eq!(res["data"]["button"], json!([{ "label": "Cancel" }]));
+		let next = res["extensions"]["next"]["button"].clone();
+		assert!(next.is_string());
+
+		let res = execute(next).await;
+		assert_eq!(res["data"]["button"], json!([{ "label": "Save" }]));
+		assert!(res.get("extensions").is_none());
+	}
+
+	#[tokio::test]
+	async fn test_mutations() {
+		for pool in TestPool::backends().await {
+			mutations(pool).await;
+		}
+	}
+
+	async fn mutations(pool: TestPool) {
+		let mut db = pool.isolated().await;
+		let components = Components::new(
+			&mut db,
+			Fields::default(),
+			Some("testfiles/components/test_load.json"),
+		)
+		.await
+		.unwrap();
+
+		let execute = |query: &str, variables: serde_json::Value| {
+			let request = Request {
+				query: query.into(),
+				operation_name: None,
+				variables: serde_json::from_value(variables).unwrap(),
+			};
+			let components = &components;
+			let conn = db.connection();
+
+			async move {
+				let res = components.execute(conn, &request).await;
+				serde_json::to_value(res).unwrap()
+			}
+		};
+
+		let res = execute(
+			"mutation { createCounter(data: { count: 12 }) { id count } }",
+			json!({}),
+		)
+		.await;
+		let counter = &res["data"]["createCounter"];
+		assert_eq!(counter["count"], 12);
+		let id = counter["id"].as_str().unwrap().to_string();
+
+		// the count is validated by the number field
+		let query = "mutation ($id: Id!, $count: Int!) {
+			updateCounter(id: $id, data: { count: $count }) { count }
+		}";
+		let res = execute(query, json!({ "id": id, "count": 30 })).await;
+		assert!(res["data"]["updateCounter"].is_null());
+		assert_eq!(res["errors"][0]["extensions"]["field"], "count");
+
+		let res = execute(query, json!({ "id": id, "count": 15 })).await;
+		assert_eq!(res["data"]["updateCounter"]["count"], 15);
+
+		// the instance belongs to another component
+		let query = "mutation ($id: Id!) {
+			updateButton(id: $id, data: { label: \"Save\" }) { id }
+		}";
+		let res = execute(query, json!({ "id": id })).await;
+		assert!(res["data"]["updateButton"].is_null());
+
+		let res = execute("{ counter { count } }", json!({})).await;
+		assert_eq!(res["data"]["counter"], json!([{ "count": 15 }]));
+
+		let query = "mutation ($id: Id!) { deleteCounter(id: $id) { id } }";
+		let res = execute(query, json!({ "id": id })).await;
+		assert_eq!(res["data"]["deleteCounter"]["id"], id);
+		let res = execute(query, json!({ "id": id })).await;
+		assert!(res["data"]["deleteCounter"].is_null());
+		assert!(res.get("errors").is_none());
+
+		// the id is parsed by the id scalar
+		let res = execute(query, json!({ "id": "1" })).await;
+		assert!(res["data"]["deleteCounter"].is_null());
+		assert_eq!(res["errors"][0]["extensions"]["field"], "id");
+
+		let res = execute("{ counter { count } }", json!({})).await;
+		assert_eq!(res["data"]["counter"], json!([]));
+	}
+
+	#[tokio::test]
+	async fn test_graphql_names() {
+		for pool in TestPool::backends().await {
+			graphql_names(pool).await;
+		}
+	}
+
+	async fn graphql_names(pool: TestPool) {
+		let mut db = pool.isolated().await;
+		let components = Components::new(
+			&mut db,
+			Fields::default(),
+			Some("testfiles/components/names.json"),
+		)
+		.await
+		.unwrap();
+
+		let execute = |query: &str| {
+			let request = Request {
+				query: query.into(),
+				operation_name: None,
+				variables: JsonMap::new(),
+			};
+			let components = &components;
+			let conn = db.connection();
+
+			async move {
+				let res = components.execute(conn, &request).await;
+				serde_json::to_value(res).unwrap()
+			}
+		};
+
+		let res = execute(
+			r#"mutation {
+				createCallToAction(data: { linkTarget: "/docs", buttonLabel: "Docs" }) {
+					linkTarget
+				}
+			}"#,
+		)
+		.await;
+		assert_eq!(res["data"]["createCallToAction"]["linkTarget"], "/docs");
+
+		// the data is stored with the names of the fields
+		let instances = components.instances(db.connection());
+		let page = instances
+			.list("call-to-action", &Default::default(), &Default::default())
+			.await
+			.unwrap();
+		assert_eq!(page.items[0].data["link target"], "/docs");
+
+		let res = execute(
+			r#"{
+				callToAction(
+					filter: { linkTarget: { eq: "/docs" } }
+					order: [{ field: buttonLabel }]
+				) { buttonLabel }
+			}"#,
+		)
+		.await;
+		assert!(res.get("errors").is_none(), "{res}");
+		assert_eq!(
+			res["data"]["callToAction"],
+			json!([{ "buttonLabel": "Docs" }])
+		);
+
+		let res = execute(
+			r#"mutation {
+				createCallToAction(data: { linkTarget: "/a/long/target" }) { id }
+			}"#,
+		)
+		.await;
+		assert_eq!(res["errors"][0]["extensions"]["field"], "linkTarget");
+	}
+
+	#[tokio::test]
+	async fn test_scalars() {
+		for pool in TestPool::backends().await {
+			scalars(pool).await;
+		}
+	}
+
+	async fn scalars(pool: TestPool) {
+		let mut db = pool.isolated().await;
+		let components = Components::new(
+			&mut db,
+			Fields::default(),
+			Some("testfiles/components/events.json"),
+		)
+		.await
+		.unwrap();
+
+		let execute = |query: &str, variables: serde_json::Value| {
+			let request = Request {
+				query: query.into(),
+				operation_name: None,
+				variables: serde_json::from_value(variables).unwrap(),
+			};
+			let components = &components;
+			let conn = db.connection();
+
+			async move {
+				let res = components.execute(conn, &request).await;
+				serde_json::to_value(res).unwrap()
+			}
+		};
+
+		let query = "mutation ($data: EventInput!) {
+			createEvent(data: $data) { starts website details }
+		}";
+		let data = json!({
+			"starts": "2024-04-01T12:00:00+02:00",
+			"website": "https://zipp.rs",
+			"details": { "seats": [1, 2] }
+		});
+		let res = execute(query, json!({ "data": data })).await;
+		assert_eq!(
+			res["data"]["createEvent"],
+			json!({
+				"starts": "2024-04-01T10:00:00Z",
+				"website": "https://zipp.rs",
+				"details": { "seats": [1, 2] }
+			})
+		);
+
+		let data = json!({ "starts": "2024-03-01T00:00:00Z" });
+		execute(query, json!({ "data": data })).await;
+
+		let data = json!({ "website": "zipp.rs" });
+		let res = execute(query, json!({ "data": data })).await;
+		assert!(res["data"]["createEvent"].is_null());
+		assert_eq!(res["errors"][0]["extensions"]["field"], "website");
+
+		// the dates are compared as dates and not as text
+		let res = execute(
+			r#"{
+				event(filter: { starts: { gt: "2024-04-01T11:00:00+02:00" } }) {
+					starts
+				}
+			}"#,
+			json!({}),
+		)
+		.await;
+		assert_eq!(
+			res["data"]["event"],
+			json!([{ "starts": "2024-04-01T10:00:00Z" }])
+		);
+	}
+
+	#[tokio::test]
+	async fn test_references() {
+		for pool in TestPool::backends().await {
+			references(pool).await;
+		}
+	}
+
+	async fn references(pool: TestPool) {
+		let mut db = pool.isolated().await;
+		let components = Components::new(
+			&mut db,
+			Fields::default(),
+			Some("testfiles/components/goal.json"),
+		)
+		.await
+		.unwrap();
+
+		let create = |handle: &'static str, data: serde_json::Value| {
+			let instances = components.instances(db.connection());
+			let data = serde_json::from_value(data).unwrap();
+
+			async move {
+				let instance = instances.create(handle, data).await.unwrap();
+				json!({ "component": handle, "id": instance.id.to_string() })
+			}
+		};
+
+		let internal = create("internal-link", json!({ "page": "/" })).await;
+		let external =
+			create("external-link", json!({ "url": "https://zipp.rs" })).await;
+		let home =
+			create("button", json!({ "label": "Home", "link": internal }))
+				.await;
+		let docs =
+			create("button", json!({ "label": "Docs", "link": external }))
+				.await;
+		create("menu", json!({ "buttons": [home, docs] })).await;
+
+		let execute = |query: &str, variables: serde_json::Value| {
+			let request = Request {
+				query: query.into(),
+				operation_name: None,
+				variables: serde_json::from_value(variables).unwrap(),
+			};
+			let components = &components;
+			let conn = db.connection();
+
+			async move {
+				let res = components.execute(conn, &request).await;
+				serde_json::to_value(res).unwrap()
+			}
+		};
+
+		let menu = r#"{
+			menu {
+				buttons {
+					label
+					link {
+						__typename
+						... on Component { id }
+						... on InternalLink { page }
+						... on ExternalLink { url }
+					}
+				}
+			}
+		}"#;
+		let res = execute(menu, json!({})).await;
+
+		assert!(res.get("errors").is_none(), "{res}");
+		assert_eq!(
+			res["data"]["menu"][0]["buttons"],
+			json!([
+				{
+					"label": "Home",
+					"link": {
+						"__typename": "InternalLink",
+						"id": internal["id"],
+						"page": "/"
+					}
+				},
+				{
+					"label": "Docs",
+					"link": {
+						"__typename": "ExternalLink",
+						"id": external["id"],
+						"url": "https://zipp.rs"
+					}
+				}
+			])
+		);
+
+		// references are set with the type name
+		let res = execute(
+			"mutation ($link: ReferenceInput!) {
+				createButton(data: { label: \"Blog\", link: $link }) {
+					link { ... on ExternalLink { url } }
+				}
+			}",
+			json!({ "link": { "component": "ExternalLink", "id": external["id"] } }),
+		)
+		.await;
+		assert!(res.get("errors").is_none(), "{res}");
+		assert_eq!(
+			res["data"]["createButton"]["link"]["url"],
+			"https://zipp.rs"
+		);
+
+		let res = execute(
+			"mutation ($link: ReferenceInput!) {
+				createButton(data: { label: \"Blog\", link: $link }) { label }
+			}",
+			json!({ "link": { "component": "Menu", "id": external["id"] } }),
+		)
+		.await;
+		assert_eq!(res["errors"][0]["extensions"]["field"], "link");
+
+		// a deleted instance is null in a list
+		let instances = components.instances(db.connection());
+		let docs_id = docs["id"].as_str().unwrap().parse::<Id>().unwrap();
+		instances.delete("button", &docs_id).await.unwrap().unwrap();
+		let res = execute(menu, json!({})).await;
+		assert!(res.get("errors").is_none(), "{res}");
+		assert!(res["data"]["menu"][0]["buttons"][1].is_null());
+
+		// a required reference is an error
+		let id = internal["id"].as_str().unwrap().parse::<Id>().unwrap();
+		instances
+			.delete("internal-link", &id)
+			.await
+			.unwrap()
+			.unwrap();
+		let res = execute(menu, json!({})).await;
+		assert_eq!(
+			res["errors"][0]["path"],
+			json!(["menu", 0, "buttons", 0, "link"])
+		);
+		assert_eq!(res["data"]["menu"][0]["buttons"], json!([null, null]));
+	}
+
+	#[tokio::test]
+	async fn test_dump_instances() {
+		for pool in TestPool::backends().await {
+			dump_instances(pool).await;
+		}
+	}
+
+	async fn dump_instances(pool: TestPool) {
+		let mut db = pool.isolated().await;
+		let components = Components::new(
+			&mut db,
+			Fields::default(),
+			Some("testfiles/components/test_load.json"),
+		)
+		.await
+		.unwrap();
+
+		let data = serde_json::from_value(json!({ "label": "Save" })).unwrap();
+		let button = components
+			.instances(db.connection())
+			.create("button", data)
+			.await
+			.unwrap();
+
+		let mut dump = Dump::new();
+		dump.register(components.instances_table());
+		let archive = dump.export(&db).await.unwrap();
+
+		let mut other = pool.isolated().await;
+		dump.restore(&mut other, archive).await.unwrap();
+
+		let instances = components.instances(other.connection());
+		let restored = instances.by_id("button", &button.id).await.unwrap();
+		assert_eq!(restored, Some(button));
+	}
 }
@@ -10,13 +10,59 @@ mod persistent;
 mod schema;
 
 use persistent::Persistent;
-use schema::ComponentSchema;
-
+pub use persistent::{PersistentError, SchemaComponentDto};
+pub use schema::ComponentSchema;
+
+use database::types::schema::{Schema as Layout, SchemaError as LayoutError};
+use graphql::{
+	filter,
+	mutation::{self, MutationProperty},
+	names::{self, NameError, NameMap},
+	Schema, SchemaError, Valid,
+};
 use indexmap::IndexMap;
 
-use crate::fields::Fields;
-
-use self::persistent::PersistentError;
+use crate::fields::{Fields, ParseFieldError};
+
+/// Types which are part of every graphql schema
+const RESERVED_TYPES: &[&str] = &[
+	"Query",
+	"Mutation",
+	"Subscription",
+	schema::COMPONENT_INTERFACE,
+	"ID",
+	"String",
+	"Int",
+	"Float",
+	"Boolean",
+	"OrderDirection",
+	"IDFilter",
+	"StringFilter",
+	"IntFilter",
+	"FloatFilter",
+	"BooleanFilter",
+	mutation::REFERENCE_INPUT,
+	// the custom scalars of the ids and fields
+	"Id",
+	"IdFilter",
+	"DateTime",
+	"DateTimeFilter",
+	"URL",
+	"URLFilter",
+	"JSON",
+];
+
+/// The filter, order and input types generated for every component
+const GENERATED_SUFFIXES: &[&str] = &["Filter", "Order", "OrderField", "Input"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum InsertError {
+	#[error("the field could not be parsed {0}")]
+	Parse(#[from] ParseFieldError),
+
+	#[error("the component is invalid {0}")]
+	Invalid(#[from] LayoutError),
+}
 
 // component schemas
 #[derive(Debug)]
@@ -60,6 +106,7 @@ impl ComponentSchemas {
 	/// Replaces the current schemas
 	pub async fn load(&mut self) -> Result<(), PersistentError> {
 		let schemas = self.persistent.load(&self.fields).await?;
+		validate(&schemas)?;
 		self.inner =
 			schemas.into_iter().map(|s| (s.handle.clone(), s)).collect();
 		Ok(())
@@ -70,6 +117,7 @@ impl ComponentSchemas {
 		self.inner.values()
 	}
 
+	#[cfg(test)]
 	pub fn len(&self) -> usize {
 		self.inner.len()
 	}
@@ -79,20 +127,178 @@ impl ComponentSchemas {
 		self.inner.get(handle)
 	}
 
-	/// Removes a schema by its handle
-	pub fn remove_by_handle(&mut self, handle: &str) {
+	/// Removes a schema by its handle, fails if another schema references
+	/// it
+	#[cfg(test)]
+	pub fn remove_by_handle(
+		&mut self,
+		handle: &str,
+	) -> Result<(), LayoutError> {
+		validate(self.get_all().filter(|s| s.handle != handle))?;
 		self.inner.swap_remove(handle);
+
+		Ok(())
 	}
 
-	/// Inserts a schema
-	pub fn insert(&mut self, component: ComponentSchema) {
+	/// Inserts or replaces a schema
+	///
+	/// Fails if the schema is invalid or if the replacement breaks a schema
+	/// which references it.
+	#[cfg(test)]
+	pub fn insert(
+		&mut self,
+		component: ComponentSchema,
+	) -> Result<(), LayoutError> {
+		let others = self.get_all().filter(|s| s.handle != component.handle);
+		validate(others.chain([&component]))?;
 		self.inner.insert(component.handle.clone(), component);
+
+		Ok(())
+	}
+
+	/// Returns all schemas in their serialized form
+	pub fn to_dtos(&self) -> Vec<SchemaComponentDto> {
+		self.get_all().cloned().map(Into::into).collect()
+	}
+
+	/// Inserts schemas from their serialized form, the schemas can reference
+	/// each other
+	pub fn insert_dtos(
+		&mut self,
+		dtos: Vec<SchemaComponentDto>,
+	) -> Result<(), InsertError> {
+		let schemas = dtos
+			.into_iter()
+			.map(|dto| persistent::component_dto_to_schema(dto, &self.fields))
+			.collect::<Result<Vec<_>, _>>()?;
+
+		let others = self
+			.get_all()
+			.filter(|s| !schemas.iter().any(|n| n.handle == s.handle));
+		validate(others.chain(&schemas))?;
+
+		for schema in schemas {
+			self.inner.insert(schema.handle.clone(), schema);
+		}
+
+		Ok(())
 	}
 
 	pub async fn save(&mut self) -> Result<(), PersistentError> {
 		let schemas = self.get_all().cloned().collect::<Vec<_>>();
 		self.persistent.save(&schemas).await
 	}
+
+	/// Returns the graphql type name of every component by it's handle
+	///
+	/// The names cannot collide with each other, with the types every schema
+	/// contains or with the types generated for the components and their
+	/// fields.
+	pub fn graphql_names(&self) -> Result<NameMap, NameError> {
+		let mut names = NameMap::new();
+		let mut unions = vec![];
+		for schema in self.get_all() {
+			names.insert(&schema.handle, schema.graphql_name()?)?;
+
+			for field in schema.fields.values() {
+				let ty = field.inner.graphql_type()?;
+				unions.extend(union_name(&ty).map(String::from));
+			}
+		}
+
+		let generated = names.iter().flat_map(|(_, type_name)| {
+			GENERATED_SUFFIXES
+				.iter()
+				.map(move |suffix| format!("{type_name}{suffix}"))
+		});
+		let reserved = RESERVED_TYPES.iter().map(|t| t.to_string());
+		for graphql_name in reserved.chain(generated).chain(unions) {
+			if let Some(name) = names.name(&graphql_name) {
+				return Err(NameError::Reserved {
+					name: name.into(),
+					graphql_name,
+				});
+			}
+		}
+
+		Ok(names)
+	}
+
+	/// Returns the root fields of the graphql schema, every component can be
+	/// listed with a field named after it's handle in camel case
+	///
+	/// The names are the [`graphql_names`](Self::graphql_names).
+	pub fn graphql_properties(
+		&self,
+		names: &NameMap,
+	) -> Result<Vec<graphql::Property>, SchemaError> {
+		self.get_all()
+			.map(|schema| {
+				let field = names::field_name(&schema.handle)?;
+				let object = schema.graphql_type(names)?;
+				Ok(filter::list_property(field, object)?)
+			})
+			.collect()
+	}
+
+	/// Returns the create, update and delete mutations of every component
+	///
+	/// The names are the [`graphql_names`](Self::graphql_names).
+	pub fn graphql_mutations(
+		&self,
+		names: &NameMap,
+	) -> Result<Vec<MutationProperty>, NameError> {
+		let mut mutations = vec![];
+		for schema in self.get_all() {
+			let object = schema.graphql_type(names)?;
+			mutations.extend(mutation::mutation_properties(&object));
+		}
+
+		Ok(mutations)
+	}
+
+	/// Builds the graphql schema from the [`graphql_properties`](Self::graphql_properties)
+	/// and [`graphql_mutations`](Self::graphql_mutations), the properties can
+	/// be subscribed to as well
+	pub fn graphql_schema(&self) -> Result<Valid<Schema>, SchemaError> {
+		let names = self.graphql_names()?;
+		let props = self.graphql_properties(&names)?;
+		let mutations = self.graphql_mutations(&names)?;
+		let mutations = mutations.into_iter().map(|m| m.property).collect();
+
+		graphql::create_schema(props.clone(), mutations, props)
+	}
+
+	/// Returns the schema with the graphql type name
+	pub fn get_by_graphql_name(
+		&self,
+		names: &NameMap,
+		type_name: &str,
+	) -> Option<&ComponentSchema> {
+		names
+			.name(type_name)
+			.and_then(|handle| self.get_by_handle(handle))
+	}
+}
+
+/// Returns the name of the union a field type generates
+fn union_name(ty: &graphql::Type) -> Option<&str> {
+	match ty {
+		graphql::Type::List(ty) | graphql::Type::NonNull(ty) => union_name(ty),
+		graphql::Type::Union { type_name, .. } => Some(type_name),
+		_ => None,
+	}
+}
+
+/// Validates the schemas as database components, see
+/// [`ComponentSchema::database_component`]
+fn validate<'a>(
+	schemas: impl IntoIterator<Item = &'a ComponentSchema>,
+) -> Result<(), LayoutError> {
+	let components = schemas.into_iter().map(|s| s.database_component());
+	Layout::from_components(components)?;
+
+	Ok(())
 }
 
 #[cfg(test)]
@@ -110,6 +316,8 @@ mod tests {
 	// - [x] thread safe
 	// - [ ] equal comparison for testing
 
+	use database::types::validate::ValidationError;
+
 	use super::schema::FieldSchema;
 	use crate::fields::defaults::{NumberField, TextField};
 
@@ -152,7 +360,7 @@ mod tests {
 			load_with_defaults("testfiles/components/minimal.json").await;
 
 		let component = ComponentSchema::new("Test", "test");
-		components.insert(component);
+		components.insert(component).unwrap();
 		assert_eq!(3, components.len());
 	}
 	// todo: test a component that has fields
@@ -202,13 +410,155 @@ mod tests {
 		let mut components =
 			load_with_defaults("testfiles/components/minimal.json").await;
 
-		components.remove_by_handle("button");
+		components.remove_by_handle("button").unwrap();
 
 		let all = components.get_all();
 
 		assert_eq!(1, all.len());
 	}
 
+	#[tokio::test]
+	async fn test_graphql_schema() {
+		let mut components =
+			load_with_defaults("testfiles/components/test_load.json").await;
+		components
+			.insert(ComponentSchema::new("Internal Link", "internal-link"))
+			.unwrap();
+
+		let schema = components.graphql_schema().unwrap();
+		let ty = |ty: &str, name: &str| {
+			schema.type_field(ty, name).unwrap().ty.to_string()
+		};
+
+		assert_eq!(ty("Query", "button"), "[Button!]!");
+		assert_eq!(ty("Query", "internalLink"), "[InternalLink!]!");
+		assert_eq!(ty("Button", "id"), "Id!");
+		assert_eq!(ty("Button", "label"), "String");
+		// the minimum of the number requires a value
+		assert_eq!(ty("Counter", "count"), "Int!");
+
+		let button = schema.type_field("Query", "button").unwrap();
+		let filter = button.argument_by_name("filter").unwrap();
+		assert_eq!(filter.ty.to_string(), "ButtonFilter");
+
+		assert_eq!(ty("Subscription", "button"), "[Button!]!");
+		assert_eq!(ty("Mutation", "createCounter"), "Counter!");
+		assert_eq!(ty("Mutation", "updateInternalLink"), "InternalLink");
+		let counter = schema.get_input_object("CounterInput").unwrap();
+		assert_eq!(counter.fields["count"].ty.to_string(), "Int!");
+	}
+
+	#[tokio::test]
+	async fn test_graphql_references() {
+		let components =
+			load_with_defaults("testfiles/components/goal.json").await;
+
+		let schema = components.graphql_schema().unwrap();
+		let ty = |ty: &str, name: &str| {
+			schema.type_field(ty, name).unwrap().ty.to_string()
+		};
+
+		assert_eq!(ty("Button", "link"), "InternalLinkOrExternalLink!");
+		// a deleted instance leaves a null in the list
+		assert_eq!(ty("Menu", "buttons"), "[Button]");
+		let link = schema.get_union("InternalLinkOrExternalLink").unwrap();
+		assert_eq!(link.members.len(), 2);
+		assert!(schema.is_subtype("Component", "ExternalLink"));
+		let button = schema.get_input_object("ButtonInput").unwrap();
+		assert_eq!(button.fields["link"].ty.to_string(), "ReferenceInput!");
+		let menu = schema.get_input_object("MenuInput").unwrap();
+		assert_eq!(menu.fields["buttons"].ty.to_string(), "[ReferenceInput]");
+
+		// a component cannot be named like the union of a field
+		let mut components = components;
+		let handle = "internal-link-or-external-link";
+		components
+			.insert(ComponentSchema::new("Link", handle))
+			.unwrap();
+		assert!(matches!(
+			components.graphql_schema(),
+			Err(SchemaError::Name(NameError::Reserved { name, .. }))
+				if name == handle
+		));
+	}
+
+	#[tokio::test]
+	async fn test_graphql_names() {
+		let mut components =
+			load_with_defaults("testfiles/components/names.json").await;
+
+		let schema = components.graphql_schema().unwrap();
+		let ty = |ty: &str, name: &str| {
+			schema.type_field(ty, name).unwrap().ty.to_string()
+		};
+		assert_eq!(ty("Query", "callToAction"), "[CallToAction!]!");
+		assert_eq!(ty("CallToAction", "linkTarget"), "String");
+		assert_eq!(ty("CallToAction", "buttonLabel"), "String");
+
+		// two handles cannot map to the same type
+		components
+			.insert(ComponentSchema::new("CTA", "call_to_action"))
+			.unwrap();
+		assert!(matches!(
+			components.graphql_schema().unwrap_err(),
+			SchemaError::Name(e) if e == NameError::Collision {
+				first: "call-to-action".into(),
+				second: "call_to_action".into(),
+				graphql_name: "CallToAction".into()
+			}
+		));
+		components.remove_by_handle("call_to_action").unwrap();
+
+		for handle in ["query", "call-to-action-input", "42", "date-time"] {
+			components
+				.insert(ComponentSchema::new("Invalid", handle))
+				.unwrap();
+			assert!(components.graphql_schema().is_err(), "{handle}");
+			components.remove_by_handle(handle).unwrap();
+		}
+
+		let mut component = ComponentSchema::new("Link", "link");
+		component.fields.insert(
+			"ID".into(),
+			FieldSchema::new(Box::new(TextField::default())),
+		);
+		components.insert(component).unwrap();
+		assert!(matches!(
+			components.graphql_schema(),
+			Err(SchemaError::Name(NameError::Reserved { .. }))
+		));
+	}
+
+	#[tokio::test]
+	async fn test_validate() {
+		let mut components =
+			load_with_defaults("testfiles/components/minimal.json").await;
+
+		let settings = [
+			("component".to_string(), serde_json::json!(["button"])),
+			("max".to_string(), 1.into()),
+		];
+		let field = Fields::default()
+			.parse_field("component", settings.into_iter().collect())
+			.unwrap();
+		let mut teaser = ComponentSchema::new("Teaser", "teaser");
+		teaser
+			.fields
+			.insert("button".into(), FieldSchema::new(field));
+		components.insert(teaser.clone()).unwrap();
+
+		// the teaser references the button
+		assert!(matches!(
+			components.remove_by_handle("button"),
+			Err(LayoutError::Invalid(ValidationError::UnknownRelated { .. }))
+		));
+		assert_eq!(components.len(), 3);
+
+		components.remove_by_handle("teaser").unwrap();
+		components.remove_by_handle("button").unwrap();
+		assert!(components.insert(teaser).is_err());
+	}
+
 	#[tokio::test]
 	async fn test_update() {
 		let mut components =
@@ -222,11 +572,11 @@ mod tests {
 			FieldSchema::new(Box::new(TextField::default())),
 		);
 
-		components.insert(component);
+		components.insert(component).unwrap();
 
 		let updated = components.get_by_handle("button").unwrap();
 
 		assert_eq!("new name", updated.name);
-		assert!(updated.fields.get("new field").is_some());
+		assert!(updated.fields.contains_key("new field"));
 	}
 }
@@ -103,7 +103,7 @@ impl From<ComponentSchema> for SchemaComponentDto {
 	}
 }
 
-fn component_dto_to_schema(
+pub fn component_dto_to_schema(
 	dto: SchemaComponentDto,
 	fields_kinds: &Fields,
 ) -> Result<ComponentSchema, ParseFieldError> {
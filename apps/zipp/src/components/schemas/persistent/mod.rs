@@ -1,9 +1,13 @@
 mod json;
 mod memory;
 
+pub use json::{component_dto_to_schema, SchemaComponentDto};
+
 use std::fmt::Debug;
 use std::io;
 
+use database::types::schema::SchemaError;
+
 use crate::fields::{Fields, ParseFieldError};
 
 use super::schema::ComponentSchema;
@@ -24,6 +28,9 @@ pub enum PersistentError {
 		error: ParseFieldError,
 		file_name: String,
 	},
+
+	#[error("the schemas are invalid: {0}")]
+	Invalid(#[from] SchemaError),
 }
 
 #[async_trait::async_trait]
@@ -1,7 +1,18 @@
-use std::any::Any;
 use std::collections::BTreeMap;
 
-use crate::fields::Field;
+use database::types::{
+	component::{self, Component, FieldKind},
+	validate::COMPONENT_PREFIX,
+};
+use graphql::{
+	names::{self, NameError, NameMap},
+	scalar::Scalar,
+};
+
+use crate::fields::{defaults::ComponentField, Field, ValidateError};
+
+/// The graphql interface implemented by every component
+pub const COMPONENT_INTERFACE: &str = "Component";
 
 // pub mod component_store;
 // pub mod default_field_kinds;
@@ -24,7 +35,7 @@ impl FieldSchema {
 }
 
 impl PartialEq for FieldSchema {
-	fn eq(&self, other: &Self) -> bool {
+	fn eq(&self, _other: &Self) -> bool {
 		todo!("Field::eq")
 	}
 }
@@ -45,6 +56,7 @@ pub struct ComponentSchema {
 }
 
 impl ComponentSchema {
+	#[cfg(test)]
 	pub fn new(name: impl Into<String>, handle: impl Into<String>) -> Self {
 		Self {
 			name: name.into(),
@@ -53,6 +65,137 @@ impl ComponentSchema {
 		}
 	}
 
+	/// Returns the name of the graphql type, the handle in pascal case
+	///
+	/// `internal-link` becomes `InternalLink`.
+	pub fn graphql_name(&self) -> Result<String, NameError> {
+		names::type_name(&self.handle)
+	}
+
+	/// Returns the graphql name of every field, in camel case
+	///
+	/// The `id` is reserved for the id of the instance.
+	pub fn graphql_fields(&self) -> Result<NameMap, NameError> {
+		let mut fields = NameMap::new();
+		for name in self.fields.keys() {
+			let graphql_name = names::field_name(name)?;
+			if graphql_name == "id" {
+				return Err(NameError::Reserved {
+					name: name.clone(),
+					graphql_name,
+				});
+			}
+
+			fields.insert(name, graphql_name)?;
+		}
+
+		Ok(fields)
+	}
+
+	/// Returns the graphql object, every field contributes it's own type
+	///
+	/// The type name is taken from the names of all components, see
+	/// [`ComponentSchemas::graphql_names`](super::ComponentSchemas::graphql_names).
+	/// Every component implements the `Component` interface.
+	pub fn graphql_type(
+		&self,
+		names: &NameMap,
+	) -> Result<graphql::Type, NameError> {
+		let type_name = names
+			.graphql_name(&self.handle)
+			.ok_or_else(|| NameError::Invalid(self.handle.clone()))?;
+
+		let id = graphql::Property {
+			name: "id".into(),
+			arguments: vec![],
+			ty: graphql::Type::Scalar(Scalar::id()).non_null(),
+		};
+		let interface = graphql::Type::Interface {
+			type_name: COMPONENT_INTERFACE.into(),
+			fields: vec![id.clone()],
+		};
+
+		// the names are in the same order as the fields
+		let names = self.graphql_fields()?;
+		let mut fields = vec![id];
+		for ((_, name), field) in names.iter().zip(self.fields.values()) {
+			fields.push(graphql::Property {
+				name: name.into(),
+				arguments: vec![],
+				ty: field.inner.graphql_type()?,
+			});
+		}
+
+		Ok(graphql::Type::Object {
+			type_name: type_name.into(),
+			fields,
+			interfaces: vec![interface],
+		})
+	}
+
+	/// Returns the component the database validates, a single reference to
+	/// one component is related to it's id
+	pub fn database_component(&self) -> Component {
+		let id = component::Field {
+			name: "id".into(),
+			kind: FieldKind::Id,
+			related: None,
+			primary: true,
+			index: false,
+		};
+
+		let fields = self.fields.iter().map(|(name, field)| {
+			let kind = field.inner.database_kind();
+			let related = field
+				.downcast_ref::<ComponentField>()
+				.filter(|_| kind == FieldKind::ComponentId)
+				.and_then(|f| match f.component.as_slice() {
+					[handle] => Some(format!("{}.id", database_name(handle))),
+					_ => None,
+				});
+
+			component::Field {
+				name: database_field_name(name),
+				kind,
+				related,
+				primary: false,
+				index: false,
+			}
+		});
+
+		Component {
+			name: database_name(&self.handle),
+			fields: [id].into_iter().chain(fields).collect(),
+		}
+	}
+
+	/// Returns the database kind of every field, the instances are queried
+	/// with them
+	pub fn field_kinds(&self) -> BTreeMap<String, FieldKind> {
+		self.fields
+			.iter()
+			.map(|(name, field)| (name.clone(), field.inner.database_kind()))
+			.collect()
+	}
+
+	/// Validates the value of every field and returns the invalid ones
+	///
+	/// Missing and null values are not validated, the graphql types already
+	/// require the values which cannot be null.
+	pub fn validate(
+		&self,
+		data: &serde_json::Map<String, serde_json::Value>,
+	) -> Vec<(&str, ValidateError)> {
+		self.fields
+			.iter()
+			.filter_map(|(name, field)| {
+				let value = data.get(name).filter(|v| !v.is_null())?;
+				let err = field.inner.validate(value).err()?;
+				Some((name.as_str(), err))
+			})
+			.collect()
+	}
+
 	// pub fn from_dto(dto: SchemaDto, field_kinds: &FieldKinds) -> Self {
 	// 	Self {
 	// 		name: dto.name,
@@ -68,6 +211,20 @@ impl ComponentSchema {
 	//
 }
 
+/// Returns the name of the component in the database, `internal-link`
+/// becomes `component_internal_link`
+pub fn database_name(handle: &str) -> String {
+	let name = database_field_name(handle).to_ascii_lowercase();
+	format!("{COMPONENT_PREFIX}{name}")
+}
+
+/// Replaces every character the database does not allow in a name
+fn database_field_name(name: &str) -> String {
+	name.chars()
+		.map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+		.collect()
+}
+
 impl PartialEq for ComponentSchema {
 	fn eq(&self, other: &Self) -> bool {
 		self.name == other.name
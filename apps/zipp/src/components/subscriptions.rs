@@ -0,0 +1,560 @@
+//! Subscriptions
+//!
+//! Subscriptions are served over a WebSocket using the
+//! [graphql-transport-ws](https://github.com/enisdenjo/graphql-ws/blob/master/PROTOCOL.md)
+//! protocol. A subscription lists instances like a query, the list is sent
+//! again every time it changes because of a created, updated or deleted
+//! instance. Queries and mutations complete after their first result.
+
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
+
+use database::{
+	id::Id, tenant::Tenant, Connection, Database, DatabaseError, DatabasePool,
+};
+use fire_http::{
+	error::ClientErrorKind,
+	header::{Method, SEC_WEBSOCKET_PROTOCOL},
+	resources::Resources,
+	routes::{HyperRequest, ParamsNames, PathParams, RawRoute, RoutePath},
+	util::PinnedFuture,
+	ws::{self, CloseCode, JsonError, Message, WebSocket},
+	Response,
+};
+use graphql::execute::{
+	GraphQLError, Request, Response as GraphqlResponse, ResponseData,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::broadcast::error::RecvError, time};
+use tracing::error;
+
+use super::{instances::Change, Components};
+use crate::users::api::{Actors, ACTOR_HEADER, TENANT_HEADER};
+
+const PROTOCOL: &str = "graphql-transport-ws";
+
+/// How long the client has to send `connection_init` after the socket was
+/// opened, the default of the reference implementation
+const CONNECTION_INIT_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+	ConnectionInit,
+	Ping,
+	Pong,
+	Subscribe { id: String, payload: Request },
+	Complete { id: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+	ConnectionAck,
+	Pong,
+	Next {
+		id: String,
+		payload: GraphqlResponse,
+	},
+	/// The request was invalid, no result follows
+	Error {
+		id: String,
+		payload: Vec<GraphQLError>,
+	},
+	Complete {
+		id: String,
+	},
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+	#[error("the websocket failed {0}")]
+	WebSocket(#[from] ws::Error),
+
+	#[error("the message could not be sent {0}")]
+	Json(#[from] JsonError),
+
+	#[error("no database connection {0}")]
+	Database(#[from] DatabaseError),
+
+	#[error("the tenant could not be set {0}")]
+	Tenant(#[from] database::Error),
+}
+
+/// What the session sends back
+#[derive(Debug, Clone)]
+pub enum Reply {
+	Message(ServerMessage),
+	/// Closes the socket with a code of the protocol
+	Close(u16, String),
+}
+
+#[derive(Debug)]
+struct Subscription {
+	request: Request,
+	/// The handles of the subscribed components
+	components: Vec<String>,
+	/// The payload sent last, an unchanged result is not sent again
+	last: serde_json::Value,
+}
+
+/// The state of one socket
+#[derive(Debug)]
+pub struct Session {
+	components: Components,
+	/// The actor of all accesses, taken from the upgrade request
+	actor: Option<Id>,
+	/// The tenant of all connections, taken from the upgrade request
+	tenant: Option<Tenant>,
+	acknowledged: bool,
+	subscriptions: HashMap<String, Subscription>,
+}
+
+impl Session {
+	pub fn new(
+		components: Components,
+		actor: Option<Id>,
+		tenant: Option<Tenant>,
+	) -> Self {
+		Self {
+			components,
+			actor,
+			tenant,
+			acknowledged: false,
+			subscriptions: HashMap::new(),
+		}
+	}
+
+	pub async fn handle(
+		&mut self,
+		conn: Connection<'_>,
+		msg: ClientMessage,
+	) -> Vec<Reply> {
+		match msg {
+			ClientMessage::ConnectionInit if self.acknowledged => {
+				vec![close(4429, "Too many initialisation requests")]
+			}
+			ClientMessage::ConnectionInit => {
+				self.acknowledged = true;
+				vec![Reply::Message(ServerMessage::ConnectionAck)]
+			}
+			ClientMessage::Ping => vec![Reply::Message(ServerMessage::Pong)],
+			ClientMessage::Pong => vec![],
+			ClientMessage::Subscribe { .. } if !self.acknowledged => {
+				vec![close(4401, "Unauthorized")]
+			}
+			ClientMessage::Subscribe { id, .. }
+				if self.subscriptions.contains_key(&id) =>
+			{
+				vec![close(
+					4409,
+					&format!("Subscriber for {id} already exists"),
+				)]
+			}
+			ClientMessage::Subscribe { id, payload } => {
+				self.subscribe(conn, id, payload).await
+			}
+			ClientMessage::Complete { id } => {
+				self.subscriptions.remove(&id);
+				vec![]
+			}
+		}
+	}
+
+	async fn subscribe(
+		&mut self,
+		conn: Connection<'_>,
+		id: String,
+		request: Request,
+	) -> Vec<Reply> {
+		let components = match self.components.subscription(&request).await {
+			Ok(components) => components,
+			Err(e) => {
+				let sources = Default::default();
+				let payload =
+					vec![GraphQLError::new(e.to_string(), None, &sources)];
+				return vec![Reply::Message(ServerMessage::Error {
+					id,
+					payload,
+				})];
+			}
+		};
+		let response = self.components.execute(conn, &request).await;
+
+		// a request error has no data
+		if matches!(response.data, ResponseData::Absent) {
+			let payload = response.errors;
+			return vec![Reply::Message(ServerMessage::Error { id, payload })];
+		}
+
+		if components.is_empty() {
+			return vec![
+				next(id.clone(), response),
+				Reply::Message(ServerMessage::Complete { id }),
+			];
+		}
+
+		let subscription = Subscription {
+			request,
+			components,
+			last: serde_json::to_value(&response).unwrap(),
+		};
+		self.subscriptions.insert(id.clone(), subscription);
+
+		vec![next(id, response)]
+	}
+
+	/// Executes the affected subscriptions again, every subscription is
+	/// affected if the change is unknown because changes were missed
+	pub async fn changed(
+		&mut self,
+		conn: Connection<'_>,
+		change: Option<&Change>,
+	) -> Vec<Reply> {
+		if change.is_some_and(|c| c.tenant != conn.tenant()) {
+			return vec![];
+		}
+
+		let mut replies = vec![];
+		for (id, subscription) in &mut self.subscriptions {
+			let affected = change
+				.is_none_or(|c| subscription.components.contains(&c.component));
+			if !affected {
+				continue;
+			}
+
+			let response =
+				self.components.execute(conn, &subscription.request).await;
+			let value = serde_json::to_value(&response).unwrap();
+			if value != subscription.last {
+				subscription.last = value;
+				replies.push(next(id.clone(), response));
+			}
+		}
+
+		replies
+	}
+
+	/// Returns a database for one message or change, scoped like the upgrade
+	/// request
+	async fn database(&self, pool: &DatabasePool) -> Result<Database, Error> {
+		let mut db = pool.get_with_deadline(pool.request_deadline()).await?;
+		db.set_actor(self.actor);
+		db.set_tenant(self.tenant).await?;
+
+		Ok(db)
+	}
+
+	/// Handles the messages and changes until the socket is closed
+	async fn run(
+		mut self,
+		mut ws: WebSocket,
+		pool: DatabasePool,
+	) -> Result<(), Error> {
+		let mut changes = self.components.subscribe_changes();
+		let init_timeout = time::sleep(CONNECTION_INIT_TIMEOUT);
+		tokio::pin!(init_timeout);
+
+		loop {
+			let replies = tokio::select! {
+				_ = &mut init_timeout, if !self.acknowledged => {
+					vec![close(4408, "Connection initialisation timeout")]
+				}
+				msg = ws.receive() => match msg?.map(|m| parse(&m)) {
+					None => return Ok(()),
+					Some(None) => vec![close(4400, "Invalid message received")],
+					Some(Some(msg)) => {
+						let db = self.database(&pool).await?;
+						self.handle(db.connection(), msg).await
+					}
+				},
+				change = changes.recv() => {
+					let change = match change {
+						Ok(change) => Some(change),
+						Err(RecvError::Lagged(_)) => None,
+						Err(RecvError::Closed) => return Ok(()),
+					};
+
+					let db = self.database(&pool).await?;
+					self.changed(db.connection(), change.as_ref()).await
+				}
+			};
+
+			for reply in replies {
+				match reply {
+					Reply::Message(msg) => ws.serialize(&msg).await?,
+					Reply::Close(code, reason) => {
+						ws.close(CloseCode::from(code), reason).await;
+						return Ok(());
+					}
+				}
+			}
+		}
+	}
+}
+
+fn parse(msg: &Message) -> Option<ClientMessage> {
+	match msg {
+		Message::Text(text) => serde_json::from_str(text).ok(),
+		Message::Binary(bytes) => serde_json::from_slice(bytes).ok(),
+	}
+}
+
+fn close(code: u16, reason: &str) -> Reply {
+	Reply::Close(code, reason.to_string())
+}
+
+fn next(id: String, payload: GraphqlResponse) -> Reply {
+	Reply::Message(ServerMessage::Next { id, payload })
+}
+
+/// The WebSocket route at `/graphql`
+///
+/// Written by hand since the subprotocol needs to be confirmed in the
+/// response.
+pub struct GraphqlWs;
+
+impl RawRoute for GraphqlWs {
+	fn validate_requirements(&self, _params: &ParamsNames, data: &Resources) {
+		assert!(data.exists::<Components>(), "Components resource missing");
+		assert!(data.exists::<Actors>(), "Actors resource missing");
+		assert!(
+			data.exists::<DatabasePool>(),
+			"DatabasePool resource missing"
+		);
+	}
+
+	fn path(&self) -> RoutePath {
+		RoutePath {
+			method: Some(Method::GET),
+			path: "/graphql".into(),
+		}
+	}
+
+	fn call<'a>(
+		&'a self,
+		req: &'a mut HyperRequest,
+		_address: SocketAddr,
+		_params: &'a PathParams,
+		resources: &'a Resources,
+	) -> PinnedFuture<'a, Option<fire_http::Result<Response>>> {
+		PinnedFuture::new(async move {
+			let protocols = req
+				.headers()
+				.get(SEC_WEBSOCKET_PROTOCOL)
+				.and_then(|v| v.to_str().ok())
+				.unwrap_or_default();
+			if !protocols.split(',').any(|p| p.trim() == PROTOCOL) {
+				return Some(Err(ClientErrorKind::BadRequest.into()));
+			}
+
+			let header = |name: &str| {
+				req.headers()
+					.get(name)
+					.map(|v| v.to_str().unwrap_or_default())
+			};
+			let actors = resources.get::<Actors>()?;
+			let (Ok(actor), Ok(tenant)) = (
+				actors.actor(header(ACTOR_HEADER)),
+				actors.tenant(header(TENANT_HEADER)),
+			) else {
+				return Some(Err(ClientErrorKind::BadRequest.into()));
+			};
+
+			let (on_upgrade, ws_accept) = match ws::util::upgrade(req) {
+				Ok(o) => o,
+				Err(e) => return Some(Err(e)),
+			};
+
+			let components = resources.get::<Components>()?.clone();
+			let pool = resources.get::<DatabasePool>()?.clone();
+
+			tokio::spawn(async move {
+				let upgraded = match on_upgrade.await {
+					Ok(upgraded) => upgraded,
+					Err(e) => return error!("websocket upgrade error {e:?}"),
+				};
+
+				let ws = WebSocket::new(upgraded).await;
+				if let Err(e) =
+					Session::new(components, actor, tenant).run(ws, pool).await
+				{
+					error!("graphql subscription failed {e:?}");
+				}
+			});
+
+			let mut resp = ws::util::switching_protocols(ws_accept);
+			resp.header.values.insert(SEC_WEBSOCKET_PROTOCOL, PROTOCOL);
+
+			Some(Ok(resp))
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use database::testing::TestPool;
+	use serde_json::json;
+
+	use super::*;
+	use crate::fields::Fields;
+
+	fn replies(replies: Vec<Reply>) -> serde_json::Value {
+		replies
+			.into_iter()
+			.map(|reply| match reply {
+				Reply::Message(msg) => serde_json::to_value(msg).unwrap(),
+				Reply::Close(code, _) => json!({ "close": code }),
+			})
+			.collect()
+	}
+
+	fn subscribe(id: &str, query: &str) -> ClientMessage {
+		serde_json::from_value(json!({
+			"type": "subscribe",
+			"id": id,
+			"payload": { "query": query }
+		}))
+		.unwrap()
+	}
+
+	#[tokio::test]
+	async fn test_invalid_schema() {
+		let pool = TestPool::memory();
+		let mut db = pool.isolated().await;
+		// without any component the query root has no fields
+		let components = Components::new(&mut db, Fields::default(), None)
+			.await
+			.unwrap();
+		assert!(components.graphql_schema().await.is_err());
+
+		let conn = db.connection();
+		let mut session = Session::new(components, None, None);
+		session.handle(conn, ClientMessage::ConnectionInit).await;
+
+		let query = "subscription { counter { count } }";
+		let res = replies(session.handle(conn, subscribe("1", query)).await);
+		assert_eq!(res[0]["type"], "error");
+		assert_eq!(res[0]["id"], "1");
+		assert!(session.subscriptions.is_empty());
+	}
+
+	#[tokio::test]
+	async fn test_session_tenant() {
+		let pool = TestPool::memory();
+		let mut db = pool.isolated().await;
+		let components = Components::new(&mut db, Fields::default(), None)
+			.await
+			.unwrap();
+
+		let tenant =
+			Tenant::new(pool.new_id(database::id::Kind::new(false, 2)));
+		let session = Session::new(components, None, Some(tenant));
+		let db = session.database(pool.pool()).await.unwrap();
+		assert_eq!(db.tenant(), Some(tenant));
+	}
+
+	#[tokio::test]
+	async fn test_session() {
+		for pool in TestPool::backends().await {
+			session(pool).await;
+		}
+	}
+
+	async fn session(pool: TestPool) {
+		let mut db = pool.isolated().await;
+		let components = Components::new(
+			&mut db,
+			Fields::default(),
+			Some("testfiles/components/test_load.json"),
+		)
+		.await
+		.unwrap();
+		let mut changes = components.subscribe_changes();
+		let conn = db.connection();
+		let mut session = Session::new(components.clone(), None, None);
+
+		let counters = "subscription { counter { count } }";
+		let res = session.handle(conn, subscribe("1", counters)).await;
+		assert_eq!(replies(res), json!([{ "close": 4401 }]));
+
+		let res = session.handle(conn, ClientMessage::ConnectionInit).await;
+		assert_eq!(replies(res), json!([{ "type": "connection_ack" }]));
+		let res = session.handle(conn, ClientMessage::Ping).await;
+		assert_eq!(replies(res), json!([{ "type": "pong" }]));
+
+		let res = session.handle(conn, subscribe("1", counters)).await;
+		assert_eq!(
+			replies(res),
+			json!([{
+				"type": "next",
+				"id": "1",
+				"payload": { "data": { "counter": [] } }
+			}])
+		);
+		let res = session.handle(conn, subscribe("1", counters)).await;
+		assert_eq!(replies(res), json!([{ "close": 4409 }]));
+		let buttons = "subscription { button { label } }";
+		let res = session.handle(conn, subscribe("b", buttons)).await;
+		assert_eq!(replies(res)[0]["payload"]["data"]["button"], json!([]));
+
+		// only the subscriptions of the changed component run again, the
+		// new button is not sent before it's change
+		let instances = components.instances(conn);
+		let data = serde_json::from_value(json!({ "count": 12 })).unwrap();
+		instances.create("counter", data).await.unwrap();
+		let data = serde_json::from_value(json!({ "label": "Save" })).unwrap();
+		instances.create("button", data).await.unwrap();
+
+		let change = changes.recv().await.unwrap();
+		assert_eq!(change.component, "counter");
+		let res = session.changed(conn, Some(&change)).await;
+		assert_eq!(
+			replies(res),
+			json!([{
+				"type": "next",
+				"id": "1",
+				"payload": { "data": { "counter": [{ "count": 12 }] } }
+			}])
+		);
+		let change = changes.recv().await.unwrap();
+		let res = session.changed(conn, Some(&change)).await;
+		assert_eq!(
+			replies(res),
+			json!([{
+				"type": "next",
+				"id": "b",
+				"payload": { "data": { "button": [{ "label": "Save" }] } }
+			}])
+		);
+		// the result did not change
+		assert!(session.changed(conn, None).await.is_empty());
+
+		// queries complete after the result
+		let res = session
+			.handle(conn, subscribe("2", "{ button { label } }"))
+			.await;
+		assert_eq!(
+			replies(res),
+			json!([
+				{
+					"type": "next",
+					"id": "2",
+					"payload": { "data": { "button": [{ "label": "Save" }] } }
+				},
+				{ "type": "complete", "id": "2" }
+			])
+		);
+
+		let res = session.handle(conn, subscribe("3", "{ unknown }")).await;
+		let res = replies(res);
+		assert_eq!(res[0]["type"], "error");
+		assert!(!res[0]["payload"].as_array().unwrap().is_empty());
+
+		let complete = ClientMessage::Complete { id: "1".into() };
+		assert!(session.handle(conn, complete).await.is_empty());
+		let data = serde_json::from_value(json!({ "count": 13 })).unwrap();
+		instances.create("counter", data).await.unwrap();
+		let change = changes.recv().await.unwrap();
+		assert!(session.changed(conn, Some(&change)).await.is_empty());
+	}
+}
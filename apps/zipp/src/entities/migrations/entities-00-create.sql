@@ -0,0 +1,25 @@
+-- create entity tables
+CREATE TABLE entity (
+    id text PRIMARY KEY,
+    type_handle text NOT NULL,
+    kind smallint NOT NULL,
+    "order" bigint NOT NULL
+);
+
+CREATE INDEX ON entity (type_handle);
+CREATE INDEX ON entity ("order");
+
+CREATE TABLE entity_site (
+    id text PRIMARY KEY,
+    entry_id text NOT NULL REFERENCES entity (id) ON DELETE CASCADE,
+    site_id text NOT NULL,
+    state smallint NOT NULL,
+    updated_on timestamp NOT NULL,
+    component_id text,
+    UNIQUE (entry_id, site_id)
+);
+
+CREATE INDEX ON entity_site (site_id);
+CREATE INDEX ON entity_site (state);
+CREATE INDEX ON entity_site (updated_on);
+CREATE INDEX ON entity_site (component_id);
@@ -0,0 +1,453 @@
+//! Entities
+//!
+//! An entry exists once, for every site it can have a variant with it's own
+//! state and component. See `docs/database.md` for the layout of the
+//! `entity` and `entity_site` tables.
+
+mod persistent;
+
+use database::{
+	batch::BatchError,
+	deadline::DeadlineExceeded,
+	dump::{DumpError, Dumpable},
+	id::{Id, Kind},
+	kv,
+	query::QueryError,
+	Connection, Database, DatabaseKind,
+};
+use fire_http::Resource;
+use fire_postgres::time::DateTime;
+use serde::{Deserialize, Serialize};
+
+use self::persistent::{
+	kv::KvBuilder, memory::MemoryBuilder, postgres::PostgresBuilder,
+	EntitiesPersistent, EntitiesPersistentBuilder,
+};
+
+pub const ENTRY_KIND: Kind = Kind::new(false, 2);
+pub const ENTRY_SITE_KIND: Kind = Kind::new(false, 3);
+
+/// The table names, used to cache queries
+const ENTRY_TABLE: &str = "entity";
+const ENTRY_SITE_TABLE: &str = "entity_site";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+	pub id: Id,
+	pub type_handle: String,
+	/// Called `type` in the docs, single, channel, ...
+	pub kind: i16,
+	pub order: i64,
+}
+
+/// The variant of an entry on a site
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntrySite {
+	pub id: Id,
+	pub entry_id: Id,
+	pub site_id: Id,
+	pub state: i16,
+	pub updated_on: DateTime,
+	pub component_id: Option<Id>,
+}
+
+/// A variant in a dump
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DumpedSite {
+	id: Id,
+	entry_id: Id,
+	site_id: Id,
+	state: i16,
+	/// DateTime can't be serialized
+	updated_on_us: i64,
+	component_id: Option<Id>,
+}
+
+/// An entry together with the variant of one site
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SiteEntry {
+	pub entry: Entry,
+	pub site: EntrySite,
+}
+
+#[derive(Debug, Clone)]
+pub struct CreateEntry {
+	pub type_handle: String,
+	pub kind: i16,
+	pub order: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SetEntrySite {
+	pub site_id: Id,
+	pub state: i16,
+	pub component_id: Option<Id>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+	#[error("the entry {0} was not found")]
+	NotFound(Id),
+
+	#[error("a postgres error occured!")]
+	Postgres(#[from] database::Error),
+
+	#[error("the query failed {0}")]
+	Query(#[from] QueryError),
+
+	#[error("the batch failed {0}")]
+	Batch(#[from] BatchError),
+
+	#[error("{0}")]
+	Deadline(#[from] DeadlineExceeded),
+
+	#[error("a key-value store error occured!")]
+	KeyValue(#[from] kv::Error),
+}
+
+#[derive(Debug, Resource)]
+pub struct Entities {
+	inner: Box<dyn EntitiesPersistentBuilder>,
+}
+
+impl Entities {
+	pub async fn new(conn: &mut Database) -> Result<Self, Error> {
+		let persistent: Box<dyn EntitiesPersistentBuilder> = match conn.kind() {
+			DatabaseKind::Memory => Box::new(MemoryBuilder::new()),
+			DatabaseKind::Postgres => {
+				Box::new(PostgresBuilder::new(conn).await?)
+			}
+			DatabaseKind::KeyValue => Box::new(KvBuilder::new()),
+		};
+
+		Ok(Self { inner: persistent })
+	}
+
+	/// The variants of all entries, these are dumped separately from the
+	/// entries
+	pub fn sites_table(&self) -> EntitySites {
+		EntitySites(self.clone())
+	}
+
+	pub fn with_conn<'a>(
+		&'a self,
+		conn: Connection<'a>,
+	) -> EntitiesWithConn<'a> {
+		EntitiesWithConn {
+			inner: self.inner.with_conn(conn),
+			conn,
+		}
+	}
+}
+
+#[derive(Debug)]
+pub struct EntitiesWithConn<'a> {
+	inner: Box<dyn EntitiesPersistent + 'a>,
+	conn: Connection<'a>,
+}
+
+impl EntitiesWithConn<'_> {
+	pub async fn create_entry(
+		&self,
+		entry: CreateEntry,
+	) -> Result<Entry, Error> {
+		let entry = Entry {
+			id: self.conn.new_id(ENTRY_KIND),
+			type_handle: entry.type_handle,
+			kind: entry.kind,
+			order: entry.order,
+		};
+
+		let res = self.inner.insert_entry(&entry).await;
+		self.conn.invalidate(ENTRY_TABLE);
+		res?;
+
+		Ok(entry)
+	}
+
+	pub async fn by_id(&self, id: &Id) -> Result<Option<Entry>, Error> {
+		self.conn
+			.cached(ENTRY_TABLE, format!("by_id {id}"), || {
+				self.inner.entry_by_id(id)
+			})
+			.await
+	}
+
+	/// Creates or updates the variant of the entry on the site
+	pub async fn set_site(
+		&self,
+		entry_id: &Id,
+		site: SetEntrySite,
+	) -> Result<EntrySite, Error> {
+		if self.by_id(entry_id).await?.is_none() {
+			return Err(Error::NotFound(*entry_id));
+		}
+
+		let site = EntrySite {
+			id: self.conn.new_id(ENTRY_SITE_KIND),
+			entry_id: *entry_id,
+			site_id: site.site_id,
+			state: site.state,
+			updated_on: DateTime::from_std(self.conn.now()),
+			component_id: site.component_id,
+		};
+
+		let res = self.inner.upsert_site(&site).await;
+		self.conn.invalidate(ENTRY_SITE_TABLE);
+
+		res
+	}
+
+	/// Returns the variants of the entry on all sites
+	pub async fn sites(&self, entry_id: &Id) -> Result<Vec<EntrySite>, Error> {
+		self.conn
+			.cached(ENTRY_SITE_TABLE, format!("sites {entry_id}"), || {
+				self.inner.sites(entry_id)
+			})
+			.await
+	}
+
+	/// Returns the entry which was updated last on the site with the given
+	/// state
+	pub async fn latest(
+		&self,
+		site_id: &Id,
+		state: i16,
+	) -> Result<Option<SiteEntry>, Error> {
+		self.conn
+			.cached(
+				ENTRY_SITE_TABLE,
+				format!("latest {site_id} {state}"),
+				|| self.inner.latest(site_id, state),
+			)
+			.await
+	}
+}
+
+#[async_trait::async_trait]
+impl Dumpable for Entities {
+	fn name(&self) -> &str {
+		ENTRY_TABLE
+	}
+
+	async fn dump(
+		&self,
+		conn: Connection<'_>,
+	) -> Result<Vec<serde_json::Value>, DumpError> {
+		let entries = self
+			.inner
+			.with_conn(conn)
+			.all_entries()
+			.await
+			.map_err(|e| DumpError::table(ENTRY_TABLE, e))?;
+
+		entries
+			.iter()
+			.map(serde_json::to_value)
+			.collect::<Result<_, _>>()
+			.map_err(Into::into)
+	}
+
+	async fn restore(
+		&self,
+		conn: Connection<'_>,
+		rows: Vec<serde_json::Value>,
+	) -> Result<(), DumpError> {
+		let entries = rows
+			.into_iter()
+			.map(serde_json::from_value)
+			.collect::<Result<Vec<Entry>, _>>()?;
+
+		self.inner
+			.with_conn(conn)
+			.restore_entries(&entries)
+			.await
+			.map_err(|e| DumpError::table(ENTRY_TABLE, e))
+	}
+}
+
+impl Clone for Entities {
+	fn clone(&self) -> Self {
+		Self {
+			inner: self.inner.clone_box(),
+		}
+	}
+}
+
+/// The variants of all entries in a dump, needs to be registered after
+/// [`Entities`]
+#[derive(Debug, Clone)]
+pub struct EntitySites(Entities);
+
+#[async_trait::async_trait]
+impl Dumpable for EntitySites {
+	fn name(&self) -> &str {
+		ENTRY_SITE_TABLE
+	}
+
+	async fn dump(
+		&self,
+		conn: Connection<'_>,
+	) -> Result<Vec<serde_json::Value>, DumpError> {
+		let sites = self
+			.0
+			.inner
+			.with_conn(conn)
+			.all_sites()
+			.await
+			.map_err(|e| DumpError::table(ENTRY_SITE_TABLE, e))?;
+
+		sites
+			.into_iter()
+			.map(|s| serde_json::to_value(DumpedSite::from(s)))
+			.collect::<Result<_, _>>()
+			.map_err(Into::into)
+	}
+
+	async fn restore(
+		&self,
+		conn: Connection<'_>,
+		rows: Vec<serde_json::Value>,
+	) -> Result<(), DumpError> {
+		let sites = rows
+			.into_iter()
+			.map(|row| {
+				serde_json::from_value::<DumpedSite>(row).map(Into::into)
+			})
+			.collect::<Result<Vec<EntrySite>, _>>()?;
+
+		self.0
+			.inner
+			.with_conn(conn)
+			.restore_sites(&sites)
+			.await
+			.map_err(|e| DumpError::table(ENTRY_SITE_TABLE, e))
+	}
+}
+
+impl From<EntrySite> for DumpedSite {
+	fn from(s: EntrySite) -> Self {
+		Self {
+			id: s.id,
+			entry_id: s.entry_id,
+			site_id: s.site_id,
+			state: s.state,
+			updated_on_us: s.updated_on.to_microsecs_since_2000(),
+			component_id: s.component_id,
+		}
+	}
+}
+
+impl From<DumpedSite> for EntrySite {
+	fn from(s: DumpedSite) -> Self {
+		Self {
+			id: s.id,
+			entry_id: s.entry_id,
+			site_id: s.site_id,
+			state: s.state,
+			updated_on: DateTime::from_microsecs_since_2000(s.updated_on_us),
+			component_id: s.component_id,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use database::{dump::Dump, testing::TestPool};
+
+	use super::*;
+
+	fn create(handle: &str) -> CreateEntry {
+		CreateEntry {
+			type_handle: handle.into(),
+			kind: 0,
+			order: 0,
+		}
+	}
+
+	fn site(site_id: Id, state: i16) -> SetEntrySite {
+		SetEntrySite {
+			site_id,
+			state,
+			component_id: None,
+		}
+	}
+
+	#[tokio::test]
+	async fn test_latest() {
+		for pool in TestPool::backends().await {
+			latest(pool).await;
+		}
+	}
+
+	async fn latest(pool: TestPool) {
+		let mut db = pool.isolated().await;
+		let entities = Entities::new(&mut db).await.unwrap();
+		let entities = entities.with_conn(db.connection());
+
+		let site_a = Id::new(Kind::new(false, 4));
+		let site_b = Id::new(Kind::new(false, 4));
+
+		let news = entities.create_entry(create("news")).await.unwrap();
+		let event = entities.create_entry(create("event")).await.unwrap();
+
+		entities.set_site(&news.id, site(site_a, 5)).await.unwrap();
+		pool.advance(Duration::from_secs(1));
+		entities.set_site(&event.id, site(site_a, 5)).await.unwrap();
+		entities.set_site(&news.id, site(site_b, 5)).await.unwrap();
+
+		let latest = entities.latest(&site_a, 5).await.unwrap().unwrap();
+		assert_eq!(latest.entry, event);
+		assert_eq!(latest.site.site_id, site_a);
+
+		// updating the variant makes it the latest again
+		pool.advance(Duration::from_secs(1));
+		let variant =
+			entities.set_site(&news.id, site(site_a, 5)).await.unwrap();
+		let latest = entities.latest(&site_a, 5).await.unwrap().unwrap();
+		assert_eq!(latest.entry, news);
+		assert_eq!(latest.site, variant);
+		assert_eq!(entities.sites(&news.id).await.unwrap().len(), 2);
+
+		assert!(entities.latest(&site_b, 1).await.unwrap().is_none());
+
+		let res = entities.set_site(&site_a, site(site_a, 5)).await;
+		assert!(matches!(res, Err(Error::NotFound(_))));
+	}
+
+	#[tokio::test]
+	async fn test_dump_restore() {
+		for pool in TestPool::backends().await {
+			dump_restore(pool).await;
+		}
+	}
+
+	async fn dump_restore(pool: TestPool) {
+		let mut db = pool.isolated().await;
+		let entities = Entities::new(&mut db).await.unwrap();
+		let site_id = Id::new(Kind::new(false, 4));
+
+		let conn = entities.with_conn(db.connection());
+		let news = conn.create_entry(create("news")).await.unwrap();
+		let variant = conn.set_site(&news.id, site(site_id, 5)).await.unwrap();
+
+		let mut dump = Dump::new();
+		dump.register(entities.clone());
+		dump.register(entities.sites_table());
+		let archive = dump.export(&db).await.unwrap();
+
+		let mut other = pool.isolated().await;
+		dump.restore(&mut other, archive.clone()).await.unwrap();
+
+		let conn = entities.with_conn(other.connection());
+		let latest = conn.latest(&site_id, 5).await.unwrap().unwrap();
+		assert_eq!(latest.entry, news);
+		assert_eq!(latest.site, variant);
+		drop(conn);
+
+		// the ids already exist
+		assert!(dump.restore(&mut other, archive).await.is_err());
+	}
+}
@@ -0,0 +1,326 @@
+use database::{
+	batch::BatchMode,
+	id::Id,
+	kv::{Index, Table, TableWithConn},
+	query::{Record, Value},
+	trace::{Statement, ToSql},
+	Connection,
+};
+use fire_postgres::time::DateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::entities::{ENTRY_SITE_TABLE, ENTRY_TABLE};
+
+use super::{
+	EntitiesPersistent, EntitiesPersistentBuilder, Entry, EntrySite, Error,
+	SiteEntry,
+};
+
+const ENTRIES: Table<EntryRow> = Table::new(ENTRY_TABLE);
+const BY_ENTRY: Index<EntrySiteRow> =
+	Index::new("entry_id", |s| s.entry_id.into());
+const BY_SITE: Index<EntrySiteRow> =
+	Index::new("site_id", |s| s.site_id.into());
+const SITES: Table<EntrySiteRow> =
+	Table::with_indexes(ENTRY_SITE_TABLE, &[BY_ENTRY, BY_SITE]);
+
+#[derive(Debug, Clone)]
+pub struct KvBuilder;
+
+impl KvBuilder {
+	pub fn new() -> Self {
+		Self
+	}
+}
+
+impl EntitiesPersistentBuilder for KvBuilder {
+	fn with_conn<'a>(
+		&'a self,
+		conn: Connection<'a>,
+	) -> Box<dyn EntitiesPersistent + 'a> {
+		Box::new(Kv {
+			entries: ENTRIES.with_conn(conn.into_kv()),
+			sites: SITES.with_conn(conn.into_kv()),
+			conn,
+		})
+	}
+
+	fn clone_box(&self) -> Box<dyn EntitiesPersistentBuilder> {
+		Box::new(self.clone())
+	}
+}
+
+#[derive(Debug)]
+pub struct Kv<'a> {
+	entries: TableWithConn<'a, EntryRow>,
+	sites: TableWithConn<'a, EntrySiteRow>,
+	conn: Connection<'a>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EntryRow {
+	id: Id,
+	type_handle: String,
+	kind: i16,
+	order: i64,
+}
+
+impl Record for EntryRow {
+	fn id(&self) -> Id {
+		self.id
+	}
+
+	fn value(&self, column: &str) -> Option<Value> {
+		match column {
+			"id" => Some(self.id.into()),
+			"type_handle" => Some(self.type_handle.as_str().into()),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EntrySiteRow {
+	id: Id,
+	entry_id: Id,
+	site_id: Id,
+	state: i16,
+	/// DateTime can't be serialized
+	updated_on_us: i64,
+	component_id: Option<Id>,
+}
+
+impl Record for EntrySiteRow {
+	fn id(&self) -> Id {
+		self.id
+	}
+
+	fn value(&self, column: &str) -> Option<Value> {
+		match column {
+			"id" => Some(self.id.into()),
+			"entry_id" => Some(self.entry_id.into()),
+			"site_id" => Some(self.site_id.into()),
+			_ => None,
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl EntitiesPersistent for Kv<'_> {
+	async fn insert_entry(&self, entry: &Entry) -> Result<(), Error> {
+		let stmt = Statement::new(ENTRY_TABLE, "insert");
+
+		self.conn
+			.traced(stmt, async {
+				self.entries
+					.insert(&entry.clone().into())
+					.await
+					.map_err(Into::into)
+			})
+			.await
+	}
+
+	async fn entry_by_id(&self, id: &Id) -> Result<Option<Entry>, Error> {
+		let params: [&(dyn ToSql + Sync); 1] = [id];
+		let stmt = Statement::new(ENTRY_TABLE, "by_id").params(&params);
+
+		self.conn
+			.traced(stmt, async {
+				Ok(self.entries.get(id).await?.map(Into::into))
+			})
+			.await
+	}
+
+	async fn upsert_site(&self, site: &EntrySite) -> Result<EntrySite, Error> {
+		let stmt = Statement::new(ENTRY_SITE_TABLE, "upsert");
+
+		self.conn
+			.traced(stmt, async {
+				let site = site.clone();
+				let mut stored = self
+					.sites
+					.upsert_with(move |sites| {
+						// keep the id of the existing variant
+						let id = sites
+							.iter()
+							.find(|s| {
+								s.entry_id == site.entry_id
+									&& s.site_id == site.site_id
+							})
+							.map(|s| s.id)
+							.unwrap_or(site.id);
+
+						vec![EntrySite { id, ..site }.into()]
+					})
+					.await?;
+
+				Ok(stored.remove(0).into())
+			})
+			.await
+	}
+
+	async fn sites(&self, entry_id: &Id) -> Result<Vec<EntrySite>, Error> {
+		let params: [&(dyn ToSql + Sync); 1] = [entry_id];
+		let stmt = Statement::new(ENTRY_SITE_TABLE, "sites").params(&params);
+
+		self.conn
+			.traced(stmt, async {
+				let sites =
+					self.sites.find_by(BY_ENTRY.name(), *entry_id).await?;
+
+				Ok(sites.into_iter().map(Into::into).collect())
+			})
+			.await
+	}
+
+	async fn latest(
+		&self,
+		site_id: &Id,
+		state: i16,
+	) -> Result<Option<SiteEntry>, Error> {
+		let params: [&(dyn ToSql + Sync); 2] = [site_id, &state];
+		let stmt = Statement::new(ENTRY_SITE_TABLE, "latest").params(&params);
+
+		self.conn
+			.traced(stmt, async {
+				let site = self
+					.sites
+					.find_by(BY_SITE.name(), *site_id)
+					.await?
+					.into_iter()
+					.filter(|s| s.state == state)
+					.max_by_key(|s| (s.updated_on_us, s.id));
+
+				let Some(site) = site else {
+					return Ok(None);
+				};
+
+				Ok(self.entries.get(&site.entry_id).await?.map(|entry| {
+					SiteEntry {
+						entry: entry.into(),
+						site: site.into(),
+					}
+				}))
+			})
+			.await
+	}
+
+	async fn all_entries(&self) -> Result<Vec<Entry>, Error> {
+		let stmt = Statement::new(ENTRY_TABLE, "all");
+
+		self.conn
+			.traced(stmt, async {
+				Ok(self
+					.entries
+					.values()
+					.await?
+					.into_iter()
+					.map(Into::into)
+					.collect())
+			})
+			.await
+	}
+
+	async fn all_sites(&self) -> Result<Vec<EntrySite>, Error> {
+		let stmt = Statement::new(ENTRY_SITE_TABLE, "all");
+
+		self.conn
+			.traced(stmt, async {
+				Ok(self
+					.sites
+					.values()
+					.await?
+					.into_iter()
+					.map(Into::into)
+					.collect())
+			})
+			.await
+	}
+
+	async fn restore_entries(&self, entries: &[Entry]) -> Result<(), Error> {
+		let stmt = Statement::new(ENTRY_TABLE, "restore");
+
+		self.conn
+			.traced(stmt, async {
+				let rows = entries
+					.iter()
+					.map(|e| EntryRow::from(e.clone()))
+					.collect::<Vec<_>>();
+				self.entries.insert_many(&rows, BatchMode::Abort).await?;
+
+				Ok(())
+			})
+			.await
+	}
+
+	async fn restore_sites(&self, sites: &[EntrySite]) -> Result<(), Error> {
+		let stmt = Statement::new(ENTRY_SITE_TABLE, "restore");
+
+		self.conn
+			.traced(stmt, async {
+				let rows = sites
+					.iter()
+					.map(|s| EntrySiteRow::from(s.clone()))
+					.collect::<Vec<_>>();
+				self.sites
+					.insert_many_by(
+						&rows,
+						BatchMode::Abort,
+						BY_ENTRY.name(),
+						|s| (s.entry_id, s.site_id),
+					)
+					.await?;
+
+				Ok(())
+			})
+			.await
+	}
+}
+
+impl From<Entry> for EntryRow {
+	fn from(e: Entry) -> Self {
+		Self {
+			id: e.id,
+			type_handle: e.type_handle,
+			kind: e.kind,
+			order: e.order,
+		}
+	}
+}
+
+impl From<EntryRow> for Entry {
+	fn from(e: EntryRow) -> Self {
+		Self {
+			id: e.id,
+			type_handle: e.type_handle,
+			kind: e.kind,
+			order: e.order,
+		}
+	}
+}
+
+impl From<EntrySite> for EntrySiteRow {
+	fn from(s: EntrySite) -> Self {
+		Self {
+			id: s.id,
+			entry_id: s.entry_id,
+			site_id: s.site_id,
+			state: s.state,
+			updated_on_us: s.updated_on.to_microsecs_since_2000(),
+			component_id: s.component_id,
+		}
+	}
+}
+
+impl From<EntrySiteRow> for EntrySite {
+	fn from(s: EntrySiteRow) -> Self {
+		Self {
+			id: s.id,
+			entry_id: s.entry_id,
+			site_id: s.site_id,
+			state: s.state,
+			updated_on: DateTime::from_microsecs_since_2000(s.updated_on_us),
+			component_id: s.component_id,
+		}
+	}
+}
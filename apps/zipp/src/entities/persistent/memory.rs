@@ -0,0 +1,206 @@
+use database::{
+	batch::BatchMode,
+	id::Id,
+	memory::{Partitioned, ReadWrite, Table},
+	trace::{Statement, ToSql},
+	Connection,
+};
+
+use crate::entities::{ENTRY_SITE_TABLE, ENTRY_TABLE};
+
+use super::{
+	EntitiesPersistent, EntitiesPersistentBuilder, Entry, EntrySite, Error,
+	SiteEntry,
+};
+
+#[derive(Debug, Default)]
+struct Tables {
+	entries: Table<Id, Entry>,
+	sites: Table<Id, EntrySite>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryBuilder {
+	tables: Partitioned<Tables>,
+}
+
+impl MemoryBuilder {
+	pub fn new() -> Self {
+		Self {
+			tables: Partitioned::new(),
+		}
+	}
+}
+
+impl EntitiesPersistentBuilder for MemoryBuilder {
+	fn with_conn<'a>(
+		&'a self,
+		conn: Connection<'a>,
+	) -> Box<dyn EntitiesPersistent + 'a> {
+		Box::new(Memory {
+			inner: self.tables.get(conn.tenant()),
+			conn,
+		})
+	}
+
+	fn clone_box(&self) -> Box<dyn EntitiesPersistentBuilder> {
+		Box::new(self.clone())
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Memory<'a> {
+	inner: ReadWrite<Tables>,
+	conn: Connection<'a>,
+}
+
+#[async_trait::async_trait]
+impl EntitiesPersistent for Memory<'_> {
+	async fn insert_entry(&self, entry: &Entry) -> Result<(), Error> {
+		let stmt = Statement::new(ENTRY_TABLE, "insert");
+
+		self.conn
+			.traced(stmt, async {
+				let mut tables = self.inner.write();
+
+				// ids are unique
+				tables.entries.insert(entry.id, entry.clone()).unwrap();
+
+				Ok(())
+			})
+			.await
+	}
+
+	async fn entry_by_id(&self, id: &Id) -> Result<Option<Entry>, Error> {
+		let params: [&(dyn ToSql + Sync); 1] = [id];
+		let stmt = Statement::new(ENTRY_TABLE, "by_id").params(&params);
+
+		self.conn
+			.traced(stmt, async {
+				Ok(self.inner.read().entries.get(id).cloned())
+			})
+			.await
+	}
+
+	async fn upsert_site(&self, site: &EntrySite) -> Result<EntrySite, Error> {
+		let stmt = Statement::new(ENTRY_SITE_TABLE, "upsert");
+
+		self.conn
+			.traced(stmt, async {
+				let mut tables = self.inner.write();
+
+				// keep the id of the existing variant
+				let id = tables
+					.sites
+					.find(|s| {
+						s.entry_id == site.entry_id && s.site_id == site.site_id
+					})
+					.map(|s| s.id)
+					.unwrap_or(site.id);
+
+				let site = EntrySite { id, ..site.clone() };
+				tables.sites.upsert_many([(id, site.clone())]);
+
+				Ok(site)
+			})
+			.await
+	}
+
+	async fn sites(&self, entry_id: &Id) -> Result<Vec<EntrySite>, Error> {
+		let params: [&(dyn ToSql + Sync); 1] = [entry_id];
+		let stmt = Statement::new(ENTRY_SITE_TABLE, "sites").params(&params);
+
+		self.conn
+			.traced(stmt, async {
+				let tables = self.inner.read();
+
+				Ok(tables
+					.sites
+					.values()
+					.filter(|s| s.entry_id == *entry_id)
+					.cloned()
+					.collect())
+			})
+			.await
+	}
+
+	async fn latest(
+		&self,
+		site_id: &Id,
+		state: i16,
+	) -> Result<Option<SiteEntry>, Error> {
+		let params: [&(dyn ToSql + Sync); 2] = [site_id, &state];
+		let stmt = Statement::new(ENTRY_SITE_TABLE, "latest").params(&params);
+
+		self.conn
+			.traced(stmt, async {
+				let tables = self.inner.read();
+
+				let site = tables
+					.sites
+					.values()
+					.filter(|s| s.site_id == *site_id && s.state == state)
+					.max_by_key(|s| (s.updated_on, s.id));
+
+				Ok(site.and_then(|site| {
+					Some(SiteEntry {
+						entry: tables.entries.get(&site.entry_id)?.clone(),
+						site: site.clone(),
+					})
+				}))
+			})
+			.await
+	}
+	async fn all_entries(&self) -> Result<Vec<Entry>, Error> {
+		let stmt = Statement::new(ENTRY_TABLE, "all");
+
+		self.conn
+			.traced(stmt, async {
+				Ok(self.inner.read().entries.values().cloned().collect())
+			})
+			.await
+	}
+
+	async fn all_sites(&self) -> Result<Vec<EntrySite>, Error> {
+		let stmt = Statement::new(ENTRY_SITE_TABLE, "all");
+
+		self.conn
+			.traced(stmt, async {
+				Ok(self.inner.read().sites.values().cloned().collect())
+			})
+			.await
+	}
+
+	async fn restore_entries(&self, entries: &[Entry]) -> Result<(), Error> {
+		let stmt = Statement::new(ENTRY_TABLE, "restore");
+
+		self.conn
+			.traced(stmt, async {
+				let rows = entries.iter().map(|e| (e.id, e.clone()));
+				self.inner
+					.write()
+					.entries
+					.insert_many(rows, BatchMode::Abort)?;
+
+				Ok(())
+			})
+			.await
+	}
+
+	async fn restore_sites(&self, sites: &[EntrySite]) -> Result<(), Error> {
+		let stmt = Statement::new(ENTRY_SITE_TABLE, "restore");
+
+		self.conn
+			.traced(stmt, async {
+				let rows = sites.iter().map(|s| (s.id, s.clone()));
+				self.inner.write().sites.insert_many_by(
+					rows,
+					BatchMode::Abort,
+					|s| (s.entry_id, s.site_id),
+				)?;
+
+				Ok(())
+			})
+			.await
+	}
+}
@@ -0,0 +1,57 @@
+pub mod kv;
+pub mod memory;
+pub mod postgres;
+
+use std::fmt;
+
+use database::{id::Id, trace::RowCount, Connection};
+
+use super::{Entry, EntrySite, Error, SiteEntry};
+
+impl RowCount for EntrySite {
+	fn row_count(&self) -> Option<usize> {
+		Some(1)
+	}
+}
+
+#[async_trait::async_trait]
+pub trait EntitiesPersistentBuilder: fmt::Debug + Send + Sync {
+	fn with_conn<'a>(
+		&'a self,
+		conn: Connection<'a>,
+	) -> Box<dyn EntitiesPersistent + 'a>;
+
+	fn clone_box(&self) -> Box<dyn EntitiesPersistentBuilder>;
+}
+
+#[async_trait::async_trait]
+pub trait EntitiesPersistent: fmt::Debug + Send + Sync {
+	async fn insert_entry(&self, entry: &Entry) -> Result<(), Error>;
+
+	async fn entry_by_id(&self, id: &Id) -> Result<Option<Entry>, Error>;
+
+	/// Inserts the variant or updates the existing variant of the same entry
+	/// and site, returns the stored variant
+	async fn upsert_site(&self, site: &EntrySite) -> Result<EntrySite, Error>;
+
+	async fn sites(&self, entry_id: &Id) -> Result<Vec<EntrySite>, Error>;
+
+	/// Returns the entry with the most recently updated variant on the site
+	async fn latest(
+		&self,
+		site_id: &Id,
+		state: i16,
+	) -> Result<Option<SiteEntry>, Error>;
+
+	async fn all_entries(&self) -> Result<Vec<Entry>, Error>;
+
+	/// Returns the variants of all entries
+	async fn all_sites(&self) -> Result<Vec<EntrySite>, Error>;
+
+	/// Inserts the entries of a dump, fails if an id already exists
+	async fn restore_entries(&self, entries: &[Entry]) -> Result<(), Error>;
+
+	/// Inserts the variants of a dump, fails if an id or the same entry and
+	/// site already exists
+	async fn restore_sites(&self, sites: &[EntrySite]) -> Result<(), Error>;
+}
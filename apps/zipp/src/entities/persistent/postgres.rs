@@ -0,0 +1,343 @@
+use database::{
+	batch::{self, BatchMode},
+	id::Id,
+	migration_files,
+	query::{self, Record, Value},
+	tenant,
+	trace::{Statement, ToSql},
+	Connection, Database,
+};
+use fire_postgres::{time::DateTime, FromRow, ToRow};
+
+use crate::entities::{ENTRY_SITE_TABLE, ENTRY_TABLE};
+
+use super::{
+	EntitiesPersistent, EntitiesPersistentBuilder, Entry, EntrySite, Error,
+	SiteEntry,
+};
+
+const MIGRATIONS: &[(&str, &str)] = migration_files!["entities-00-create"];
+
+const UPSERT_SITE_SQL: &str = "\
+INSERT INTO entity_site (id, entry_id, site_id, state, updated_on, component_id)
+VALUES ($1, $2, $3, $4, $5, $6)
+ON CONFLICT (entry_id, site_id) DO UPDATE SET
+	state = EXCLUDED.state,
+	updated_on = EXCLUDED.updated_on,
+	component_id = EXCLUDED.component_id
+RETURNING id, entry_id, site_id, state, updated_on, component_id";
+
+const LATEST_SQL: &str = "\
+SELECT e.id, e.type_handle, e.kind, e.\"order\",
+	s.id AS variant_id, s.site_id, s.state, s.updated_on, s.component_id
+FROM entity_site s
+JOIN entity e ON e.id = s.entry_id
+WHERE s.site_id = $1 AND s.state = $2
+ORDER BY s.updated_on DESC, s.id DESC
+LIMIT 1";
+
+#[derive(Debug, Clone)]
+pub struct PostgresBuilder;
+
+impl PostgresBuilder {
+	pub async fn new(db: &mut Database) -> Result<Self, Error> {
+		let migrations = db.migrations().unwrap();
+
+		for (name, sql) in MIGRATIONS {
+			migrations.add(db.connection_owned(), name, sql).await?;
+		}
+
+		for (name, table) in [
+			("entities-01-tenant-entity", ENTRY_TABLE),
+			("entities-01-tenant-entity-site", ENTRY_SITE_TABLE),
+		] {
+			let scope_sql = tenant::scope_table_sql(table);
+			migrations
+				.add(db.connection_owned(), name, &scope_sql)
+				.await?;
+		}
+
+		Ok(Self)
+	}
+}
+
+impl EntitiesPersistentBuilder for PostgresBuilder {
+	fn with_conn<'a>(
+		&'a self,
+		conn: Connection<'a>,
+	) -> Box<dyn EntitiesPersistent + 'a> {
+		Box::new(Postgres { conn })
+	}
+
+	fn clone_box(&self) -> Box<dyn EntitiesPersistentBuilder> {
+		Box::new(self.clone())
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Postgres<'a> {
+	conn: Connection<'a>,
+}
+
+// match these with migrations
+#[derive(Debug, Clone, FromRow, ToRow)]
+struct EntryTable {
+	id: Id,
+	type_handle: String,
+	kind: i16,
+	order: i64,
+}
+
+#[derive(Debug, Clone, FromRow, ToRow)]
+struct EntrySiteTable {
+	id: Id,
+	entry_id: Id,
+	site_id: Id,
+	state: i16,
+	updated_on: DateTime,
+	component_id: Option<Id>,
+}
+
+impl Record for EntryTable {
+	fn id(&self) -> Id {
+		self.id
+	}
+
+	fn value(&self, column: &str) -> Option<Value> {
+		match column {
+			"id" => Some(self.id.into()),
+			"type_handle" => Some(self.type_handle.as_str().into()),
+			_ => None,
+		}
+	}
+}
+
+impl Record for EntrySiteTable {
+	fn id(&self) -> Id {
+		self.id
+	}
+
+	fn value(&self, column: &str) -> Option<Value> {
+		match column {
+			"id" => Some(self.id.into()),
+			"entry_id" => Some(self.entry_id.into()),
+			"site_id" => Some(self.site_id.into()),
+			_ => None,
+		}
+	}
+}
+
+/// The row returned by [`LATEST_SQL`]
+#[derive(Debug, Clone, FromRow)]
+struct LatestRow {
+	id: Id,
+	type_handle: String,
+	kind: i16,
+	order: i64,
+	variant_id: Id,
+	site_id: Id,
+	state: i16,
+	updated_on: DateTime,
+	component_id: Option<Id>,
+}
+
+#[async_trait::async_trait]
+impl EntitiesPersistent for Postgres<'_> {
+	async fn insert_entry(&self, entry: &Entry) -> Result<(), Error> {
+		let row = EntryTable::from(entry.clone());
+
+		query::postgres::insert(&self.conn, ENTRY_TABLE, &row).await?;
+
+		Ok(())
+	}
+
+	async fn entry_by_id(&self, id: &Id) -> Result<Option<Entry>, Error> {
+		let rows = query::postgres::select_where::<EntryTable>(
+			&self.conn,
+			ENTRY_TABLE,
+			&[("id", id)],
+		)
+		.await?;
+
+		Ok(rows.into_iter().next().map(Into::into))
+	}
+
+	async fn upsert_site(&self, site: &EntrySite) -> Result<EntrySite, Error> {
+		let pg = self.conn.into_postgres();
+		let params: [&(dyn ToSql + Sync); 6] = [
+			&site.id,
+			&site.entry_id,
+			&site.site_id,
+			&site.state,
+			&site.updated_on,
+			&site.component_id,
+		];
+		let stmt =
+			Statement::new(ENTRY_SITE_TABLE, UPSERT_SITE_SQL).params(&params);
+
+		self.conn
+			.traced(stmt, async {
+				let stmt = pg.prepare_cached(UPSERT_SITE_SQL).await?;
+				pg.query_one::<EntrySiteTable, _>(&stmt, &params)
+					.await
+					.map(Into::into)
+			})
+			.await
+			.map_err(Into::into)
+	}
+
+	async fn sites(&self, entry_id: &Id) -> Result<Vec<EntrySite>, Error> {
+		let rows = query::postgres::select_where::<EntrySiteTable>(
+			&self.conn,
+			ENTRY_SITE_TABLE,
+			&[("entry_id", entry_id)],
+		)
+		.await?;
+
+		Ok(rows.into_iter().map(Into::into).collect())
+	}
+
+	async fn latest(
+		&self,
+		site_id: &Id,
+		state: i16,
+	) -> Result<Option<SiteEntry>, Error> {
+		let pg = self.conn.into_postgres();
+		let params: [&(dyn ToSql + Sync); 2] = [site_id, &state];
+		let stmt = Statement::new(ENTRY_SITE_TABLE, LATEST_SQL).params(&params);
+
+		self.conn
+			.traced(stmt, async {
+				let stmt = pg.prepare_cached(LATEST_SQL).await?;
+				pg.query_opt::<LatestRow, _>(&stmt, &params).await
+			})
+			.await
+			.map(|opt| opt.map(Into::into))
+			.map_err(Into::into)
+	}
+
+	async fn all_entries(&self) -> Result<Vec<Entry>, Error> {
+		let rows = query::postgres::select_where::<EntryTable>(
+			&self.conn,
+			ENTRY_TABLE,
+			&[],
+		)
+		.await?;
+
+		Ok(rows.into_iter().map(Into::into).collect())
+	}
+
+	async fn all_sites(&self) -> Result<Vec<EntrySite>, Error> {
+		let rows = query::postgres::select_where::<EntrySiteTable>(
+			&self.conn,
+			ENTRY_SITE_TABLE,
+			&[],
+		)
+		.await?;
+
+		Ok(rows.into_iter().map(Into::into).collect())
+	}
+
+	async fn restore_entries(&self, entries: &[Entry]) -> Result<(), Error> {
+		let rows = entries
+			.iter()
+			.map(|e| EntryTable::from(e.clone()))
+			.collect::<Vec<_>>();
+
+		batch::postgres::insert_many(
+			&self.conn,
+			ENTRY_TABLE,
+			&rows,
+			BatchMode::Abort,
+		)
+		.await?;
+
+		Ok(())
+	}
+
+	async fn restore_sites(&self, sites: &[EntrySite]) -> Result<(), Error> {
+		let rows = sites
+			.iter()
+			.map(|s| EntrySiteTable::from(s.clone()))
+			.collect::<Vec<_>>();
+
+		batch::postgres::insert_many(
+			&self.conn,
+			ENTRY_SITE_TABLE,
+			&rows,
+			BatchMode::Abort,
+		)
+		.await?;
+
+		Ok(())
+	}
+}
+
+impl From<Entry> for EntryTable {
+	fn from(e: Entry) -> Self {
+		Self {
+			id: e.id,
+			type_handle: e.type_handle,
+			kind: e.kind,
+			order: e.order,
+		}
+	}
+}
+
+impl From<EntryTable> for Entry {
+	fn from(e: EntryTable) -> Self {
+		Self {
+			id: e.id,
+			type_handle: e.type_handle,
+			kind: e.kind,
+			order: e.order,
+		}
+	}
+}
+
+impl From<EntrySite> for EntrySiteTable {
+	fn from(s: EntrySite) -> Self {
+		Self {
+			id: s.id,
+			entry_id: s.entry_id,
+			site_id: s.site_id,
+			state: s.state,
+			updated_on: s.updated_on,
+			component_id: s.component_id,
+		}
+	}
+}
+
+impl From<EntrySiteTable> for EntrySite {
+	fn from(s: EntrySiteTable) -> Self {
+		Self {
+			id: s.id,
+			entry_id: s.entry_id,
+			site_id: s.site_id,
+			state: s.state,
+			updated_on: s.updated_on,
+			component_id: s.component_id,
+		}
+	}
+}
+
+impl From<LatestRow> for SiteEntry {
+	fn from(r: LatestRow) -> Self {
+		Self {
+			site: EntrySite {
+				id: r.variant_id,
+				entry_id: r.id,
+				site_id: r.site_id,
+				state: r.state,
+				updated_on: r.updated_on,
+				component_id: r.component_id,
+			},
+			entry: Entry {
+				id: r.id,
+				type_handle: r.type_handle,
+				kind: r.kind,
+				order: r.order,
+			},
+		}
+	}
+}
@@ -1,8 +1,11 @@
-use std::any::Any;
-
+use database::{id::Id, types::component};
 use serde_json::Value;
 
 use super::{Field, FieldKind, ParseFieldError, Settings, ValidateError};
+use graphql::{
+	names::{self, NameError},
+	scalar::Scalar,
+};
 
 #[derive(Debug, Clone)]
 pub struct NumberFieldKind;
@@ -23,10 +26,25 @@ impl FieldKind for NumberFieldKind {
 		let field: NumberField =
 			serde_json::from_value(settings).expect("todo");
 
+		let mut invalid = vec![];
+		if field.max > NUMBER_MAX {
+			invalid.push(format!("max needs to be at most {NUMBER_MAX}"));
+		}
+		if field.min >= NUMBER_MAX {
+			invalid.push(format!("min needs to be below {NUMBER_MAX}"));
+		}
+		if !invalid.is_empty() {
+			return Err(ParseFieldError::InvalidSettings { settings: invalid });
+		}
+
 		Ok(field)
 	}
 }
 
+/// The exclusive maximum of numbers, values need to fit into a graphql
+/// `Int`, which is an i32
+const NUMBER_MAX: u32 = i32::MAX as u32 + 1;
+
 #[derive(Debug, Clone, serde::Deserialize)]
 #[serde(default = "NumberField::default")]
 pub struct NumberField {
@@ -55,7 +73,7 @@ impl NumberField {
 impl Default for NumberField {
 	fn default() -> Self {
 		Self {
-			max: u32::MAX,
+			max: NUMBER_MAX,
 			min: u32::MIN,
 		}
 	}
@@ -72,18 +90,22 @@ impl Field for NumberField {
 		if self.max != NumberField::default().max {
 			settings.insert(
 				"max".to_string(),
-				serde_json::to_value(&self.max).expect("todo"),
+				serde_json::to_value(self.max).expect("todo"),
 			);
 		}
 		if self.min != NumberField::default().min {
 			settings.insert(
 				"min".to_string(),
-				serde_json::to_value(&self.min).expect("todo"),
+				serde_json::to_value(self.min).expect("todo"),
 			);
 		}
 		settings
 	}
 
+	fn database_kind(&self) -> component::FieldKind {
+		component::FieldKind::Int
+	}
+
 	fn clone_box(&self) -> Box<dyn Field> {
 		Box::new(self.clone())
 	}
@@ -91,6 +113,14 @@ impl Field for NumberField {
 	fn validate(&self, value: &Value) -> Result<(), ValidateError> {
 		self.max_validate(value).and(self.min_validate(value))
 	}
+
+	/// A minimum above zero requires a value
+	fn graphql_type(&self) -> Result<graphql::Type, NameError> {
+		Ok(match self.min {
+			0 => graphql::Type::Int,
+			_ => graphql::Type::Int.non_null(),
+		})
+	}
 }
 
 #[derive(Debug, Clone)]
@@ -149,7 +179,393 @@ impl Field for TextField {
 			.ok_or(ValidateError::ValidationFailed)
 	}
 
+	fn graphql_type(&self) -> Result<graphql::Type, NameError> {
+		Ok(graphql::Type::String)
+	}
+
+	fn database_kind(&self) -> component::FieldKind {
+		component::FieldKind::Text
+	}
+
+	fn clone_box(&self) -> Box<dyn Field> {
+		Box::new(self.clone())
+	}
+}
+
+/// Validates a value with the input conversion of a scalar
+fn validate_scalar(scalar: Scalar, value: &Value) -> Result<(), ValidateError> {
+	(scalar.parse)(&value.clone().into())
+		.map(|_| ())
+		.map_err(|_| ValidateError::ValidationFailed)
+}
+
+#[derive(Debug, Clone)]
+pub struct DateTimeFieldKind;
+
+impl FieldKind for DateTimeFieldKind {
+	type Field = DateTimeField;
+
+	fn name() -> String {
+		"date-time".to_string()
+	}
+
+	fn parse(
+		&self,
+		_settings: Settings,
+	) -> Result<DateTimeField, ParseFieldError> {
+		Ok(DateTimeField)
+	}
+}
+
+/// A timestamp, stored as an RFC 3339 string in UTC
+#[derive(Debug, Clone)]
+pub struct DateTimeField;
+
+impl Field for DateTimeField {
+	fn kind(&self) -> String {
+		DateTimeFieldKind::name()
+	}
+
+	fn settings(&self) -> Settings {
+		Settings::new()
+	}
+
+	fn validate(&self, value: &Value) -> Result<(), ValidateError> {
+		validate_scalar(Scalar::date_time(), value)
+	}
+
+	fn graphql_type(&self) -> Result<graphql::Type, NameError> {
+		Ok(graphql::Type::Scalar(Scalar::date_time()))
+	}
+
+	fn database_kind(&self) -> component::FieldKind {
+		component::FieldKind::DateTime
+	}
+
+	fn clone_box(&self) -> Box<dyn Field> {
+		Box::new(self.clone())
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct UrlFieldKind;
+
+impl FieldKind for UrlFieldKind {
+	type Field = UrlField;
+
+	fn name() -> String {
+		"url".to_string()
+	}
+
+	fn parse(&self, _settings: Settings) -> Result<UrlField, ParseFieldError> {
+		Ok(UrlField)
+	}
+}
+
+/// An absolute url
+#[derive(Debug, Clone)]
+pub struct UrlField;
+
+impl Field for UrlField {
+	fn kind(&self) -> String {
+		UrlFieldKind::name()
+	}
+
+	fn settings(&self) -> Settings {
+		Settings::new()
+	}
+
+	fn validate(&self, value: &Value) -> Result<(), ValidateError> {
+		validate_scalar(Scalar::url(), value)
+	}
+
+	fn graphql_type(&self) -> Result<graphql::Type, NameError> {
+		Ok(graphql::Type::Scalar(Scalar::url()))
+	}
+
+	fn database_kind(&self) -> component::FieldKind {
+		component::FieldKind::Text
+	}
+
+	fn clone_box(&self) -> Box<dyn Field> {
+		Box::new(self.clone())
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct JsonFieldKind;
+
+impl FieldKind for JsonFieldKind {
+	type Field = JsonField;
+
+	fn name() -> String {
+		"json".to_string()
+	}
+
+	fn parse(&self, _settings: Settings) -> Result<JsonField, ParseFieldError> {
+		Ok(JsonField)
+	}
+}
+
+/// Any json value, it cannot be filtered by
+#[derive(Debug, Clone)]
+pub struct JsonField;
+
+impl Field for JsonField {
+	fn kind(&self) -> String {
+		JsonFieldKind::name()
+	}
+
+	fn settings(&self) -> Settings {
+		Settings::new()
+	}
+
+	fn validate(&self, _value: &Value) -> Result<(), ValidateError> {
+		Ok(())
+	}
+
+	fn graphql_type(&self) -> Result<graphql::Type, NameError> {
+		Ok(graphql::Type::Scalar(Scalar::json()))
+	}
+
+	fn database_kind(&self) -> component::FieldKind {
+		component::FieldKind::Json
+	}
+
+	fn clone_box(&self) -> Box<dyn Field> {
+		Box::new(self.clone())
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ComponentFieldKind;
+
+impl FieldKind for ComponentFieldKind {
+	type Field = ComponentField;
+
+	fn name() -> String {
+		"component".to_string()
+	}
+
+	fn parse(
+		&self,
+		settings: Settings,
+	) -> Result<ComponentField, ParseFieldError> {
+		let settings = Value::Object(settings.into_iter().collect());
+
+		serde_json::from_value(settings).map_err(|e| {
+			ParseFieldError::InvalidSettings {
+				settings: vec![e.to_string()],
+			}
+		})
+	}
+}
+
+/// References instances of other components
+///
+/// The value is a reference `{ "component": handle, "id": id }` if at most
+/// one instance is allowed, else a list of references.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ComponentField {
+	/// The handles of the components which can be referenced
+	pub(crate) component: Vec<String>,
+	#[serde(default)]
+	pub(crate) min: u32,
+	#[serde(default)]
+	pub(crate) max: Option<u32>,
+}
+
+impl ComponentField {
+	pub(crate) fn is_single(&self) -> bool {
+		self.max == Some(1)
+	}
+
+	/// Returns the handle and id of every referenced instance, invalid
+	/// references are ignored
+	pub fn references<'a>(
+		&self,
+		value: &'a Value,
+	) -> impl Iterator<Item = (&'a str, &'a str)> {
+		let refs = match value {
+			Value::Array(refs) => refs.as_slice(),
+			value => std::slice::from_ref(value),
+		};
+
+		refs.iter().filter_map(|r| {
+			Some((r.get("component")?.as_str()?, r.get("id")?.as_str()?))
+		})
+	}
+
+	fn validate_reference(&self, value: &Value) -> Result<(), ValidateError> {
+		let component = value.get("component").and_then(Value::as_str);
+		let id = value.get("id").and_then(Value::as_str);
+
+		match (component, id) {
+			(Some(component), Some(id))
+				if self.component.iter().any(|c| c == component)
+					&& id.parse::<Id>().is_ok() =>
+			{
+				Ok(())
+			}
+			_ => Err(ValidateError::ValidationFailed),
+		}
+	}
+}
+
+impl Field for ComponentField {
+	fn kind(&self) -> String {
+		ComponentFieldKind::name()
+	}
+
+	fn settings(&self) -> Settings {
+		let mut settings = Settings::new();
+		settings.insert("component".to_string(), self.component.clone().into());
+		if self.min != 0 {
+			settings.insert("min".to_string(), self.min.into());
+		}
+		if let Some(max) = self.max {
+			settings.insert("max".to_string(), max.into());
+		}
+		settings
+	}
+
+	fn validate(&self, value: &Value) -> Result<(), ValidateError> {
+		if self.is_single() {
+			return self.validate_reference(value);
+		}
+
+		let refs = value.as_array().ok_or(ValidateError::ValidationFailed)?;
+		let len = refs.len() as u64;
+		if len < self.min as u64 || self.max.is_some_and(|m| len > m as u64) {
+			return Err(ValidateError::ValidationFailed);
+		}
+
+		refs.iter().try_for_each(|r| self.validate_reference(r))
+	}
+
+	/// Multiple components become a union named after its members,
+	/// `InternalLinkOrExternalLink`
+	///
+	/// The items of a list are nullable, a reference to a deleted instance
+	/// is null.
+	fn graphql_type(&self) -> Result<graphql::Type, NameError> {
+		let names = self
+			.component
+			.iter()
+			.map(|c| names::type_name(c))
+			.collect::<Result<Vec<_>, _>>()?;
+		let item = match names.as_slice() {
+			[name] => graphql::Type::Named(name.clone()),
+			_ => graphql::Type::Union {
+				type_name: names.join("Or"),
+				types: names.into_iter().map(graphql::Type::Named).collect(),
+			},
+		};
+
+		Ok(match (self.is_single(), self.min) {
+			(true, 0) => item,
+			(true, _) => item.non_null(),
+			(false, 0) => item.list(),
+			(false, _) => item.list().non_null(),
+		})
+	}
+
+	/// A single reference is the id of the instance, else the references
+	/// are stored as json
+	fn database_kind(&self) -> component::FieldKind {
+		if self.is_single() {
+			component::FieldKind::ComponentId
+		} else {
+			component::FieldKind::Json
+		}
+	}
+
 	fn clone_box(&self) -> Box<dyn Field> {
 		Box::new(self.clone())
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use database::id::Kind;
+	use serde_json::json;
+
+	use super::*;
+
+	#[test]
+	fn graphql_types() {
+		let field = NumberField::default();
+		assert!(!field.graphql_type().unwrap().is_non_null());
+
+		let field = NumberField { min: 1, max: 10 };
+		assert!(field.graphql_type().unwrap().is_non_null());
+
+		assert!(!TextField::default().graphql_type().unwrap().is_non_null());
+	}
+
+	#[test]
+	fn scalar_fields() {
+		let date_time = DateTimeField;
+		assert!(date_time.validate(&json!("2024-04-01T12:00:00Z")).is_ok());
+		assert!(date_time.validate(&json!("tomorrow")).is_err());
+
+		assert!(UrlField.validate(&json!("https://zipp.rs")).is_ok());
+		assert!(UrlField.validate(&json!("zipp.rs")).is_err());
+
+		assert!(JsonField.validate(&json!({ "a": [1] })).is_ok());
+		assert_eq!(JsonField.database_kind(), component::FieldKind::Json);
+	}
+
+	#[test]
+	fn number_fits_int() {
+		let parse = |settings: Value| {
+			NumberFieldKind.parse(serde_json::from_value(settings).unwrap())
+		};
+
+		let field = parse(json!({ "max": NUMBER_MAX })).unwrap();
+		assert!(field.validate(&json!(i32::MAX)).is_ok());
+		assert!(field.validate(&json!(i32::MAX as u32 + 1)).is_err());
+
+		// bounds outside of an Int are rejected instead of lowered
+		let err = parse(json!({ "max": u32::MAX })).unwrap_err();
+		assert!(matches!(
+			err,
+			ParseFieldError::InvalidSettings { settings } if settings.len() == 1
+		));
+		let err = parse(json!({ "min": NUMBER_MAX, "max": u32::MAX }));
+		assert!(matches!(
+			err.unwrap_err(),
+			ParseFieldError::InvalidSettings { settings } if settings.len() == 2
+		));
+	}
+
+	#[test]
+	fn component_field() {
+		let field = |settings: Value| {
+			let settings = serde_json::from_value(settings).unwrap();
+			ComponentFieldKind.parse(settings).unwrap()
+		};
+		let link = field(json!({
+			"component": ["internal-link", "external-link"],
+			"min": 1,
+			"max": 1
+		}));
+		let ty = link.graphql_type().unwrap();
+		assert!(ty.is_non_null());
+		assert!(matches!(
+			ty.nullable(),
+			graphql::Type::Union { type_name, .. }
+				if type_name == "InternalLinkOrExternalLink"
+		));
+
+		let id = Id::new(Kind::new(false, 4)).to_string();
+		let reference = |c: &str| json!({ "component": c, "id": id });
+		assert!(link.validate(&reference("internal-link")).is_ok());
+		assert!(link.validate(&reference("button")).is_err());
+
+		let buttons = field(json!({ "component": ["button"] }));
+		assert!(!buttons.graphql_type().unwrap().is_non_null());
+		assert!(buttons.validate(&json!([reference("button")])).is_ok());
+		assert!(buttons.validate(&reference("button")).is_err());
+	}
+}
@@ -7,11 +7,13 @@
 
 pub mod defaults;
 
-use std::any::Any;
 use std::collections::BTreeMap;
 use std::fmt::{self, Debug};
 use std::sync::{Arc, RwLock};
 
+use database::types::component;
+use graphql::names::NameError;
+
 use crate::utils::AsAny;
 
 pub type Settings = BTreeMap<String, serde_json::Value>;
@@ -33,6 +35,16 @@ pub trait Field: AsAny + Debug + Send + Sync {
 	/// validates field data
 	fn validate(&self, value: &serde_json::Value) -> Result<(), ValidateError>;
 
+	/// returns the graphql type of the field data
+	///
+	/// The type should be non-null if the settings require a value. Fails if
+	/// a referenced type has no valid graphql name.
+	fn graphql_type(&self) -> Result<graphql::Type, NameError>;
+
+	/// returns how the field data is stored, used to validate the component
+	/// schemas
+	fn database_kind(&self) -> component::FieldKind;
+
 	/// makes a clone of the field
 	fn clone_box(&self) -> Box<dyn Field>;
 }
@@ -158,10 +170,14 @@ impl Inner {
 
 impl Default for Fields {
 	fn default() -> Self {
-		let mut this = Self::new();
+		let this = Self::new();
 
 		this.insert(defaults::NumberFieldKind);
 		this.insert(defaults::TextFieldKind);
+		this.insert(defaults::ComponentFieldKind);
+		this.insert(defaults::DateTimeFieldKind);
+		this.insert(defaults::UrlFieldKind);
+		this.insert(defaults::JsonFieldKind);
 
 		// boolean, number, text, relation, media, component, richtext
 
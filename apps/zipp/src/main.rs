@@ -11,20 +11,26 @@
 //! Events should only be triggered by controllers
 
 mod components;
+mod entities;
 mod fields;
 mod users;
 mod utils;
 
-use std::fs;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
 
 use clap::Parser;
-use database::{Config as DbConfig, DatabasePool};
+use database::{
+	audit,
+	dump::{Archive, Dump},
+	Config as DbConfig, DatabasePool,
+};
 use fire_http::get;
 use serde::Deserialize;
-use tracing::info;
-use users::Users;
+use tracing::{error, info};
+use users::{api::Actors, Users};
 
-use crate::{components::Components, fields::Fields};
+use crate::{components::Components, entities::Entities, fields::Fields};
 
 #[derive(Debug, Parser)]
 struct Opts {
@@ -34,6 +40,10 @@ struct Opts {
 	#[clap(long)]
 	use_memory_db: bool,
 
+	/// Stores all data in an embedded key-value database at this path
+	#[clap(long)]
+	kv_db: Option<String>,
+
 	#[clap(long)]
 	config: Option<String>,
 
@@ -44,11 +54,22 @@ struct Opts {
 const DEFAULT_CONFIG_PATH: &str = "./zipp.toml";
 
 #[derive(Debug, Parser)]
-enum SubCommand {}
+enum SubCommand {
+	/// Writes all data into an ndjson archive
+	Dump { file: String },
+	/// Restores all data from an ndjson archive
+	Restore { file: String },
+}
 
 #[derive(Debug, Default, Deserialize)]
 pub struct Config {
 	pub db: Option<DbConfig>,
+	/// The json file containing the component schemas
+	pub components: Option<String>,
+	/// Accepts the actor header, only enable it if a proxy in front of the
+	/// server sets the header and removes it from client requests
+	#[serde(default)]
+	pub trusted_proxy: bool,
 }
 
 #[get("/")]
@@ -57,6 +78,23 @@ async fn hello_world() -> String {
 	"Hello, world!".into()
 }
 
+/// Returns the graphql schema derived from the component schemas
+#[get("/graphql/schema")]
+async fn graphql_schema(
+	components: &Components,
+) -> Result<String, fire_http::Error> {
+	match components.graphql_schema().await {
+		Ok(schema) => Ok(schema.to_string()),
+		Err(e) => Err(fire_http::Error::from_server_error(e)),
+	}
+}
+
+/// Exposes the database metrics in the prometheus text format
+#[get("/metrics")]
+async fn metrics(db: &DatabasePool) -> String {
+	db.metrics().snapshot().to_prometheus()
+}
+
 #[tokio::main]
 async fn main() {
 	// read args
@@ -83,6 +121,12 @@ async fn main() {
 
 	// create a database connection
 	let db_pool = match (cfg!(debug_assertions), opts.use_memory_db, cfg.db) {
+		(_, false, _) if opts.kv_db.is_some() => {
+			let path = opts.kv_db.unwrap();
+			info!("Using key-value database {path}");
+
+			DatabasePool::new_kv(path).expect("database failed")
+		}
 		(_, true, _) | (true, _, None) => {
 			info!("Using memory database");
 
@@ -95,10 +139,56 @@ async fn main() {
 	};
 	let mut db = db_pool.get().await.unwrap();
 
+	// remove audit entries older than the retention
+	if let Some(log) = db_pool.audit() {
+		audit::migrate(&mut db).await.unwrap();
+		let purged = log.purge(&db.connection()).await.unwrap();
+		info!("Purged {purged} audit entries");
+	}
+
 	// create instances
 	let users = Users::new(&mut db).await.unwrap();
+	let entities = Entities::new(&mut db).await.unwrap();
 	let fields = Fields::default();
-	let components = Components::new(&mut db).await.unwrap();
+	let components =
+		Components::new(&mut db, fields.clone(), cfg.components.as_deref())
+			.await
+			.unwrap();
+
+	if let Some(subcmd) = opts.subcmd {
+		let mut dump = Dump::new();
+		// tables are restored in this order, the variants reference entries
+		let instances = components.instances_table();
+		let sites = entities.sites_table();
+		dump.register(components);
+		dump.register(instances);
+		dump.register(entities);
+		dump.register(sites);
+		dump.register(users);
+
+		match subcmd {
+			SubCommand::Dump { file } => {
+				let archive = dump.export(&db).await.unwrap();
+				let file = File::create(&file).expect("could not create file");
+				archive.write_ndjson(BufWriter::new(file)).unwrap();
+			}
+			SubCommand::Restore { file } => {
+				let file = File::open(&file).expect("could not open file");
+				let archive =
+					Archive::read_ndjson(BufReader::new(file)).unwrap();
+				dump.restore(&mut db, archive).await.unwrap();
+			}
+		}
+
+		return;
+	}
+
+	// the server cannot run with an invalid schema, a restore above can still
+	// replace the component schemas
+	if let Err(e) = components.graphql_schema().await {
+		error!("{e}");
+		std::process::exit(1);
+	}
 
 	// since we don't need the database anymore, we can drop it
 	// this makes sure we don't keep a connection running
@@ -110,12 +200,17 @@ async fn main() {
 	// add global data
 	fire.add_data(db_pool);
 	fire.add_data(users);
+	fire.add_data(Actors::new(cfg.trusted_proxy));
+	fire.add_data(entities);
 	fire.add_data(fields);
 	fire.add_data(components);
 
 	// register routes
 	users::api::register(&mut fire);
+	components::api::register(&mut fire);
 	fire.add_route(hello_world);
+	fire.add_route(metrics);
+	fire.add_route(graphql_schema);
 
 	// todo run plugins before building
 
@@ -1,18 +1,27 @@
 use database::DatabasePool;
-use fire_http::FireBuilder;
+use fire_http::{header::RequestHeader, FireBuilder};
 use fire_http_api::api;
 use tracing::info;
 
-use super::{Error, Login, LoginReq};
+use super::{Actors, Error, Login, LoginReq, ACTOR_HEADER, TENANT_HEADER};
 use crate::users::Users;
 
 #[api(LoginReq)]
 pub async fn login(
 	req: LoginReq,
+	header: &RequestHeader,
+	actors: &Actors,
 	users: &Users,
 	db: &DatabasePool,
 ) -> Result<Login, Error> {
-	let db = db.get().await.map_err(Error::string_internal)?;
+	let mut db = db
+		.get_with_deadline(db.request_deadline())
+		.await
+		.map_err(Error::string_internal)?;
+	db.set_actor(actors.actor(header.value(ACTOR_HEADER))?);
+	db.set_tenant(actors.tenant(header.value(TENANT_HEADER))?)
+		.await
+		.map_err(Error::string_internal)?;
 	let users = users.with_conn(db.connection());
 
 	let user = users
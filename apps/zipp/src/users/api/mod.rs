@@ -4,13 +4,72 @@ pub mod handlers;
 pub use error::Error;
 pub use handlers::register;
 
+use database::{id::Id, tenant::Tenant};
 use email_address::EmailAddress;
 
+use fire_http::Resource;
 use fire_http_api::{Method, Request};
 use serde::{Deserialize, Serialize};
 
 use super::User;
 
+/// The header containing the id of the user making the request, recorded
+/// as the actor in the audit log
+pub const ACTOR_HEADER: &str = "x-actor";
+
+/// The header containing the id of the tenant the request is scoped to
+pub const TENANT_HEADER: &str = "x-tenant";
+
+/// Takes the actor of a request from the [`ACTOR_HEADER`] and the tenant
+/// from the [`TENANT_HEADER`]
+///
+/// Until sessions exist the headers are the only source of the actor and the
+/// tenant. They are only accepted if the `trusted_proxy` config is enabled,
+/// the proxy then needs to set the headers and remove them from client
+/// requests. Otherwise a request with a header is rejected, any client could
+/// write another identity into the audit log or read another tenant.
+#[derive(Debug, Clone, Copy, Default, Resource)]
+pub struct Actors {
+	trusted_proxy: bool,
+}
+
+impl Actors {
+	pub fn new(trusted_proxy: bool) -> Self {
+		Self { trusted_proxy }
+	}
+
+	/// Returns the actor from the value of the [`ACTOR_HEADER`]
+	pub fn actor(&self, header: Option<&str>) -> Result<Option<Id>, Error> {
+		self.forwarded(ACTOR_HEADER, header)
+	}
+
+	/// Returns the tenant from the value of the [`TENANT_HEADER`]
+	pub fn tenant(
+		&self,
+		header: Option<&str>,
+	) -> Result<Option<Tenant>, Error> {
+		self.forwarded(TENANT_HEADER, header)
+			.map(|id| id.map(Tenant::new))
+	}
+
+	fn forwarded(
+		&self,
+		name: &str,
+		header: Option<&str>,
+	) -> Result<Option<Id>, Error> {
+		match header {
+			None => Ok(None),
+			Some(_) if !self.trusted_proxy => Err(Error::Request(format!(
+				"the {name} header is only accepted from a trusted proxy"
+			))),
+			Some(v) => v
+				.parse()
+				.map(Some)
+				.map_err(|_| Error::Request(format!("invalid {name} header"))),
+		}
+	}
+}
+
 // todo
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Session {
@@ -36,3 +95,40 @@ impl Request for LoginReq {
 	const PATH: &'static str = "/users/login";
 	const METHOD: Method = Method::POST;
 }
+
+#[cfg(test)]
+mod tests {
+	use database::id::Kind;
+
+	use super::*;
+
+	#[test]
+	fn actor_header() {
+		let id = Id::new(Kind::new(false, 1));
+		let header = id.to_string();
+
+		let actors = Actors::default();
+		assert_eq!(actors.actor(None).unwrap(), None);
+		assert!(actors.actor(Some(&header)).is_err());
+
+		let actors = Actors::new(true);
+		assert_eq!(actors.actor(Some(&header)).unwrap(), Some(id));
+		assert!(actors.actor(Some("1")).is_err());
+	}
+
+	#[test]
+	fn tenant_header() {
+		let id = Id::new(Kind::new(false, 2));
+		let header = id.to_string();
+
+		assert!(Actors::default().tenant(Some(&header)).is_err());
+
+		let actors = Actors::new(true);
+		assert_eq!(actors.tenant(None).unwrap(), None);
+		assert_eq!(
+			actors.tenant(Some(&header)).unwrap(),
+			Some(Tenant::new(id))
+		);
+		assert!(actors.tenant(Some("1")).is_err());
+	}
+}
@@ -0,0 +1,3 @@
+-- emails only need to be unique per tenant
+ALTER TABLE users DROP CONSTRAINT users_email_key;
+CREATE UNIQUE INDEX users_tenant_email ON users (COALESCE(tenant, ''), email);
@@ -2,22 +2,31 @@ pub mod api;
 mod persistent;
 
 use database::{
+	audit::{AuditError, Operation},
+	batch::{BatchError, BatchMode, RowFailure},
+	deadline::DeadlineExceeded,
+	dump::{DumpError, Dumpable},
 	id::{Id, Kind},
+	kv,
+	query::{Cursor, Page, Query, QueryError},
 	Connection, Database, DatabaseKind,
 };
 use email_address::EmailAddress;
 use fire_http::Resource;
 use serde::{Deserialize, Serialize};
 
-use crate::users::persistent::memory::Memory;
+use crate::users::persistent::memory::MemoryBuilder;
 
 use self::persistent::{
-	postgres::PostgresBuilder, InsertRawUser, RawUser, UsersPersistent,
-	UsersPersistentBuilder,
+	kv::KvBuilder, postgres::PostgresBuilder, InsertRawUser, RawUser,
+	UsersPersistent, UsersPersistentBuilder,
 };
 
 pub const KIND: Kind = Kind::new(false, 1);
 
+/// The table name, used to cache queries
+const TABLE: &str = "users";
+
 // contains all migration files
 // const MIGRATIONS: &[Migration] = &[];
 
@@ -42,6 +51,13 @@ pub struct CreateUser {
 	pub email: EmailAddress,
 }
 
+#[derive(Debug, Clone)]
+pub struct CreatedUsers {
+	pub users: Vec<User>,
+	/// The index of the failure points to the list of [`CreateUser`]
+	pub failures: Vec<RowFailure>,
+}
+
 // #[derive(Debug, Clone)]
 // pub struct Rights {
 // 	inner: BTreeMap<String, bool>,
@@ -57,6 +73,21 @@ pub enum Error {
 
 	#[error("a postgres error occured!")]
 	Postgres(#[from] database::Error),
+
+	#[error("the query failed {0}")]
+	Query(#[from] QueryError),
+
+	#[error("the batch failed {0}")]
+	Batch(#[from] BatchError),
+
+	#[error("{0}")]
+	Deadline(#[from] DeadlineExceeded),
+
+	#[error("a key-value store error occured!")]
+	KeyValue(#[from] kv::Error),
+
+	#[error("the access could not be audited {0}")]
+	Audit(#[from] AuditError),
 }
 
 #[derive(Debug, Resource)]
@@ -67,10 +98,11 @@ pub struct Users {
 impl Users {
 	pub async fn new(conn: &mut Database) -> Result<Self, Error> {
 		let persistent: Box<dyn UsersPersistentBuilder> = match conn.kind() {
-			DatabaseKind::Memory => Box::new(Memory::new()),
+			DatabaseKind::Memory => Box::new(MemoryBuilder::new()),
 			DatabaseKind::Postgres => {
 				Box::new(PostgresBuilder::new(conn).await?)
 			}
+			DatabaseKind::KeyValue => Box::new(KvBuilder::new()),
 		};
 
 		Ok(Self { inner: persistent })
@@ -78,14 +110,19 @@ impl Users {
 
 	pub fn with_conn<'a>(&'a self, conn: Connection<'a>) -> UsersWithConn<'a> {
 		UsersWithConn {
+			builder: &*self.inner,
 			inner: self.inner.with_conn(conn),
+			conn,
 		}
 	}
 }
 
 #[derive(Debug)]
 pub struct UsersWithConn<'a> {
+	/// Creates persistents for transactions
+	builder: &'a dyn UsersPersistentBuilder,
 	inner: Box<dyn UsersPersistent + 'a>,
+	conn: Connection<'a>,
 }
 
 impl UsersWithConn<'_> {
@@ -94,28 +131,151 @@ impl UsersWithConn<'_> {
 			email: user.email.as_ref(),
 		};
 
-		let user = self.inner.insert(insert_user).await?;
+		let user = self
+			.conn
+			.atomic(|conn| async move {
+				let user =
+					self.builder.with_conn(conn).insert(insert_user).await?;
+				conn.audit(Operation::Insert, TABLE, &[user.id]).await?;
+
+				Ok::<_, Error>(user)
+			})
+			.await;
+		self.conn.invalidate(TABLE);
+
+		Ok(user?.into())
+	}
 
-		Ok(user.into())
+	/// Creates many users at once
+	///
+	/// With [`BatchMode::Continue`] users with an existing email are
+	/// reported as failures while the others are still created.
+	pub async fn create_users(
+		&self,
+		users: Vec<CreateUser>,
+		mode: BatchMode,
+	) -> Result<CreatedUsers, Error> {
+		let insert_users = users
+			.iter()
+			.map(|u| InsertRawUser {
+				email: u.email.as_ref(),
+			})
+			.collect::<Vec<_>>();
+
+		let inserted = self
+			.conn
+			.atomic(|conn| async move {
+				let inserted = self
+					.builder
+					.with_conn(conn)
+					.insert_many(&insert_users, mode)
+					.await?;
+				let ids = inserted.0.iter().map(|u| u.id).collect::<Vec<_>>();
+				conn.audit(Operation::Insert, TABLE, &ids).await?;
+
+				Ok::<_, Error>(inserted)
+			})
+			.await;
+		self.conn.invalidate(TABLE);
+		let (users, failures) = inserted?;
+
+		Ok(CreatedUsers {
+			users: users.into_iter().map(Into::into).collect(),
+			failures,
+		})
 	}
 
-	// pub async fn all_users(&self) -> Vec<User> {
-	// 	let users = self.inner.read_all().await;
+	/// Returns a page of users ordered by their email
+	pub async fn list(
+		&self,
+		after: Option<Cursor>,
+		limit: u32,
+	) -> Result<Page<User>, Error> {
+		let query = Query::new().order_asc("email").limit(limit).after(after);
+
+		let page = self
+			.conn
+			.cached(TABLE, format!("list {}", query.cache_key()), || {
+				self.inner.list(&query)
+			})
+			.await?;
+		let ids = page.items.iter().map(|u| u.id).collect::<Vec<_>>();
+		self.conn.audit(Operation::Read, TABLE, &ids).await?;
 
-	// 	users.into_iter().map(Into::into).collect()
-	// }
+		Ok(page.map(Into::into))
+	}
 
 	pub async fn by_email(&self, email: &str) -> Result<Option<User>, Error> {
-		let user = self.inner.by_email(email).await?;
+		let user = self
+			.conn
+			.cached(TABLE, format!("by_email {email}"), || {
+				self.inner.by_email(email)
+			})
+			.await?;
+		self.audit_read(user.as_ref()).await?;
 
 		Ok(user.map(Into::into))
 	}
 
 	pub async fn by_id(&self, id: &Id) -> Result<Option<User>, Error> {
-		let user = self.inner.by_id(id).await?;
+		let user = self
+			.conn
+			.cached(TABLE, format!("by_id {id}"), || self.inner.by_id(id))
+			.await?;
+		self.audit_read(user.as_ref()).await?;
 
 		Ok(user.map(Into::into))
 	}
+
+	/// Cached reads are audited as well
+	async fn audit_read(&self, user: Option<&RawUser>) -> Result<(), Error> {
+		let ids = user.map(|u| u.id).into_iter().collect::<Vec<_>>();
+		self.conn.audit(Operation::Read, TABLE, &ids).await?;
+
+		Ok(())
+	}
+}
+
+#[async_trait::async_trait]
+impl Dumpable for Users {
+	fn name(&self) -> &str {
+		TABLE
+	}
+
+	async fn dump(
+		&self,
+		conn: Connection<'_>,
+	) -> Result<Vec<serde_json::Value>, DumpError> {
+		let page = self
+			.inner
+			.with_conn(conn)
+			.list(&Query::new())
+			.await
+			.map_err(|e| DumpError::table(TABLE, e))?;
+
+		page.items
+			.iter()
+			.map(serde_json::to_value)
+			.collect::<Result<_, _>>()
+			.map_err(Into::into)
+	}
+
+	async fn restore(
+		&self,
+		conn: Connection<'_>,
+		rows: Vec<serde_json::Value>,
+	) -> Result<(), DumpError> {
+		let users = rows
+			.into_iter()
+			.map(serde_json::from_value)
+			.collect::<Result<Vec<RawUser>, _>>()?;
+
+		self.inner
+			.with_conn(conn)
+			.restore(&users)
+			.await
+			.map_err(|e| DumpError::table(TABLE, e))
+	}
 }
 
 impl Clone for Users {
@@ -128,32 +288,340 @@ impl Clone for Users {
 
 #[cfg(test)]
 mod tests {
-	use database::DatabasePool;
+	use std::time::Duration;
+
+	use database::{
+		audit::{AuditConfig, AuditFilter},
+		cache::CacheConfig,
+		deadline::Deadline,
+		dump::{Archive, Dump},
+		tenant::Tenant,
+		testing::{Fixtures, TestPool},
+		DatabasePool,
+	};
 
 	use super::*;
 
 	#[tokio::test]
 	async fn test_users() {
+		for pool in TestPool::backends().await {
+			let mut db = pool.isolated().await;
+
+			let users = Users::new(&mut db).await.unwrap();
+			let users = users.with_conn(db.connection());
+
+			let user = users
+				.create_user(CreateUser {
+					email: "rust@rust.com".parse().unwrap(),
+				})
+				.await
+				.unwrap();
+
+			assert_eq!(user.email.as_ref(), "rust@rust.com");
+
+			let n_user =
+				users.by_email(user.email.as_ref()).await.unwrap().unwrap();
+			assert_eq!(n_user.id, user.id);
+
+			let n_user = users.by_id(&user.id).await.unwrap().unwrap();
+			assert_eq!(n_user.id, user.id);
+		}
+	}
+
+	#[tokio::test]
+	async fn test_conformance() {
+		for pool in TestPool::backends().await {
+			let mut db = pool.isolated().await;
+			let users = Users::new(&mut db).await.unwrap();
+
+			persistent::conformance::run(&*users.inner, &pool).await;
+		}
+	}
+
+	#[tokio::test]
+	async fn test_deterministic_ids() {
+		async fn create(pool: TestPool) -> Id {
+			let mut db = pool.isolated().await;
+			let users = Users::new(&mut db).await.unwrap();
+			let users = users.with_conn(db.connection());
+
+			let user = users
+				.create_user(CreateUser {
+					email: "rust@rust.com".parse().unwrap(),
+				})
+				.await
+				.unwrap();
+
+			user.id
+		}
+
+		assert_eq!(
+			create(TestPool::memory()).await,
+			create(TestPool::memory()).await
+		);
+	}
+
+	#[tokio::test]
+	async fn test_fixtures() {
+		let fixtures = Fixtures::from_json(
+			r#"{ "users": [
+				{ "id": "ZVNxAAAAAAAAAAAB", "email": "a@rust.com" },
+				{ "id": "ZVNxAAAAAAAAAAEB", "email": "b@rust.com" }
+			] }"#,
+		)
+		.unwrap();
+
+		let pool = TestPool::memory();
+		let mut db = pool.isolated().await;
+		let users = Users::new(&mut db).await.unwrap();
+
+		let mut dump = Dump::new();
+		dump.register(users.clone());
+		fixtures.load(&dump, &mut db).await.unwrap();
+
+		// rows of other tests are not visible
+		let other = pool.isolated().await;
+		let other = users.with_conn(other.connection());
+		assert!(other.by_email("a@rust.com").await.unwrap().is_none());
+
+		let users = users.with_conn(db.connection());
+		let user = users.by_email("b@rust.com").await.unwrap().unwrap();
+		assert_eq!(user.id.to_string(), "ZVNxAAAAAAAAAAEB");
+	}
+
+	#[tokio::test]
+	async fn test_audit() {
+		let pool = TestPool::memory();
+		let audited = pool.pool().clone().with_audit(AuditConfig::default());
+		let mut db = audited.get().await.unwrap();
+		let users = Users::new(&mut db).await.unwrap();
+
+		let actor = pool.new_id(KIND);
+		db.set_actor(Some(actor));
+		let users = users.with_conn(db.connection());
+
+		let user = users
+			.create_user(CreateUser {
+				email: "rust@rust.com".parse().unwrap(),
+			})
+			.await
+			.unwrap();
+		pool.advance(Duration::from_secs(1));
+		users.by_id(&user.id).await.unwrap();
+		assert!(users.by_email("none@rust.com").await.unwrap().is_none());
+
+		let log = audited.audit().unwrap();
+		let filter = AuditFilter::new().actor(actor).row(user.id);
+		let trail = log.trail(&db.connection(), &filter).await.unwrap();
+		let ops = trail.iter().map(|e| e.operation).collect::<Vec<_>>();
+		assert_eq!(ops, [Operation::Read, Operation::Insert]);
+		assert!(trail.iter().all(|e| e.table == TABLE));
+	}
+
+	#[tokio::test]
+	async fn test_list_users() {
 		let db = DatabasePool::new_memory();
 		let mut db = db.get().await.unwrap();
 
 		let users = Users::new(&mut db).await.unwrap();
 		let users = users.with_conn(db.connection());
 
+		for email in ["c@rust.com", "a@rust.com", "b@rust.com"] {
+			users
+				.create_user(CreateUser {
+					email: email.parse().unwrap(),
+				})
+				.await
+				.unwrap();
+		}
+
+		let page = users.list(None, 2).await.unwrap();
+		let emails: Vec<_> =
+			page.items.iter().map(|u| u.email.as_ref()).collect();
+		assert_eq!(emails, ["a@rust.com", "b@rust.com"]);
+
+		let page = users.list(page.next, 2).await.unwrap();
+		assert_eq!(page.items.len(), 1);
+		assert_eq!(page.items[0].email.as_ref(), "c@rust.com");
+		assert!(page.next.is_none());
+	}
+
+	#[tokio::test]
+	async fn test_tenants() {
+		let pool = DatabasePool::new_memory();
+		let mut db = pool.get().await.unwrap();
+		let users = Users::new(&mut db).await.unwrap();
+
+		let tenant = Tenant::new(Id::new(Kind::new(false, 2)));
+		let tenant_db = pool.get_tenant(tenant).await.unwrap();
+		let tenant_users = users.with_conn(tenant_db.connection());
+
+		let user = tenant_users
+			.create_user(CreateUser {
+				email: "rust@rust.com".parse().unwrap(),
+			})
+			.await
+			.unwrap();
+		assert!(tenant_users.by_id(&user.id).await.unwrap().is_some());
+
+		// the same email can be used by another tenant
+		let users = users.with_conn(db.connection());
+		assert!(users.by_id(&user.id).await.unwrap().is_none());
+		users
+			.create_user(CreateUser {
+				email: "rust@rust.com".parse().unwrap(),
+			})
+			.await
+			.unwrap();
+	}
+
+	#[tokio::test]
+	async fn test_create_users() {
+		let db = DatabasePool::new_memory();
+		let mut db = db.get().await.unwrap();
+
+		let users = Users::new(&mut db).await.unwrap();
+		let users = users.with_conn(db.connection());
+
+		let create = ["a@rust.com", "b@rust.com", "a@rust.com"]
+			.into_iter()
+			.map(|email| CreateUser {
+				email: email.parse().unwrap(),
+			})
+			.collect::<Vec<_>>();
+
+		let res = users.create_users(create.clone(), BatchMode::Abort).await;
+		assert!(matches!(res, Err(Error::Batch(BatchError::Aborted(_)))));
+		assert!(users.by_email("a@rust.com").await.unwrap().is_none());
+
+		let created = users
+			.create_users(create, BatchMode::Continue)
+			.await
+			.unwrap();
+		assert_eq!(created.users.len(), 2);
+		assert_eq!(created.failures.len(), 1);
+		assert_eq!(created.failures[0].index, 2);
+	}
+
+	#[tokio::test]
+	async fn test_kv_users() {
+		let pool = DatabasePool::new_kv_memory().unwrap();
+		let mut db = pool.get().await.unwrap();
+		let users = Users::new(&mut db).await.unwrap();
+
+		let tenant = Tenant::new(Id::new(Kind::new(false, 2)));
+		let tenant_db = pool.get_tenant(tenant).await.unwrap();
+		let tenant_users = users.with_conn(tenant_db.connection());
+		let users = users.with_conn(db.connection());
+
+		let user = users
+			.create_user(CreateUser {
+				email: "a@rust.com".parse().unwrap(),
+			})
+			.await
+			.unwrap();
+		let res = users
+			.create_user(CreateUser {
+				email: "a@rust.com".parse().unwrap(),
+			})
+			.await;
+		assert!(matches!(res, Err(Error::AlreadyExists { .. })));
+
+		assert_eq!(users.by_id(&user.id).await.unwrap().unwrap().id, user.id);
+		assert!(tenant_users.by_id(&user.id).await.unwrap().is_none());
+
+		let create = ["b@rust.com", "a@rust.com"]
+			.into_iter()
+			.map(|email| CreateUser {
+				email: email.parse().unwrap(),
+			})
+			.collect::<Vec<_>>();
+		let res = users.create_users(create.clone(), BatchMode::Abort).await;
+		assert!(matches!(res, Err(Error::Batch(BatchError::Aborted(_)))));
+
+		let page = users.list(None, 10).await.unwrap();
+		assert_eq!(page.items.len(), 1);
+	}
+
+	#[tokio::test]
+	async fn test_cached_users() {
+		let pool =
+			DatabasePool::new_memory().with_cache(CacheConfig::default());
+		let mut db = pool.get().await.unwrap();
+
+		let users = Users::new(&mut db).await.unwrap();
+		let users = users.with_conn(db.connection());
+
+		assert!(users.by_email("a@rust.com").await.unwrap().is_none());
+		assert!(users.by_email("a@rust.com").await.unwrap().is_none());
+		let stats = pool.cache().unwrap().stats();
+		assert_eq!((stats.hits, stats.misses), (1, 1));
+
+		// the write invalidates the cached miss
+		users
+			.create_user(CreateUser {
+				email: "a@rust.com".parse().unwrap(),
+			})
+			.await
+			.unwrap();
+		assert!(users.by_email("a@rust.com").await.unwrap().is_some());
+		assert_eq!(users.list(None, 10).await.unwrap().items.len(), 1);
+	}
+
+	#[tokio::test]
+	async fn test_dump_restore() {
+		let pool = DatabasePool::new_memory();
+		let mut db = pool.get().await.unwrap();
+		let users = Users::new(&mut db).await.unwrap();
+
 		let user = users
+			.with_conn(db.connection())
 			.create_user(CreateUser {
 				email: "rust@rust.com".parse().unwrap(),
 			})
 			.await
 			.unwrap();
 
-		assert_eq!(user.email.as_ref(), "rust@rust.com");
+		let mut dump = Dump::new();
+		dump.register(users);
+		let archive = dump.export(&db).await.unwrap();
+
+		let mut buf = vec![];
+		archive.write_ndjson(&mut buf).unwrap();
+		let archive = Archive::read_ndjson(buf.as_slice()).unwrap();
+
+		// restore into a fresh instance
+		let mut db = DatabasePool::new_memory().get().await.unwrap();
+		let users = Users::new(&mut db).await.unwrap();
+		let mut dump = Dump::new();
+		dump.register(users.clone());
+		dump.restore(&mut db, archive.clone()).await.unwrap();
 
-		let n_user =
-			users.by_email(user.email.as_ref()).await.unwrap().unwrap();
-		assert_eq!(n_user.id, user.id);
+		let n_user = users
+			.with_conn(db.connection())
+			.by_id(&user.id)
+			.await
+			.unwrap()
+			.unwrap();
+		assert_eq!(n_user.email, user.email);
+
+		// the ids already exist
+		assert!(dump.restore(&mut db, archive).await.is_err());
+	}
+
+	#[tokio::test]
+	async fn test_deadline() {
+		let pool = DatabasePool::new_memory();
+		let mut db = pool.get().await.unwrap();
+		let users = Users::new(&mut db).await.unwrap();
+
+		let deadline = Deadline::after(Duration::from_millis(10));
+		let db = pool.get_with_deadline(deadline).await.unwrap();
+		let users = users.with_conn(db.connection());
+		assert!(users.by_email("a@rust.com").await.unwrap().is_none());
 
-		let n_user = users.by_id(&user.id).await.unwrap().unwrap();
-		assert_eq!(n_user.id, user.id);
+		tokio::time::sleep(Duration::from_millis(20)).await;
+		let res = users.by_email("a@rust.com").await;
+		assert!(matches!(res, Err(Error::Deadline(_))));
 	}
 }
@@ -0,0 +1,214 @@
+//! Scenarios every [`UsersPersistent`] backend needs to pass
+//!
+//! Call [`run`] with the builder of a backend, every scenario gets it's own
+//! isolated database.
+
+use database::{
+	batch::{BatchError, BatchMode, RowError, RowFailure},
+	query::Query,
+	testing::TestPool,
+};
+
+use super::{InsertRawUser, RawUser, UsersPersistent, UsersPersistentBuilder};
+use crate::users::{Error, KIND};
+
+pub async fn run(builder: &dyn UsersPersistentBuilder, pool: &TestPool) {
+	let db = pool.isolated().await;
+	insert(&*builder.with_conn(db.connection())).await;
+
+	let db = pool.isolated().await;
+	duplicate_email(&*builder.with_conn(db.connection())).await;
+
+	let db = pool.isolated().await;
+	insert_many_abort(&*builder.with_conn(db.connection())).await;
+
+	let db = pool.isolated().await;
+	insert_many_continue(&*builder.with_conn(db.connection())).await;
+
+	let db = pool.isolated().await;
+	restore(&*builder.with_conn(db.connection()), pool).await;
+
+	let db = pool.isolated().await;
+	list(&*builder.with_conn(db.connection())).await;
+
+	let db = pool.isolated().await;
+	let other = pool.isolated().await;
+	tenants(
+		&*builder.with_conn(db.connection()),
+		&*builder.with_conn(other.connection()),
+	)
+	.await;
+}
+
+fn raw(email: &str) -> InsertRawUser<'_> {
+	InsertRawUser { email }
+}
+
+fn already_exists(indexes: &[usize]) -> Vec<RowFailure> {
+	indexes
+		.iter()
+		.map(|index| RowFailure {
+			index: *index,
+			error: RowError::AlreadyExists,
+		})
+		.collect()
+}
+
+async fn emails(users: &dyn UsersPersistent) -> Vec<String> {
+	let page = users.list(&Query::new().order_asc("email")).await.unwrap();
+
+	page.items.into_iter().map(|u| u.email).collect()
+}
+
+async fn insert(users: &dyn UsersPersistent) {
+	let user = users.insert(raw("a@rust.com")).await.unwrap();
+	assert_eq!(user.email, "a@rust.com");
+	assert_eq!(user.id.kind(), KIND);
+
+	let by_id = users.by_id(&user.id).await.unwrap().unwrap();
+	assert_eq!(by_id.email, user.email);
+
+	let by_email = users.by_email("a@rust.com").await.unwrap().unwrap();
+	assert_eq!(by_email.id, user.id);
+
+	assert!(users.by_email("b@rust.com").await.unwrap().is_none());
+}
+
+async fn duplicate_email(users: &dyn UsersPersistent) {
+	users.insert(raw("a@rust.com")).await.unwrap();
+
+	let res = users.insert(raw("a@rust.com")).await;
+	assert!(
+		matches!(&res, Err(Error::AlreadyExists { email }) if email == "a@rust.com"),
+		"{res:?}"
+	);
+
+	assert_eq!(emails(users).await, ["a@rust.com"]);
+}
+
+async fn insert_many_abort(users: &dyn UsersPersistent) {
+	users.insert(raw("a@rust.com")).await.unwrap();
+
+	// a duplicate inside the batch
+	let batch = [raw("b@rust.com"), raw("c@rust.com"), raw("b@rust.com")];
+	let res = users.insert_many(&batch, BatchMode::Abort).await;
+	assert!(
+		matches!(&res, Err(Error::Batch(BatchError::Aborted(f))) if *f == already_exists(&[2])),
+		"{res:?}"
+	);
+
+	// an email which already exists
+	let batch = [raw("a@rust.com"), raw("b@rust.com")];
+	let res = users.insert_many(&batch, BatchMode::Abort).await;
+	assert!(
+		matches!(&res, Err(Error::Batch(BatchError::Aborted(f))) if *f == already_exists(&[0])),
+		"{res:?}"
+	);
+
+	assert_eq!(emails(users).await, ["a@rust.com"]);
+
+	let batch = [raw("b@rust.com"), raw("c@rust.com")];
+	let (inserted, failures) =
+		users.insert_many(&batch, BatchMode::Abort).await.unwrap();
+	assert_eq!(inserted.len(), 2);
+	assert!(failures.is_empty());
+}
+
+async fn insert_many_continue(users: &dyn UsersPersistent) {
+	users.insert(raw("a@rust.com")).await.unwrap();
+
+	let batch = [
+		raw("b@rust.com"),
+		raw("a@rust.com"),
+		raw("c@rust.com"),
+		raw("b@rust.com"),
+	];
+	let (inserted, failures) = users
+		.insert_many(&batch, BatchMode::Continue)
+		.await
+		.unwrap();
+
+	let inserted = inserted.into_iter().map(|u| u.email).collect::<Vec<_>>();
+	assert_eq!(inserted, ["b@rust.com", "c@rust.com"]);
+	assert_eq!(failures, already_exists(&[1, 3]));
+
+	assert_eq!(
+		emails(users).await,
+		["a@rust.com", "b@rust.com", "c@rust.com"]
+	);
+}
+
+async fn restore(users: &dyn UsersPersistent, pool: &TestPool) {
+	let a = RawUser {
+		id: pool.new_id(KIND),
+		email: "a@rust.com".into(),
+	};
+	let b = RawUser {
+		id: pool.new_id(KIND),
+		email: "b@rust.com".into(),
+	};
+	users.restore(&[a.clone(), b.clone()]).await.unwrap();
+
+	let restored = users.by_email("b@rust.com").await.unwrap().unwrap();
+	assert_eq!(restored.id, b.id);
+
+	// the id already exists
+	let c = RawUser {
+		id: a.id,
+		email: "c@rust.com".into(),
+	};
+	let res = users.restore(&[c]).await;
+	assert!(
+		matches!(&res, Err(Error::Batch(BatchError::Aborted(f))) if *f == already_exists(&[0])),
+		"{res:?}"
+	);
+
+	// the email already exists
+	let d = RawUser {
+		id: pool.new_id(KIND),
+		email: "a@rust.com".into(),
+	};
+	let res = users.restore(&[d]).await;
+	assert!(
+		matches!(&res, Err(Error::Batch(BatchError::Aborted(f))) if *f == already_exists(&[0])),
+		"{res:?}"
+	);
+
+	assert_eq!(emails(users).await, ["a@rust.com", "b@rust.com"]);
+}
+
+async fn list(users: &dyn UsersPersistent) {
+	for email in ["c@rust.com", "a@rust.com", "d@rust.com", "b@rust.com"] {
+		users.insert(raw(email)).await.unwrap();
+	}
+
+	let query = Query::new().order_asc("email").limit(3);
+	let page = users.list(&query).await.unwrap();
+	let emails = page
+		.items
+		.iter()
+		.map(|u| u.email.as_str())
+		.collect::<Vec<_>>();
+	assert_eq!(emails, ["a@rust.com", "b@rust.com", "c@rust.com"]);
+
+	let page = users.list(&query.after(page.next)).await.unwrap();
+	assert_eq!(page.items.len(), 1);
+	assert_eq!(page.items[0].email, "d@rust.com");
+	assert!(page.next.is_none());
+
+	let query = Query::new().order_desc("email").limit(1);
+	let page = users.list(&query).await.unwrap();
+	assert_eq!(page.items[0].email, "d@rust.com");
+	assert!(page.next.is_some());
+}
+
+async fn tenants(users: &dyn UsersPersistent, other: &dyn UsersPersistent) {
+	let user = users.insert(raw("a@rust.com")).await.unwrap();
+
+	assert!(other.by_id(&user.id).await.unwrap().is_none());
+	assert!(other.by_email("a@rust.com").await.unwrap().is_none());
+	assert!(emails(other).await.is_empty());
+
+	// emails only need to be unique per tenant
+	other.insert(raw("a@rust.com")).await.unwrap();
+}
@@ -0,0 +1,155 @@
+use database::{
+	batch::{BatchError, BatchMode, RowFailure},
+	id::Id,
+	kv::{self, Index, Table, TableWithConn},
+	query::{Page, Query},
+	trace::Statement,
+	Connection,
+};
+
+use crate::users::{KIND, TABLE};
+
+use super::{
+	Error, InsertRawUser, RawUser, UsersPersistent, UsersPersistentBuilder,
+};
+
+const BY_EMAIL: Index<RawUser> =
+	Index::new("email", |u| u.email.as_str().into());
+const USERS: Table<RawUser> = Table::with_indexes(TABLE, &[BY_EMAIL]);
+
+#[derive(Debug, Clone)]
+pub struct KvBuilder;
+
+impl KvBuilder {
+	pub fn new() -> Self {
+		Self
+	}
+}
+
+impl UsersPersistentBuilder for KvBuilder {
+	fn with_conn<'a>(
+		&'a self,
+		conn: Connection<'a>,
+	) -> Box<dyn UsersPersistent + 'a> {
+		Box::new(Kv {
+			table: USERS.with_conn(conn.into_kv()),
+			conn,
+		})
+	}
+
+	fn clone_box(&self) -> Box<dyn UsersPersistentBuilder> {
+		Box::new(self.clone())
+	}
+}
+
+#[derive(Debug)]
+pub struct Kv<'a> {
+	table: TableWithConn<'a, RawUser>,
+	conn: Connection<'a>,
+}
+
+impl Kv<'_> {
+	fn stmt(statement: &str) -> Statement<'_> {
+		Statement::new(TABLE, statement)
+	}
+
+	/// Inserts the users, users with an existing email fail
+	async fn insert_users(
+		&self,
+		users: &[RawUser],
+		mode: BatchMode,
+	) -> Result<(Vec<RawUser>, Vec<RowFailure>), Error> {
+		let res = self
+			.table
+			.insert_many_by(users, mode, BY_EMAIL.name(), |u| u.email.clone())
+			.await
+			.map_err(|e| match e {
+				kv::Error::Batch(e) => Error::Batch(e),
+				e => e.into(),
+			})?;
+
+		let inserted = res.applied.iter().map(|i| users[*i].clone());
+
+		Ok((inserted.collect(), res.failures))
+	}
+}
+
+#[async_trait::async_trait]
+impl UsersPersistent for Kv<'_> {
+	async fn insert(&self, user: InsertRawUser<'_>) -> Result<RawUser, Error> {
+		self.conn
+			.traced(Self::stmt("insert"), async {
+				let raw = RawUser {
+					id: self.conn.new_id(KIND),
+					email: user.email.to_string(),
+				};
+
+				match self.insert_users(&[raw], BatchMode::Abort).await {
+					Ok((mut inserted, _)) => Ok(inserted.remove(0)),
+					Err(Error::Batch(BatchError::Aborted(_))) => {
+						Err(Error::AlreadyExists {
+							email: user.email.to_string(),
+						})
+					}
+					Err(e) => Err(e),
+				}
+			})
+			.await
+	}
+
+	async fn insert_many(
+		&self,
+		users: &[InsertRawUser<'_>],
+		mode: BatchMode,
+	) -> Result<(Vec<RawUser>, Vec<RowFailure>), Error> {
+		self.conn
+			.traced(Self::stmt("insert_many"), async {
+				let users = users
+					.iter()
+					.map(|u| RawUser {
+						id: self.conn.new_id(KIND),
+						email: u.email.to_string(),
+					})
+					.collect::<Vec<_>>();
+
+				self.insert_users(&users, mode).await
+			})
+			.await
+	}
+
+	async fn restore(&self, users: &[RawUser]) -> Result<(), Error> {
+		self.conn
+			.traced(Self::stmt("restore"), async {
+				self.insert_users(users, BatchMode::Abort).await?;
+
+				Ok(())
+			})
+			.await
+	}
+
+	async fn by_email(&self, email: &str) -> Result<Option<RawUser>, Error> {
+		self.conn
+			.traced(Self::stmt("by_email").params(&[&email]), async {
+				let users = self.table.find_by(BY_EMAIL.name(), email).await?;
+
+				Ok(users.into_iter().next())
+			})
+			.await
+	}
+
+	async fn by_id(&self, id: &Id) -> Result<Option<RawUser>, Error> {
+		self.conn
+			.traced(Self::stmt("by_id").params(&[id]), async {
+				self.table.get(id).await.map_err(Into::into)
+			})
+			.await
+	}
+
+	async fn list(&self, query: &Query) -> Result<Page<RawUser>, Error> {
+		self.conn
+			.traced(Self::stmt("list"), async {
+				self.table.query(query).await.map_err(Into::into)
+			})
+			.await
+	}
+}
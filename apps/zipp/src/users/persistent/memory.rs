@@ -1,47 +1,118 @@
 use database::{
+	batch::{BatchMode, RowFailure},
 	id::Id,
-	memory::{ReadWrite, Table},
+	memory::{Partitioned, ReadWrite, Table},
+	query::{Page, Query},
+	trace::Statement,
 	Connection,
 };
 
-use crate::users::KIND;
+use crate::users::{KIND, TABLE};
 
 use super::{
 	Error, InsertRawUser, RawUser, UsersPersistent, UsersPersistentBuilder,
 };
 
 #[derive(Debug, Clone)]
-pub struct Memory {
-	inner: ReadWrite<Table<Id, RawUser>>,
+pub struct MemoryBuilder {
+	tables: Partitioned<Table<Id, RawUser>>,
 }
 
-impl Memory {
+impl MemoryBuilder {
 	pub fn new() -> Self {
 		Self {
-			inner: ReadWrite::new(Table::new()),
+			tables: Partitioned::new(),
 		}
 	}
 }
 
-impl UsersPersistentBuilder for Memory {
-	fn with_conn(&self, conn: Connection<'_>) -> Box<dyn UsersPersistent> {
-		let _conn = conn.into_memory();
-
-		Box::new(Self {
-			inner: self.inner.clone(),
+impl UsersPersistentBuilder for MemoryBuilder {
+	fn with_conn<'a>(
+		&'a self,
+		conn: Connection<'a>,
+	) -> Box<dyn UsersPersistent + 'a> {
+		Box::new(Memory {
+			inner: self.tables.get(conn.tenant()),
+			conn,
 		})
 	}
 
 	fn clone_box(&self) -> Box<dyn UsersPersistentBuilder> {
-		Box::new(Self {
-			inner: self.inner.clone(),
-		})
+		Box::new(self.clone())
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Memory<'a> {
+	inner: ReadWrite<Table<Id, RawUser>>,
+	conn: Connection<'a>,
+}
+
+impl Memory<'_> {
+	fn stmt(statement: &str) -> Statement<'_> {
+		Statement::new(TABLE, statement)
 	}
 }
 
 #[async_trait::async_trait]
-impl UsersPersistent for Memory {
+impl UsersPersistent for Memory<'_> {
 	async fn insert(&self, user: InsertRawUser<'_>) -> Result<RawUser, Error> {
+		self.conn
+			.traced(Self::stmt("insert"), async { self.insert_sync(user) })
+			.await
+	}
+
+	async fn insert_many(
+		&self,
+		users: &[InsertRawUser<'_>],
+		mode: BatchMode,
+	) -> Result<(Vec<RawUser>, Vec<RowFailure>), Error> {
+		self.conn
+			.traced(Self::stmt("insert_many"), async {
+				self.insert_many_sync(users, mode)
+			})
+			.await
+	}
+
+	async fn restore(&self, users: &[RawUser]) -> Result<(), Error> {
+		self.conn
+			.traced(Self::stmt("restore"), async { self.restore_sync(users) })
+			.await
+	}
+
+	async fn by_email(&self, email: &str) -> Result<Option<RawUser>, Error> {
+		self.conn
+			.traced(Self::stmt("by_email").params(&[&email]), async {
+				let table = self.inner.read();
+
+				Ok(table.find(|u| u.email == email).cloned())
+			})
+			.await
+	}
+
+	async fn by_id(&self, id: &Id) -> Result<Option<RawUser>, Error> {
+		self.conn
+			.traced(Self::stmt("by_id").params(&[id]), async {
+				let table = self.inner.read();
+
+				Ok(table.get(id).cloned())
+			})
+			.await
+	}
+
+	async fn list(&self, query: &Query) -> Result<Page<RawUser>, Error> {
+		self.conn
+			.traced(Self::stmt("list"), async {
+				let table = self.inner.read();
+
+				table.query(query).map_err(Into::into)
+			})
+			.await
+	}
+}
+
+impl Memory<'_> {
+	fn insert_sync(&self, user: InsertRawUser<'_>) -> Result<RawUser, Error> {
 		let mut table = self.inner.write();
 
 		// check email does not exist
@@ -51,7 +122,7 @@ impl UsersPersistent for Memory {
 			});
 		}
 
-		let id = Id::new(KIND);
+		let id = self.conn.new_id(KIND);
 
 		let raw_user = RawUser {
 			id,
@@ -63,15 +134,41 @@ impl UsersPersistent for Memory {
 		Ok(raw_user)
 	}
 
-	async fn by_email(&self, email: &str) -> Result<Option<RawUser>, Error> {
-		let table = self.inner.read();
+	fn insert_many_sync(
+		&self,
+		users: &[InsertRawUser<'_>],
+		mode: BatchMode,
+	) -> Result<(Vec<RawUser>, Vec<RowFailure>), Error> {
+		let mut table = self.inner.write();
+
+		let raw_users = users
+			.iter()
+			.map(|u| RawUser {
+				id: self.conn.new_id(KIND),
+				email: u.email.to_string(),
+			})
+			.collect::<Vec<_>>();
+
+		let res = table.insert_many_by(
+			raw_users.iter().map(|u| (u.id, u.clone())),
+			mode,
+			|u| u.email.clone(),
+		)?;
 
-		Ok(table.find(|u| u.email == email).cloned())
+		let inserted = res.applied.iter().map(|i| raw_users[*i].clone());
+
+		Ok((inserted.collect(), res.failures))
 	}
 
-	async fn by_id(&self, id: &Id) -> Result<Option<RawUser>, Error> {
-		let table = self.inner.read();
+	fn restore_sync(&self, users: &[RawUser]) -> Result<(), Error> {
+		let mut table = self.inner.write();
+
+		table.insert_many_by(
+			users.iter().map(|u| (u.id, u.clone())),
+			BatchMode::Abort,
+			|u| u.email.clone(),
+		)?;
 
-		Ok(table.get(id).cloned())
+		Ok(())
 	}
 }
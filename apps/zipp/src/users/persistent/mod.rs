@@ -1,19 +1,48 @@
+#[cfg(test)]
+pub mod conformance;
+pub mod kv;
 pub mod memory;
 pub mod postgres;
 
 use std::fmt;
 
-use database::{id::Id, Connection};
-use serde::Deserialize;
+use database::{
+	batch::{BatchMode, RowFailure},
+	id::Id,
+	query::{Page, Query, Record, Value},
+	trace::RowCount,
+	Connection,
+};
+use serde::{Deserialize, Serialize};
 
 use super::Error;
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RawUser {
 	pub id: Id,
 	pub email: String,
 }
 
+impl Record for RawUser {
+	fn id(&self) -> Id {
+		self.id
+	}
+
+	fn value(&self, column: &str) -> Option<Value> {
+		match column {
+			"id" => Some(self.id.into()),
+			"email" => Some(self.email.as_str().into()),
+			_ => None,
+		}
+	}
+}
+
+impl RowCount for RawUser {
+	fn row_count(&self) -> Option<usize> {
+		Some(1)
+	}
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct InsertRawUser<'a> {
 	pub email: &'a str,
@@ -33,7 +62,19 @@ pub trait UsersPersistentBuilder: fmt::Debug + Send + Sync {
 pub trait UsersPersistent: fmt::Debug + Send + Sync {
 	async fn insert(&self, user: InsertRawUser<'_>) -> Result<RawUser, Error>;
 
+	/// Returns the inserted users and the failed rows
+	async fn insert_many(
+		&self,
+		users: &[InsertRawUser<'_>],
+		mode: BatchMode,
+	) -> Result<(Vec<RawUser>, Vec<RowFailure>), Error>;
+
+	/// Inserts the users with their ids, fails if any user already exists
+	async fn restore(&self, users: &[RawUser]) -> Result<(), Error>;
+
 	async fn by_email(&self, email: &str) -> Result<Option<RawUser>, Error>;
 
 	async fn by_id(&self, id: &Id) -> Result<Option<RawUser>, Error>;
+
+	async fn list(&self, query: &Query) -> Result<Page<RawUser>, Error>;
 }
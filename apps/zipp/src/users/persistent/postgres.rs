@@ -1,34 +1,47 @@
-use database::{id::Id, migration_files, Connection, Database};
-use fire_postgres::{
-	filter,
-	table::{table::TableWithConn, Table},
-	FromRow, ToRow,
+use std::collections::HashSet;
+
+use database::{
+	batch::{self, BatchError, BatchMode, RowError, RowFailure},
+	id::Id,
+	migration_files,
+	query::{self, Page, Query, QueryError, Record, Value},
+	tenant,
+	trace::{Statement, ToSql},
+	Connection, Database,
 };
+use fire_postgres::{FromRow, ToRow};
 
-use crate::users::KIND;
+use crate::users::{KIND, TABLE};
 
 use super::{
 	Error, InsertRawUser, RawUser, UsersPersistent, UsersPersistentBuilder,
 };
 
 const MIGRATIONS: &[(&str, &str)] = migration_files!["users-00-create"];
+const TENANT_MIGRATIONS: &[(&str, &str)] =
+	migration_files!["users-02-tenant-email"];
 
 #[derive(Debug, Clone)]
-pub struct PostgresBuilder {
-	table: Table,
-}
+pub struct PostgresBuilder;
 
 impl PostgresBuilder {
 	pub async fn new(db: &mut Database) -> Result<Self, Error> {
 		let migrations = db.migrations().unwrap();
 
 		for (name, sql) in MIGRATIONS {
-			migrations.add(db.connection_owned(), *name, *sql).await?;
+			migrations.add(db.connection_owned(), name, sql).await?;
+		}
+
+		let scope_sql = tenant::scope_table_sql(TABLE);
+		migrations
+			.add(db.connection_owned(), "users-01-tenant", &scope_sql)
+			.await?;
+
+		for (name, sql) in TENANT_MIGRATIONS {
+			migrations.add(db.connection_owned(), name, sql).await?;
 		}
 
-		Ok(Self {
-			table: Table::new("users"),
-		})
+		Ok(Self)
 	}
 }
 
@@ -37,21 +50,17 @@ impl UsersPersistentBuilder for PostgresBuilder {
 		&'a self,
 		conn: Connection<'a>,
 	) -> Box<dyn UsersPersistent + 'a> {
-		Box::new(Postgres {
-			table: self.table.with_conn(conn.into_postgres()),
-		})
+		Box::new(Postgres { conn })
 	}
 
 	fn clone_box(&self) -> Box<dyn UsersPersistentBuilder> {
-		Box::new(Self {
-			table: self.table.clone(),
-		})
+		Box::new(Self)
 	}
 }
 
 #[derive(Debug, Clone)]
 pub struct Postgres<'a> {
-	table: TableWithConn<'a>,
+	conn: Connection<'a>,
 }
 
 // match this with migrations
@@ -61,36 +70,160 @@ struct FullUserTable {
 	email: String,
 }
 
+impl Record for FullUserTable {
+	fn id(&self) -> Id {
+		self.id
+	}
+
+	fn value(&self, column: &str) -> Option<Value> {
+		match column {
+			"id" => Some(self.id.into()),
+			"email" => Some(self.email.as_str().into()),
+			_ => None,
+		}
+	}
+}
+
 #[async_trait::async_trait]
 impl UsersPersistent for Postgres<'_> {
 	async fn insert(&self, user: InsertRawUser<'_>) -> Result<RawUser, Error> {
 		let user = FullUserTable {
-			id: Id::new(KIND),
+			id: self.conn.new_id(KIND),
 			email: user.email.to_string(),
 		};
 
-		self.table.insert(&user).await?;
+		match query::postgres::insert(&self.conn, TABLE, &user).await {
+			Ok(()) => Ok(user.into()),
+			Err(QueryError::Postgres(database::Error::UniqueViolation(_))) => {
+				Err(Error::AlreadyExists { email: user.email })
+			}
+			Err(e) => Err(e.into()),
+		}
+	}
 
-		Ok(user.into())
+	async fn insert_many(
+		&self,
+		users: &[InsertRawUser<'_>],
+		mode: BatchMode,
+	) -> Result<(Vec<RawUser>, Vec<RowFailure>), Error> {
+		let rows = users
+			.iter()
+			.map(|u| FullUserTable {
+				id: self.conn.new_id(KIND),
+				email: u.email.to_string(),
+			})
+			.collect::<Vec<_>>();
+
+		let res =
+			batch::postgres::insert_many(&self.conn, TABLE, &rows, mode).await;
+		let res = self.email_conflicts(&rows, res).await?;
+
+		let inserted = res.applied.iter().map(|i| rows[*i].clone().into());
+
+		Ok((inserted.collect(), res.failures))
+	}
+
+	async fn restore(&self, users: &[RawUser]) -> Result<(), Error> {
+		let rows = users
+			.iter()
+			.map(|u| FullUserTable {
+				id: u.id,
+				email: u.email.clone(),
+			})
+			.collect::<Vec<_>>();
+
+		let res = batch::postgres::insert_many(
+			&self.conn,
+			TABLE,
+			&rows,
+			BatchMode::Abort,
+		)
+		.await;
+		self.email_conflicts(&rows, res).await?;
+
+		Ok(())
 	}
 
 	async fn by_email(&self, email: &str) -> Result<Option<RawUser>, Error> {
-		self.table
-			.select_opt::<FullUserTable>(filter!(&email))
-			.await
-			.map(|opt| opt.map(Into::into))
-			.map_err(Into::into)
+		let users = query::postgres::select_where::<FullUserTable>(
+			&self.conn,
+			TABLE,
+			&[("email", &email)],
+		)
+		.await?;
+
+		Ok(users.into_iter().next().map(Into::into))
 	}
 
 	async fn by_id(&self, id: &Id) -> Result<Option<RawUser>, Error> {
-		self.table
-			.select_opt::<FullUserTable>(filter!(id))
+		let users = query::postgres::select_where::<FullUserTable>(
+			&self.conn,
+			TABLE,
+			&[("id", id)],
+		)
+		.await?;
+
+		Ok(users.into_iter().next().map(Into::into))
+	}
+
+	async fn list(&self, query: &Query) -> Result<Page<RawUser>, Error> {
+		query::postgres::select_page::<FullUserTable>(&self.conn, TABLE, query)
 			.await
-			.map(|opt| opt.map(Into::into))
+			.map(|page| page.map(Into::into))
 			.map_err(Into::into)
 	}
 }
 
+impl Postgres<'_> {
+	/// An aborted batch only reports conflicting ids, rows with an existing
+	/// email fail with a unique violation
+	///
+	/// Reports these rows like the other backends.
+	async fn email_conflicts(
+		&self,
+		rows: &[FullUserTable],
+		res: Result<batch::BatchResult, BatchError>,
+	) -> Result<batch::BatchResult, Error> {
+		match res {
+			Err(BatchError::Postgres(database::Error::UniqueViolation(_))) => {}
+			res => return res.map_err(Into::into),
+		}
+
+		let emails = rows.iter().map(|r| r.email.as_str()).collect::<Vec<_>>();
+		// check the tenant in case the role bypasses row level security
+		let sql = format!(
+			"SELECT email FROM \"{TABLE}\" WHERE email = ANY($1) AND {}",
+			tenant::CURRENT_CONDITION
+		);
+		let pg = self.conn.into_postgres();
+
+		let params: [&(dyn ToSql + Sync); 1] = [&emails];
+		let existing = self
+			.conn
+			.traced(Statement::new(TABLE, &sql).params(&params), async {
+				let stmt = pg.prepare_cached(&sql).await?;
+				pg.query::<[String; 1], _>(&stmt, &params).await
+			})
+			.await?;
+
+		let mut seen = existing
+			.into_iter()
+			.map(|[email]| email)
+			.collect::<HashSet<_>>();
+		let failures = rows
+			.iter()
+			.enumerate()
+			.filter(|(_, row)| !seen.insert(row.email.clone()))
+			.map(|(index, _)| RowFailure {
+				index,
+				error: RowError::AlreadyExists,
+			})
+			.collect();
+
+		Err(BatchError::Aborted(failures).into())
+	}
+}
+
 impl From<FullUserTable> for RawUser {
 	fn from(user: FullUserTable) -> Self {
 		Self {
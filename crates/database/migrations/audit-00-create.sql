@@ -0,0 +1,21 @@
+-- create the append-only audit log
+CREATE TABLE audit_log (
+    id text PRIMARY KEY,
+    time timestamptz NOT NULL,
+    tenant text,
+    actor text,
+    operation text NOT NULL,
+    "table" text NOT NULL,
+    row_id text NOT NULL
+);
+CREATE INDEX audit_log_tenant_time ON audit_log (tenant, time);
+CREATE INDEX audit_log_time ON audit_log (time);
+
+-- entries can only be removed, never changed
+CREATE FUNCTION audit_log_reject_update() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'the audit log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+CREATE TRIGGER audit_log_append_only BEFORE UPDATE ON audit_log
+    FOR EACH ROW EXECUTE FUNCTION audit_log_reject_update();
@@ -0,0 +1,14 @@
+-- entries can only be removed by the purge, which sets the cutoff for it's
+-- transaction
+CREATE FUNCTION audit_log_reject_delete() RETURNS trigger AS $$
+BEGIN
+    IF OLD.time < NULLIF(current_setting('app.audit_cutoff', true), '')::timestamptz THEN
+        RETURN OLD;
+    END IF;
+    RAISE EXCEPTION 'the audit log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+CREATE TRIGGER audit_log_reject_delete BEFORE DELETE ON audit_log
+    FOR EACH ROW EXECUTE FUNCTION audit_log_reject_delete();
+CREATE TRIGGER audit_log_reject_truncate BEFORE TRUNCATE ON audit_log
+    FOR EACH STATEMENT EXECUTE FUNCTION audit_log_reject_update();
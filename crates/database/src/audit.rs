@@ -0,0 +1,559 @@
+//! Audit trail
+//!
+//! An opt-in, append-only log of who read or changed which rows, enabled
+//! with [`DatabasePool::with_audit`](crate::DatabasePool::with_audit).
+//! Modules call [`Connection::audit`] after accessing sensitive rows, the
+//! entry records the actor of the database, see
+//! [`Database::set_actor`](crate::Database::set_actor). Writes record their
+//! entries in the same transaction, see [`Connection::atomic`].
+//!
+//! The memory backend keeps the log in the pool, the key-value backend in
+//! the `audit_log` table and postgres in the `audit_log` table created by
+//! [`migrate`]. Updates and deletes of entries are rejected, entries are
+//! only removed by [`AuditLog::purge`] once they are older than the
+//! retention.
+
+use std::{
+	error::Error as StdError,
+	fmt::{self, Write},
+	str::FromStr,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use ::postgres::row::{FromRow, Row};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	batch::BatchMode,
+	deadline::DeadlineExceeded,
+	id::{Id, Kind},
+	kv,
+	query::{Record, Value},
+	tenant::Tenant,
+	trace::{Statement, ToSql},
+	Connection, ConnectionInner, Database,
+};
+
+pub const TABLE: &str = "audit_log";
+
+/// The kind of the ids of audit entries
+pub const KIND: Kind = Kind::new(false, 0x7ffe);
+
+const KV_TABLE: kv::Table<AuditEntry> = kv::Table::new(TABLE);
+
+const MIGRATIONS: &[(&str, &str)] = &[
+	(
+		"audit-00-create",
+		include_str!("../migrations/audit-00-create.sql"),
+	),
+	(
+		"audit-01-reject-delete",
+		include_str!("../migrations/audit-01-reject-delete.sql"),
+	),
+];
+
+/// The postgres setting which allows to delete older entries, only set
+/// by [`AuditLog::purge`]
+const CUTOFF_SETTING: &str = "app.audit_cutoff";
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuditError {
+	#[error("a postgres error occured {0}")]
+	Postgres(#[from] crate::Error),
+
+	#[error("a key-value store error occured {0}")]
+	KeyValue(#[from] kv::Error),
+
+	#[error("{0}")]
+	Deadline(#[from] DeadlineExceeded),
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditConfig {
+	/// Entries older than this are removed by [`AuditLog::purge`], None
+	/// keeps them forever
+	#[serde(default)]
+	pub retention_days: Option<u64>,
+}
+
+impl AuditConfig {
+	pub fn retention(&self) -> Option<Duration> {
+		self.retention_days
+			.map(|days| Duration::from_secs(days * 24 * 60 * 60))
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+	Read,
+	Insert,
+	Update,
+	Delete,
+}
+
+impl Operation {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Read => "read",
+			Self::Insert => "insert",
+			Self::Update => "update",
+			Self::Delete => "delete",
+		}
+	}
+}
+
+impl fmt::Display for Operation {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+impl FromStr for Operation {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"read" => Ok(Self::Read),
+			"insert" => Ok(Self::Insert),
+			"update" => Ok(Self::Update),
+			"delete" => Ok(Self::Delete),
+			s => Err(format!("unknown operation {s}")),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+	pub id: Id,
+	pub time: DateTime<Utc>,
+	pub tenant: Option<Tenant>,
+	/// None if the database had no actor
+	pub actor: Option<Id>,
+	pub operation: Operation,
+	pub table: String,
+	pub row: Id,
+}
+
+impl Record for AuditEntry {
+	fn id(&self) -> Id {
+		self.id
+	}
+
+	fn value(&self, column: &str) -> Option<Value> {
+		match column {
+			"id" => Some(self.id.into()),
+			"time" => Some(Value::DateTime(self.time)),
+			"table" => Some(self.table.as_str().into()),
+			"row" => Some(self.row.into()),
+			_ => None,
+		}
+	}
+}
+
+impl<'r> FromRow<'r> for AuditEntry {
+	fn from_row(row: &'r Row) -> Result<Self, Box<dyn StdError + Sync + Send>> {
+		let operation: &str = row.try_get("operation")?;
+
+		Ok(Self {
+			id: row.try_get("id")?,
+			time: row.try_get("time")?,
+			tenant: row.try_get::<_, Option<Id>>("tenant")?.map(Tenant::new),
+			actor: row.try_get("actor")?,
+			operation: operation.parse()?,
+			table: row.try_get("table")?,
+			row: row.try_get("row_id")?,
+		})
+	}
+}
+
+/// Selects entries of the audit trail, all conditions need to match
+///
+/// Entries are returned newest first.
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+	pub actor: Option<Id>,
+	pub operation: Option<Operation>,
+	pub table: Option<String>,
+	pub row: Option<Id>,
+	/// Only entries at or after this time
+	pub since: Option<DateTime<Utc>>,
+	/// Only entries before this time
+	pub until: Option<DateTime<Utc>>,
+	pub limit: Option<u32>,
+}
+
+impl AuditFilter {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn actor(mut self, actor: Id) -> Self {
+		self.actor = Some(actor);
+		self
+	}
+
+	pub fn operation(mut self, operation: Operation) -> Self {
+		self.operation = Some(operation);
+		self
+	}
+
+	pub fn table(mut self, table: impl Into<String>) -> Self {
+		self.table = Some(table.into());
+		self
+	}
+
+	pub fn row(mut self, row: Id) -> Self {
+		self.row = Some(row);
+		self
+	}
+
+	pub fn since(mut self, since: DateTime<Utc>) -> Self {
+		self.since = Some(since);
+		self
+	}
+
+	pub fn until(mut self, until: DateTime<Utc>) -> Self {
+		self.until = Some(until);
+		self
+	}
+
+	pub fn limit(mut self, limit: u32) -> Self {
+		self.limit = Some(limit);
+		self
+	}
+
+	pub fn matches(&self, entry: &AuditEntry) -> bool {
+		self.actor.is_none_or(|a| entry.actor == Some(a))
+			&& self.operation.is_none_or(|o| entry.operation == o)
+			&& self.table.as_ref().is_none_or(|t| entry.table == *t)
+			&& self.row.is_none_or(|r| entry.row == r)
+			&& self.since.is_none_or(|s| entry.time >= s)
+			&& self.until.is_none_or(|u| entry.time < u)
+	}
+}
+
+/// Creates the `audit_log` table, does nothing if the database is not
+/// postgres
+pub async fn migrate(db: &mut Database) -> Result<(), crate::Error> {
+	let Some(migrations) = db.migrations() else {
+		return Ok(());
+	};
+
+	for (name, sql) in MIGRATIONS {
+		migrations.add(db.connection_owned(), name, sql).await?;
+	}
+
+	Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+	config: AuditConfig,
+	/// Only used by the memory backend
+	memory: Arc<Mutex<Vec<AuditEntry>>>,
+}
+
+impl AuditLog {
+	pub fn new(config: AuditConfig) -> Self {
+		Self {
+			config,
+			memory: Arc::new(Mutex::new(vec![])),
+		}
+	}
+
+	pub fn config(&self) -> &AuditConfig {
+		&self.config
+	}
+
+	/// Appends an entry for every row
+	pub(crate) async fn record(
+		&self,
+		conn: &Connection<'_>,
+		operation: Operation,
+		table: &str,
+		rows: &[Id],
+	) -> Result<(), AuditError> {
+		let time = DateTime::<Utc>::from(conn.now());
+		let entries = rows
+			.iter()
+			.map(|row| AuditEntry {
+				id: conn.new_id(KIND),
+				time,
+				tenant: conn.tenant(),
+				actor: conn.actor(),
+				operation,
+				table: table.to_string(),
+				row: *row,
+			})
+			.collect::<Vec<_>>();
+
+		match conn.inner {
+			ConnectionInner::Memory(_) => {
+				let stmt = Statement::new(TABLE, "record");
+				conn.traced(stmt, async {
+					self.memory.lock().unwrap().extend(entries);
+					Ok(())
+				})
+				.await
+			}
+			ConnectionInner::KeyValue(kv) => {
+				let stmt = Statement::new(TABLE, "record");
+				conn.traced(stmt, async {
+					let table = KV_TABLE
+						.with_conn(kv::Connection::new(kv.store(), None));
+					// one transaction for all entries
+					table.insert_many(&entries, BatchMode::Abort).await?;
+
+					Ok(())
+				})
+				.await
+			}
+			ConnectionInner::Postgres(pg) => {
+				// one statement for all rows
+				let sql = "INSERT INTO audit_log \
+					(id, time, tenant, actor, operation, \"table\", row_id) \
+					SELECT id, $3, $4, $5, $6, $7, row_id \
+					FROM UNNEST($1::text[], $2::text[]) AS r(id, row_id)";
+
+				let ids = entries.iter().map(|e| e.id).collect::<Vec<_>>();
+				let tenant = conn.tenant().map(|t| t.id());
+				let actor = conn.actor();
+				let operation = operation.as_str();
+				let params: [&(dyn ToSql + Sync); 7] =
+					[&ids, &rows, &time, &tenant, &actor, &operation, &table];
+
+				let stmt = Statement::new(TABLE, sql).params(&params);
+				conn.traced(stmt, async {
+					let stmt = pg.prepare_cached(sql).await?;
+					pg.execute(&stmt, &params).await
+				})
+				.await?;
+
+				Ok(())
+			}
+		}
+	}
+
+	/// Returns the entries of the tenant of the connection which match the
+	/// filter
+	pub async fn trail(
+		&self,
+		conn: &Connection<'_>,
+		filter: &AuditFilter,
+	) -> Result<Vec<AuditEntry>, AuditError> {
+		let tenant = conn.tenant();
+		let limit = filter.limit.map_or(usize::MAX, |l| l as usize);
+		let select = |entries: Vec<AuditEntry>| {
+			let mut entries = entries
+				.into_iter()
+				.filter(|e| e.tenant == tenant && filter.matches(e))
+				.collect::<Vec<_>>();
+			entries.sort_by_key(|e| std::cmp::Reverse((e.time, e.id)));
+			entries.truncate(limit);
+			entries
+		};
+
+		match conn.inner {
+			ConnectionInner::Memory(_) => {
+				let stmt = Statement::new(TABLE, "trail");
+				conn.traced(stmt, async {
+					let entries = self.memory.lock().unwrap().clone();
+					Ok(select(entries))
+				})
+				.await
+			}
+			ConnectionInner::KeyValue(kv) => {
+				let stmt = Statement::new(TABLE, "trail");
+				conn.traced(stmt, async {
+					let table = KV_TABLE
+						.with_conn(kv::Connection::new(kv.store(), None));
+					Ok(select(table.values().await?))
+				})
+				.await
+			}
+			ConnectionInner::Postgres(pg) => {
+				let tenant = tenant.map(|t| t.id());
+				let operation = filter.operation.map(|o| o.as_str());
+
+				let mut conditions: Vec<(&str, &(dyn ToSql + Sync))> = vec![];
+				if let Some(actor) = &filter.actor {
+					conditions.push(("actor =", actor));
+				}
+				if let Some(operation) = &operation {
+					conditions.push(("operation =", operation));
+				}
+				if let Some(table) = &filter.table {
+					conditions.push(("\"table\" =", table));
+				}
+				if let Some(row) = &filter.row {
+					conditions.push(("row_id =", row));
+				}
+				if let Some(since) = &filter.since {
+					conditions.push(("time >=", since));
+				}
+				if let Some(until) = &filter.until {
+					conditions.push(("time <", until));
+				}
+
+				let mut sql = String::from(
+					"SELECT id, time, tenant, actor, operation, \"table\", \
+					row_id FROM audit_log WHERE tenant IS NOT DISTINCT FROM $1",
+				);
+				let mut params: Vec<&(dyn ToSql + Sync)> = vec![&tenant];
+				for (condition, param) in conditions {
+					params.push(param);
+					write!(sql, " AND {condition} ${}", params.len()).unwrap();
+				}
+
+				sql.push_str(" ORDER BY time DESC, id DESC");
+				if let Some(limit) = filter.limit {
+					write!(sql, " LIMIT {limit}").unwrap();
+				}
+
+				let stmt = Statement::new(TABLE, &sql).params(&params);
+				conn.traced(stmt, async {
+					let stmt = pg.prepare_cached(&sql).await?;
+					pg.query::<AuditEntry, _>(&stmt, &params).await
+				})
+				.await
+				.map_err(Into::into)
+			}
+		}
+	}
+
+	/// Removes the entries of all tenants which are older than the
+	/// retention, returns the amount of removed entries
+	pub async fn purge(
+		&self,
+		conn: &Connection<'_>,
+	) -> Result<u64, AuditError> {
+		let Some(retention) = self.config.retention() else {
+			return Ok(0);
+		};
+		let cutoff = DateTime::<Utc>::from(conn.now() - retention);
+
+		match conn.inner {
+			ConnectionInner::Memory(_) => {
+				let stmt = Statement::new(TABLE, "purge");
+				conn.traced(stmt, async {
+					let mut entries = self.memory.lock().unwrap();
+					let len = entries.len();
+					entries.retain(|e| e.time >= cutoff);
+					Ok((len - entries.len()) as u64)
+				})
+				.await
+			}
+			ConnectionInner::KeyValue(kv) => {
+				let stmt = Statement::new(TABLE, "purge");
+				conn.traced(stmt, async {
+					let table = KV_TABLE
+						.with_conn(kv::Connection::new(kv.store(), None));
+					let removed =
+						table.delete_where(move |e| e.time < cutoff).await?;
+					Ok(removed as u64)
+				})
+				.await
+			}
+			ConnectionInner::Postgres(pg) => {
+				let sql = "DELETE FROM audit_log WHERE time < $1";
+				let params: [&(dyn ToSql + Sync); 1] = [&cutoff];
+				let setting = cutoff.to_rfc3339();
+
+				// the trigger rejects deletes of entries after the cutoff
+				conn.atomic(|_| async {
+					pg.execute(
+						"SELECT set_config($1, $2, true)",
+						&[&CUTOFF_SETTING, &setting],
+					)
+					.await?;
+
+					let stmt = Statement::new(TABLE, sql).params(&params);
+					conn.traced(stmt, async {
+						let stmt = pg.prepare_cached(sql).await?;
+						pg.execute(&stmt, &params).await
+					})
+					.await
+					.map_err(Into::into)
+				})
+				.await
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::TestPool;
+
+	#[test]
+	fn cutoff_setting() {
+		// the trigger reads the setting
+		let (_, sql) = MIGRATIONS[1];
+		assert!(sql.contains(CUTOFF_SETTING));
+	}
+
+	#[tokio::test]
+	async fn audit_log() {
+		for pool in [TestPool::memory(), TestPool::kv()] {
+			let config = AuditConfig {
+				retention_days: Some(30),
+			};
+			let audited = pool.pool().clone().with_audit(config);
+			let alice = pool.new_id(Kind::new(false, 1));
+			let row = pool.new_id(Kind::new(false, 2));
+
+			let mut db = audited.get().await.unwrap();
+			db.set_actor(Some(alice));
+			let conn = db.connection();
+			conn.audit(Operation::Insert, "users", &[row])
+				.await
+				.unwrap();
+
+			pool.advance(Duration::from_secs(60 * 60 * 24 * 20));
+			conn.audit(Operation::Read, "users", &[row]).await.unwrap();
+
+			// other tenants don't see the entries
+			let tenant = Tenant::new(pool.new_id(Kind::new(false, 3)));
+			let other = audited.get_tenant(tenant).await.unwrap();
+			other
+				.connection()
+				.audit(Operation::Read, "users", &[row])
+				.await
+				.unwrap();
+
+			let log = audited.audit().unwrap();
+			let trail = log.trail(&conn, &AuditFilter::new()).await.unwrap();
+			let ops = trail.iter().map(|e| e.operation).collect::<Vec<_>>();
+			assert_eq!(ops, [Operation::Read, Operation::Insert]);
+			assert_eq!(trail[0].actor, Some(alice));
+			assert_eq!(trail[0].row, row);
+
+			let filter = AuditFilter::new().operation(Operation::Insert);
+			assert_eq!(log.trail(&conn, &filter).await.unwrap().len(), 1);
+			let filter = AuditFilter::new().actor(row);
+			assert!(log.trail(&conn, &filter).await.unwrap().is_empty());
+
+			// the insert is older than 30 days
+			pool.advance(Duration::from_secs(60 * 60 * 24 * 15));
+			assert_eq!(log.purge(&conn).await.unwrap(), 1);
+			let trail = log.trail(&conn, &AuditFilter::new()).await.unwrap();
+			assert_eq!(trail.len(), 1);
+			assert_eq!(trail[0].operation, Operation::Read);
+
+			// a database from the pool without an audit log records nothing
+			let db = pool.pool().get().await.unwrap();
+			db.connection()
+				.audit(Operation::Read, "users", &[row])
+				.await
+				.unwrap();
+			assert_eq!(
+				log.trail(&conn, &AuditFilter::new()).await.unwrap().len(),
+				1
+			);
+		}
+	}
+}
@@ -0,0 +1,72 @@
+//! Batch operations
+//!
+//! Inserts, upserts and deletes many rows at once. Failing rows are
+//! reported with their index in the batch, the other rows are still applied
+//! unless [`BatchMode::Abort`] is used.
+
+pub mod postgres;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BatchMode {
+	/// Apply all valid rows and report the failing ones
+	#[default]
+	Continue,
+	/// Don't apply any row if one of them fails
+	Abort,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum RowError {
+	#[error("the row already exists")]
+	AlreadyExists,
+
+	#[error("the row was not found")]
+	NotFound,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowFailure {
+	/// The index of the row in the batch
+	pub index: usize,
+	pub error: RowError,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchResult {
+	/// The indexes of the rows which where applied
+	pub applied: Vec<usize>,
+	pub failures: Vec<RowFailure>,
+}
+
+impl BatchResult {
+	/// Returns true if all rows where applied
+	pub fn is_complete(&self) -> bool {
+		self.failures.is_empty()
+	}
+
+	pub(crate) fn push_failure(&mut self, index: usize, error: RowError) {
+		self.failures.push(RowFailure { index, error });
+	}
+
+	/// Returns an error if the batch needs to be aborted
+	pub(crate) fn check(self, mode: BatchMode) -> Result<Self, BatchError> {
+		match mode {
+			BatchMode::Abort if !self.is_complete() => {
+				Err(BatchError::Aborted(self.failures))
+			}
+			_ => Ok(self),
+		}
+	}
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BatchError {
+	#[error("the batch was aborted, {} rows failed", .0.len())]
+	Aborted(Vec<RowFailure>),
+
+	#[error("the table {0} is not valid")]
+	InvalidTable(String),
+
+	#[error("a postgres error occured {0}")]
+	Postgres(#[from] crate::Error),
+}
@@ -0,0 +1,397 @@
+//! Executes batches with postgres
+//!
+//! Rows are written with multi-row statements. In [`BatchMode::Continue`]
+//! conflicting rows are skipped with `ON CONFLICT DO NOTHING` and detected
+//! by the returned ids.
+//!
+//! A batch which does not fit into a single statement is split. In
+//! [`BatchMode::Abort`] and for upserts the statements run
+//! [atomically](Connection::atomic), so either every statement is applied or
+//! none.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use ::postgres::{row::ToRowStatic, Error};
+
+use super::{BatchError, BatchMode, BatchResult, RowError, RowFailure};
+use crate::{
+	id::Id,
+	query::{validate_column, Record},
+	trace::Statement,
+	Connection,
+};
+
+/// Postgres allows at most this many parameters per statement
+const MAX_PARAMS: usize = u16::MAX as usize;
+
+/// Inserts all rows, rows with an existing id or unique value fail
+pub async fn insert_many<U>(
+	conn: &Connection<'_>,
+	table: &str,
+	rows: &[U],
+	mode: BatchMode,
+) -> Result<BatchResult, BatchError>
+where
+	U: ToRowStatic + Record,
+{
+	validate_table(table)?;
+
+	if mode == BatchMode::Abort {
+		let failures = duplicate_ids(rows.iter().map(Record::id));
+		if !failures.is_empty() {
+			return Err(BatchError::Aborted(failures));
+		}
+
+		let rows_ref = &rows.iter().collect::<Vec<_>>();
+		let res = conn
+			.atomic(|conn| async move {
+				for (_, chunk) in chunks::<U, _>(rows_ref) {
+					let sql = insert_statement::<U>(table, chunk.len(), "");
+					execute(&conn, table, &sql, chunk).await?;
+				}
+
+				Ok(())
+			})
+			.await;
+
+		return match res {
+			Ok(()) => Ok(BatchResult {
+				applied: (0..rows.len()).collect(),
+				failures: vec![],
+			}),
+			// nothing was inserted, so every existing id is a conflict
+			Err(BatchError::Postgres(Error::UniqueViolation(e))) => {
+				let ids = rows.iter().map(Record::id).collect::<Vec<_>>();
+				let existing = existing_ids(conn, table, &ids).await?;
+
+				let failures = failures_where(&ids, 0, |id| {
+					existing.contains(id).then_some(RowError::AlreadyExists)
+				});
+				// the conflict might be on another unique column
+				if failures.is_empty() {
+					return Err(Error::UniqueViolation(e).into());
+				}
+
+				Err(BatchError::Aborted(failures))
+			}
+			Err(e) => Err(e),
+		};
+	}
+
+	let pg = conn.into_postgres();
+	let mut result = BatchResult::default();
+
+	for (offset, chunk) in chunks::<U, _>(rows) {
+		let params = chunk.iter().flat_map(|r| r.params()).collect::<Vec<_>>();
+
+		let sql = insert_statement::<U>(
+			table,
+			chunk.len(),
+			" ON CONFLICT DO NOTHING RETURNING \"id\"",
+		);
+		let mut inserted = conn
+			.traced(Statement::new(table, &sql).params(&params), async {
+				let stmt = pg.prepare_cached(&sql).await?;
+				pg.query::<[Id; 1], _>(&stmt, &params).await
+			})
+			.await?
+			.into_iter()
+			.map(|[id]| id)
+			.collect::<HashSet<_>>();
+
+		for (i, row) in chunk.iter().enumerate() {
+			// a second row with the same id was not inserted
+			if inserted.remove(&row.id()) {
+				result.applied.push(offset + i);
+			} else {
+				result.push_failure(offset + i, RowError::AlreadyExists);
+			}
+		}
+	}
+
+	Ok(result)
+}
+
+/// Inserts all rows or updates them if a row with the same id exists
+///
+/// Like with the memory table the last row with an id wins if the id is
+/// used multiple times.
+pub async fn upsert_many<U>(
+	conn: &Connection<'_>,
+	table: &str,
+	rows: &[U],
+) -> Result<BatchResult, BatchError>
+where
+	U: ToRowStatic + Record,
+{
+	validate_table(table)?;
+
+	let mut on_conflict = String::from(" ON CONFLICT (\"id\") DO UPDATE SET ");
+	let columns = U::insert_columns()
+		.split(", ")
+		.filter(|c| *c != "\"id\"")
+		.collect::<Vec<_>>();
+	if columns.is_empty() {
+		on_conflict = " ON CONFLICT (\"id\") DO NOTHING".into();
+	}
+	for (i, column) in columns.into_iter().enumerate() {
+		if i != 0 {
+			on_conflict.push_str(", ");
+		}
+		write!(on_conflict, "{column} = EXCLUDED.{column}").unwrap();
+	}
+
+	// postgres can't update the same row twice in one statement
+	let last = rows
+		.iter()
+		.enumerate()
+		.map(|(i, row)| (row.id(), i))
+		.collect::<HashMap<_, _>>();
+	let unique = rows
+		.iter()
+		.enumerate()
+		.filter(|(i, row)| last[&row.id()] == *i)
+		.map(|(_, row)| row)
+		.collect::<Vec<_>>();
+
+	let (unique, on_conflict) = (&unique, &on_conflict);
+	conn.atomic(|conn| async move {
+		for (_, chunk) in chunks::<U, _>(unique) {
+			let sql = insert_statement::<U>(table, chunk.len(), on_conflict);
+			execute(&conn, table, &sql, chunk).await?;
+		}
+
+		Ok::<_, BatchError>(())
+	})
+	.await?;
+
+	Ok(BatchResult {
+		applied: (0..rows.len()).collect(),
+		failures: vec![],
+	})
+}
+
+/// Deletes the rows with the given ids, ids which don't exist fail
+pub async fn delete_many(
+	conn: &Connection<'_>,
+	table: &str,
+	ids: &[Id],
+	mode: BatchMode,
+) -> Result<BatchResult, BatchError> {
+	validate_table(table)?;
+	let pg = conn.into_postgres();
+
+	let deleted = match mode {
+		BatchMode::Continue => {
+			let sql = format!(
+				"DELETE FROM \"{table}\" WHERE \"id\" = ANY($1) RETURNING \"id\""
+			);
+			conn.traced(Statement::new(table, &sql).params(&[&ids]), async {
+				let stmt = pg.prepare_cached(&sql).await?;
+				pg.query::<[Id; 1], _>(&stmt, &[&ids]).await
+			})
+			.await?
+		}
+		BatchMode::Abort => {
+			let failures = duplicate_ids(ids.iter().copied());
+			if !failures.is_empty() {
+				return Err(BatchError::Aborted(failures));
+			}
+
+			// only delete if every id exists
+			let sql = format!(
+				"DELETE FROM \"{table}\" WHERE \"id\" = ANY($1) AND \
+				(SELECT COUNT(*) FROM \"{table}\" WHERE \"id\" = ANY($1)) = $2 \
+				RETURNING \"id\""
+			);
+			let len = ids.len() as i64;
+
+			let deleted = conn
+				.traced(
+					Statement::new(table, &sql).params(&[&ids, &len]),
+					async {
+						let stmt = pg.prepare_cached(&sql).await?;
+						pg.query::<[Id; 1], _>(&stmt, &[&ids, &len]).await
+					},
+				)
+				.await?;
+			if deleted.len() != ids.len() {
+				let existing = existing_ids(conn, table, ids).await?;
+				let failures = failures_where(ids, 0, |id| {
+					(!existing.contains(id)).then_some(RowError::NotFound)
+				});
+
+				return Err(BatchError::Aborted(failures));
+			}
+
+			deleted
+		}
+	};
+
+	let mut deleted =
+		deleted.into_iter().map(|[id]| id).collect::<HashSet<_>>();
+
+	let mut result = BatchResult::default();
+	for (i, id) in ids.iter().enumerate() {
+		// a second occurence of the same id was not deleted
+		if deleted.remove(id) {
+			result.applied.push(i);
+		} else {
+			result.push_failure(i, RowError::NotFound);
+		}
+	}
+
+	Ok(result)
+}
+
+/// Executes an insert statement with the parameters of the rows
+async fn execute<U>(
+	conn: &Connection<'_>,
+	table: &str,
+	sql: &str,
+	rows: &[&U],
+) -> Result<(), Error>
+where
+	U: ToRowStatic,
+{
+	let pg = conn.into_postgres();
+	let params = rows.iter().flat_map(|r| r.params()).collect::<Vec<_>>();
+
+	conn.traced(Statement::new(table, sql).params(&params), async {
+		let stmt = pg.prepare_cached(sql).await?;
+		pg.execute(&stmt, &params).await
+	})
+	.await?;
+
+	Ok(())
+}
+
+fn validate_table(table: &str) -> Result<(), BatchError> {
+	validate_column(table)
+		.map_err(|_| BatchError::InvalidTable(table.to_string()))
+}
+
+async fn existing_ids(
+	conn: &Connection<'_>,
+	table: &str,
+	ids: &[Id],
+) -> Result<HashSet<Id>, Error> {
+	let sql = format!("SELECT \"id\" FROM \"{table}\" WHERE \"id\" = ANY($1)");
+	let pg = conn.into_postgres();
+
+	let rows = conn
+		.traced(Statement::new(table, &sql).params(&[&ids]), async {
+			let stmt = pg.prepare_cached(&sql).await?;
+			pg.query::<[Id; 1], _>(&stmt, &[&ids]).await
+		})
+		.await?;
+
+	Ok(rows.into_iter().map(|[id]| id).collect())
+}
+
+/// Returns a failure for every id where f returns an error
+fn failures_where<F>(ids: &[Id], offset: usize, f: F) -> Vec<RowFailure>
+where
+	F: Fn(&Id) -> Option<RowError>,
+{
+	ids.iter()
+		.enumerate()
+		.filter_map(|(i, id)| {
+			f(id).map(|error| RowFailure {
+				index: offset + i,
+				error,
+			})
+		})
+		.collect()
+}
+
+fn duplicate_ids(ids: impl Iterator<Item = Id>) -> Vec<RowFailure> {
+	let mut seen = HashSet::new();
+
+	ids.enumerate()
+		.filter(|(_, id)| !seen.insert(*id))
+		.map(|(index, _)| RowFailure {
+			index,
+			error: RowError::AlreadyExists,
+		})
+		.collect()
+}
+
+/// Splits the rows so that each statement stays below the parameter limit
+fn chunks<U, T>(rows: &[T]) -> impl Iterator<Item = (usize, &[T])>
+where
+	U: ToRowStatic,
+{
+	let size = (MAX_PARAMS / U::params_len().max(1)).max(1);
+
+	rows.chunks(size)
+		.enumerate()
+		.map(move |(i, chunk)| (i * size, chunk))
+}
+
+fn insert_statement<U>(table: &str, rows: usize, suffix: &str) -> String
+where
+	U: ToRowStatic,
+{
+	let mut sql =
+		format!("INSERT INTO \"{table}\" ({}) VALUES ", U::insert_columns());
+
+	let len = U::params_len();
+	for row in 0..rows {
+		if row != 0 {
+			sql.push_str(", ");
+		}
+
+		sql.push('(');
+		for i in 0..len {
+			if i != 0 {
+				sql.push_str(", ");
+			}
+			write!(sql, "${}", row * len + i + 1).unwrap();
+		}
+		sql.push(')');
+	}
+
+	sql.push_str(suffix);
+	sql
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use postgres_types::ToSql;
+
+	struct Row;
+
+	impl ToRowStatic for Row {
+		fn insert_columns() -> &'static str {
+			"\"id\", \"email\""
+		}
+
+		fn insert_values() -> &'static str {
+			"$1, $2"
+		}
+
+		fn update_columns() -> &'static str {
+			"\"id\" = $1, \"email\" = $2"
+		}
+
+		fn params_len() -> usize {
+			2
+		}
+
+		fn params(&self) -> impl ExactSizeIterator<Item = &(dyn ToSql + Sync)> {
+			[].into_iter()
+		}
+	}
+
+	#[test]
+	fn statement() {
+		let sql = insert_statement::<Row>("users", 2, "");
+		assert_eq!(
+			sql,
+			"INSERT INTO \"users\" (\"id\", \"email\") VALUES ($1, $2), ($3, $4)"
+		);
+	}
+}
@@ -0,0 +1,255 @@
+//! Query cache
+//!
+//! An optional read-through cache in front of query execution. Entries are
+//! keyed by the table, the tenant and a normalized query, and are dropped
+//! as soon as the table of the same tenant is written to.
+//!
+//! Invalidation only sees writes made through connections of this process.
+
+use std::{
+	any::Any,
+	collections::HashMap,
+	future::Future,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+};
+
+use serde::Deserialize;
+
+use crate::tenant::Tenant;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CacheConfig {
+	/// The maximum amount of cached queries, the least recently used query
+	/// get's evicted first
+	pub max_entries: usize,
+}
+
+impl Default for CacheConfig {
+	fn default() -> Self {
+		Self { max_entries: 1024 }
+	}
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+	pub hits: u64,
+	pub misses: u64,
+	pub evictions: u64,
+	pub invalidations: u64,
+	pub entries: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct QueryCache {
+	inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+	config: CacheConfig,
+	entries: Mutex<Entries>,
+	hits: AtomicU64,
+	misses: AtomicU64,
+	evictions: AtomicU64,
+	invalidations: AtomicU64,
+}
+
+type TableKey = (String, Option<Tenant>);
+
+#[derive(Debug, Default)]
+struct Entries {
+	map: HashMap<Key, Entry>,
+	/// Incremented on every write to a table, a loaded value is only
+	/// stored if the table was not written to while loading
+	generations: HashMap<TableKey, u64>,
+	tick: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key {
+	table: TableKey,
+	query: String,
+}
+
+#[derive(Debug)]
+struct Entry {
+	value: Arc<dyn Any + Send + Sync>,
+	last_used: u64,
+}
+
+impl QueryCache {
+	pub fn new(config: CacheConfig) -> Self {
+		Self {
+			inner: Arc::new(Inner {
+				config,
+				entries: Mutex::new(Entries::default()),
+				hits: AtomicU64::new(0),
+				misses: AtomicU64::new(0),
+				evictions: AtomicU64::new(0),
+				invalidations: AtomicU64::new(0),
+			}),
+		}
+	}
+
+	/// Returns the cached value or loads and caches it
+	///
+	/// Errors are not cached.
+	pub async fn get_or_load<T, E, F, Fut>(
+		&self,
+		table: &str,
+		tenant: Option<Tenant>,
+		query: String,
+		load: F,
+	) -> Result<T, E>
+	where
+		T: Clone + Send + Sync + 'static,
+		F: FnOnce() -> Fut,
+		Fut: Future<Output = Result<T, E>>,
+	{
+		let key = Key {
+			table: (table.to_string(), tenant),
+			query,
+		};
+
+		let generation = {
+			let mut entries = self.inner.entries.lock().unwrap();
+
+			if let Some(value) = entries.get(&key) {
+				if let Some(value) = value.downcast_ref::<T>() {
+					self.inner.hits.fetch_add(1, Ordering::Relaxed);
+					return Ok(value.clone());
+				}
+			}
+
+			entries.generation(&key.table)
+		};
+
+		self.inner.misses.fetch_add(1, Ordering::Relaxed);
+		let value = load().await?;
+
+		let mut entries = self.inner.entries.lock().unwrap();
+		if entries.generation(&key.table) == generation {
+			let evicted = entries.insert(
+				key,
+				value.clone(),
+				self.inner.config.max_entries,
+			);
+			self.inner.evictions.fetch_add(evicted, Ordering::Relaxed);
+		}
+
+		Ok(value)
+	}
+
+	/// Drops all cached queries of the table and tenant
+	pub fn invalidate(&self, table: &str, tenant: Option<Tenant>) {
+		let table = (table.to_string(), tenant);
+
+		let mut entries = self.inner.entries.lock().unwrap();
+		*entries.generations.entry(table.clone()).or_default() += 1;
+		entries.map.retain(|k, _| k.table != table);
+
+		self.inner.invalidations.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn stats(&self) -> CacheStats {
+		let entries = self.inner.entries.lock().unwrap().map.len();
+
+		CacheStats {
+			hits: self.inner.hits.load(Ordering::Relaxed),
+			misses: self.inner.misses.load(Ordering::Relaxed),
+			evictions: self.inner.evictions.load(Ordering::Relaxed),
+			invalidations: self.inner.invalidations.load(Ordering::Relaxed),
+			entries,
+		}
+	}
+}
+
+impl Entries {
+	fn generation(&self, table: &TableKey) -> u64 {
+		self.generations.get(table).copied().unwrap_or(0)
+	}
+
+	fn get(&mut self, key: &Key) -> Option<Arc<dyn Any + Send + Sync>> {
+		self.tick += 1;
+		let entry = self.map.get_mut(key)?;
+		entry.last_used = self.tick;
+
+		Some(entry.value.clone())
+	}
+
+	/// Returns the amount of evicted entries
+	fn insert<T>(&mut self, key: Key, value: T, max_entries: usize) -> u64
+	where
+		T: Send + Sync + 'static,
+	{
+		let mut evicted = 0;
+
+		while !self.map.contains_key(&key) && self.map.len() >= max_entries {
+			let Some(lru) = self
+				.map
+				.iter()
+				.min_by_key(|(_, e)| e.last_used)
+				.map(|(k, _)| k.clone())
+			else {
+				// max_entries is zero
+				return 0;
+			};
+
+			self.map.remove(&lru);
+			evicted += 1;
+		}
+
+		self.tick += 1;
+		self.map.insert(
+			key,
+			Entry {
+				value: Arc::new(value),
+				last_used: self.tick,
+			},
+		);
+
+		evicted
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	async fn load(
+		cache: &QueryCache,
+		table: &str,
+		query: &str,
+		value: u32,
+	) -> u32 {
+		cache
+			.get_or_load(table, None, query.into(), || async {
+				Ok::<_, ()>(value)
+			})
+			.await
+			.unwrap()
+	}
+
+	#[tokio::test]
+	async fn read_through() {
+		let cache = QueryCache::new(CacheConfig { max_entries: 2 });
+
+		assert_eq!(load(&cache, "users", "a", 1).await, 1);
+		assert_eq!(load(&cache, "users", "b", 2).await, 2);
+		assert_eq!(load(&cache, "users", "a", 3).await, 1);
+		assert_eq!(load(&cache, "other", "a", 4).await, 4);
+
+		// b was the least recently used
+		let stats = cache.stats();
+		assert_eq!((stats.hits, stats.misses), (1, 3));
+		assert_eq!((stats.evictions, stats.entries), (1, 2));
+		assert_eq!(load(&cache, "users", "a", 5).await, 1);
+
+		cache.invalidate("users", None);
+		assert_eq!(load(&cache, "users", "a", 6).await, 6);
+		assert_eq!(load(&cache, "other", "a", 7).await, 4);
+	}
+}
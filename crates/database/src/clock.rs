@@ -0,0 +1,128 @@
+//! Clock and id generation
+//!
+//! Every [`DatabasePool`](crate::DatabasePool) creates ids and timestamps
+//! with an [`IdGenerator`], see
+//! [`Connection::new_id`](crate::Connection::new_id). Tests can replace it
+//! with a seeded generator and a [`ManualClock`] to get the same ids on
+//! every run.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::id::{Id, Kind};
+
+pub trait Clock: fmt::Debug + Send + Sync {
+	fn now(&self) -> SystemTime;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+	fn now(&self) -> SystemTime {
+		SystemTime::now()
+	}
+}
+
+/// A clock which only moves when it is told to
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+	now: Arc<Mutex<SystemTime>>,
+}
+
+impl ManualClock {
+	pub fn new(start: SystemTime) -> Self {
+		Self {
+			now: Arc::new(Mutex::new(start)),
+		}
+	}
+
+	pub fn set(&self, now: SystemTime) {
+		*self.now.lock().unwrap() = now;
+	}
+
+	pub fn advance(&self, duration: Duration) {
+		*self.now.lock().unwrap() += duration;
+	}
+}
+
+impl Clock for ManualClock {
+	fn now(&self) -> SystemTime {
+		*self.now.lock().unwrap()
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct IdGenerator {
+	clock: Arc<dyn Clock>,
+	/// None uses the os random generator
+	rng: Option<Arc<Mutex<StdRng>>>,
+}
+
+impl IdGenerator {
+	/// Uses the system time and the os random generator, like [`Id::new`]
+	pub fn system() -> Self {
+		Self {
+			clock: Arc::new(SystemClock),
+			rng: None,
+		}
+	}
+
+	/// Generates the same ids every time if the clock returns the same
+	/// times
+	pub fn seeded(seed: u64, clock: impl Clock + 'static) -> Self {
+		Self {
+			clock: Arc::new(clock),
+			rng: Some(Arc::new(Mutex::new(StdRng::seed_from_u64(seed)))),
+		}
+	}
+
+	pub fn now(&self) -> SystemTime {
+		self.clock.now()
+	}
+
+	pub fn new_id(&self, kind: Kind) -> Id {
+		match &self.rng {
+			Some(rng) => {
+				Id::with_rng(self.now(), &mut *rng.lock().unwrap(), kind)
+			}
+			None => Id::with_rng(self.now(), &mut rand::rngs::OsRng, kind),
+		}
+	}
+}
+
+impl Default for IdGenerator {
+	fn default() -> Self {
+		Self::system()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::UNIX_EPOCH;
+
+	use super::*;
+
+	#[test]
+	fn seeded() {
+		let kind = Kind::new(false, 1);
+		let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+		let a = IdGenerator::seeded(1, ManualClock::new(start));
+		let b = IdGenerator::seeded(1, ManualClock::new(start));
+		let ids = (0..3).map(|_| a.new_id(kind)).collect::<Vec<_>>();
+		assert_eq!(ids, (0..3).map(|_| b.new_id(kind)).collect::<Vec<_>>());
+		assert_ne!(ids[0], ids[1]);
+
+		let clock = ManualClock::new(start);
+		let c = IdGenerator::seeded(2, clock.clone());
+		assert_ne!(c.new_id(kind), ids[0]);
+
+		clock.advance(Duration::from_secs(10));
+		assert_eq!(c.now(), start + Duration::from_secs(10));
+		assert!(c.new_id(kind) > ids[2]);
+	}
+}
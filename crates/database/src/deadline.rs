@@ -0,0 +1,144 @@
+//! Deadlines
+//!
+//! A deadline limits how long a [`Database`](crate::Database) can be used,
+//! usually it is set to the time a request is allowed to take.
+//!
+//! Every statement executed with
+//! [`Connection::traced`](crate::Connection::traced) fails with
+//! [`DeadlineExceeded`] once the deadline passed, memory operations are not
+//! started anymore. Postgres additionally get's the remaining time as
+//! `statement_timeout` before every statement, so statements which are
+//! still running after the request was dropped get cancelled by the server.
+
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::time::{self, Instant};
+
+/// Resets the statement timeout, executed when the pool recycles a
+/// connection
+pub(crate) const RESET_SQL: &str = "RESET statement_timeout";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+	pub fn at(instant: Instant) -> Self {
+		Self(instant)
+	}
+
+	pub fn after(timeout: Duration) -> Self {
+		Self(Instant::now() + timeout)
+	}
+
+	pub fn instant(&self) -> Instant {
+		self.0
+	}
+
+	/// Returns zero if the deadline is exceeded
+	pub fn remaining(&self) -> Duration {
+		self.0.saturating_duration_since(Instant::now())
+	}
+
+	pub fn is_exceeded(&self) -> bool {
+		self.remaining().is_zero()
+	}
+
+	/// Runs the future until it completes or the deadline is exceeded
+	///
+	/// The future is not polled if the deadline is already exceeded.
+	pub async fn run<F: Future>(
+		self,
+		fut: F,
+	) -> Result<F::Output, DeadlineExceeded> {
+		if self.is_exceeded() {
+			return Err(DeadlineExceeded);
+		}
+
+		time::timeout_at(self.0, fut)
+			.await
+			.map_err(|_| DeadlineExceeded)
+	}
+
+	/// The value for the postgres `statement_timeout` setting in
+	/// milliseconds, at least one since zero disables the timeout
+	pub(crate) fn statement_timeout(&self) -> String {
+		self.remaining().as_millis().max(1).to_string()
+	}
+}
+
+/// Sets the postgres `statement_timeout` to the time remaining until the
+/// deadline, without a deadline the timeout is disabled
+pub(crate) async fn set_statement_timeout(
+	conn: postgres::Connection<'_>,
+	deadline: Option<Deadline>,
+) -> Result<(), crate::Error> {
+	let timeout = match deadline {
+		Some(d) => d.statement_timeout(),
+		// disables the timeout
+		None => "0".into(),
+	};
+
+	conn.execute(
+		"SELECT set_config('statement_timeout', $1, false)",
+		&[&timeout],
+	)
+	.await?;
+
+	Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("the deadline was exceeded")]
+pub struct DeadlineExceeded;
+
+impl From<DeadlineExceeded> for crate::Error {
+	fn from(e: DeadlineExceeded) -> Self {
+		Self::Unknown(Box::new(e))
+	}
+}
+
+/// Returns true if the error was caused by an exceeded deadline, either
+/// locally or by the postgres `statement_timeout`
+pub fn is_exceeded(e: &crate::Error) -> bool {
+	match e {
+		crate::Error::Unknown(e) => e.is::<DeadlineExceeded>(),
+		crate::Error::Other(e) => {
+			e.code() == Some(&tokio_postgres::error::SqlState::QUERY_CANCELED)
+		}
+		_ => false,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn run() {
+		let deadline = Deadline::after(Duration::from_millis(20));
+		assert_eq!(deadline.run(async { 1 }).await, Ok(1));
+
+		let res = deadline.run(time::sleep(Duration::from_secs(10))).await;
+		assert_eq!(res, Err(DeadlineExceeded));
+		assert!(deadline.is_exceeded());
+
+		// not polled anymore
+		assert_eq!(deadline.run(async { 1 }).await, Err(DeadlineExceeded));
+		assert!(is_exceeded(&DeadlineExceeded.into()));
+	}
+
+	#[tokio::test]
+	async fn request_deadline() {
+		let pool = crate::DatabasePool::new_memory()
+			.with_request_timeout(Duration::from_secs(1));
+
+		// only requests get a deadline
+		assert!(pool.get().await.unwrap().deadline().is_none());
+		let db = pool
+			.get_with_deadline(pool.request_deadline())
+			.await
+			.unwrap();
+		assert!(db.deadline().is_some());
+	}
+}
@@ -0,0 +1,333 @@
+//! Dump and restore
+//!
+//! Exports the rows of all registered tables into a versioned archive and
+//! restores them into any backend, the [`Id`](crate::id::Id)s of the rows
+//! are preserved.
+//!
+//! An archive can be stored as a single json document or as ndjson, where
+//! the first line contains the [`Header`] and every following line a
+//! single row. Rows are restored in the order the tables where registered
+//! to [`Dump`], so tables referencing others should be registered last.
+//!
+//! A dump only contains the rows of the tenant of the database, restoring
+//! places them into the tenant of the target database.
+
+use std::collections::BTreeMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+	tenant::Tenant, Connection, ConnectionInner, Database, DatabaseInner,
+};
+
+/// The version of the archive format
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DumpError {
+	#[error("the archive version {0} is not supported")]
+	UnsupportedVersion(u32),
+
+	#[error("the archive is empty")]
+	MissingHeader,
+
+	#[error("the migration {0} was not executed on the target database")]
+	MissingMigration(String),
+
+	#[error("the table {0} is not registered")]
+	UnknownTable(String),
+
+	#[error("the table {table} failed {error}")]
+	Table {
+		table: String,
+		#[source]
+		error: Box<dyn StdError + Send + Sync>,
+	},
+
+	#[error("a json error occured {0}")]
+	Json(#[from] serde_json::Error),
+
+	#[error("an io error occured {0}")]
+	Io(#[from] io::Error),
+
+	#[error("a postgres error occured {0}")]
+	Postgres(#[from] crate::Error),
+}
+
+impl DumpError {
+	pub fn table(
+		table: impl Into<String>,
+		error: impl StdError + Send + Sync + 'static,
+	) -> Self {
+		Self::Table {
+			table: table.into(),
+			error: Box::new(error),
+		}
+	}
+}
+
+/// A table which can be dumped and restored
+#[async_trait::async_trait]
+pub trait Dumpable: fmt::Debug + Send + Sync {
+	/// The name of the table in the archive
+	///
+	/// Cached queries of the table with the same name get invalidated after
+	/// a restore.
+	fn name(&self) -> &str;
+
+	/// Returns all rows visible to the connection
+	async fn dump(&self, conn: Connection<'_>)
+		-> Result<Vec<Value>, DumpError>;
+
+	/// Inserts the rows, should fail if a row already exists
+	async fn restore(
+		&self,
+		conn: Connection<'_>,
+		rows: Vec<Value>,
+	) -> Result<(), DumpError>;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Header {
+	pub version: u32,
+	/// The tenant the rows where dumped from
+	pub tenant: Option<Tenant>,
+	/// The migrations which where executed on the dumped database
+	pub migrations: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Archive {
+	pub header: Header,
+	/// The rows of every table
+	pub tables: BTreeMap<String, Vec<Value>>,
+}
+
+/// A line of an ndjson archive after the header
+#[derive(Debug, Serialize, Deserialize)]
+struct Line {
+	table: String,
+	row: Value,
+}
+
+impl Archive {
+	pub fn to_json(&self) -> Result<String, DumpError> {
+		serde_json::to_string(self).map_err(Into::into)
+	}
+
+	pub fn from_json(s: &str) -> Result<Self, DumpError> {
+		let archive: Self = serde_json::from_str(s)?;
+		check_version(&archive.header)?;
+
+		Ok(archive)
+	}
+
+	pub fn write_ndjson<W: Write>(&self, mut w: W) -> Result<(), DumpError> {
+		serde_json::to_writer(&mut w, &self.header)?;
+		w.write_all(b"\n")?;
+
+		for (table, rows) in &self.tables {
+			for row in rows {
+				serde_json::to_writer(
+					&mut w,
+					&Line {
+						table: table.clone(),
+						row: row.clone(),
+					},
+				)?;
+				w.write_all(b"\n")?;
+			}
+		}
+
+		w.flush().map_err(Into::into)
+	}
+
+	pub fn read_ndjson<R: BufRead>(r: R) -> Result<Self, DumpError> {
+		let mut lines = r.lines().filter(|l| match l {
+			Ok(l) => !l.trim().is_empty(),
+			Err(_) => true,
+		});
+
+		let header: Header = match lines.next() {
+			Some(line) => serde_json::from_str(&line?)?,
+			None => return Err(DumpError::MissingHeader),
+		};
+		check_version(&header)?;
+
+		let mut tables: BTreeMap<_, Vec<_>> = BTreeMap::new();
+		for line in lines {
+			let line: Line = serde_json::from_str(&line?)?;
+			tables.entry(line.table).or_default().push(line.row);
+		}
+
+		Ok(Self { header, tables })
+	}
+}
+
+fn check_version(header: &Header) -> Result<(), DumpError> {
+	if header.version > VERSION {
+		return Err(DumpError::UnsupportedVersion(header.version));
+	}
+
+	Ok(())
+}
+
+/// Holds all tables which are part of a dump
+#[derive(Debug, Default)]
+pub struct Dump {
+	tables: Vec<Box<dyn Dumpable>>,
+}
+
+impl Dump {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn register(&mut self, table: impl Dumpable + 'static) {
+		self.tables.push(Box::new(table));
+	}
+
+	/// Exports all registered tables
+	pub async fn export(&self, db: &Database) -> Result<Archive, DumpError> {
+		let conn = db.connection();
+
+		let mut tables = BTreeMap::new();
+		for table in &self.tables {
+			let rows = table.dump(conn).await?;
+			tables.insert(table.name().to_string(), rows);
+		}
+
+		Ok(Archive {
+			header: Header {
+				version: VERSION,
+				tenant: db.tenant(),
+				migrations: db.executed_migrations().await?,
+			},
+			tables,
+		})
+	}
+
+	/// Restores the archive
+	///
+	/// Every migration of the archive needs to be executed on the target
+	/// before restoring. With postgres the restore runs in a transaction,
+	/// with the memory database tables restored before an error are kept.
+	pub async fn restore(
+		&self,
+		db: &mut Database,
+		mut archive: Archive,
+	) -> Result<(), DumpError> {
+		if let Some(table) = archive.tables.keys().find(|t| !self.contains(t)) {
+			return Err(DumpError::UnknownTable(table.clone()));
+		}
+
+		if let DatabaseInner::Postgres { .. } = &db.inner {
+			let executed = db.executed_migrations().await?;
+			let missing = archive
+				.header
+				.migrations
+				.iter()
+				.find(|m| !executed.contains(m));
+			if let Some(missing) = missing {
+				return Err(DumpError::MissingMigration(missing.clone()));
+			}
+		}
+
+		let tenant = db.tenant;
+		let cache = db.cache.clone();
+		let metrics = db.metrics.clone();
+		let ids = db.ids.clone();
+
+		if let DatabaseInner::Postgres { conn, .. } = &mut db.inner {
+			let trans = conn.transaction().await?;
+			let conn = Connection {
+				inner: ConnectionInner::Postgres(trans.connection()),
+				tenant,
+				deadline: db.deadline,
+				cache: cache.as_ref(),
+				trace: db.trace,
+				metrics: &metrics,
+				ids: &ids,
+				// restoring is not an access by the actor
+				audit: None,
+				actor: db.actor,
+				transaction: true,
+			};
+
+			self.restore_tables(conn, &mut archive).await?;
+			trans.commit().await?;
+		} else {
+			self.restore_tables(db.connection(), &mut archive).await?;
+		}
+
+		if let Some(cache) = &cache {
+			for table in &self.tables {
+				cache.invalidate(table.name(), tenant);
+			}
+		}
+
+		Ok(())
+	}
+
+	async fn restore_tables(
+		&self,
+		conn: Connection<'_>,
+		archive: &mut Archive,
+	) -> Result<(), DumpError> {
+		for table in &self.tables {
+			let Some(rows) = archive.tables.remove(table.name()) else {
+				continue;
+			};
+
+			table.restore(conn, rows).await?;
+		}
+
+		Ok(())
+	}
+
+	fn contains(&self, name: &str) -> bool {
+		self.tables.iter().any(|t| t.name() == name)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn ndjson() {
+		let archive = Archive {
+			header: Header {
+				version: VERSION,
+				tenant: None,
+				migrations: vec!["users-00-create".into()],
+			},
+			tables: BTreeMap::from([(
+				"users".to_string(),
+				vec![
+					serde_json::json!({ "email": "a@rust.com" }),
+					serde_json::json!({ "email": "b@rust.com" }),
+				],
+			)]),
+		};
+
+		let mut buf = vec![];
+		archive.write_ndjson(&mut buf).unwrap();
+		assert_eq!(String::from_utf8_lossy(&buf).lines().count(), 3);
+
+		let n_archive = Archive::read_ndjson(buf.as_slice()).unwrap();
+		assert_eq!(n_archive, archive);
+
+		let mut archive = archive;
+		archive.header.version = VERSION + 1;
+		let json = archive.to_json().unwrap();
+		assert!(matches!(
+			Archive::from_json(&json),
+			Err(DumpError::UnsupportedVersion(_))
+		));
+	}
+}
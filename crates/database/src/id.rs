@@ -69,7 +69,17 @@ pub struct Id([u8; 12]);
 impl Id {
 	/// Create a new Id based on the kind
 	pub fn new(kind: Kind) -> Self {
-		let secs_bytes = SystemTime::now()
+		Self::with_rng(SystemTime::now(), &mut OsRng, kind)
+	}
+
+	/// Create a new Id from the time and random bytes of the rng, see
+	/// [`IdGenerator`](crate::clock::IdGenerator)
+	pub fn with_rng<R: RngCore>(
+		time: SystemTime,
+		rng: &mut R,
+		kind: Kind,
+	) -> Self {
+		let secs_bytes = time
 			.duration_since(UNIX_EPOCH)
 			.expect("SystemTime before UNIX EPOCH!")
 			.as_secs()
@@ -78,7 +88,7 @@ impl Id {
 		let mut bytes = [0u8; 12];
 		bytes[..5].copy_from_slice(&secs_bytes[3..8]);
 
-		OsRng.fill_bytes(&mut bytes[5..11]);
+		rng.fill_bytes(&mut bytes[5..11]);
 
 		bytes[10] = kind.0[0];
 		bytes[11] = kind.0[1];
@@ -219,11 +229,11 @@ mod tests {
 	#[test]
 	fn kind() {
 		let kind = Kind::new(true, 0x7FFF);
-		assert_eq!(kind.is_component(), true);
+		assert!(kind.is_component());
 		assert_eq!(kind.kind(), 0x7FFF);
 
 		let kind = Kind::new(false, 0x7FFF);
-		assert_eq!(kind.is_component(), false);
+		assert!(!kind.is_component());
 		assert_eq!(kind.kind(), 0x7FFF);
 	}
 
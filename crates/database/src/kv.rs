@@ -0,0 +1,940 @@
+//! Key-value storage
+//!
+//! An embedded alternative to postgres built on [redb], see
+//! [`DatabasePool::new_kv`](crate::DatabasePool::new_kv). Rows are stored as
+//! json under the 12 bytes of their [`Id`], so tables are ordered by id.
+//! Every tenant get's it's own redb table.
+//!
+//! Tables can have secondary [`Index`]es, which are stored in their own
+//! redb tables and updated in the same transaction as the values.
+//!
+//! redb is synchronous, transactions run on the blocking pool of tokio.
+
+use std::{
+	collections::HashSet, fmt, hash::Hash, panic, path::Path, sync::Arc,
+};
+
+use redb::{
+	backends::InMemoryBackend, MultimapTableDefinition, MultimapTableHandle,
+	ReadableMultimapTable, ReadableTable, TableDefinition,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::task;
+
+use crate::{
+	batch::{BatchError, BatchMode, BatchResult, RowError},
+	id::Id,
+	query::{self, Filter, Operator, Page, Query, QueryError, Record, Value},
+	tenant::Tenant,
+};
+
+type Definition<'a> = TableDefinition<'a, &'static [u8], &'static [u8]>;
+type IndexDefinition<'a> =
+	MultimapTableDefinition<'a, &'static [u8], &'static [u8]>;
+type IndexTable<'t> = redb::MultimapTable<'t, &'static [u8], &'static [u8]>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+	#[error("the row {0} already exists")]
+	AlreadyExists(Id),
+
+	#[error("the batch failed {0}")]
+	Batch(#[from] BatchError),
+
+	#[error("the index {0} does not exist")]
+	UnknownIndex(String),
+
+	#[error("the query failed {0}")]
+	Query(#[from] QueryError),
+
+	#[error("the row could not be serialized {0}")]
+	Json(#[from] serde_json::Error),
+
+	/// Boxed since redb errors are large
+	#[error("a redb error occured {0}")]
+	Storage(Box<redb::Error>),
+}
+
+macro_rules! storage_error {
+	($($ty:ident),*) => {
+		$(
+			impl From<redb::$ty> for Error {
+				fn from(e: redb::$ty) -> Self {
+					Self::Storage(Box::new(e.into()))
+				}
+			}
+		)*
+	};
+}
+
+storage_error!(
+	Error,
+	DatabaseError,
+	TransactionError,
+	TableError,
+	StorageError,
+	CommitError
+);
+
+/// The redb database, shared by all databases of a pool
+#[derive(Clone)]
+pub struct Store {
+	inner: Arc<redb::Database>,
+}
+
+impl Store {
+	/// Opens the file or creates it if it does not exist
+	pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+		Ok(Self {
+			inner: Arc::new(redb::Database::create(path)?),
+		})
+	}
+
+	/// Creates a store which is not persisted, useful for tests
+	pub fn in_memory() -> Result<Self, Error> {
+		let db = redb::Database::builder()
+			.create_with_backend(InMemoryBackend::new())?;
+
+		Ok(Self {
+			inner: Arc::new(db),
+		})
+	}
+}
+
+impl fmt::Debug for Store {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Store").finish_non_exhaustive()
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Connection<'a> {
+	store: &'a Store,
+	tenant: Option<Tenant>,
+}
+
+impl<'a> Connection<'a> {
+	pub(crate) fn new(store: &'a Store, tenant: Option<Tenant>) -> Self {
+		Self { store, tenant }
+	}
+
+	pub(crate) fn store(&self) -> &'a Store {
+		self.store
+	}
+}
+
+/// A secondary index of a table, stores the ids of the values under the
+/// key returned by `key`
+///
+/// Every index is stored in it's own redb table and kept up to date with
+/// every write, see [`TableWithConn::find_by`].
+pub struct Index<V> {
+	name: &'static str,
+	key: fn(&V) -> Value,
+}
+
+impl<V> Index<V> {
+	pub const fn new(name: &'static str, key: fn(&V) -> Value) -> Self {
+		Self { name, key }
+	}
+
+	pub fn name(&self) -> &'static str {
+		self.name
+	}
+
+	fn key(&self, value: &V) -> Result<Vec<u8>, Error> {
+		serde_json::to_vec(&(self.key)(value)).map_err(Into::into)
+	}
+}
+
+impl<V> Clone for Index<V> {
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+
+impl<V> Copy for Index<V> {}
+
+impl<V> fmt::Debug for Index<V> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Index").field("name", &self.name).finish()
+	}
+}
+
+/// A table storing values of the type `V`
+#[derive(Debug)]
+pub struct Table<V: 'static> {
+	name: &'static str,
+	indexes: &'static [Index<V>],
+}
+
+impl<V> Table<V> {
+	pub const fn new(name: &'static str) -> Self {
+		Self::with_indexes(name, &[])
+	}
+
+	pub const fn with_indexes(
+		name: &'static str,
+		indexes: &'static [Index<V>],
+	) -> Self {
+		Self { name, indexes }
+	}
+
+	pub fn name(&self) -> &'static str {
+		self.name
+	}
+
+	pub fn with_conn<'a>(&self, conn: Connection<'a>) -> TableWithConn<'a, V> {
+		let name = match conn.tenant {
+			Some(tenant) => format!("{}@{tenant}", self.name),
+			None => self.name.to_string(),
+		};
+
+		TableWithConn {
+			store: conn.store,
+			name,
+			indexes: self.indexes,
+		}
+	}
+}
+
+impl<V> Clone for Table<V> {
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+
+impl<V> Copy for Table<V> {}
+
+#[derive(Debug)]
+pub struct TableWithConn<'a, V: 'static> {
+	store: &'a Store,
+	/// The name of the redb table, includes the tenant
+	name: String,
+	indexes: &'static [Index<V>],
+}
+
+impl<V> TableWithConn<'_, V>
+where
+	V: Record + Clone + Serialize + DeserializeOwned + Send + 'static,
+{
+	pub async fn get(&self, id: &Id) -> Result<Option<V>, Error> {
+		let id = *id;
+		self.blocking(move |t| t.get(&id)).await
+	}
+
+	/// Returns all values ordered by their id
+	pub async fn values(&self) -> Result<Vec<V>, Error> {
+		self.blocking(|t| t.values()).await
+	}
+
+	pub async fn find<F>(&self, f: F) -> Result<Option<V>, Error>
+	where
+		F: Fn(&V) -> bool,
+	{
+		Ok(self.values().await?.into_iter().find(f))
+	}
+
+	/// Returns all values with the key in the index, ordered by their id
+	///
+	/// An index which does not exist yet is filled with the existing values.
+	pub async fn find_by(
+		&self,
+		index: &str,
+		key: impl Into<Value>,
+	) -> Result<Vec<V>, Error> {
+		let index = self.index(index)?;
+		let key = serde_json::to_vec(&key.into())?;
+
+		self.blocking(move |t| t.find_by(&index, &key)).await
+	}
+
+	/// Returns a page of values matching the query
+	///
+	/// If the filter requires a column to equal a value and an index has the
+	/// name of the column, only the values with that key are loaded.
+	pub async fn query(&self, query: &Query) -> Result<Page<V>, Error> {
+		let indexed = query.filter.as_ref().and_then(|f| self.indexed(f));
+		let values = match indexed {
+			Some((index, key)) => {
+				let key = serde_json::to_vec(key)?;
+				self.blocking(move |t| t.find_by(&index, &key)).await?
+			}
+			None => self.values().await?,
+		};
+
+		query::select_page(&values, query).map_err(Into::into)
+	}
+
+	/// Returns an error if the value already exists
+	pub async fn insert(&self, value: &V) -> Result<(), Error> {
+		let value = value.clone();
+
+		self.blocking(move |t| {
+			t.write(|w| {
+				let id = value.id();
+				if w.get(&id)?.is_some() {
+					return Err(Error::AlreadyExists(id));
+				}
+
+				w.insert(&value)
+			})
+		})
+		.await
+	}
+
+	/// Inserts all rows, rows with an existing id fail
+	pub async fn insert_many<'v, I>(
+		&self,
+		rows: I,
+		mode: BatchMode,
+	) -> Result<BatchResult, Error>
+	where
+		I: IntoIterator<Item = &'v V>,
+	{
+		let rows = rows.into_iter().cloned().collect::<Vec<_>>();
+
+		self.blocking(move |t| {
+			t.write(|w| {
+				let mut result = BatchResult::default();
+				let mut ids = HashSet::new();
+
+				for (i, value) in rows.iter().enumerate() {
+					let id = value.id();
+					if ids.contains(&id) || w.get(&id)?.is_some() {
+						result.push_failure(i, RowError::AlreadyExists);
+					} else {
+						result.applied.push(i);
+						ids.insert(id);
+					}
+				}
+
+				w.insert_applied(&rows, result.check(mode)?)
+			})
+		})
+		.await
+	}
+
+	/// Inserts all rows, rows with an existing id or with a `unique` key
+	/// which another value already has fail
+	///
+	/// The existing values are looked up in the index, so `unique` needs to
+	/// include the key of the index. See
+	/// [`memory::Table::insert_many_by`](crate::memory::Table::insert_many_by).
+	pub async fn insert_many_by<'v, I, F, U>(
+		&self,
+		rows: I,
+		mode: BatchMode,
+		index: &str,
+		unique: F,
+	) -> Result<BatchResult, Error>
+	where
+		I: IntoIterator<Item = &'v V>,
+		F: Fn(&V) -> U + Send + 'static,
+		U: Hash + Eq,
+	{
+		let index = self.index(index)?;
+		let rows = rows.into_iter().cloned().collect::<Vec<_>>();
+
+		self.blocking(move |t| {
+			t.write(|w| {
+				let mut result = BatchResult::default();
+				let mut ids = HashSet::new();
+				let mut taken = HashSet::new();
+
+				for (i, value) in rows.iter().enumerate() {
+					let id = value.id();
+					let unique_key = unique(value);
+					let exists = ids.contains(&id)
+						|| taken.contains(&unique_key)
+						|| w.get(&id)?.is_some()
+						|| w.find_by(&index, &index.key(value)?)?
+							.iter()
+							.any(|v| unique(v) == unique_key);
+
+					if exists {
+						result.push_failure(i, RowError::AlreadyExists);
+					} else {
+						result.applied.push(i);
+						ids.insert(id);
+						taken.insert(unique_key);
+					}
+				}
+
+				w.insert_applied(&rows, result.check(mode)?)
+			})
+		})
+		.await
+	}
+
+	/// Inserts the value or replaces it if the id already exists
+	pub async fn upsert(&self, value: &V) -> Result<(), Error> {
+		let value = value.clone();
+
+		self.blocking(move |t| t.write(|w| w.insert(&value))).await
+	}
+
+	/// Calls `f` with all values and upserts the returned values in the same
+	/// transaction
+	///
+	/// Can be used to update a value which is not identified by it's id.
+	pub async fn upsert_with<F>(&self, f: F) -> Result<Vec<V>, Error>
+	where
+		F: FnOnce(Vec<V>) -> Vec<V> + Send + 'static,
+	{
+		self.blocking(move |t| {
+			t.write(|w| {
+				let values = f(w.values()?);
+
+				for value in &values {
+					w.insert(value)?;
+				}
+
+				Ok(values)
+			})
+		})
+		.await
+	}
+
+	/// Replaces the existing value if `f` returns true for it, the check and
+	/// the write happen in the same transaction
+	///
+	/// Returns false if the value did not exist or `f` returned false.
+	pub async fn update_if<F>(&self, value: &V, f: F) -> Result<bool, Error>
+	where
+		F: FnOnce(&V) -> bool + Send + 'static,
+	{
+		let value = value.clone();
+
+		self.blocking(move |t| {
+			t.write(|w| match w.get(&value.id())? {
+				Some(existing) if f(&existing) => {
+					w.insert(&value)?;
+					Ok(true)
+				}
+				_ => Ok(false),
+			})
+		})
+		.await
+	}
+
+	/// Returns false if the value did not exist
+	pub async fn delete(&self, id: &Id) -> Result<bool, Error> {
+		let id = *id;
+
+		self.blocking(move |t| t.write(|w| w.remove(&id))).await
+	}
+
+	/// Deletes the value if `f` returns true for it, returns the deleted
+	/// value
+	pub async fn delete_if<F>(&self, id: &Id, f: F) -> Result<Option<V>, Error>
+	where
+		F: FnOnce(&V) -> bool + Send + 'static,
+	{
+		let id = *id;
+
+		self.blocking(move |t| {
+			t.write(|w| match w.get(&id)? {
+				Some(existing) if f(&existing) => {
+					w.remove(&id)?;
+					Ok(Some(existing))
+				}
+				_ => Ok(None),
+			})
+		})
+		.await
+	}
+
+	/// Deletes all values where `f` returns true, returns the amount of
+	/// deleted values
+	pub async fn delete_where<F>(&self, f: F) -> Result<usize, Error>
+	where
+		F: Fn(&V) -> bool + Send + 'static,
+	{
+		self.blocking(move |t| {
+			t.write(|w| {
+				let values = w.values()?;
+				let mut deleted = 0;
+
+				for value in values.iter().filter(|v| f(v)) {
+					w.remove(&value.id())?;
+					deleted += 1;
+				}
+
+				Ok(deleted)
+			})
+		})
+		.await
+	}
+
+	fn index(&self, name: &str) -> Result<Index<V>, Error> {
+		self.indexes
+			.iter()
+			.find(|i| i.name == name)
+			.copied()
+			.ok_or_else(|| Error::UnknownIndex(name.to_string()))
+	}
+
+	/// Returns the index and the key if the filter requires the column of an
+	/// index to equal a value
+	fn indexed<'f>(&self, filter: &'f Filter) -> Option<(Index<V>, &'f Value)> {
+		match filter {
+			Filter::Compare {
+				column,
+				op: Operator::Eq,
+				value,
+			} => self.index(column).ok().map(|index| (index, value)),
+			Filter::And(filters) => {
+				filters.iter().find_map(|f| self.indexed(f))
+			}
+			_ => None,
+		}
+	}
+
+	/// Runs `f` on the blocking pool of tokio
+	async fn blocking<T, F>(&self, f: F) -> Result<T, Error>
+	where
+		F: FnOnce(&Blocking<V>) -> Result<T, Error> + Send + 'static,
+		T: Send + 'static,
+	{
+		let table = Blocking {
+			store: self.store.clone(),
+			name: self.name.clone(),
+			indexes: self.indexes,
+		};
+
+		match task::spawn_blocking(move || f(&table)).await {
+			Ok(res) => res,
+			Err(e) => panic::resume_unwind(e.into_panic()),
+		}
+	}
+}
+
+/// The synchronous part of a [`TableWithConn`], which can be moved to
+/// another thread
+struct Blocking<V: 'static> {
+	store: Store,
+	name: String,
+	indexes: &'static [Index<V>],
+}
+
+impl<V> Blocking<V>
+where
+	V: Record + Serialize + DeserializeOwned,
+{
+	fn get(&self, id: &Id) -> Result<Option<V>, Error> {
+		let txn = self.store.inner.begin_read()?;
+		let table = match txn.open_table(self.definition()) {
+			Ok(table) => table,
+			Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+			Err(e) => return Err(e.into()),
+		};
+
+		read_value(&table, id)
+	}
+
+	fn values(&self) -> Result<Vec<V>, Error> {
+		let txn = self.store.inner.begin_read()?;
+		let table = match txn.open_table(self.definition()) {
+			Ok(table) => table,
+			Err(redb::TableError::TableDoesNotExist(_)) => return Ok(vec![]),
+			Err(e) => return Err(e.into()),
+		};
+
+		read_values(&table)
+	}
+
+	fn find_by(&self, index: &Index<V>, key: &[u8]) -> Result<Vec<V>, Error> {
+		let txn = self.store.inner.begin_read()?;
+		let table = match txn.open_table(self.definition()) {
+			Ok(table) => table,
+			Err(redb::TableError::TableDoesNotExist(_)) => return Ok(vec![]),
+			Err(e) => return Err(e.into()),
+		};
+
+		let index_name = self.index_name(index);
+		let ids =
+			match txn.open_multimap_table(IndexDefinition::new(&index_name)) {
+				Ok(ids) => ids,
+				// the index was added after the values where stored, a write
+				// fills it
+				Err(redb::TableError::TableDoesNotExist(_)) => {
+					drop(table);
+					drop(txn);
+					self.write(|_| Ok(()))?;
+					return self.find_by(index, key);
+				}
+				Err(e) => return Err(e.into()),
+			};
+
+		let mut values = vec![];
+		for id in ids.get(key)? {
+			let bytes = id?.value().try_into().expect("ids are 12 bytes");
+			let id = Id::from_bytes(bytes);
+			values.extend(read_value(&table, &id)?);
+		}
+
+		Ok(values)
+	}
+
+	fn definition(&self) -> Definition<'_> {
+		TableDefinition::new(&self.name)
+	}
+
+	fn index_name(&self, index: &Index<V>) -> String {
+		format!("{}#{}", self.name, index.name)
+	}
+
+	/// Executes `f` in a write transaction, which is only committed if `f`
+	/// succeeds
+	///
+	/// Indexes which do not exist yet are filled with the existing values.
+	fn write<T, F>(&self, f: F) -> Result<T, Error>
+	where
+		F: FnOnce(&mut Writer<V>) -> Result<T, Error>,
+	{
+		let txn = self.store.inner.begin_write()?;
+
+		let res = {
+			let existing = txn
+				.list_multimap_tables()?
+				.map(|t| t.name().to_string())
+				.collect::<HashSet<_>>();
+
+			let mut writer = Writer {
+				table: txn.open_table(self.definition())?,
+				indexes: vec![],
+			};
+
+			for index in self.indexes {
+				let name = self.index_name(index);
+				let mut ids =
+					txn.open_multimap_table(IndexDefinition::new(&name))?;
+
+				if !existing.contains(&name) {
+					for value in writer.values()? {
+						ids.insert(
+							index.key(&value)?.as_slice(),
+							value.id().as_slice(),
+						)?;
+					}
+				}
+
+				writer.indexes.push((*index, ids));
+			}
+
+			f(&mut writer)?
+		};
+
+		txn.commit()?;
+
+		Ok(res)
+	}
+}
+
+/// The tables of a write transaction, keeps the indexes up to date
+struct Writer<'t, V> {
+	table: redb::Table<'t, &'static [u8], &'static [u8]>,
+	indexes: Vec<(Index<V>, IndexTable<'t>)>,
+}
+
+impl<V> Writer<'_, V>
+where
+	V: Record + Serialize + DeserializeOwned,
+{
+	fn get(&self, id: &Id) -> Result<Option<V>, Error> {
+		read_value(&self.table, id)
+	}
+
+	fn values(&self) -> Result<Vec<V>, Error> {
+		read_values(&self.table)
+	}
+
+	/// Inserts or replaces the value
+	fn insert(&mut self, value: &V) -> Result<(), Error> {
+		self.remove(&value.id())?;
+
+		let id = value.id();
+		let bytes = serde_json::to_vec(value)?;
+		self.table.insert(id.as_slice(), bytes.as_slice())?;
+
+		for (index, ids) in &mut self.indexes {
+			ids.insert(index.key(value)?.as_slice(), id.as_slice())?;
+		}
+
+		Ok(())
+	}
+
+	/// Returns the values with the key in the index
+	fn find_by(&self, index: &Index<V>, key: &[u8]) -> Result<Vec<V>, Error> {
+		let Some((_, ids)) =
+			self.indexes.iter().find(|(i, _)| i.name == index.name)
+		else {
+			return Err(Error::UnknownIndex(index.name.to_string()));
+		};
+
+		let mut values = vec![];
+		for id in ids.get(key)? {
+			let bytes = id?.value().try_into().expect("ids are 12 bytes");
+			values.extend(self.get(&Id::from_bytes(bytes))?);
+		}
+
+		Ok(values)
+	}
+
+	/// Inserts the applied rows of a batch which was not aborted
+	///
+	/// Dropping the transaction without a commit aborts it.
+	fn insert_applied(
+		&mut self,
+		rows: &[V],
+		result: BatchResult,
+	) -> Result<BatchResult, Error> {
+		for i in &result.applied {
+			self.insert(&rows[*i])?;
+		}
+
+		Ok(result)
+	}
+
+	/// Returns false if the value did not exist
+	fn remove(&mut self, id: &Id) -> Result<bool, Error> {
+		let Some(old) = self.get(id)? else {
+			return Ok(false);
+		};
+
+		for (index, ids) in &mut self.indexes {
+			ids.remove(index.key(&old)?.as_slice(), id.as_slice())?;
+		}
+		self.table.remove(id.as_slice())?;
+
+		Ok(true)
+	}
+}
+
+fn read_value<V, T>(table: &T, id: &Id) -> Result<Option<V>, Error>
+where
+	V: DeserializeOwned,
+	T: ReadableTable<&'static [u8], &'static [u8]>,
+{
+	match table.get(id.as_slice())? {
+		Some(value) => {
+			serde_json::from_slice(value.value()).map_err(Into::into)
+		}
+		None => Ok(None),
+	}
+}
+
+fn read_values<V, T>(table: &T) -> Result<Vec<V>, Error>
+where
+	V: DeserializeOwned,
+	T: ReadableTable<&'static [u8], &'static [u8]>,
+{
+	let mut values = vec![];
+	for entry in table.iter()? {
+		let (_, value) = entry?;
+		values.push(serde_json::from_slice(value.value())?);
+	}
+
+	Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+	use serde::Deserialize;
+
+	use super::*;
+	use crate::{id::Kind, query::Value};
+
+	#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+	struct Row {
+		id: Id,
+		name: String,
+	}
+
+	impl Record for Row {
+		fn id(&self) -> Id {
+			self.id
+		}
+
+		fn value(&self, column: &str) -> Option<Value> {
+			match column {
+				"id" => Some(self.id.into()),
+				"name" => Some(self.name.as_str().into()),
+				_ => None,
+			}
+		}
+	}
+
+	fn row(name: &str) -> Row {
+		Row {
+			id: Id::new(Kind::new(false, 1)),
+			name: name.into(),
+		}
+	}
+
+	#[tokio::test]
+	async fn table() {
+		const BY_NAME: Index<Row> =
+			Index::new("name", |r| r.name.as_str().into());
+		const TABLE: Table<Row> = Table::with_indexes("rows", &[BY_NAME]);
+
+		let store = Store::in_memory().unwrap();
+		let tenant = Tenant::new(Id::new(Kind::new(false, 2)));
+		let table = TABLE.with_conn(Connection::new(&store, None));
+		let scoped = TABLE.with_conn(Connection::new(&store, Some(tenant)));
+
+		let a = row("a");
+		assert_eq!(table.get(&a.id).await.unwrap(), None);
+		table.insert(&a).await.unwrap();
+		assert!(matches!(
+			table.insert(&a).await,
+			Err(Error::AlreadyExists(_))
+		));
+		assert_eq!(table.get(&a.id).await.unwrap(), Some(a.clone()));
+		assert_eq!(scoped.get(&a.id).await.unwrap(), None);
+
+		// the duplicate name aborts the batch
+		let (b, c) = (row("b"), row("a"));
+		let res =
+			table.insert_many_by([&b, &c], BatchMode::Abort, "name", |r| {
+				r.name.clone()
+			});
+		assert!(matches!(
+			res.await,
+			Err(Error::Batch(BatchError::Aborted(_)))
+		));
+		assert_eq!(table.values().await.unwrap().len(), 1);
+
+		let res = table
+			.insert_many_by([&b, &c], BatchMode::Continue, "name", |r| {
+				r.name.clone()
+			})
+			.await
+			.unwrap();
+		assert_eq!(res.applied, [0]);
+
+		let page = table.query(&Query::new().order_desc("name")).await.unwrap();
+		assert_eq!(page.items, [b.clone(), a.clone()]);
+
+		let renamed = table
+			.upsert_with(|values| {
+				let mut a = values.into_iter().find(|r| r.name == "a").unwrap();
+				a.name = "c".into();
+				vec![a]
+			})
+			.await
+			.unwrap();
+		assert_eq!(renamed[0].id, a.id);
+		assert_eq!(table.get(&a.id).await.unwrap().unwrap().name, "c");
+
+		let mut renamed = a.clone();
+		renamed.name = "d".into();
+		assert!(!table.update_if(&renamed, |r| r.name == "a").await.unwrap());
+		assert!(table.update_if(&renamed, |r| r.name == "c").await.unwrap());
+		assert!(!table.update_if(&row("e"), |_| true).await.unwrap());
+		assert_eq!(table.get(&a.id).await.unwrap(), Some(renamed.clone()));
+
+		assert_eq!(
+			table.delete_if(&a.id, |r| r.name == "c").await.unwrap(),
+			None
+		);
+		assert_eq!(
+			table.delete_if(&a.id, |r| r.name == "d").await.unwrap(),
+			Some(renamed)
+		);
+		table.insert(&a).await.unwrap();
+
+		assert!(table.delete(&a.id).await.unwrap());
+		assert!(!table.delete(&a.id).await.unwrap());
+		assert_eq!(table.values().await.unwrap(), std::slice::from_ref(&b));
+
+		table.insert(&a).await.unwrap();
+		assert_eq!(table.delete_where(|r| r.name == "a").await.unwrap(), 1);
+		assert_eq!(table.values().await.unwrap(), [b]);
+	}
+
+	#[tokio::test]
+	async fn index() {
+		const BY_NAME: Index<Row> =
+			Index::new("name", |r| r.name.as_str().into());
+		const TABLE: Table<Row> = Table::with_indexes("rows", &[BY_NAME]);
+		const PLAIN: Table<Row> = Table::new("rows");
+
+		let store = Store::in_memory().unwrap();
+		let conn = Connection::new(&store, None);
+
+		// values stored before the index existed
+		let (a, b) = (row("a"), row("b"));
+		PLAIN.with_conn(conn).insert(&a).await.unwrap();
+		PLAIN.with_conn(conn).insert(&b).await.unwrap();
+
+		let table = TABLE.with_conn(conn);
+		assert_eq!(
+			table.find_by("name", "a").await.unwrap(),
+			std::slice::from_ref(&a)
+		);
+		assert!(matches!(
+			table.find_by("email", "a").await,
+			Err(Error::UnknownIndex(_))
+		));
+
+		let c = row("a");
+		table.insert(&c).await.unwrap();
+		// ordered by id
+		let mut expected = vec![a.clone(), c.clone()];
+		expected.sort_by_key(|r| r.id);
+		assert_eq!(table.find_by("name", "a").await.unwrap(), expected);
+
+		let mut renamed = a.clone();
+		renamed.name = "b".into();
+		table.upsert(&renamed).await.unwrap();
+		assert_eq!(
+			table.find_by("name", "a").await.unwrap(),
+			std::slice::from_ref(&c)
+		);
+		let mut expected = vec![renamed, b.clone()];
+		expected.sort_by_key(|r| r.id);
+		assert_eq!(table.find_by("name", "b").await.unwrap(), expected);
+
+		table.delete(&c.id).await.unwrap();
+		let id = b.id;
+		table.delete_where(move |r| r.id == id).await.unwrap();
+		assert!(table.find_by("name", "a").await.unwrap().is_empty());
+		assert_eq!(table.find_by("name", "b").await.unwrap().len(), 1);
+
+		// a value written without the index is only found by a full scan,
+		// which shows that queries on the column use the index
+		let x = row("x");
+		PLAIN.with_conn(conn).insert(&x).await.unwrap();
+		let query = Query::new().filter(Filter::And(vec![
+			Filter::eq("name", "x"),
+			Filter::eq("id", x.id),
+		]));
+		assert!(table.query(&query).await.unwrap().items.is_empty());
+		let query =
+			Query::new().filter(Filter::compare("name", Operator::Gte, "x"));
+		assert_eq!(table.query(&query).await.unwrap().items, [x]);
+
+		let query = Query::new().filter(Filter::eq("name", "b"));
+		assert_eq!(table.query(&query).await.unwrap().items.len(), 1);
+
+		// only rows sharing the key of the index are compared
+		let (y, z) = (row("b"), row("z"));
+		let res = table
+			.insert_many_by([&y, &z], BatchMode::Continue, "name", |r| {
+				r.name.clone()
+			})
+			.await
+			.unwrap();
+		assert_eq!(res.applied, [1]);
+		assert!(matches!(
+			table
+				.insert_many_by([&y], BatchMode::Abort, "email", |_| ())
+				.await,
+			Err(Error::UnknownIndex(_))
+		));
+		let res = table.insert_many([&z, &y, &y], BatchMode::Continue);
+		assert_eq!(res.await.unwrap().applied, [1]);
+	}
+}
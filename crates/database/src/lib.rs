@@ -4,6 +4,7 @@
 //! helper functions to implement a memory database.
 //!
 //! Maybe the tree should be
+//! ```text
 //! DatabasePool
 //! > Database
 //! > .transaction
@@ -11,20 +12,48 @@
 //! > > .connection
 //! > > > Connection
 //! > .connection
-//! > > Connection (MemoryConnection, PostgresConnection)
+//! > > Connection (MemoryConnection, PostgresConnection, KvConnection)
+//! ```
 
+use std::future::Future;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+use audit::{AuditConfig, AuditError, AuditLog, Operation};
+use cache::{CacheConfig, QueryCache};
+use clock::IdGenerator;
+use deadline::{Deadline, DeadlineExceeded};
+use deadpool_postgres::{ManagerConfig, PoolConfig, RecyclingMethod};
 use fire_http::Resource;
-use postgres::{connection::ConnectionOwned, migrations::Migrations};
+use metrics::{InUse, Metrics};
+use postgres::{
+	connection::{ConnectionOwned, TimeoutType},
+	migrations::Migrations,
+};
+use tenant::Tenant;
+use trace::{RowCount, Statement, TraceConfig};
 
 pub use postgres::connection::Error;
 pub use postgres::database::DatabaseError;
 use serde::Deserialize;
 
+pub mod audit;
+pub mod batch;
+pub mod cache;
+pub mod clock;
+pub mod deadline;
+pub mod dump;
 pub mod id;
+pub mod kv;
 pub mod macros;
 pub mod memory;
-
-// mod types;
+pub mod metrics;
+pub mod query;
+pub mod tenant;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod trace;
+pub mod types;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
@@ -33,18 +62,41 @@ pub struct Config {
 	database: String,
 	host: Option<String>,
 	port: Option<u16>,
+	/// Enables the query cache
+	#[serde(default)]
+	cache: Option<CacheConfig>,
+	/// Statements taking longer are logged as slow queries
+	#[serde(default)]
+	slow_query_ms: Option<u64>,
+	/// The time a request can use a database, see
+	/// [`DatabasePool::request_deadline`]
+	#[serde(default)]
+	request_timeout_ms: Option<u64>,
+	/// The maximum amount of connections in the pool
+	#[serde(default)]
+	max_connections: Option<usize>,
+	/// Enables the audit log
+	#[serde(default)]
+	audit: Option<AuditConfig>,
 }
 
 #[derive(Debug, Clone)]
 enum Inner {
 	Memory,
 	Postgres(postgres::Database),
+	KeyValue(kv::Store),
 }
 
 // Maybe call this DatabasePools?
 #[derive(Debug, Clone, Resource)]
 pub struct DatabasePool {
 	inner: Inner,
+	cache: Option<QueryCache>,
+	trace: TraceConfig,
+	request_timeout: Option<Duration>,
+	metrics: Metrics,
+	ids: IdGenerator,
+	audit: Option<AuditLog>,
 }
 
 impl DatabasePool {
@@ -52,59 +104,263 @@ impl DatabasePool {
 	pub fn new_memory() -> Self {
 		Self {
 			inner: Inner::Memory,
+			cache: None,
+			trace: TraceConfig::default(),
+			request_timeout: None,
+			metrics: Metrics::new(None),
+			ids: IdGenerator::system(),
+			audit: None,
+		}
+	}
+
+	/// Create a new key-value database pool which stores all data in the
+	/// file at `path`
+	pub fn new_kv(path: impl AsRef<Path>) -> Result<Self, kv::Error> {
+		Ok(Self::with_kv_store(kv::Store::open(path)?))
+	}
+
+	/// Create a new key-value database pool which is not persisted
+	pub fn new_kv_memory() -> Result<Self, kv::Error> {
+		Ok(Self::with_kv_store(kv::Store::in_memory()?))
+	}
+
+	fn with_kv_store(store: kv::Store) -> Self {
+		Self {
+			inner: Inner::KeyValue(store),
+			cache: None,
+			trace: TraceConfig::default(),
+			request_timeout: None,
+			metrics: Metrics::new(None),
+			ids: IdGenerator::system(),
+			audit: None,
 		}
 	}
 
 	/// Create a new postgres database pool
 	pub async fn new_postgres(cfg: Config) -> Result<Self, DatabaseError> {
+		let pool = cfg.max_connections.map(PoolConfig::new).unwrap_or_default();
+		let max_size = pool.max_size;
+
 		let config = postgres::database::Config {
 			user: Some(cfg.user),
 			password: Some(cfg.password),
 			dbname: Some(cfg.database),
 			host: cfg.host,
 			port: cfg.port,
+			// make sure a tenant or a deadline never leaks to the next user of
+			// a connection
+			manager: Some(ManagerConfig {
+				// a transaction of a dropped request is rolled back as well
+				recycling_method: RecyclingMethod::Custom(format!(
+					"ROLLBACK; {}; {}",
+					tenant::RESET_SQL,
+					deadline::RESET_SQL
+				)),
+			}),
+			pool: Some(pool),
 			..Default::default()
 		};
 
+		let pg = postgres::Database::with_cfg(config).await?;
+
+		let [bypasses_rls] = pg
+			.get()
+			.await?
+			.connection()
+			.query_one::<[Option<bool>; 1], _>(tenant::BYPASSES_RLS_SQL, &[])
+			.await?;
+		if bypasses_rls.unwrap_or(false) {
+			tracing::warn!(
+				"the database role bypasses row level security, tenants are \
+				only isolated by statements which check the tenant"
+			);
+		}
+
 		Ok(Self {
-			inner: Inner::Postgres(postgres::Database::with_cfg(config).await?),
+			inner: Inner::Postgres(pg),
+			cache: cfg.cache.map(QueryCache::new),
+			trace: TraceConfig {
+				slow_query: cfg.slow_query_ms.map(Duration::from_millis),
+			},
+			request_timeout: cfg.request_timeout_ms.map(Duration::from_millis),
+			metrics: Metrics::new(Some(max_size)),
+			ids: IdGenerator::system(),
+			audit: cfg.audit.map(AuditLog::new),
 		})
 	}
 
-	/// Get a database from the pool
+	/// Enables the query cache for all databases of this pool
+	pub fn with_cache(mut self, config: CacheConfig) -> Self {
+		self.cache = Some(QueryCache::new(config));
+		self
+	}
+
+	/// Logs statements which take longer than the threshold
+	pub fn with_slow_query_threshold(mut self, threshold: Duration) -> Self {
+		self.trace.slow_query = Some(threshold);
+		self
+	}
+
+	/// Requests can use a database until the timeout, see
+	/// [`Self::request_deadline`]
+	pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+		self.request_timeout = Some(timeout);
+		self
+	}
+
+	/// Creates ids and timestamps with the generator instead of the system
+	/// time and the os random generator
+	pub fn with_id_generator(mut self, ids: IdGenerator) -> Self {
+		self.ids = ids;
+		self
+	}
+
+	/// Records the accesses reported with [`Connection::audit`]
+	///
+	/// With postgres the table needs to be created with
+	/// [`audit::migrate`].
+	pub fn with_audit(mut self, config: AuditConfig) -> Self {
+		self.audit = Some(AuditLog::new(config));
+		self
+	}
+
+	/// Get the query cache if it is enabled
+	pub fn cache(&self) -> Option<&QueryCache> {
+		self.cache.as_ref()
+	}
+
+	/// Get the audit log if it is enabled
+	pub fn audit(&self) -> Option<&AuditLog> {
+		self.audit.as_ref()
+	}
+
+	/// Get the metrics of the pool and all statements executed with
+	/// databases from it
+	pub fn metrics(&self) -> &Metrics {
+		&self.metrics
+	}
+
+	/// Returns the deadline of a request starting now, if a request timeout
+	/// is configured
+	///
+	/// Handlers pass it to [`Self::get_with_deadline`].
+	pub fn request_deadline(&self) -> Option<Deadline> {
+		self.request_timeout.map(Deadline::after)
+	}
+
+	/// Get a database from the pool without a deadline
 	pub async fn get(&self) -> Result<Database, DatabaseError> {
-		match &self.inner {
-			Inner::Memory => Ok(Database {
-				inner: DatabaseInner::Memory,
-			}),
-			Inner::Postgres(pg) => Ok(Database {
-				inner: DatabaseInner::Postgres {
-					conn: pg.get().await?,
+		self.get_inner(None).await
+	}
+
+	/// Get a database from the pool which can only be used until the
+	/// deadline, waiting for a connection counts towards the deadline
+	pub async fn get_with_deadline(
+		&self,
+		deadline: impl Into<Option<Deadline>>,
+	) -> Result<Database, DatabaseError> {
+		self.get_inner(deadline.into()).await
+	}
+
+	async fn get_inner(
+		&self,
+		deadline: Option<Deadline>,
+	) -> Result<Database, DatabaseError> {
+		let inner = match &self.inner {
+			Inner::Memory => {
+				self.metrics.record_get(Duration::ZERO, true, false);
+
+				DatabaseInner::Memory
+			}
+			Inner::KeyValue(store) => {
+				self.metrics.record_get(Duration::ZERO, true, false);
+
+				DatabaseInner::KeyValue(store.clone())
+			}
+			Inner::Postgres(pg) => {
+				let start = Instant::now();
+				let res = match deadline {
+					Some(deadline) => deadline.run(pg.get()).await.unwrap_or(
+						Err(DatabaseError::Timeout(TimeoutType::Wait)),
+					),
+					None => pg.get().await,
+				};
+
+				// a connection which was never recycled was just opened
+				let fresh = res
+					.as_ref()
+					.is_ok_and(|conn| conn.metrics().recycle_count == 0);
+				self.metrics.record_get(start.elapsed(), res.is_ok(), fresh);
+
+				DatabaseInner::Postgres {
+					conn: Box::new(res?),
 					migrations: pg.migrations(),
-				},
-			}),
+					_in_use: self.metrics.in_use(),
+				}
+			}
+		};
+
+		let mut db = Database {
+			inner,
+			tenant: None,
+			deadline: None,
+			cache: self.cache.clone(),
+			trace: self.trace,
+			metrics: self.metrics.clone(),
+			ids: self.ids.clone(),
+			audit: self.audit.clone(),
+			actor: None,
+		};
+
+		if deadline.is_some() {
+			db.set_deadline(deadline).await?;
 		}
+
+		Ok(db)
+	}
+
+	/// Get a database from the pool which is scoped to the tenant
+	pub async fn get_tenant(
+		&self,
+		tenant: Tenant,
+	) -> Result<Database, DatabaseError> {
+		let mut db = self.get().await?;
+		db.set_tenant(Some(tenant)).await?;
+
+		Ok(db)
 	}
 }
 
 enum DatabaseInner {
 	Memory,
 	Postgres {
-		conn: ConnectionOwned,
+		conn: Box<ConnectionOwned>,
 		migrations: Migrations,
+		/// Counts the connection as in use until the database is dropped
+		_in_use: InUse,
 	},
+	KeyValue(kv::Store),
 }
 
 /// A Database from the pool
 // needs to provide transaction and commit
 pub struct Database {
 	inner: DatabaseInner,
+	tenant: Option<Tenant>,
+	deadline: Option<Deadline>,
+	cache: Option<QueryCache>,
+	trace: TraceConfig,
+	metrics: Metrics,
+	ids: IdGenerator,
+	audit: Option<AuditLog>,
+	actor: Option<id::Id>,
 }
 
 #[derive(Debug, Clone, Copy)]
 pub enum DatabaseKind {
 	Memory,
 	Postgres,
+	KeyValue,
 }
 
 impl Database {
@@ -113,34 +369,153 @@ impl Database {
 		match self.inner {
 			DatabaseInner::Memory => DatabaseKind::Memory,
 			DatabaseInner::Postgres { .. } => DatabaseKind::Postgres,
+			DatabaseInner::KeyValue(_) => DatabaseKind::KeyValue,
+		}
+	}
+
+	/// Get the tenant all connections are scoped to
+	pub fn tenant(&self) -> Option<Tenant> {
+		self.tenant
+	}
+
+	/// Scopes all following connections to the tenant
+	pub async fn set_tenant(
+		&mut self,
+		tenant: Option<Tenant>,
+	) -> Result<(), Error> {
+		if let DatabaseInner::Postgres { conn, .. } = &self.inner {
+			let value = tenant.map(|t| t.to_string()).unwrap_or_default();
+
+			conn.connection()
+				.execute(
+					"SELECT set_config($1, $2, false)",
+					&[&tenant::SETTING, &value],
+				)
+				.await?;
 		}
+
+		self.tenant = tenant;
+
+		Ok(())
+	}
+
+	/// Get the actor which is recorded in the audit log
+	pub fn actor(&self) -> Option<id::Id> {
+		self.actor
+	}
+
+	/// Records the actor for all following accesses reported to the audit
+	/// log
+	pub fn set_actor(&mut self, actor: Option<id::Id>) {
+		self.actor = actor;
+	}
+
+	/// Get the deadline after which all statements fail
+	pub fn deadline(&self) -> Option<Deadline> {
+		self.deadline
+	}
+
+	/// Sets the deadline for all following connections
+	///
+	/// With postgres the remaining time is also used as the
+	/// `statement_timeout`.
+	pub async fn set_deadline(
+		&mut self,
+		deadline: Option<Deadline>,
+	) -> Result<(), Error> {
+		if let DatabaseInner::Postgres { conn, .. } = &self.inner {
+			if deadline.is_some_and(|d| d.is_exceeded()) {
+				return Err(DeadlineExceeded.into());
+			}
+
+			deadline::set_statement_timeout(conn.connection(), deadline)
+				.await?;
+		}
+
+		self.deadline = deadline;
+
+		Ok(())
 	}
 
 	/// Get the migrations
 	pub fn migrations(&self) -> Option<Migrations> {
 		match &self.inner {
-			DatabaseInner::Memory => None,
+			DatabaseInner::Memory | DatabaseInner::KeyValue(_) => None,
 			DatabaseInner::Postgres { migrations, .. } => {
 				Some(migrations.clone())
 			}
 		}
 	}
 
+	/// Returns the names of all executed migrations, in the order they
+	/// where executed
+	///
+	/// The memory and the key-value database have no migrations.
+	pub async fn executed_migrations(&self) -> Result<Vec<String>, Error> {
+		let DatabaseInner::Postgres { conn, .. } = &self.inner else {
+			return Ok(vec![]);
+		};
+
+		let rows = conn
+			.connection()
+			.query::<[String; 1], _>(
+				"SELECT name FROM migrations ORDER BY datetime, name",
+				&[],
+			)
+			.await?;
+
+		Ok(rows.into_iter().map(|[name]| name).collect())
+	}
+
 	/// This will panic if not called when the connection is a postgres
 	pub fn connection_owned(&mut self) -> &mut ConnectionOwned {
 		match &mut self.inner {
 			DatabaseInner::Memory => panic!("memory connection"),
+			DatabaseInner::KeyValue(_) => panic!("key-value connection"),
 			DatabaseInner::Postgres { conn, .. } => conn,
 		}
 	}
 
-	pub fn connection(&self) -> Connection {
+	pub fn connection(&self) -> Connection<'_> {
 		match &self.inner {
 			DatabaseInner::Memory => Connection {
 				inner: ConnectionInner::Memory(memory::Connection::new()),
+				tenant: self.tenant,
+				deadline: self.deadline,
+				cache: self.cache.as_ref(),
+				trace: self.trace,
+				metrics: &self.metrics,
+				ids: &self.ids,
+				audit: self.audit.as_ref(),
+				actor: self.actor,
+				transaction: false,
 			},
 			DatabaseInner::Postgres { conn, .. } => Connection {
 				inner: ConnectionInner::Postgres(conn.connection()),
+				tenant: self.tenant,
+				deadline: self.deadline,
+				cache: self.cache.as_ref(),
+				trace: self.trace,
+				metrics: &self.metrics,
+				ids: &self.ids,
+				audit: self.audit.as_ref(),
+				actor: self.actor,
+				transaction: false,
+			},
+			DatabaseInner::KeyValue(store) => Connection {
+				inner: ConnectionInner::KeyValue(kv::Connection::new(
+					store,
+					self.tenant,
+				)),
+				tenant: self.tenant,
+				deadline: self.deadline,
+				cache: self.cache.as_ref(),
+				trace: self.trace,
+				metrics: &self.metrics,
+				ids: &self.ids,
+				audit: self.audit.as_ref(),
+				actor: self.actor,
+				transaction: false,
 			},
 		}
 	}
@@ -150,9 +525,199 @@ impl Database {
 #[derive(Debug, Clone, Copy)]
 pub struct Connection<'a> {
 	inner: ConnectionInner<'a>,
+	tenant: Option<Tenant>,
+	deadline: Option<Deadline>,
+	cache: Option<&'a QueryCache>,
+	trace: TraceConfig,
+	metrics: &'a Metrics,
+	ids: &'a IdGenerator,
+	audit: Option<&'a AuditLog>,
+	actor: Option<id::Id>,
+	/// True if the statements already run inside of a transaction
+	transaction: bool,
 }
 
 impl<'a> Connection<'a> {
+	/// Get the tenant this connection is scoped to
+	pub fn tenant(&self) -> Option<Tenant> {
+		self.tenant
+	}
+
+	/// Get the deadline after which all statements fail
+	pub fn deadline(&self) -> Option<Deadline> {
+		self.deadline
+	}
+
+	/// Get the query cache if it is enabled
+	pub fn cache(&self) -> Option<&'a QueryCache> {
+		self.cache
+	}
+
+	/// Get the actor which is recorded in the audit log
+	pub fn actor(&self) -> Option<id::Id> {
+		self.actor
+	}
+
+	/// Get the audit log if it is enabled
+	pub fn audit_log(&self) -> Option<&'a AuditLog> {
+		self.audit
+	}
+
+	/// Returns true if the connection is part of a transaction
+	pub fn in_transaction(&self) -> bool {
+		self.transaction
+	}
+
+	/// Creates a new id with the generator of the pool
+	pub fn new_id(&self, kind: id::Kind) -> id::Id {
+		self.ids.new_id(kind)
+	}
+
+	/// Returns the current time of the pools clock
+	pub fn now(&self) -> SystemTime {
+		self.ids.now()
+	}
+
+	/// Returns the cached result of the query or executes it
+	///
+	/// The key needs to identify the query on this table, see
+	/// [`Query::cache_key`](query::Query::cache_key).
+	pub async fn cached<T, E, F, Fut>(
+		&self,
+		table: &str,
+		key: String,
+		load: F,
+	) -> Result<T, E>
+	where
+		T: Clone + Send + Sync + 'static,
+		F: FnOnce() -> Fut,
+		Fut: Future<Output = Result<T, E>>,
+	{
+		match self.cache {
+			Some(cache) => {
+				cache.get_or_load(table, self.tenant, key, load).await
+			}
+			None => load().await,
+		}
+	}
+
+	/// Executes the statement inside a tracing span and fails if the
+	/// deadline is exceeded
+	///
+	/// With postgres the `statement_timeout` is set to the remaining time
+	/// before the statement, so the server cancels it at the deadline.
+	///
+	/// See [`trace`] for what get's recorded.
+	pub async fn traced<T, E, Fut>(
+		&self,
+		stmt: Statement<'_>,
+		fut: Fut,
+	) -> Result<T, E>
+	where
+		T: RowCount,
+		E: std::fmt::Display + From<DeadlineExceeded> + From<Error>,
+		Fut: Future<Output = Result<T, E>>,
+	{
+		let backend = match self.inner {
+			ConnectionInner::Memory(_) => "memory",
+			ConnectionInner::Postgres(_) => "postgres",
+			ConnectionInner::KeyValue(_) => "kv",
+		};
+
+		let fut = async {
+			let Some(deadline) = self.deadline else {
+				return fut.await;
+			};
+
+			deadline
+				.run(async {
+					// the timeout set when the connection was handed out
+					// would give every statement the whole request
+					if let ConnectionInner::Postgres(pg) = self.inner {
+						deadline::set_statement_timeout(pg, Some(deadline))
+							.await?;
+					}
+
+					fut.await
+				})
+				.await?
+		};
+
+		let start = Instant::now();
+		let res = trace::traced(self.trace, backend, stmt, fut).await;
+		self.metrics
+			.record_statement(stmt.table, start.elapsed(), res.is_ok());
+
+		res
+	}
+
+	/// Runs the future returned by `f` in a transaction, or in a savepoint if
+	/// the connection already is part of a transaction
+	///
+	/// `f` get's a connection which is part of the transaction, writes and
+	/// their audit entries are either both applied or none. The memory and
+	/// the key-value database have no transactions spanning many statements,
+	/// with them `f` is just called.
+	pub async fn atomic<T, E, F, Fut>(&self, f: F) -> Result<T, E>
+	where
+		F: FnOnce(Connection<'a>) -> Fut,
+		Fut: Future<Output = Result<T, E>>,
+		E: From<Error>,
+	{
+		let ConnectionInner::Postgres(pg) = self.inner else {
+			return f(*self).await;
+		};
+
+		let (begin, commit, rollback) = match self.transaction {
+			true => (
+				"SAVEPOINT atomic",
+				"RELEASE SAVEPOINT atomic",
+				"ROLLBACK TO SAVEPOINT atomic; RELEASE SAVEPOINT atomic",
+			),
+			false => ("BEGIN", "COMMIT", "ROLLBACK"),
+		};
+
+		pg.batch_execute(begin).await?;
+
+		let conn = Connection {
+			transaction: true,
+			..*self
+		};
+		match f(conn).await {
+			Ok(v) => {
+				pg.batch_execute(commit).await?;
+				Ok(v)
+			}
+			Err(e) => {
+				pg.batch_execute(rollback).await?;
+				Err(e)
+			}
+		}
+	}
+
+	/// Records that the rows of the table where accessed by the actor, does
+	/// nothing if the audit log is not enabled
+	pub async fn audit(
+		&self,
+		operation: Operation,
+		table: &str,
+		rows: &[id::Id],
+	) -> Result<(), AuditError> {
+		match self.audit {
+			Some(log) if !rows.is_empty() => {
+				log.record(self, operation, table, rows).await
+			}
+			_ => Ok(()),
+		}
+	}
+
+	/// Needs to be called after the table was written to
+	pub fn invalidate(&self, table: &str) {
+		if let Some(cache) = self.cache {
+			cache.invalidate(table, self.tenant);
+		}
+	}
+
 	pub fn get<T: FromConnection<'a>>(&self) -> T {
 		T::from_connection(*self)
 	}
@@ -160,21 +725,28 @@ impl<'a> Connection<'a> {
 	pub fn into_memory(self) -> memory::Connection<'a> {
 		match self.inner {
 			ConnectionInner::Memory(mem) => mem,
-			ConnectionInner::Postgres(_) => unreachable!("memory expected"),
+			_ => unreachable!("memory expected"),
 		}
 	}
 
 	pub fn into_postgres(self) -> postgres::Connection<'a> {
 		match self.inner {
-			ConnectionInner::Memory(_) => unreachable!("postgres expected"),
 			ConnectionInner::Postgres(pg) => pg,
+			_ => unreachable!("postgres expected"),
 		}
 	}
 
 	pub fn try_into_postgres(self) -> Option<postgres::Connection<'a>> {
 		match self.inner {
-			ConnectionInner::Memory(_) => None,
 			ConnectionInner::Postgres(pg) => Some(pg),
+			_ => None,
+		}
+	}
+
+	pub fn into_kv(self) -> kv::Connection<'a> {
+		match self.inner {
+			ConnectionInner::KeyValue(kv) => kv,
+			_ => unreachable!("key-value expected"),
 		}
 	}
 }
@@ -183,13 +755,14 @@ impl<'a> Connection<'a> {
 enum ConnectionInner<'a> {
 	Memory(memory::Connection<'a>),
 	Postgres(postgres::Connection<'a>),
+	KeyValue(kv::Connection<'a>),
 }
 
 impl<'a> From<Connection<'a>> for memory::Connection<'a> {
 	fn from(conn: Connection<'a>) -> Self {
 		match conn.inner {
 			ConnectionInner::Memory(mem) => mem,
-			ConnectionInner::Postgres(_) => unreachable!("memory expected"),
+			_ => unreachable!("memory expected"),
 		}
 	}
 }
@@ -202,7 +775,7 @@ impl<'a> FromConnection<'a> for memory::Connection<'a> {
 	fn from_connection(conn: Connection<'a>) -> Self {
 		match conn.inner {
 			ConnectionInner::Memory(mem) => mem,
-			ConnectionInner::Postgres(_) => unreachable!("memory expected"),
+			_ => unreachable!("memory expected"),
 		}
 	}
 }
@@ -212,14 +785,22 @@ impl private::Sealed for memory::Connection<'_> {}
 impl<'a> FromConnection<'a> for postgres::Connection<'a> {
 	fn from_connection(conn: Connection<'a>) -> Self {
 		match conn.inner {
-			ConnectionInner::Memory(_) => unreachable!("postgres expected"),
 			ConnectionInner::Postgres(pg) => pg,
+			_ => unreachable!("postgres expected"),
 		}
 	}
 }
 
 impl private::Sealed for postgres::Connection<'_> {}
 
+impl<'a> FromConnection<'a> for kv::Connection<'a> {
+	fn from_connection(conn: Connection<'a>) -> Self {
+		conn.into_kv()
+	}
+}
+
+impl private::Sealed for kv::Connection<'_> {}
+
 mod private {
 	pub trait Sealed {}
 }
@@ -1,10 +1,17 @@
 use std::{
-	collections::BTreeMap,
+	collections::{BTreeMap, HashSet},
 	hash::Hash,
 	marker::PhantomData,
 	sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
 };
 
+use crate::{
+	batch::{BatchError, BatchMode, BatchResult, RowError},
+	id::Id,
+	query::{self, Page, Query, QueryError, Record},
+	tenant::Tenant,
+};
+
 #[derive(Debug, Clone, Copy)]
 pub struct Connection<'a> {
 	inner: PhantomData<&'a ()>,
@@ -35,6 +42,10 @@ where
 		self.inner.get(key)
 	}
 
+	pub fn values(&self) -> impl Iterator<Item = &V> {
+		self.inner.values()
+	}
+
 	pub fn find<F>(&self, f: F) -> Option<&V>
 	where
 		F: Fn(&V) -> bool,
@@ -66,6 +77,139 @@ where
 
 		Ok(())
 	}
+
+	/// Inserts all rows, rows with an existing key fail
+	pub fn insert_many<I>(
+		&mut self,
+		rows: I,
+		mode: BatchMode,
+	) -> Result<BatchResult, BatchError>
+	where
+		I: IntoIterator<Item = (K, V)>,
+	{
+		let mut result = BatchResult::default();
+		let mut accepted = BTreeMap::new();
+
+		for (i, (key, value)) in rows.into_iter().enumerate() {
+			if self.inner.contains_key(&key) || accepted.contains_key(&key) {
+				result.push_failure(i, RowError::AlreadyExists);
+			} else {
+				result.applied.push(i);
+				accepted.insert(key, value);
+			}
+		}
+
+		// only modify the table if the batch is not aborted
+		let result = result.check(mode)?;
+		self.inner.extend(accepted);
+
+		Ok(result)
+	}
+
+	/// Inserts all rows, rows with an existing key or with a `unique` key
+	/// which another value already has fail
+	///
+	/// Can be used to enforce unique columns.
+	pub fn insert_many_by<I, F, U>(
+		&mut self,
+		rows: I,
+		mode: BatchMode,
+		unique: F,
+	) -> Result<BatchResult, BatchError>
+	where
+		I: IntoIterator<Item = (K, V)>,
+		F: Fn(&V) -> U,
+		U: Hash + Eq,
+	{
+		let mut result = BatchResult::default();
+		let mut accepted = BTreeMap::new();
+		let mut taken =
+			self.inner.values().map(&unique).collect::<HashSet<_>>();
+
+		for (i, (key, value)) in rows.into_iter().enumerate() {
+			let unique_key = unique(&value);
+			let exists = self.inner.contains_key(&key)
+				|| accepted.contains_key(&key)
+				|| taken.contains(&unique_key);
+
+			if exists {
+				result.push_failure(i, RowError::AlreadyExists);
+			} else {
+				result.applied.push(i);
+				taken.insert(unique_key);
+				accepted.insert(key, value);
+			}
+		}
+
+		// only modify the table if the batch is not aborted
+		let result = result.check(mode)?;
+		self.inner.extend(accepted);
+
+		Ok(result)
+	}
+
+	/// Inserts all rows or replaces them if the key already exists
+	pub fn upsert_many<I>(&mut self, rows: I) -> BatchResult
+	where
+		I: IntoIterator<Item = (K, V)>,
+	{
+		let mut result = BatchResult::default();
+
+		for (i, (key, value)) in rows.into_iter().enumerate() {
+			self.inner.insert(key, value);
+			result.applied.push(i);
+		}
+
+		result
+	}
+
+	/// Deletes the rows with the given keys, keys which don't exist fail
+	pub fn delete_many<I>(
+		&mut self,
+		keys: I,
+		mode: BatchMode,
+	) -> Result<BatchResult, BatchError>
+	where
+		I: IntoIterator<Item = K>,
+	{
+		let mut result = BatchResult::default();
+		let mut accepted: Vec<K> = vec![];
+
+		for (i, key) in keys.into_iter().enumerate() {
+			if self.inner.contains_key(&key) && !accepted.contains(&key) {
+				result.applied.push(i);
+				accepted.push(key);
+			} else {
+				result.push_failure(i, RowError::NotFound);
+			}
+		}
+
+		let result = result.check(mode)?;
+		for key in accepted {
+			self.inner.remove(&key);
+		}
+
+		Ok(result)
+	}
+}
+
+impl<V> Table<Id, V>
+where
+	V: Record + Clone,
+{
+	/// Returns a page of values matching the query
+	pub fn query(&self, query: &Query) -> Result<Page<V>, QueryError> {
+		query::select_page(self.inner.values(), query)
+	}
+}
+
+impl<K, V> Default for Table<K, V>
+where
+	K: Ord + Eq + Hash,
+{
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 #[derive(Debug)]
@@ -99,3 +243,156 @@ impl<T> Clone for ReadWrite<T> {
 		}
 	}
 }
+
+/// Holds a separate value for every tenant
+#[derive(Debug)]
+pub struct Partitioned<T> {
+	inner: ReadWrite<BTreeMap<Option<Tenant>, ReadWrite<T>>>,
+}
+
+impl<T> Partitioned<T>
+where
+	T: Default,
+{
+	pub fn new() -> Self {
+		Self {
+			inner: ReadWrite::new(BTreeMap::new()),
+		}
+	}
+
+	/// Returns the partition of the tenant, creates it if it does not
+	/// exist
+	pub fn get(&self, tenant: Option<Tenant>) -> ReadWrite<T> {
+		if let Some(part) = self.inner.read().get(&tenant) {
+			return part.clone();
+		}
+
+		self.inner
+			.write()
+			.entry(tenant)
+			.or_insert_with(|| ReadWrite::new(T::default()))
+			.clone()
+	}
+}
+
+impl<T> Default for Partitioned<T>
+where
+	T: Default,
+{
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<T> Clone for Partitioned<T> {
+	fn clone(&self) -> Self {
+		Self {
+			inner: self.inner.clone(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		id::Kind,
+		query::{Cursor, Value},
+	};
+
+	#[derive(Debug, Clone)]
+	struct Row {
+		id: Id,
+		name: String,
+	}
+
+	impl Record for Row {
+		fn id(&self) -> Id {
+			self.id
+		}
+
+		fn value(&self, column: &str) -> Option<Value> {
+			match column {
+				"name" => Some(self.name.as_str().into()),
+				_ => None,
+			}
+		}
+	}
+
+	fn insert(table: &mut Table<Id, Row>, name: &str) {
+		let id = Id::new(Kind::new(false, 1));
+		table
+			.insert(
+				id,
+				Row {
+					id,
+					name: name.into(),
+				},
+			)
+			.unwrap();
+	}
+
+	#[test]
+	fn query_pages() {
+		let mut table = Table::new();
+		for name in ["d", "b", "a", "c", "b"] {
+			insert(&mut table, name);
+		}
+
+		let query = Query::new().order_asc("name").limit(2);
+		let page = table.query(&query).unwrap();
+		let names: Vec<_> = page.items.iter().map(|r| &r.name).collect();
+		assert_eq!(names, ["a", "b"]);
+
+		// rows inserted before the cursor don't shift the next page
+		insert(&mut table, "a");
+
+		let query = query.after(page.next);
+		let page = table.query(&query).unwrap();
+		let names: Vec<_> = page.items.iter().map(|r| &r.name).collect();
+		assert_eq!(names, ["b", "c"]);
+
+		let page = table.query(&query.after(page.next)).unwrap();
+		assert_eq!(page.items.len(), 1);
+		assert!(page.next.is_none());
+
+		let invalid = Cursor::new(vec![], page.items[0].id);
+		let query = Query::new().order_asc("name").after(Some(invalid));
+		assert!(matches!(
+			table.query(&query),
+			Err(QueryError::InvalidCursor)
+		));
+	}
+
+	#[test]
+	fn batches() {
+		let mut table = Table::new();
+		table.insert(1, "a").unwrap();
+
+		// duplicates fail, inside the batch and with the table
+		let rows = [(1, "b"), (2, "b"), (2, "c")];
+		let err = table.insert_many(rows, BatchMode::Abort).unwrap_err();
+		assert!(matches!(err, BatchError::Aborted(f) if f.len() == 2));
+		assert!(table.get(&2).is_none());
+
+		let res = table.insert_many(rows, BatchMode::Continue).unwrap();
+		assert_eq!(res.applied, [1]);
+		assert_eq!(res.failures.len(), 2);
+		assert_eq!(res.failures[0].error, RowError::AlreadyExists);
+
+		let res = table.insert_many_by([(3, "b")], BatchMode::Continue, |v| *v);
+		assert_eq!(res.unwrap().failures.len(), 1);
+
+		table.upsert_many([(1, "d"), (3, "e")]);
+		assert_eq!(table.get(&1), Some(&"d"));
+
+		let res = table.delete_many([1, 4], BatchMode::Abort);
+		assert!(res.is_err());
+		assert!(table.get(&1).is_some());
+
+		let res = table.delete_many([1, 4], BatchMode::Continue).unwrap();
+		assert_eq!(res.applied, [0]);
+		assert_eq!(res.failures[0].error, RowError::NotFound);
+		assert!(table.get(&1).is_none());
+	}
+}
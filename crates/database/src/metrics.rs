@@ -0,0 +1,314 @@
+//! Metrics
+//!
+//! Every [`DatabasePool`](crate::DatabasePool) counts how long
+//! [`get`](crate::DatabasePool::get) waited for a connection, how many
+//! connections are in use and how many statements where executed per table.
+//!
+//! A [`MetricsSnapshot`] can be serialized or rendered in the prometheus
+//! text format.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+	inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+	/// The maximum amount of connections, None with the memory backend
+	max_size: Option<usize>,
+	opened: AtomicU64,
+	in_use: AtomicU64,
+	gets: AtomicU64,
+	get_errors: AtomicU64,
+	wait_total_us: AtomicU64,
+	wait_max_us: AtomicU64,
+	tables: Mutex<BTreeMap<String, TableStats>>,
+}
+
+impl Metrics {
+	pub(crate) fn new(max_size: Option<usize>) -> Self {
+		Self {
+			inner: Arc::new(Inner {
+				max_size,
+				..Default::default()
+			}),
+		}
+	}
+
+	/// Records a call to get, `fresh` is true if the connection was newly
+	/// opened by the pool
+	pub(crate) fn record_get(&self, wait: Duration, ok: bool, fresh: bool) {
+		let inner = &self.inner;
+		let wait = wait.as_micros() as u64;
+
+		inner.gets.fetch_add(1, Ordering::Relaxed);
+		inner.wait_total_us.fetch_add(wait, Ordering::Relaxed);
+		inner.wait_max_us.fetch_max(wait, Ordering::Relaxed);
+
+		if !ok {
+			inner.get_errors.fetch_add(1, Ordering::Relaxed);
+		}
+
+		if fresh {
+			inner.opened.fetch_add(1, Ordering::Relaxed);
+		}
+	}
+
+	/// Returns a guard which counts the connection as in use until it is
+	/// dropped
+	pub(crate) fn in_use(&self) -> InUse {
+		self.inner.in_use.fetch_add(1, Ordering::Relaxed);
+		InUse(self.clone())
+	}
+
+	pub(crate) fn record_statement(
+		&self,
+		table: &str,
+		duration: Duration,
+		ok: bool,
+	) {
+		let mut tables = self.inner.tables.lock().unwrap();
+
+		// don't allocate the name for every statement
+		if !tables.contains_key(table) {
+			tables.insert(table.to_string(), TableStats::default());
+		}
+		let stats = tables.get_mut(table).unwrap();
+
+		stats.queries += 1;
+		stats.total_ms += duration.as_secs_f64() * 1000.0;
+		if !ok {
+			stats.errors += 1;
+		}
+	}
+
+	pub fn snapshot(&self) -> MetricsSnapshot {
+		let inner = &self.inner;
+		let in_use = inner.in_use.load(Ordering::Relaxed) as usize;
+
+		let pool = inner.max_size.map(|max_size| {
+			// connections which where closed by the pool are not known, so
+			// this can only be an estimate
+			let open = (inner.opened.load(Ordering::Relaxed) as usize)
+				.clamp(in_use, max_size.max(in_use));
+
+			PoolStats {
+				max_size,
+				in_use,
+				idle: open - in_use,
+			}
+		});
+
+		let ms = |us: &AtomicU64| us.load(Ordering::Relaxed) as f64 / 1000.0;
+
+		MetricsSnapshot {
+			pool,
+			gets: inner.gets.load(Ordering::Relaxed),
+			get_errors: inner.get_errors.load(Ordering::Relaxed),
+			wait_total_ms: ms(&inner.wait_total_us),
+			wait_max_ms: ms(&inner.wait_max_us),
+			tables: inner.tables.lock().unwrap().clone(),
+		}
+	}
+}
+
+/// Counts a connection as in use
+#[derive(Debug)]
+pub(crate) struct InUse(Metrics);
+
+impl Drop for InUse {
+	fn drop(&mut self) {
+		self.0.inner.in_use.fetch_sub(1, Ordering::Relaxed);
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PoolStats {
+	pub max_size: usize,
+	pub in_use: usize,
+	pub idle: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TableStats {
+	pub queries: u64,
+	pub errors: u64,
+	pub total_ms: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MetricsSnapshot {
+	/// Only available with postgres
+	pub pool: Option<PoolStats>,
+	pub gets: u64,
+	pub get_errors: u64,
+	pub wait_total_ms: f64,
+	pub wait_max_ms: f64,
+	pub tables: BTreeMap<String, TableStats>,
+}
+
+impl MetricsSnapshot {
+	/// The amount of failed gets and statements
+	pub fn errors(&self) -> u64 {
+		self.get_errors + self.tables.values().map(|t| t.errors).sum::<u64>()
+	}
+
+	/// Renders the metrics in the prometheus text format
+	pub fn to_prometheus(&self) -> String {
+		let mut s = String::new();
+
+		if let Some(pool) = &self.pool {
+			let name = "database_pool_connections";
+			header(&mut s, name, "gauge", "Connections of the pool by state.");
+			writeln!(s, "{name}{{state=\"in_use\"}} {}", pool.in_use).unwrap();
+			writeln!(s, "{name}{{state=\"idle\"}} {}", pool.idle).unwrap();
+
+			let name = "database_pool_max_size";
+			header(&mut s, name, "gauge", "The maximum amount of connections.");
+			writeln!(s, "{name} {}", pool.max_size).unwrap();
+		}
+
+		let name = "database_pool_gets_total";
+		header(
+			&mut s,
+			name,
+			"counter",
+			"Databases requested from the pool.",
+		);
+		writeln!(s, "{name} {}", self.gets).unwrap();
+
+		let name = "database_pool_get_errors_total";
+		header(&mut s, name, "counter", "Failed requests to the pool.");
+		writeln!(s, "{name} {}", self.get_errors).unwrap();
+
+		let name = "database_pool_wait_seconds_total";
+		header(
+			&mut s,
+			name,
+			"counter",
+			"Time spent waiting for a connection.",
+		);
+		writeln!(s, "{name} {}", self.wait_total_ms / 1000.0).unwrap();
+
+		let name = "database_pool_wait_seconds_max";
+		header(&mut s, name, "gauge", "The longest wait for a connection.");
+		writeln!(s, "{name} {}", self.wait_max_ms / 1000.0).unwrap();
+
+		self.per_table(
+			&mut s,
+			"database_queries_total",
+			"Statements executed per table.",
+			|t| t.queries as f64,
+		);
+		self.per_table(
+			&mut s,
+			"database_query_errors_total",
+			"Statements which failed per table.",
+			|t| t.errors as f64,
+		);
+		self.per_table(
+			&mut s,
+			"database_query_seconds_total",
+			"Time spent executing statements per table.",
+			|t| t.total_ms / 1000.0,
+		);
+
+		s
+	}
+
+	fn per_table<F>(&self, s: &mut String, name: &str, help: &str, value: F)
+	where
+		F: Fn(&TableStats) -> f64,
+	{
+		header(s, name, "counter", help);
+
+		for (table, stats) in &self.tables {
+			let table = escape_label(table);
+			writeln!(s, "{name}{{table=\"{table}\"}} {}", value(stats))
+				.unwrap();
+		}
+	}
+}
+
+fn header(s: &mut String, name: &str, kind: &str, help: &str) {
+	writeln!(s, "# HELP {name} {help}\n# TYPE {name} {kind}").unwrap();
+}
+
+fn escape_label(value: &str) -> String {
+	value
+		.replace('\\', "\\\\")
+		.replace('"', "\\\"")
+		.replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{trace::Statement, DatabasePool};
+
+	#[test]
+	fn snapshot() {
+		let metrics = Metrics::new(Some(4));
+
+		let a = metrics.in_use();
+		metrics.record_get(Duration::from_millis(3), true, true);
+		let b = metrics.in_use();
+		metrics.record_get(Duration::from_millis(1), true, true);
+		metrics.record_get(Duration::from_millis(5), false, false);
+		drop(b);
+
+		metrics.record_statement("users", Duration::from_millis(2), true);
+		metrics.record_statement("users", Duration::from_millis(2), false);
+		metrics.record_statement("entity", Duration::from_millis(1), true);
+
+		let snapshot = metrics.snapshot();
+		assert_eq!(
+			snapshot.pool,
+			Some(PoolStats {
+				max_size: 4,
+				in_use: 1,
+				idle: 1
+			})
+		);
+		assert_eq!(snapshot.gets, 3);
+		assert_eq!(snapshot.wait_total_ms, 9.0);
+		assert_eq!(snapshot.wait_max_ms, 5.0);
+		assert_eq!(snapshot.tables["users"].queries, 2);
+		assert_eq!(snapshot.errors(), 2);
+
+		let text = snapshot.to_prometheus();
+		assert!(text.contains("database_pool_connections{state=\"idle\"} 1\n"));
+		assert!(text.contains("database_queries_total{table=\"users\"} 2\n"));
+		assert!(
+			text.contains("database_query_errors_total{table=\"entity\"} 0\n")
+		);
+
+		drop(a);
+		assert_eq!(metrics.snapshot().pool.unwrap().idle, 2);
+	}
+
+	#[tokio::test]
+	async fn memory_pool() {
+		let pool = DatabasePool::new_memory();
+		let db = pool.get().await.unwrap();
+		let conn = db.connection();
+
+		let stmt = Statement::new("users", "by_id");
+		let res: Result<Vec<()>, crate::Error> =
+			conn.traced(stmt, async { Ok(vec![(), ()]) }).await;
+		assert!(res.is_ok());
+
+		let snapshot = pool.metrics().snapshot();
+		assert_eq!(snapshot.pool, None);
+		assert_eq!(snapshot.gets, 1);
+		assert_eq!(snapshot.tables["users"].queries, 1);
+	}
+}
@@ -0,0 +1,105 @@
+use std::{borrow::Cow, fmt, str::FromStr};
+
+use base64::engine::{general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{QueryError, Value};
+use crate::id::Id;
+
+/// An opaque cursor
+///
+/// Contains the sort key of a row and its id as the tie-breaker.
+/// Should only be handed out as a base64 string.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+	values: Vec<Value>,
+	id: Id,
+}
+
+impl Cursor {
+	pub fn new(values: Vec<Value>, id: Id) -> Self {
+		Self { values, id }
+	}
+
+	/// The values of the sort key
+	pub fn values(&self) -> &[Value] {
+		&self.values
+	}
+
+	/// The id of the row
+	pub fn id(&self) -> Id {
+		self.id
+	}
+
+	pub fn to_b64(&self) -> String {
+		let json = serde_json::to_vec(&(&self.values, &self.id))
+			.expect("cursor serialization failed");
+
+		URL_SAFE_NO_PAD.encode(json)
+	}
+
+	pub fn parse_b64<T>(b64: T) -> Result<Self, QueryError>
+	where
+		T: AsRef<[u8]>,
+	{
+		let json = URL_SAFE_NO_PAD
+			.decode(b64)
+			.map_err(|_| QueryError::InvalidCursor)?;
+
+		let (values, id) = serde_json::from_slice(&json)
+			.map_err(|_| QueryError::InvalidCursor)?;
+
+		Ok(Self { values, id })
+	}
+}
+
+impl fmt::Display for Cursor {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		self.to_b64().fmt(f)
+	}
+}
+
+impl FromStr for Cursor {
+	type Err = QueryError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Self::parse_b64(s)
+	}
+}
+
+impl Serialize for Cursor {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		serializer.serialize_str(&self.to_b64())
+	}
+}
+
+impl<'de> Deserialize<'de> for Cursor {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		let s: Cow<'_, str> = Deserialize::deserialize(deserializer)?;
+		Cursor::parse_b64(s.as_ref()).map_err(D::Error::custom)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::id::Kind;
+
+	#[test]
+	fn b64() {
+		let id = Id::new(Kind::new(false, 1));
+		let cursor = Cursor::new(vec!["a".into(), Value::Int(2)], id);
+
+		let b64 = cursor.to_b64();
+		let cursor2 = Cursor::parse_b64(b64).unwrap();
+		assert_eq!(cursor, cursor2);
+
+		assert!(Cursor::parse_b64("invalid").is_err());
+	}
+}
@@ -0,0 +1,357 @@
+//! Filters
+//!
+//! A filter selects which rows are returned by a [`Query`](super::Query).
+//! Every backend needs to return the same rows, so comparisons never
+//! evaluate to null: `eq` and `ne` treat null like any other value and the
+//! other operators are false if the column is null.
+
+use std::fmt::Write;
+use std::ops;
+
+use super::{postgres::Columns, QueryError, Record, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+	Eq,
+	Ne,
+	Gt,
+	Gte,
+	Lt,
+	Lte,
+	/// The text contains the value
+	Contains,
+}
+
+impl Operator {
+	fn as_str(&self) -> &'static str {
+		match self {
+			Self::Eq => "eq",
+			Self::Ne => "ne",
+			Self::Gt => "gt",
+			Self::Gte => "gte",
+			Self::Lt => "lt",
+			Self::Lte => "lte",
+			Self::Contains => "contains",
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+	/// Matches if all filters match, an empty list matches every row
+	And(Vec<Filter>),
+	/// Matches if any filter matches, an empty list matches no row
+	Or(Vec<Filter>),
+	Not(Box<Filter>),
+	Compare {
+		column: String,
+		op: Operator,
+		value: Value,
+	},
+	/// Matches if the column equals any of the values
+	In {
+		column: String,
+		values: Vec<Value>,
+	},
+}
+
+impl Filter {
+	pub fn compare(
+		column: impl Into<String>,
+		op: Operator,
+		value: impl Into<Value>,
+	) -> Self {
+		Self::Compare {
+			column: column.into(),
+			op,
+			value: value.into(),
+		}
+	}
+
+	pub fn eq(column: impl Into<String>, value: impl Into<Value>) -> Self {
+		Self::compare(column, Operator::Eq, value)
+	}
+
+	pub fn is_in(column: impl Into<String>, values: Vec<Value>) -> Self {
+		Self::In {
+			column: column.into(),
+			values,
+		}
+	}
+
+	/// Renames the column of every comparison
+	pub fn map_columns(&mut self, f: &impl Fn(&str) -> String) {
+		match self {
+			Self::And(filters) | Self::Or(filters) => {
+				filters.iter_mut().for_each(|filter| filter.map_columns(f))
+			}
+			Self::Not(filter) => filter.map_columns(f),
+			Self::Compare { column, .. } | Self::In { column, .. } => {
+				*column = f(column)
+			}
+		}
+	}
+
+	/// Returns an error if a column does not exist on the record
+	pub fn matches<R: Record>(&self, record: &R) -> Result<bool, QueryError> {
+		let column = |column: &str| {
+			record
+				.value(column)
+				.ok_or_else(|| QueryError::InvalidColumn(column.to_string()))
+		};
+
+		match self {
+			Self::And(filters) => {
+				for filter in filters {
+					if !filter.matches(record)? {
+						return Ok(false);
+					}
+				}
+
+				Ok(true)
+			}
+			Self::Or(filters) => {
+				for filter in filters {
+					if filter.matches(record)? {
+						return Ok(true);
+					}
+				}
+
+				Ok(false)
+			}
+			Self::Not(filter) => filter.matches(record).map(|m| !m),
+			Self::Compare {
+				column: name,
+				op,
+				value,
+			} => Ok(compare(&column(name)?, *op, value)),
+			Self::In {
+				column: name,
+				values,
+			} => {
+				let a = column(name)?;
+				Ok(values.iter().any(|v| compare(&a, Operator::Eq, v)))
+			}
+		}
+	}
+
+	/// Appends a normalized representation of the filter
+	pub(crate) fn write_key(&self, key: &mut String) {
+		match self {
+			Self::And(filters) | Self::Or(filters) => {
+				let name = match self {
+					Self::And(_) => "and",
+					_ => "or",
+				};
+				write!(key, "{name}(").unwrap();
+				for filter in filters {
+					filter.write_key(key);
+					key.push(',');
+				}
+				key.push(')');
+			}
+			Self::Not(filter) => {
+				key.push_str("not(");
+				filter.write_key(key);
+				key.push(')');
+			}
+			Self::Compare { column, op, value } => {
+				write!(key, "{column} {} {value:?}", op.as_str()).unwrap();
+			}
+			Self::In { column, values } => {
+				write!(key, "{column} in {values:?}").unwrap();
+			}
+		}
+	}
+
+	/// Appends the sql condition, the values are pushed to the params and
+	/// referenced by their position
+	pub(crate) fn write_sql(
+		&self,
+		sql: &mut String,
+		params: &mut Vec<Value>,
+		columns: &dyn Columns,
+	) -> Result<(), QueryError> {
+		match self {
+			Self::And(filters) | Self::Or(filters) => {
+				let (op, empty) = match self {
+					Self::And(_) => (" AND ", "TRUE"),
+					_ => (" OR ", "FALSE"),
+				};
+				if filters.is_empty() {
+					sql.push_str(empty);
+					return Ok(());
+				}
+
+				sql.push('(');
+				for (i, filter) in filters.iter().enumerate() {
+					if i != 0 {
+						sql.push_str(op);
+					}
+					filter.write_sql(sql, params, columns)?;
+				}
+				sql.push(')');
+			}
+			Self::Not(filter) => {
+				sql.push_str("NOT ");
+				filter.write_sql(sql, params, columns)?;
+			}
+			Self::Compare { column, op, value } => {
+				let column = columns.expr(column)?;
+				params.push(value.clone());
+				let n = params.len();
+
+				match op {
+					Operator::Eq => {
+						write!(sql, "{column} IS NOT DISTINCT FROM ${n}")
+					}
+					Operator::Ne => {
+						write!(sql, "{column} IS DISTINCT FROM ${n}")
+					}
+					Operator::Contains => write!(
+						sql,
+						"COALESCE(strpos({column}, ${n}) > 0, FALSE)"
+					),
+					op => {
+						let op = match op {
+							Operator::Gt => ">",
+							Operator::Gte => ">=",
+							Operator::Lt => "<",
+							_ => "<=",
+						};
+						write!(sql, "COALESCE({column} {op} ${n}, FALSE)")
+					}
+				}
+				.unwrap();
+			}
+			Self::In { column, values } => {
+				let column = columns.expr(column)?;
+				if values.is_empty() {
+					sql.push_str("FALSE");
+					return Ok(());
+				}
+
+				write!(sql, "COALESCE({column} IN (").unwrap();
+				for (i, value) in values.iter().enumerate() {
+					params.push(value.clone());
+					if i != 0 {
+						sql.push_str(", ");
+					}
+					write!(sql, "${}", params.len()).unwrap();
+				}
+				sql.push_str("), FALSE)");
+			}
+		}
+
+		Ok(())
+	}
+}
+
+impl ops::Not for Filter {
+	type Output = Self;
+
+	fn not(self) -> Self {
+		Self::Not(Box::new(self))
+	}
+}
+
+fn compare(a: &Value, op: Operator, b: &Value) -> bool {
+	let null = matches!(a, Value::Null) || matches!(b, Value::Null);
+
+	match op {
+		Operator::Eq => a.total_cmp(b).is_eq(),
+		Operator::Ne => a.total_cmp(b).is_ne(),
+		_ if null => false,
+		Operator::Gt => a.total_cmp(b).is_gt(),
+		Operator::Gte => a.total_cmp(b).is_ge(),
+		Operator::Lt => a.total_cmp(b).is_lt(),
+		Operator::Lte => a.total_cmp(b).is_le(),
+		Operator::Contains => match (a, b) {
+			(Value::Text(a), Value::Text(b)) => a.contains(b.as_str()),
+			_ => false,
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::ops::Not;
+
+	use super::*;
+	use crate::id::{Id, Kind};
+	use crate::query::postgres::Quoted;
+
+	struct Row {
+		id: Id,
+		title: Option<&'static str>,
+		count: i64,
+	}
+
+	impl Record for Row {
+		fn id(&self) -> Id {
+			self.id
+		}
+
+		fn value(&self, column: &str) -> Option<Value> {
+			match column {
+				"id" => Some(self.id.into()),
+				"title" => Some(self.title.map_or(Value::Null, Into::into)),
+				"count" => Some(self.count.into()),
+				_ => None,
+			}
+		}
+	}
+
+	#[test]
+	fn matches() {
+		let row = Row {
+			id: Id::new(Kind::new(false, 1)),
+			title: None,
+			count: 2,
+		};
+
+		let gt = Filter::compare("count", Operator::Gt, 1i64);
+		assert!(gt.matches(&row).unwrap());
+		// comparisons with null are false, so their negation matches
+		let contains = Filter::compare("title", Operator::Contains, "a");
+		assert!(!contains.matches(&row).unwrap());
+		assert!(contains.clone().not().matches(&row).unwrap());
+		assert!(Filter::eq("title", Value::Null).matches(&row).unwrap());
+
+		let filter = Filter::Or(vec![
+			Filter::And(vec![]).not(),
+			Filter::is_in("count", vec![1i64.into(), 2i64.into()]),
+		]);
+		assert!(filter.matches(&row).unwrap());
+		assert!(!Filter::Or(vec![]).matches(&row).unwrap());
+		assert!(Filter::eq("missing", 1i64).matches(&row).is_err());
+
+		let mut renamed = Filter::eq("amount", 2i64).not();
+		renamed.map_columns(&|column| column.replace("amount", "count"));
+		assert!(!renamed.matches(&row).unwrap());
+	}
+
+	#[test]
+	fn sql() {
+		let filter = Filter::And(vec![
+			Filter::compare("title", Operator::Contains, "a"),
+			Filter::Or(vec![
+				Filter::eq("count", 1i64),
+				Filter::is_in("count", vec![2i64.into(), 3i64.into()]).not(),
+			]),
+		]);
+
+		let mut sql = String::new();
+		let mut params = vec![Value::Null];
+		filter.write_sql(&mut sql, &mut params, &Quoted).unwrap();
+		assert_eq!(
+			sql,
+			"(COALESCE(strpos(\"title\", $2) > 0, FALSE) AND \
+			(\"count\" IS NOT DISTINCT FROM $3 OR NOT COALESCE(\"count\" IN ($4, $5), FALSE)))"
+		);
+		assert_eq!(params.len(), 5);
+
+		let filter = Filter::eq("title\"", 1i64);
+		assert!(filter.write_sql(&mut sql, &mut params, &Quoted).is_err());
+	}
+}
@@ -0,0 +1,304 @@
+//! Query layer
+//!
+//! A query describes which rows of a table should be returned and in which
+//! order. It is executed by the memory [`Table`](crate::memory::Table), the
+//! key-value [`Table`](crate::kv::Table) or by the postgres helpers in
+//! [`postgres`].
+//!
+//! Instead of offsets, queries are paged with opaque cursors, which encode
+//! the sort key of the last returned row and its [`Id`] as a tie-breaker.
+//! This keeps pages stable even while rows get inserted.
+
+mod cursor;
+mod filter;
+pub mod postgres;
+mod value;
+
+use std::cmp::Ordering;
+use std::fmt::Write;
+
+pub use cursor::Cursor;
+pub use filter::{Filter, Operator};
+pub use value::Value;
+
+use crate::id::Id;
+
+/// The column which is used as the tie-breaker of every query
+pub const ID_COLUMN: &str = "id";
+
+#[derive(Debug, thiserror::Error)]
+pub enum QueryError {
+	#[error("the cursor is invalid or does not match the query")]
+	InvalidCursor,
+
+	#[error("the column {0} is not valid")]
+	InvalidColumn(String),
+
+	#[error("a postgres error occured {0}")]
+	Postgres(#[from] crate::Error),
+}
+
+/// A row which can be queried
+pub trait Record {
+	/// Returns the id of the row, used as the tie-breaker
+	fn id(&self) -> Id;
+
+	/// Returns the value of a column, or None if the column does not exist
+	fn value(&self, column: &str) -> Option<Value>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+	Asc,
+	Desc,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Order {
+	pub column: String,
+	pub direction: Direction,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+	/// The sort key, the id is always appended as the last column
+	pub order: Vec<Order>,
+	/// Returns all rows if None
+	pub limit: Option<u32>,
+	/// Only rows after this cursor are returned
+	pub after: Option<Cursor>,
+	/// Only rows matching the filter are returned
+	pub filter: Option<Filter>,
+}
+
+impl Query {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn order_asc(mut self, column: impl Into<String>) -> Self {
+		self.order.push(Order {
+			column: column.into(),
+			direction: Direction::Asc,
+		});
+		self
+	}
+
+	pub fn order_desc(mut self, column: impl Into<String>) -> Self {
+		self.order.push(Order {
+			column: column.into(),
+			direction: Direction::Desc,
+		});
+		self
+	}
+
+	pub fn limit(mut self, limit: u32) -> Self {
+		self.limit = Some(limit);
+		self
+	}
+
+	pub fn after(mut self, cursor: Option<Cursor>) -> Self {
+		self.after = cursor;
+		self
+	}
+
+	/// Rows need to match the filter, and any filter added before
+	pub fn filter(mut self, filter: Filter) -> Self {
+		self.filter = Some(match self.filter.take() {
+			Some(Filter::And(mut filters)) => {
+				filters.push(filter);
+				Filter::And(filters)
+			}
+			Some(prev) => Filter::And(vec![prev, filter]),
+			None => filter,
+		});
+		self
+	}
+
+	/// Renames the columns of the order and the filter
+	pub fn map_columns(mut self, f: impl Fn(&str) -> String) -> Self {
+		for order in &mut self.order {
+			order.column = f(&order.column);
+		}
+		if let Some(filter) = &mut self.filter {
+			filter.map_columns(&f);
+		}
+		self
+	}
+
+	/// Returns true if the record matches the filter
+	pub fn matches<R: Record>(&self, record: &R) -> Result<bool, QueryError> {
+		match &self.filter {
+			Some(filter) => filter.matches(record),
+			None => Ok(true),
+		}
+	}
+
+	/// Returns the cursor pointing at the given record
+	pub fn cursor_of<R: Record>(
+		&self,
+		record: &R,
+	) -> Result<Cursor, QueryError> {
+		let values = self
+			.order
+			.iter()
+			.map(|o| {
+				record
+					.value(&o.column)
+					.ok_or_else(|| QueryError::InvalidColumn(o.column.clone()))
+			})
+			.collect::<Result<_, _>>()?;
+
+		Ok(Cursor::new(values, record.id()))
+	}
+
+	/// Returns the cursor to start after if it matches this query
+	pub fn valid_after(&self) -> Result<Option<&Cursor>, QueryError> {
+		match &self.after {
+			Some(c) if c.values().len() != self.order.len() => {
+				Err(QueryError::InvalidCursor)
+			}
+			c => Ok(c.as_ref()),
+		}
+	}
+
+	/// Returns a normalized representation of the query, two queries which
+	/// return the same rows have the same key
+	pub fn cache_key(&self) -> String {
+		let mut key = String::new();
+
+		for order in &self.order {
+			let direction = match order.direction {
+				Direction::Asc => "asc",
+				Direction::Desc => "desc",
+			};
+			write!(key, "{} {direction},", order.column).unwrap();
+		}
+		// the id is always the last sort column
+		key.push_str("id asc;");
+
+		if let Some(limit) = self.limit {
+			write!(key, "limit {limit};").unwrap();
+		}
+		if let Some(after) = &self.after {
+			write!(key, "after {after};").unwrap();
+		}
+		if let Some(filter) = &self.filter {
+			key.push_str("filter ");
+			filter.write_key(&mut key);
+			key.push(';');
+		}
+
+		key
+	}
+
+	/// Compares two cursors using the order of this query
+	pub fn compare(&self, a: &Cursor, b: &Cursor) -> Ordering {
+		let values = a.values().iter().zip(b.values());
+
+		for (order, (a, b)) in self.order.iter().zip(values) {
+			let ord = match order.direction {
+				Direction::Asc => a.total_cmp(b),
+				Direction::Desc => b.total_cmp(a),
+			};
+
+			if ord != Ordering::Equal {
+				return ord;
+			}
+		}
+
+		a.id().cmp(&b.id())
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page<T> {
+	pub items: Vec<T>,
+	/// The cursor to request the next page, None if this is the last page
+	pub next: Option<Cursor>,
+}
+
+impl<T> Page<T> {
+	pub fn map<U, F>(self, f: F) -> Page<U>
+	where
+		F: FnMut(T) -> U,
+	{
+		Page {
+			items: self.items.into_iter().map(f).collect(),
+			next: self.next,
+		}
+	}
+
+	/// Truncates the rows to the limit of the query and creates the next
+	/// cursor if more rows exist
+	///
+	/// Expects the rows to contain at most one row more than the limit.
+	pub(crate) fn from_rows(
+		query: &Query,
+		mut items: Vec<T>,
+	) -> Result<Self, QueryError>
+	where
+		T: Record,
+	{
+		let limit = match query.limit {
+			Some(limit) if items.len() > limit as usize => limit as usize,
+			_ => return Ok(Self { items, next: None }),
+		};
+
+		items.truncate(limit);
+		let next = match items.last() {
+			Some(last) => Some(query.cursor_of(last)?),
+			None => None,
+		};
+
+		Ok(Self { items, next })
+	}
+}
+
+/// Sorts and pages the rows in memory, used by the backends which can't
+/// execute a query themselves
+pub fn select_page<'a, V, I>(
+	rows: I,
+	query: &Query,
+) -> Result<Page<V>, QueryError>
+where
+	V: Record + Clone + 'a,
+	I: IntoIterator<Item = &'a V>,
+{
+	let after = query.valid_after()?;
+
+	let mut matching = vec![];
+	for v in rows {
+		if query.matches(v)? {
+			matching.push((query.cursor_of(v)?, v));
+		}
+	}
+	let mut rows = matching;
+	rows.sort_by(|(a, _), (b, _)| query.compare(a, b));
+
+	let rows = rows
+		.into_iter()
+		.filter(|(c, _)| after.is_none_or(|a| query.compare(c, a).is_gt()))
+		.map(|(_, v)| v.clone());
+
+	let rows = match query.limit {
+		Some(limit) => rows.take(limit as usize + 1).collect(),
+		None => rows.collect(),
+	};
+
+	Page::from_rows(query, rows)
+}
+
+/// Returns an error if the column could not be used safely in a statement
+pub(crate) fn validate_column(column: &str) -> Result<(), QueryError> {
+	let valid = !column.is_empty()
+		&& column
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+	if valid {
+		Ok(())
+	} else {
+		Err(QueryError::InvalidColumn(column.to_string()))
+	}
+}
@@ -0,0 +1,363 @@
+//! Executes queries with postgres
+//!
+//! Pages are selected with a keyset condition, for the order `a ASC` the
+//! condition after a cursor looks like `"a" > $1 OR ("a" = $1 AND "id" > $2)`.
+//!
+//! Like in memory null is smaller than any other value, so it comes first in
+//! ascending and last in descending order, the conditions take this into
+//! account as well.
+//!
+//! Columns are quoted by default, with [`Columns`] they can be written as
+//! any expression, for example a field of a jsonb column.
+
+use std::fmt::Write;
+
+use ::postgres::row::{FromRowOwned, NamedColumns, ToRowStatic};
+use postgres_types::ToSql;
+
+use super::{
+	validate_column, Direction, Page, Query, QueryError, Record, Value,
+	ID_COLUMN,
+};
+use crate::{trace::Statement, Connection};
+
+/// Writes the columns of a query as sql expressions
+pub trait Columns: Sync {
+	/// Returns the expression of the column, fails if the column cannot be
+	/// used safely in a statement
+	fn expr(&self, column: &str) -> Result<String, QueryError>;
+}
+
+/// The columns of the table, quoted by their name
+#[derive(Debug, Clone, Copy)]
+pub struct Quoted;
+
+impl Columns for Quoted {
+	fn expr(&self, column: &str) -> Result<String, QueryError> {
+		validate_column(column)?;
+		Ok(format!("\"{column}\""))
+	}
+}
+
+/// Selects a page of rows from the table
+pub async fn select_page<R>(
+	conn: &Connection<'_>,
+	table: &str,
+	query: &Query,
+) -> Result<Page<R>, QueryError>
+where
+	R: FromRowOwned + NamedColumns + Record,
+{
+	select_page_with(conn, table, query, &Quoted).await
+}
+
+/// Selects a page of rows from the table, the columns of the query are
+/// written by `columns`
+///
+/// The rows are converted before the cursor is created, so the record can
+/// return values the row only stores as part of another column.
+pub async fn select_page_with<R, T>(
+	conn: &Connection<'_>,
+	table: &str,
+	query: &Query,
+	columns: &dyn Columns,
+) -> Result<Page<T>, QueryError>
+where
+	R: FromRowOwned + NamedColumns,
+	T: From<R> + Record,
+{
+	validate_column(table)?;
+
+	let (sql, params) =
+		page_statement(R::select_columns(), table, query, columns)?;
+	let params = params
+		.iter()
+		.map(|p| p as &(dyn ToSql + Sync))
+		.collect::<Vec<_>>();
+
+	let pg = conn.into_postgres();
+	let stmt = Statement::new(table, &sql).params(&params);
+	let rows = conn
+		.traced(stmt, async {
+			let stmt = pg.prepare_cached(&sql).await?;
+			pg.query::<R, _>(&stmt, &params).await
+		})
+		.await?;
+
+	Page::from_rows(query, rows.into_iter().map(Into::into).collect())
+}
+
+/// Inserts a single row
+pub async fn insert<U>(
+	conn: &Connection<'_>,
+	table: &str,
+	row: &U,
+) -> Result<(), QueryError>
+where
+	U: ToRowStatic,
+{
+	validate_column(table)?;
+
+	let sql = format!(
+		"INSERT INTO \"{table}\" ({}) VALUES ({})",
+		U::insert_columns(),
+		U::insert_values()
+	);
+	let params = row.params().collect::<Vec<_>>();
+
+	let pg = conn.into_postgres();
+	conn.traced(Statement::new(table, &sql).params(&params), async {
+		let stmt = pg.prepare_cached(&sql).await?;
+		pg.execute(&stmt, &params).await
+	})
+	.await?;
+
+	Ok(())
+}
+
+/// Selects the rows where every column equals it's value, without columns
+/// every row is selected
+pub async fn select_where<R>(
+	conn: &Connection<'_>,
+	table: &str,
+	columns: &[(&str, &(dyn ToSql + Sync))],
+) -> Result<Vec<R>, QueryError>
+where
+	R: FromRowOwned + NamedColumns,
+{
+	let names = columns.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+	let sql = select_statement(R::select_columns(), table, &names)?;
+	let params = columns.iter().map(|(_, v)| *v).collect::<Vec<_>>();
+
+	let pg = conn.into_postgres();
+	let rows = conn
+		.traced(Statement::new(table, &sql).params(&params), async {
+			let stmt = pg.prepare_cached(&sql).await?;
+			pg.query::<R, _>(&stmt, &params).await
+		})
+		.await?;
+
+	Ok(rows)
+}
+
+fn select_statement(
+	select: &str,
+	table: &str,
+	columns: &[&str],
+) -> Result<String, QueryError> {
+	validate_column(table)?;
+
+	let mut sql = format!("SELECT {select} FROM \"{table}\"");
+	for (i, column) in columns.iter().enumerate() {
+		let column = Quoted.expr(column)?;
+		let keyword = if i == 0 { "WHERE" } else { "AND" };
+		write!(sql, " {keyword} {column} = ${}", i + 1).unwrap();
+	}
+
+	Ok(sql)
+}
+
+/// Returns the sql statement and it's parameters
+fn page_statement(
+	select: &str,
+	table: &str,
+	query: &Query,
+	columns: &dyn Columns,
+) -> Result<(String, Vec<Value>), QueryError> {
+	let order = query
+		.order
+		.iter()
+		.map(|o| Ok((columns.expr(&o.column)?, o.direction)))
+		.collect::<Result<Vec<_>, QueryError>>()?;
+	let id = columns.expr(ID_COLUMN)?;
+
+	let mut sql = format!("SELECT {select} FROM \"{table}\"");
+	let mut params = vec![];
+
+	let cursor = query.valid_after()?;
+	if cursor.is_some() || query.filter.is_some() {
+		sql.push_str(" WHERE ");
+	}
+
+	if let Some(cursor) = cursor {
+		// the alternatives need to be grouped before adding the filter
+		let group = query.filter.is_some();
+		if group {
+			sql.push('(');
+		}
+		after_condition(&mut sql, &order, &id);
+		if group {
+			sql.push(')');
+		}
+
+		params.extend(cursor.values().iter().cloned());
+		params.push(cursor.id().into());
+	}
+
+	// the filter parameters follow the ones of the cursor
+	if let Some(filter) = &query.filter {
+		if !params.is_empty() {
+			sql.push_str(" AND ");
+		}
+		filter.write_sql(&mut sql, &mut params, columns)?;
+	}
+
+	sql.push_str(" ORDER BY ");
+	for (column, direction) in &order {
+		let dir = match direction {
+			Direction::Asc => "ASC NULLS FIRST",
+			Direction::Desc => "DESC NULLS LAST",
+		};
+		write!(sql, "{column} {dir}, ").unwrap();
+	}
+	write!(sql, "{id} ASC").unwrap();
+
+	// select one more row to know if there is a next page
+	if let Some(limit) = query.limit {
+		write!(sql, " LIMIT {}", limit as u64 + 1).unwrap();
+	}
+
+	Ok((sql, params))
+}
+
+/// The parameters are expected to be the values of the cursor followed by
+/// it's id, the columns are already written as expressions
+fn after_condition(sql: &mut String, order: &[(String, Direction)], id: &str) {
+	let columns = order
+		.iter()
+		.map(|(column, direction)| (column.as_str(), *direction))
+		.chain([(id, Direction::Asc)]);
+
+	for (i, (column, direction)) in columns.enumerate() {
+		if i != 0 {
+			sql.push_str(" OR ");
+		}
+
+		sql.push('(');
+		for (j, (prev, _)) in order.iter().take(i).enumerate() {
+			write!(sql, "{prev} IS NOT DISTINCT FROM ${} AND ", j + 1).unwrap();
+		}
+
+		let p = i + 1;
+		// the id is always ascending and never null
+		if i == order.len() {
+			write!(sql, "{column} > ${p})").unwrap();
+			continue;
+		}
+
+		let (op, null) = match direction {
+			Direction::Asc => {
+				(">", format!("${p} IS NULL AND {column} IS NOT NULL"))
+			}
+			Direction::Desc => {
+				("<", format!("${p} IS NOT NULL AND {column} IS NULL"))
+			}
+		};
+		write!(sql, "({column} {op} ${p} OR {null}))").unwrap();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		id::{Id, Kind},
+		query::{Cursor, Filter, Operator},
+	};
+
+	#[test]
+	fn select_statements() {
+		let sql = select_statement("\"id\"", "users", &["email"]).unwrap();
+		assert_eq!(sql, "SELECT \"id\" FROM \"users\" WHERE \"email\" = $1");
+
+		let sql = select_statement("*", "users", &["id", "tenant"]).unwrap();
+		assert_eq!(
+			sql,
+			"SELECT * FROM \"users\" WHERE \"id\" = $1 AND \"tenant\" = $2"
+		);
+
+		assert!(select_statement("*", "users", &["a\" OR 1=1"]).is_err());
+	}
+
+	#[test]
+	fn statement() {
+		let id = Id::new(Kind::new(false, 1));
+		let query = Query::new()
+			.order_asc("email")
+			.limit(10)
+			.after(Some(Cursor::new(vec!["a".into()], id)));
+
+		let (sql, params) =
+			page_statement("\"id\", \"email\"", "users", &query, &Quoted)
+				.unwrap();
+		assert_eq!(
+			sql,
+			"SELECT \"id\", \"email\" FROM \"users\" WHERE \
+			((\"email\" > $1 OR $1 IS NULL AND \"email\" IS NOT NULL)) \
+			OR (\"email\" IS NOT DISTINCT FROM $1 AND \"id\" > $2) \
+			ORDER BY \"email\" ASC NULLS FIRST, \"id\" ASC LIMIT 11"
+		);
+		assert_eq!(params.len(), 2);
+
+		let query = query.filter(Filter::compare("email", Operator::Ne, "b"));
+		let (sql, params) =
+			page_statement("\"id\", \"email\"", "users", &query, &Quoted)
+				.unwrap();
+		assert_eq!(
+			sql,
+			"SELECT \"id\", \"email\" FROM \"users\" WHERE \
+			(((\"email\" > $1 OR $1 IS NULL AND \"email\" IS NOT NULL)) \
+			OR (\"email\" IS NOT DISTINCT FROM $1 AND \"id\" > $2)) \
+			AND \"email\" IS DISTINCT FROM $3 \
+			ORDER BY \"email\" ASC NULLS FIRST, \"id\" ASC LIMIT 11"
+		);
+		assert_eq!(params.len(), 3);
+
+		// null is the smallest value like in memory
+		let query = Query::new()
+			.order_desc("name")
+			.after(Some(Cursor::new(vec![Value::Null], id)));
+		let (sql, _) =
+			page_statement("\"id\"", "users", &query, &Quoted).unwrap();
+		assert_eq!(
+			sql,
+			"SELECT \"id\" FROM \"users\" WHERE \
+			((\"name\" < $1 OR $1 IS NOT NULL AND \"name\" IS NULL)) \
+			OR (\"name\" IS NOT DISTINCT FROM $1 AND \"id\" > $2) \
+			ORDER BY \"name\" DESC NULLS LAST, \"id\" ASC"
+		);
+
+		let query = Query::new().filter(Filter::eq("email", "a"));
+		let (sql, _) =
+			page_statement("\"id\"", "users", &query, &Quoted).unwrap();
+		assert_eq!(
+			sql,
+			"SELECT \"id\" FROM \"users\" WHERE \"email\" IS NOT DISTINCT FROM $1 \
+			ORDER BY \"id\" ASC"
+		);
+
+		struct Data;
+
+		impl Columns for Data {
+			fn expr(&self, column: &str) -> Result<String, QueryError> {
+				match column {
+					ID_COLUMN => Quoted.expr(column),
+					column => Ok(format!("(data->>'{column}')::bigint")),
+				}
+			}
+		}
+
+		let query = Query::new().order_desc("count").filter(Filter::compare(
+			"count",
+			Operator::Gt,
+			1i64,
+		));
+		let (sql, _) = page_statement("\"id\"", "rows", &query, &Data).unwrap();
+		assert_eq!(
+			sql,
+			"SELECT \"id\" FROM \"rows\" WHERE \
+			COALESCE((data->>'count')::bigint > $1, FALSE) \
+			ORDER BY (data->>'count')::bigint DESC NULLS LAST, \"id\" ASC"
+		);
+	}
+}
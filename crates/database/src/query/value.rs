@@ -0,0 +1,136 @@
+use std::{cmp::Ordering, error::Error as StdError};
+
+use bytes::BytesMut;
+use chrono::{DateTime, Utc};
+use postgres_types::{to_sql_checked, IsNull, ToSql, Type};
+use serde::{Deserialize, Serialize};
+
+use crate::id::Id;
+
+/// A value of a column
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Value {
+	Null,
+	Bool(bool),
+	Int(i64),
+	Float(f64),
+	Text(String),
+	Id(Id),
+	DateTime(DateTime<Utc>),
+}
+
+impl Value {
+	fn rank(&self) -> u8 {
+		match self {
+			Self::Null => 0,
+			Self::Bool(_) => 1,
+			Self::Int(_) => 2,
+			Self::Float(_) => 3,
+			Self::Text(_) => 4,
+			Self::Id(_) => 5,
+			Self::DateTime(_) => 6,
+		}
+	}
+
+	/// Compares two values, values of different kinds are ordered by their
+	/// kind with Null being the smallest
+	pub fn total_cmp(&self, other: &Self) -> Ordering {
+		match (self, other) {
+			(Self::Bool(a), Self::Bool(b)) => a.cmp(b),
+			(Self::Int(a), Self::Int(b)) => a.cmp(b),
+			(Self::Float(a), Self::Float(b)) => a.total_cmp(b),
+			(Self::Text(a), Self::Text(b)) => a.cmp(b),
+			(Self::Id(a), Self::Id(b)) => a.cmp(b),
+			(Self::DateTime(a), Self::DateTime(b)) => a.cmp(b),
+			(a, b) => a.rank().cmp(&b.rank()),
+		}
+	}
+}
+
+impl From<bool> for Value {
+	fn from(b: bool) -> Self {
+		Self::Bool(b)
+	}
+}
+
+impl From<i64> for Value {
+	fn from(i: i64) -> Self {
+		Self::Int(i)
+	}
+}
+
+impl From<f64> for Value {
+	fn from(f: f64) -> Self {
+		Self::Float(f)
+	}
+}
+
+impl From<String> for Value {
+	fn from(s: String) -> Self {
+		Self::Text(s)
+	}
+}
+
+impl From<&str> for Value {
+	fn from(s: &str) -> Self {
+		Self::Text(s.to_string())
+	}
+}
+
+impl From<Id> for Value {
+	fn from(id: Id) -> Self {
+		Self::Id(id)
+	}
+}
+
+impl From<DateTime<Utc>> for Value {
+	fn from(dt: DateTime<Utc>) -> Self {
+		Self::DateTime(dt)
+	}
+}
+
+impl<T> From<Option<T>> for Value
+where
+	T: Into<Value>,
+{
+	fn from(opt: Option<T>) -> Self {
+		opt.map(Into::into).unwrap_or(Self::Null)
+	}
+}
+
+impl ToSql for Value {
+	fn to_sql(
+		&self,
+		ty: &Type,
+		out: &mut BytesMut,
+	) -> Result<IsNull, Box<dyn StdError + Sync + Send>> {
+		match self {
+			Self::Null => Ok(IsNull::Yes),
+			Self::Bool(b) => b.to_sql(ty, out),
+			Self::Int(i) => match *ty {
+				Type::INT2 => i16::try_from(*i)?.to_sql(ty, out),
+				Type::INT4 => i32::try_from(*i)?.to_sql(ty, out),
+				// an integer can be compared with a float column
+				Type::FLOAT8 => (*i as f64).to_sql(ty, out),
+				_ => i.to_sql(ty, out),
+			},
+			Self::Float(f) => match *ty {
+				Type::FLOAT4 => (*f as f32).to_sql(ty, out),
+				_ => f.to_sql(ty, out),
+			},
+			Self::Text(s) => s.to_sql(ty, out),
+			Self::Id(id) => id.to_sql(ty, out),
+			Self::DateTime(dt) => match *ty {
+				Type::TIMESTAMP => dt.naive_utc().to_sql(ty, out),
+				_ => dt.to_sql(ty, out),
+			},
+		}
+	}
+
+	fn accepts(_ty: &Type) -> bool {
+		// the kind of the value is only known at runtime
+		true
+	}
+
+	to_sql_checked!();
+}
@@ -0,0 +1,101 @@
+//! Tenants
+//!
+//! A tenant scopes all data read or written through a connection, this
+//! allows to host many sites from one installation.
+//!
+//! With postgres every scoped table get's a `tenant` column, which is
+//! filled by default with the tenant of the session, and a row level
+//! security policy which only allows to access rows of the same tenant.
+//! The memory database keeps a separate partition for every tenant, see
+//! [`Partitioned`](crate::memory::Partitioned).
+//!
+//! A connection without a tenant only accesses rows without a tenant.
+//!
+//! Row level security does not apply to superusers and roles with
+//! `BYPASSRLS`, the pool warns about such a role when it is created.
+//! Statements which rely on the isolation can add [`CURRENT_CONDITION`] to
+//! be safe regardless of the role.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::id::Id;
+
+/// The postgres setting which contains the current tenant
+pub const SETTING: &str = "app.tenant";
+
+/// The tenant of the session, null if there is none
+const CURRENT_SQL: &str = "NULLIF(current_setting('app.tenant', true), '')";
+
+/// A condition which only matches rows of the current tenant, the same the
+/// row level security policy uses
+pub const CURRENT_CONDITION: &str =
+	"tenant IS NOT DISTINCT FROM NULLIF(current_setting('app.tenant', true), '')";
+
+/// Returns true if row level security does not apply to the current role
+pub(crate) const BYPASSES_RLS_SQL: &str =
+	"SELECT rolsuper OR rolbypassrls FROM pg_roles WHERE rolname = current_user";
+
+/// Resets the tenant, executed when the pool recycles a connection
+pub(crate) const RESET_SQL: &str = "RESET app.tenant";
+
+#[derive(
+	Debug,
+	Clone,
+	Copy,
+	PartialEq,
+	Eq,
+	PartialOrd,
+	Ord,
+	Hash,
+	Serialize,
+	Deserialize,
+)]
+#[serde(transparent)]
+pub struct Tenant(Id);
+
+impl Tenant {
+	pub fn new(id: Id) -> Self {
+		Self(id)
+	}
+
+	pub fn id(&self) -> Id {
+		self.0
+	}
+}
+
+impl fmt::Display for Tenant {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		self.0.fmt(f)
+	}
+}
+
+/// Returns the sql to scope a table by tenant, should be used as a
+/// migration
+///
+/// Rows which already exist will not be part of any tenant.
+pub fn scope_table_sql(table: &str) -> String {
+	format!(
+		"\
+ALTER TABLE \"{table}\" ADD COLUMN tenant text DEFAULT {CURRENT_SQL};
+CREATE INDEX ON \"{table}\" (tenant);
+ALTER TABLE \"{table}\" ENABLE ROW LEVEL SECURITY;
+ALTER TABLE \"{table}\" FORCE ROW LEVEL SECURITY;
+CREATE POLICY \"{table}_tenant\" ON \"{table}\"
+	USING ({CURRENT_CONDITION})
+	WITH CHECK ({CURRENT_CONDITION});"
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn current_sql() {
+		// the setting is repeated in the constants
+		assert!(CURRENT_SQL.contains(SETTING));
+		assert!(CURRENT_CONDITION.ends_with(CURRENT_SQL));
+	}
+}
@@ -0,0 +1,205 @@
+//! Test helpers
+//!
+//! A [`TestPool`] uses a [`ManualClock`] and a seeded [`IdGenerator`], so
+//! every run creates the same ids and timestamps. Tests get a database with
+//! [`TestPool::isolated`] which is scoped to a new tenant, rows of other
+//! tests are never visible.
+//!
+//! To run the same test against every backend, set [`POSTGRES_CONFIG_ENV`] to the
+//! json [`Config`] of an empty database and iterate over
+//! [`TestPool::backends`].
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rand::{rngs::OsRng, RngCore};
+use serde_json::Value;
+
+use crate::{
+	clock::{IdGenerator, ManualClock},
+	dump::{Archive, Dump, DumpError, Header, VERSION},
+	id::{Id, Kind},
+	tenant::Tenant,
+	Config, Database, DatabasePool,
+};
+
+/// The seed used by [`TestPool::memory`]
+pub const SEED: u64 = 42;
+
+/// The environment variable containing the postgres config
+pub const POSTGRES_CONFIG_ENV: &str = "DATABASE_TEST_CONFIG";
+
+/// The kind of the tenants created by [`TestPool::isolated`]
+pub const TENANT_KIND: Kind = Kind::new(false, 0x7fff);
+
+/// The time every manual clock starts at, 2023-11-14
+pub fn start_time() -> SystemTime {
+	UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+}
+
+#[derive(Debug, Clone)]
+pub struct TestPool {
+	pool: DatabasePool,
+	clock: ManualClock,
+}
+
+impl TestPool {
+	/// Creates a memory pool seeded with [`SEED`]
+	pub fn memory() -> Self {
+		Self::memory_seeded(SEED)
+	}
+
+	pub fn memory_seeded(seed: u64) -> Self {
+		Self::with_pool(DatabasePool::new_memory(), seed)
+	}
+
+	/// Creates a key-value pool which is not persisted, seeded with
+	/// [`SEED`]
+	pub fn kv() -> Self {
+		let pool = DatabasePool::new_kv_memory().expect("kv store failed");
+		Self::with_pool(pool, SEED)
+	}
+
+	/// Creates a postgres pool if [`POSTGRES_CONFIG_ENV`] is set
+	///
+	/// The rows of earlier runs are still stored, so the seed is random
+	/// and the ids are only deterministic within a run.
+	pub async fn postgres() -> Option<Self> {
+		let config = std::env::var(POSTGRES_CONFIG_ENV).ok()?;
+		let config: Config = serde_json::from_str(&config)
+			.unwrap_or_else(|e| panic!("{POSTGRES_CONFIG_ENV} is invalid {e}"));
+
+		let pool = DatabasePool::new_postgres(config)
+			.await
+			.expect("postgres test database failed");
+
+		Some(Self::with_pool(pool, OsRng.next_u64()))
+	}
+
+	/// Returns the memory, the key-value and the postgres pool if it is
+	/// configured
+	pub async fn backends() -> Vec<Self> {
+		let mut pools = vec![Self::memory(), Self::kv()];
+		pools.extend(Self::postgres().await);
+		pools
+	}
+
+	fn with_pool(pool: DatabasePool, seed: u64) -> Self {
+		let clock = ManualClock::new(start_time());
+		let ids = IdGenerator::seeded(seed, clock.clone());
+
+		Self {
+			pool: pool.with_id_generator(ids),
+			clock,
+		}
+	}
+
+	pub fn pool(&self) -> &DatabasePool {
+		&self.pool
+	}
+
+	pub fn clock(&self) -> &ManualClock {
+		&self.clock
+	}
+
+	pub fn advance(&self, duration: Duration) {
+		self.clock.advance(duration);
+	}
+
+	/// Creates an id with the seeded generator
+	pub fn new_id(&self, kind: Kind) -> Id {
+		self.pool.ids.new_id(kind)
+	}
+
+	/// Returns a database scoped to a new tenant
+	pub async fn isolated(&self) -> Database {
+		let tenant = Tenant::new(self.new_id(TENANT_KIND));
+
+		self.pool
+			.get_tenant(tenant)
+			.await
+			.expect("could not get a database")
+	}
+}
+
+/// Rows which get restored before a test, the json contains an array of
+/// rows for every table
+///
+/// ```json
+/// { "users": [{ "id": "...", "email": "a@rust.com" }] }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Fixtures {
+	tables: BTreeMap<String, Vec<Value>>,
+}
+
+impl Fixtures {
+	pub fn from_json(s: &str) -> Result<Self, DumpError> {
+		Ok(Self {
+			tables: serde_json::from_str(s)?,
+		})
+	}
+
+	pub fn from_file(path: impl AsRef<Path>) -> Result<Self, DumpError> {
+		Self::from_json(&std::fs::read_to_string(path)?)
+	}
+
+	/// Restores the rows into the tenant of the database, see
+	/// [`Dump::restore`]
+	pub async fn load(
+		&self,
+		dump: &Dump,
+		db: &mut Database,
+	) -> Result<(), DumpError> {
+		let archive = Archive {
+			header: Header {
+				version: VERSION,
+				tenant: db.tenant(),
+				// fixtures don't depend on migrations
+				migrations: vec![],
+			},
+			tables: self.tables.clone(),
+		};
+
+		dump.restore(db, archive).await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::id::Id;
+
+	#[tokio::test]
+	async fn deterministic() {
+		let kind = Kind::new(false, 1);
+
+		async fn generate(pool: &TestPool, kind: Kind) -> (Id, SystemTime) {
+			let db = pool.isolated().await;
+			let conn = db.connection();
+			assert_eq!(conn.tenant().unwrap().id().kind(), TENANT_KIND);
+
+			(conn.new_id(kind), conn.now())
+		}
+
+		let a = TestPool::memory();
+		let b = TestPool::memory();
+		let first = generate(&a, kind).await;
+		assert_eq!(first, generate(&b, kind).await);
+		assert_eq!(first.1, start_time());
+
+		b.advance(Duration::from_secs(5));
+		let (id, now) = generate(&b, kind).await;
+		assert_eq!(now, start_time() + Duration::from_secs(5));
+		assert_ne!(generate(&a, kind).await.0, id);
+	}
+
+	#[test]
+	fn fixtures() {
+		let fixtures = Fixtures::from_json(r#"{ "users": [{ "a": 1 }] }"#);
+		assert_eq!(fixtures.unwrap().tables["users"].len(), 1);
+
+		assert!(Fixtures::from_json("[]").is_err());
+	}
+}
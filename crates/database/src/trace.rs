@@ -0,0 +1,205 @@
+//! Tracing
+//!
+//! Statements executed with [`Connection::traced`](crate::Connection::traced)
+//! run inside a `db.statement` span which records the table, the backend,
+//! the returned rows and the duration.
+//!
+//! Statements which take longer than the slow query threshold are logged as
+//! a warning with the target `database::slow_query`. Parameters are never
+//! logged, only if they are null.
+
+use std::fmt::{self, Write};
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+pub use postgres_types::ToSql;
+use tracing::{field, Instrument};
+
+use crate::{
+	batch::{BatchResult, RowFailure},
+	query::Page,
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TraceConfig {
+	/// Statements taking longer are logged as slow queries
+	pub slow_query: Option<Duration>,
+}
+
+/// A statement which is about to be executed
+#[derive(Clone, Copy)]
+pub struct Statement<'a> {
+	pub table: &'a str,
+	/// The sql or with the memory backend the name of the operation
+	pub statement: &'a str,
+	pub params: &'a [&'a (dyn ToSql + Sync)],
+}
+
+impl<'a> Statement<'a> {
+	pub fn new(table: &'a str, statement: &'a str) -> Self {
+		Self {
+			table,
+			statement,
+			params: &[],
+		}
+	}
+
+	pub fn params(mut self, params: &'a [&'a (dyn ToSql + Sync)]) -> Self {
+		self.params = params;
+		self
+	}
+}
+
+impl fmt::Debug for Statement<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Statement")
+			.field("table", &self.table)
+			.field("statement", &self.statement)
+			.field("params", &redact(self.params))
+			.finish()
+	}
+}
+
+/// The amount of rows a statement returned or affected
+pub trait RowCount {
+	/// Returns None if the amount is not known
+	fn row_count(&self) -> Option<usize>;
+}
+
+impl RowCount for () {
+	fn row_count(&self) -> Option<usize> {
+		None
+	}
+}
+
+impl RowCount for u64 {
+	fn row_count(&self) -> Option<usize> {
+		Some(*self as usize)
+	}
+}
+
+/// If a single row was affected
+impl RowCount for bool {
+	fn row_count(&self) -> Option<usize> {
+		Some(*self as usize)
+	}
+}
+
+impl<T> RowCount for Option<T> {
+	fn row_count(&self) -> Option<usize> {
+		Some(self.is_some() as usize)
+	}
+}
+
+impl<T> RowCount for Vec<T> {
+	fn row_count(&self) -> Option<usize> {
+		Some(self.len())
+	}
+}
+
+impl<T> RowCount for Page<T> {
+	fn row_count(&self) -> Option<usize> {
+		Some(self.items.len())
+	}
+}
+
+/// The applied rows and the failures of a batch
+impl<T> RowCount for (Vec<T>, Vec<RowFailure>) {
+	fn row_count(&self) -> Option<usize> {
+		Some(self.0.len())
+	}
+}
+
+impl RowCount for BatchResult {
+	fn row_count(&self) -> Option<usize> {
+		Some(self.applied.len())
+	}
+}
+
+pub(crate) async fn traced<T, E, Fut>(
+	config: TraceConfig,
+	backend: &'static str,
+	stmt: Statement<'_>,
+	fut: Fut,
+) -> Result<T, E>
+where
+	T: RowCount,
+	E: fmt::Display,
+	Fut: Future<Output = Result<T, E>>,
+{
+	let span = tracing::debug_span!(
+		"db.statement",
+		db.table = stmt.table,
+		db.backend = backend,
+		db.rows = field::Empty,
+		db.duration_ms = field::Empty,
+	);
+
+	let start = Instant::now();
+	let res = fut.instrument(span.clone()).await;
+	let elapsed = start.elapsed();
+
+	span.record("db.duration_ms", elapsed.as_secs_f64() * 1000.0);
+	match &res {
+		Ok(v) => {
+			if let Some(rows) = v.row_count() {
+				span.record("db.rows", rows);
+			}
+		}
+		Err(e) => {
+			tracing::debug!(parent: &span, error = %e, "statement failed");
+		}
+	}
+
+	if config.slow_query.is_some_and(|t| elapsed >= t) {
+		tracing::warn!(
+			target: "database::slow_query",
+			parent: &span,
+			statement = stmt.statement,
+			params = %redact(stmt.params),
+			duration_ms = elapsed.as_millis() as u64,
+			"slow query on {}",
+			stmt.table
+		);
+	}
+
+	res
+}
+
+/// Replaces every parameter which is not null
+pub fn redact(params: &[&(dyn ToSql + Sync)]) -> String {
+	let mut s = String::from("[");
+
+	for (i, param) in params.iter().enumerate() {
+		if i != 0 {
+			s.push_str(", ");
+		}
+
+		let debug = format!("{param:?}");
+		let value = match debug.as_str() {
+			"None" | "Null" => "NULL",
+			_ => "<redacted>",
+		};
+		write!(s, "${} = {value}", i + 1).unwrap();
+	}
+
+	s.push(']');
+	s
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::query::Value;
+
+	#[test]
+	fn redacts_params() {
+		let email = "secret@rust.com";
+		let none: Option<i32> = None;
+
+		assert_eq!(
+			redact(&[&email, &none, &Value::Null]),
+			"[$1 = <redacted>, $2 = NULL, $3 = NULL]"
+		);
+	}
+}
@@ -1,10 +1,15 @@
-use std::ops::{Deref, DerefMut};
+//! Guards
+//!
+//! A guard proves that a value was checked, a [`Valid`] can only be created
+//! by the validation of its value and can't be modified afterwards.
 
-#[derive(Debug, Clone)]
+use std::ops::Deref;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Valid<T>(T);
 
 impl<T> Valid<T> {
-	pub fn assume_valid(v: T) -> Self {
+	pub(crate) fn assume_valid(v: T) -> Self {
 		Self(v)
 	}
 
@@ -20,9 +25,3 @@ impl<T> Deref for Valid<T> {
 		&self.0
 	}
 }
-
-impl<T> DerefMut for Valid<T> {
-	fn deref_mut(&mut self) -> &mut Self::Target {
-		&mut self.0
-	}
-}
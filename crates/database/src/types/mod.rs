@@ -1,2 +1,4 @@
 pub mod component;
-pub(crate) mod guards;
+pub mod guards;
+pub mod schema;
+pub mod validate;
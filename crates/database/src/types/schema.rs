@@ -0,0 +1,284 @@
+//! Schema
+//!
+//! The schema holds all components and propagates them to the database
+//! layout. It only accepts components which where validated against it.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use super::component::{Component, FieldKind};
+use super::guards::Valid;
+use super::validate::{self, ValidationError};
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SchemaError {
+	#[error("the component is invalid {0}")]
+	Invalid(#[from] ValidationError),
+
+	#[error("the component {0} does not exist")]
+	NotFound(String),
+
+	#[error("the component {component} is still used by {used_by}")]
+	InUse { component: String, used_by: String },
+
+	#[error("the change breaks the component {dependent}: {error}")]
+	BreaksDependent {
+		dependent: String,
+		error: ValidationError,
+	},
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+	components: BTreeMap<String, Valid<Component>>,
+}
+
+impl Schema {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Creates a schema from components which may reference each other, so
+	/// every component is validated against all of them
+	pub fn from_components(
+		components: impl IntoIterator<Item = Component>,
+	) -> Result<Self, SchemaError> {
+		let all = components
+			.into_iter()
+			.map(|c| (c.name.clone(), Valid::assume_valid(c)))
+			.collect::<BTreeMap<_, _>>();
+
+		let components = all
+			.values()
+			.map(|c| {
+				let c = validate::validate((**c).clone(), &all)?;
+				Ok((c.name.clone(), c))
+			})
+			.collect::<Result<_, ValidationError>>()?;
+
+		Ok(Self { components })
+	}
+
+	/// Validates the component against the components of this schema
+	pub fn validate(
+		&self,
+		component: Component,
+	) -> Result<Valid<Component>, ValidationError> {
+		validate::validate(component, &self.components)
+	}
+
+	/// Inserts or replaces a component
+	///
+	/// The component is validated again, since this schema might have
+	/// changed after it was validated. A replacement fails if a component
+	/// which uses it would not be valid anymore.
+	pub fn set_component(
+		&mut self,
+		component: Valid<Component>,
+	) -> Result<(), SchemaError> {
+		let component = self.validate(component.into_inner())?;
+		let name = component.name.clone();
+
+		let mut components = self.components.clone();
+		components.insert(name.clone(), component);
+
+		for dependent in components.values().filter(|c| uses(c, &name)) {
+			if dependent.name == name {
+				continue;
+			}
+
+			validate::validate((**dependent).clone(), &components).map_err(
+				|error| SchemaError::BreaksDependent {
+					dependent: dependent.name.clone(),
+					error,
+				},
+			)?;
+		}
+
+		self.components = components;
+
+		Ok(())
+	}
+
+	/// Removes a component which is not used by another one
+	pub fn delete_component(&mut self, name: &str) -> Result<(), SchemaError> {
+		if !self.components.contains_key(name) {
+			return Err(SchemaError::NotFound(name.to_string()));
+		}
+
+		let used_by = self
+			.components
+			.values()
+			.find(|c| c.name != name && uses(c, name));
+		if let Some(used_by) = used_by {
+			return Err(SchemaError::InUse {
+				component: name.to_string(),
+				used_by: used_by.name.clone(),
+			});
+		}
+
+		self.components.remove(name);
+
+		Ok(())
+	}
+
+	pub fn component(&self, name: &str) -> Option<&Valid<Component>> {
+		self.components.get(name)
+	}
+
+	pub fn components(&self) -> impl Iterator<Item = &Valid<Component>> {
+		self.components.values()
+	}
+}
+
+/// Returns true if a field of the component is nested or related to the
+/// component with the name
+fn uses(component: &Component, name: &str) -> bool {
+	component.fields.iter().any(|f| match &f.kind {
+		FieldKind::Component { name: n } => n == name,
+		_ => f
+			.related
+			.as_ref()
+			.is_some_and(|r| r.split_once('.').is_some_and(|(c, _)| c == name)),
+	})
+}
+
+/// Returns the postgres statement which creates the table of the component
+pub fn create_table_sql(component: &Valid<Component>) -> String {
+	let mut sql = format!("CREATE TABLE \"{}\" (\n", component.name);
+	let mut indexes = String::new();
+
+	for (i, field) in component.fields.iter().enumerate() {
+		if i != 0 {
+			sql.push_str(",\n");
+		}
+
+		let ty = match &field.kind {
+			FieldKind::Id | FieldKind::ComponentId => "text",
+			// nested components reference the id of the row
+			FieldKind::Component { .. } => "text",
+			FieldKind::Boolean => "boolean",
+			FieldKind::Int => "bigint",
+			FieldKind::Float => "double precision",
+			FieldKind::Text => "text",
+			FieldKind::Json => "jsonb",
+			FieldKind::DateTime => "timestamp",
+		};
+		write!(sql, "\t\"{}\" {ty}", field.name).unwrap();
+
+		if field.primary {
+			sql.push_str(" PRIMARY KEY");
+		}
+
+		if let Some((table, column)) =
+			field.related.as_ref().and_then(|r| r.split_once('.'))
+		{
+			write!(sql, " REFERENCES \"{table}\" (\"{column}\")").unwrap();
+		}
+
+		if field.index && !field.primary {
+			write!(
+				indexes,
+				"\nCREATE INDEX ON \"{}\" (\"{}\");",
+				component.name, field.name
+			)
+			.unwrap();
+		}
+	}
+
+	sql.push_str("\n);");
+	sql.push_str(&indexes);
+	sql
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::types::component::Field;
+
+	fn component(name: &str, fields: Vec<Field>) -> Component {
+		let id = Field {
+			name: "id".into(),
+			kind: FieldKind::Id,
+			related: None,
+			primary: true,
+			index: false,
+		};
+
+		Component {
+			name: name.into(),
+			fields: [vec![id], fields].concat(),
+		}
+	}
+
+	#[test]
+	fn schema() {
+		let mut schema = Schema::new();
+
+		let artists = component("component_artists", vec![]);
+		let artists = schema.validate(artists).unwrap();
+		schema.set_component(artists).unwrap();
+
+		let event = component(
+			"component_event",
+			vec![Field {
+				name: "artists".into(),
+				kind: FieldKind::Component {
+					name: "component_artists".into(),
+				},
+				related: None,
+				primary: false,
+				index: true,
+			}],
+		);
+		let event = schema.validate(event).unwrap();
+		assert_eq!(
+			create_table_sql(&event),
+			"CREATE TABLE \"component_event\" (\n\
+			\t\"id\" text PRIMARY KEY,\n\
+			\t\"artists\" text\n);\n\
+			CREATE INDEX ON \"component_event\" (\"artists\");"
+		);
+		schema.set_component(event).unwrap();
+
+		assert!(matches!(
+			schema.delete_component("component_artists"),
+			Err(SchemaError::InUse { .. })
+		));
+		schema.delete_component("component_event").unwrap();
+		schema.delete_component("component_artists").unwrap();
+	}
+
+	#[test]
+	fn dependents() {
+		let field = |name: &str, kind, related: Option<&str>| Field {
+			name: name.into(),
+			kind,
+			related: related.map(Into::into),
+			primary: false,
+			index: false,
+		};
+		let label = field("label", FieldKind::Id, Some("component_label.name"));
+		let event = component("component_event", vec![label]);
+
+		let mut schema = Schema::from_components([
+			event.clone(),
+			component(
+				"component_label",
+				vec![field("name", FieldKind::Text, None)],
+			),
+		])
+		.unwrap();
+
+		// the event is related to the name of the label
+		let replaced = schema.validate(component("component_label", vec![]));
+		assert!(matches!(
+			schema.set_component(replaced.unwrap()),
+			Err(SchemaError::BreaksDependent { dependent, .. })
+				if dependent == "component_event"
+		));
+		assert!(schema.component("component_label").unwrap().fields.len() == 2);
+
+		assert!(Schema::from_components([event]).is_err());
+	}
+}
@@ -0,0 +1,318 @@
+//! Validation of components
+//!
+//! A component is validated against the components which already exist in a
+//! [`Schema`](super::schema::Schema), the only output is a
+//! [`Valid<Component>`].
+
+use std::collections::{BTreeMap, HashSet};
+
+use super::component::{Component, FieldKind};
+use super::guards::Valid;
+
+/// Every component name is required to start with this prefix
+pub const COMPONENT_PREFIX: &str = "component_";
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ValidationError {
+	#[error(
+		"the component name {0} needs to start with {COMPONENT_PREFIX} and \
+		 only contain a-z, 0-9 or _"
+	)]
+	InvalidComponentName(String),
+
+	#[error("the field name {0} may only contain a-z, A-Z, 0-9 or _")]
+	InvalidFieldName(String),
+
+	#[error("the field {0} exists more than once")]
+	DuplicateField(String),
+
+	#[error("exactly one primary field with the kind id is required")]
+	MissingPrimary,
+
+	#[error("more than one primary field exists: {0:?}")]
+	MultiplePrimary(Vec<String>),
+
+	#[error("the primary field {0} needs to have the kind id")]
+	PrimaryNotId(String),
+
+	#[error("the field {field} is related to {target} which does not exist")]
+	UnknownRelated { field: String, target: String },
+
+	#[error(
+		"the field {field} uses the component {component} which does not exist"
+	)]
+	UnknownComponent { field: String, component: String },
+
+	#[error("the components reference each other {0:?}")]
+	Cycle(Vec<String>),
+}
+
+/// Validates the component against the existing components
+///
+/// A component with the same name in `existing` get's replaced by the new
+/// one.
+pub(crate) fn validate(
+	component: Component,
+	existing: &BTreeMap<String, Valid<Component>>,
+) -> Result<Valid<Component>, ValidationError> {
+	if !valid_component_name(&component.name) {
+		return Err(ValidationError::InvalidComponentName(component.name));
+	}
+
+	let mut names = HashSet::new();
+	for field in &component.fields {
+		if !valid_field_name(&field.name) {
+			return Err(ValidationError::InvalidFieldName(field.name.clone()));
+		}
+
+		if !names.insert(field.name.as_str()) {
+			return Err(ValidationError::DuplicateField(field.name.clone()));
+		}
+	}
+
+	check_primary(&component)?;
+
+	let lookup = |name: &str| -> Option<&Component> {
+		if name == component.name {
+			Some(&component)
+		} else {
+			existing.get(name).map(|c| &**c)
+		}
+	};
+
+	for field in &component.fields {
+		if let Some(target) = &field.related {
+			let exists = target
+				.split_once('.')
+				.and_then(|(comp, f)| Some((lookup(comp)?, f)))
+				.is_some_and(|(comp, f)| {
+					comp.fields.iter().any(|x| x.name == f)
+				});
+
+			if !exists {
+				return Err(ValidationError::UnknownRelated {
+					field: field.name.clone(),
+					target: target.clone(),
+				});
+			}
+		}
+
+		if let FieldKind::Component { name } = &field.kind {
+			if lookup(name).is_none() {
+				return Err(ValidationError::UnknownComponent {
+					field: field.name.clone(),
+					component: name.clone(),
+				});
+			}
+		}
+	}
+
+	let mut path = vec![component.name.clone()];
+	if find_cycle(&component, &lookup, &mut path) {
+		return Err(ValidationError::Cycle(path));
+	}
+
+	Ok(Valid::assume_valid(component))
+}
+
+fn check_primary(component: &Component) -> Result<(), ValidationError> {
+	let primary = component
+		.fields
+		.iter()
+		.filter(|f| f.primary)
+		.collect::<Vec<_>>();
+
+	match primary.as_slice() {
+		[] => Err(ValidationError::MissingPrimary),
+		[field] if !matches!(field.kind, FieldKind::Id) => {
+			Err(ValidationError::PrimaryNotId(field.name.clone()))
+		}
+		[_] => Ok(()),
+		fields => Err(ValidationError::MultiplePrimary(
+			fields.iter().map(|f| f.name.clone()).collect(),
+		)),
+	}
+}
+
+/// Returns true if a component referenced from `component` leads back to a
+/// component in the path, the path then contains the cycle
+fn find_cycle<'a, F>(
+	component: &'a Component,
+	lookup: &F,
+	path: &mut Vec<String>,
+) -> bool
+where
+	F: Fn(&str) -> Option<&'a Component>,
+{
+	for field in &component.fields {
+		let FieldKind::Component { name } = &field.kind else {
+			continue;
+		};
+
+		let seen = path.contains(name);
+		path.push(name.clone());
+		if seen {
+			return true;
+		}
+
+		// unknown components are reported before
+		if let Some(next) = lookup(name) {
+			if find_cycle(next, lookup, path) {
+				return true;
+			}
+		}
+
+		path.pop();
+	}
+
+	false
+}
+
+fn valid_component_name(name: &str) -> bool {
+	name.strip_prefix(COMPONENT_PREFIX).is_some_and(|rest| {
+		!rest.is_empty()
+			&& rest.chars().all(|c| {
+				c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'
+			})
+	})
+}
+
+fn valid_field_name(name: &str) -> bool {
+	!name.is_empty()
+		&& name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::types::component::Field;
+
+	fn field(name: &str, kind: FieldKind) -> Field {
+		Field {
+			name: name.into(),
+			kind,
+			related: None,
+			primary: false,
+			index: false,
+		}
+	}
+
+	fn component(name: &str, mut fields: Vec<Field>) -> Component {
+		let mut id = field("id", FieldKind::Id);
+		id.primary = true;
+		fields.insert(0, id);
+
+		Component {
+			name: name.into(),
+			fields,
+		}
+	}
+
+	fn existing(
+		components: Vec<Component>,
+	) -> BTreeMap<String, Valid<Component>> {
+		components
+			.into_iter()
+			.map(|c| (c.name.clone(), Valid::assume_valid(c)))
+			.collect()
+	}
+
+	#[test]
+	fn valid() {
+		let artists = component("component_artists", vec![]);
+		let mut label = field("label", FieldKind::Id);
+		label.related = Some("component_artists.id".into());
+		let event = component(
+			"component_event",
+			vec![
+				field(
+					"artists",
+					FieldKind::Component {
+						name: "component_artists".into(),
+					},
+				),
+				label,
+			],
+		);
+
+		let valid = validate(event, &existing(vec![artists])).unwrap();
+		assert_eq!(valid.name, "component_event");
+	}
+
+	#[test]
+	fn invalid() {
+		let none = BTreeMap::new();
+
+		let comp = component("event", vec![]);
+		assert!(matches!(
+			validate(comp, &none),
+			Err(ValidationError::InvalidComponentName(_))
+		));
+
+		let comp =
+			component("component_event", vec![field("id", FieldKind::Id)]);
+		assert!(matches!(
+			validate(comp, &none),
+			Err(ValidationError::DuplicateField(_))
+		));
+
+		let mut comp = component("component_event", vec![]);
+		comp.fields[0].primary = false;
+		assert_eq!(validate(comp, &none), Err(ValidationError::MissingPrimary));
+
+		let mut comp = component("component_event", vec![]);
+		comp.fields[0].kind = FieldKind::Text;
+		assert!(matches!(
+			validate(comp, &none),
+			Err(ValidationError::PrimaryNotId(_))
+		));
+
+		let mut related = field("artist", FieldKind::Id);
+		related.related = Some("component_artists.id".into());
+		let comp = component("component_event", vec![related]);
+		assert!(matches!(
+			validate(comp, &none),
+			Err(ValidationError::UnknownRelated { .. })
+		));
+
+		let nested = field(
+			"artists",
+			FieldKind::Component {
+				name: "component_artists".into(),
+			},
+		);
+		let comp = component("component_event", vec![nested]);
+		assert!(matches!(
+			validate(comp, &none),
+			Err(ValidationError::UnknownComponent { .. })
+		));
+	}
+
+	#[test]
+	fn cycle() {
+		let nested = |name: &str| {
+			field("nested", FieldKind::Component { name: name.into() })
+		};
+
+		// a references b, updating b to reference a creates a cycle
+		let a = component("component_a", vec![nested("component_b")]);
+		let b = component("component_b", vec![]);
+		let existing = existing(vec![a, b]);
+
+		let b = component("component_b", vec![nested("component_a")]);
+		assert_eq!(
+			validate(b, &existing),
+			Err(ValidationError::Cycle(vec![
+				"component_b".into(),
+				"component_a".into(),
+				"component_b".into()
+			]))
+		);
+
+		let c = component("component_c", vec![nested("component_c")]);
+		assert!(matches!(
+			validate(c, &existing),
+			Err(ValidationError::Cycle(_))
+		));
+	}
+}
@@ -0,0 +1,1418 @@
+//! Execution
+//!
+//! Every root field lists the objects of a type. The arguments of the field
+//! are translated into a database [`Query`] and a [`Resolver`] loads the
+//! objects, the selection set, including fragments and inline fragments, is
+//! then applied to them.
+//!
+//! Mutations are executed one after another, the data is passed to the
+//! [`Resolver`] which validates it and reports the invalid fields.
+//!
+//! Every list can be subscribed to as well. A subscription is executed like a
+//! query, the transport executes it again when the objects of the
+//! [subscribed types](Executor::subscribed_types) change.
+//!
+//! The values of custom scalars are serialized and the inputs parsed by the
+//! hooks of their [`Scalar`](crate::scalar::Scalar).
+//!
+//! Errors are reported like the spec describes, with the path of the field
+//! and null propagated to the closest nullable parent.
+//!
+//! A list which has more objects than returned reports the cursor of the next
+//! page in the `next` extension, by the response key of the field. Passing
+//! it as the `after` argument returns the following objects.
+//!
+//! ```json
+//! { "data": { "events": [...] }, "extensions": { "next": { "events": "..." } } }
+//! ```
+
+use std::collections::HashMap;
+
+use apollo_compiler::{
+	ast::{
+		DirectiveList, Name, OperationType, Type as ApolloType,
+		Value as ApolloValue,
+	},
+	executable::{self, Field, SelectionSet},
+	execution::{coerce_variable_values, ResponseDataPathElement},
+	schema::ExtendedType,
+	ExecutableDocument, Node,
+};
+use database::query::{Page, Query};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+pub use apollo_compiler::execution::{
+	GraphQLError, JsonMap, JsonValue, Response, ResponseData,
+};
+
+use crate::{
+	create_schema, filter,
+	mutation::{Action, MutationProperty},
+	scalar::{ScalarError, Scalars},
+	Property, Schema, SchemaError, Type, Valid,
+};
+
+/// A request as sent by a client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Request {
+	pub query: String,
+	#[serde(default)]
+	pub operation_name: Option<String>,
+	#[serde(default)]
+	pub variables: JsonMap,
+}
+
+/// The objects a root field needs
+#[derive(Debug, Clone)]
+pub struct Plan {
+	/// The name of the object type
+	pub type_name: String,
+	pub query: Query,
+	/// The fields which are selected by the query
+	pub columns: Vec<String>,
+	/// The columns with the fields selected on the objects they return
+	pub selection: Selection,
+}
+
+/// The fields selected on objects, with the fields selected on the objects
+/// those fields return
+///
+/// The fields of every object type an interface or union can return are
+/// merged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selection {
+	fields: IndexMap<String, Selection>,
+}
+
+impl Selection {
+	/// Returns the selection of the objects the field returns, None if the
+	/// field is not selected
+	pub fn field(&self, name: &str) -> Option<&Selection> {
+		self.fields.get(name)
+	}
+
+	/// Returns the names of the selected fields in the order of the request
+	pub fn names(&self) -> impl Iterator<Item = &str> {
+		self.fields.keys().map(String::as_str)
+	}
+
+	pub fn merge(&mut self, other: Selection) {
+		for (name, selection) in other.fields {
+			self.fields.entry(name).or_default().merge(selection);
+		}
+	}
+}
+
+/// A mutation of an object
+#[derive(Debug, Clone)]
+pub struct Mutation {
+	/// The name of the object type
+	pub type_name: String,
+	pub action: Action,
+	/// Set when updating or deleting
+	pub id: Option<String>,
+	/// The fields of the input, empty when deleting
+	pub data: JsonMap,
+	/// The fields which are selected by the query
+	pub columns: Vec<String>,
+	/// The columns with the fields selected on the objects they return
+	pub selection: Selection,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct ResolveError(pub String);
+
+/// A field of the input data which is invalid
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("the field {field} is invalid: {message}")]
+pub struct FieldError {
+	pub field: String,
+	pub message: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MutationError {
+	/// Every invalid field is reported as its own error
+	#[error("the data is invalid")]
+	Invalid(Vec<FieldError>),
+
+	#[error("{0}")]
+	Resolve(#[from] ResolveError),
+}
+
+#[async_trait::async_trait]
+pub trait Resolver: Send + Sync {
+	/// Returns the page of objects matching the plan, a missing field is
+	/// treated as null
+	async fn list(&self, plan: &Plan) -> Result<Page<JsonMap>, ResolveError>;
+
+	/// Applies the mutation and returns the object, None if the object to
+	/// update or delete does not exist
+	async fn mutate(
+		&self,
+		_mutation: &Mutation,
+	) -> Result<Option<JsonMap>, MutationError> {
+		Err(ResolveError("mutations are not supported".into()).into())
+	}
+}
+
+#[derive(Debug)]
+pub struct Executor {
+	schema: Valid<Schema>,
+	/// The object listed by each root field
+	objects: HashMap<String, Type>,
+	/// The action and the object type of each mutation
+	mutations: HashMap<String, (Action, String)>,
+	/// The custom scalars used by the schema
+	scalars: Scalars,
+}
+
+impl Executor {
+	/// Creates the schema from the properties, a property which does not
+	/// list objects cannot be resolved
+	///
+	/// The properties are used by the query and the subscription root.
+	pub fn new(
+		props: Vec<Property>,
+		mutations: Vec<MutationProperty>,
+	) -> Result<Self, SchemaError> {
+		let objects = props
+			.iter()
+			.filter_map(|p| Some((p.name.clone(), list_object(&p.ty)?.clone())))
+			.collect();
+
+		let (mutations, mutation_props): (HashMap<_, _>, Vec<_>) = mutations
+			.into_iter()
+			.map(|m| {
+				let type_name = type_name(&m.property.ty).to_string();
+				((m.property.name.clone(), (m.action, type_name)), m.property)
+			})
+			.unzip();
+
+		let mut scalars = Scalars::new();
+		scalars.collect(&props);
+		scalars.collect(&mutation_props);
+
+		Ok(Self {
+			schema: create_schema(props.clone(), mutation_props, props)?,
+			objects,
+			mutations,
+			scalars,
+		})
+	}
+
+	pub fn schema(&self) -> &Valid<Schema> {
+		&self.schema
+	}
+
+	/// Returns the object types listed by a subscription, empty if the
+	/// request is not a valid subscription
+	pub fn subscribed_types(&self, request: &Request) -> Vec<String> {
+		let Ok(document) = ExecutableDocument::parse_and_validate(
+			&self.schema,
+			&request.query,
+			"request.graphql",
+		) else {
+			return vec![];
+		};
+
+		let operation =
+			document.get_operation(request.operation_name.as_deref());
+		let Ok(operation) = operation else {
+			return vec![];
+		};
+		if operation.operation_type != OperationType::Subscription {
+			return vec![];
+		}
+
+		let mut types = vec![];
+		for field in operation.selection_set.fields() {
+			let type_name = field.ty().inner_named_type().to_string();
+			if field.name != "__typename" && !types.contains(&type_name) {
+				types.push(type_name);
+			}
+		}
+
+		types
+	}
+
+	pub async fn execute(
+		&self,
+		resolver: &dyn Resolver,
+		request: &Request,
+	) -> Response {
+		let document = match ExecutableDocument::parse_and_validate(
+			&self.schema,
+			&request.query,
+			"request.graphql",
+		) {
+			Ok(doc) => doc,
+			Err(e) => {
+				return Response {
+					errors: e.errors.iter().map(|d| d.to_json()).collect(),
+					data: ResponseData::Absent,
+					extensions: JsonMap::new(),
+				}
+			}
+		};
+
+		let sources = &document.sources;
+		let operation =
+			match document.get_operation(request.operation_name.as_deref()) {
+				Ok(op) => op,
+				Err(_) => {
+					return Response::from_request_error(GraphQLError::new(
+						"the operation does not exist",
+						None,
+						sources,
+					))
+				}
+			};
+
+		let variables = match coerce_variable_values(
+			&self.schema,
+			operation,
+			&request.variables,
+		) {
+			Ok(v) => v.into_inner(),
+			Err(e) => {
+				return Response::from_request_error(
+					e.into_graphql_error(sources),
+				)
+			}
+		};
+
+		let mut ctx = Context {
+			schema: &self.schema,
+			document: &document,
+			variables: &variables,
+			scalars: &self.scalars,
+			errors: vec![],
+			next: JsonMap::new(),
+		};
+		let data = self
+			.execute_root(
+				resolver,
+				&mut ctx,
+				operation.operation_type,
+				&operation.selection_set,
+			)
+			.await;
+
+		let mut extensions = JsonMap::new();
+		if !ctx.next.is_empty() {
+			extensions.insert("next", JsonValue::Object(ctx.next));
+		}
+
+		Response {
+			errors: ctx.errors,
+			data: data.ok().into(),
+			extensions,
+		}
+	}
+
+	/// The root fields are executed one after another, which is required for
+	/// mutations
+	async fn execute_root<'a>(
+		&self,
+		resolver: &dyn Resolver,
+		ctx: &mut Context<'a>,
+		operation: OperationType,
+		selection_set: &'a SelectionSet,
+	) -> Result<JsonMap, PropagateNull> {
+		let type_name = selection_set.ty.as_str();
+		let mut fields = IndexMap::new();
+		ctx.collect_fields(type_name, selection_set, &mut fields);
+
+		let mut data = JsonMap::new();
+		for (key, fields) in fields {
+			let field = fields[0];
+			if field.name == "__typename" {
+				data.insert(key.as_str(), type_name.into());
+				continue;
+			}
+
+			let mut path = vec![ResponseDataPathElement::Field(key.clone())];
+			let ty = &field.definition.ty;
+			let value = match operation {
+				OperationType::Mutation => {
+					self.mutate(resolver, ctx, &fields).await
+				}
+				_ => match self.resolve(resolver, ctx, &fields).await {
+					Ok(page) => {
+						if let Some(next) = page.next {
+							ctx.next.insert(key.as_str(), next.to_b64().into());
+						}
+
+						let objects =
+							page.items.into_iter().map(JsonValue::Object);
+						Ok(JsonValue::Array(objects.collect()))
+					}
+					Err(e) => Err(e.into()),
+				},
+			};
+
+			let value = match value {
+				Ok(value) => ctx.complete_value(ty, &fields, value, &mut path),
+				Err(e) => {
+					match e {
+						MutationError::Invalid(errors) => {
+							for error in errors {
+								ctx.error(error.to_string(), field, &path)
+									.extensions
+									.insert("field", error.field.into());
+							}
+						}
+						e => {
+							ctx.error(e.to_string(), field, &path);
+						}
+					}
+
+					match ty.is_non_null() {
+						true => Err(PropagateNull),
+						false => Ok(JsonValue::Null),
+					}
+				}
+			};
+
+			data.insert(key.as_str(), value?);
+		}
+
+		Ok(data)
+	}
+
+	/// Plans and loads the objects of a root field
+	async fn resolve(
+		&self,
+		resolver: &dyn Resolver,
+		ctx: &Context<'_>,
+		fields: &[&Node<Field>],
+	) -> Result<Page<JsonMap>, ResolveError> {
+		let field = fields[0];
+		let object =
+			self.objects.get(field.name.as_str()).ok_or_else(|| {
+				ResolveError(format!(
+					"the field {} cannot be resolved",
+					field.name
+				))
+			})?;
+
+		let query = filter::query_from_arguments(
+			object,
+			&field.arguments,
+			ctx.variables,
+		)
+		.map_err(|e| ResolveError(e.to_string()))?;
+
+		let type_name = field.definition.ty.inner_named_type();
+		let selection = ctx.selection(type_name, fields);
+		let plan = Plan {
+			type_name: type_name.to_string(),
+			query,
+			columns: selection.names().map(Into::into).collect(),
+			selection,
+		};
+
+		resolver.list(&plan).await
+	}
+
+	/// Applies a mutation and returns the object or null
+	async fn mutate(
+		&self,
+		resolver: &dyn Resolver,
+		ctx: &Context<'_>,
+		fields: &[&Node<Field>],
+	) -> Result<JsonValue, MutationError> {
+		let field = fields[0];
+		let (action, type_name) =
+			self.mutations.get(field.name.as_str()).ok_or_else(|| {
+				ResolveError(format!(
+					"the field {} cannot be resolved",
+					field.name
+				))
+			})?;
+
+		let mut id = None;
+		let mut data = JsonMap::new();
+		for arg in &field.arguments {
+			match (arg.name.as_str(), value_to_json(&arg.value, ctx.variables))
+			{
+				("id", Some(value)) => id = Some(value),
+				("data", Some(JsonValue::Object(object))) => data = object,
+				_ => {}
+			}
+		}
+
+		// an id of a custom scalar is parsed as well
+		let id_arg = field.definition.argument_by_name("id");
+		if let (Some(value), Some(arg)) = (&mut id, id_arg) {
+			ctx.parse_input(&arg.ty, value).map_err(|e| {
+				MutationError::Invalid(vec![FieldError {
+					field: "id".into(),
+					message: e.to_string(),
+				}])
+			})?;
+		}
+		let id = id.and_then(|id| match id {
+			JsonValue::String(s) => Some(s.as_str().into()),
+			JsonValue::Number(n) => Some(n.to_string()),
+			_ => None,
+		});
+
+		// the custom scalars of the data are parsed
+		let input = field.definition.argument_by_name("data").and_then(|arg| {
+			ctx.schema.get_input_object(arg.ty.inner_named_type())
+		});
+		if let Some(input) = input {
+			let mut errors = vec![];
+			for (name, value) in data.iter_mut() {
+				let Some(field) = input.fields.get(name.as_str()) else {
+					continue;
+				};
+
+				if let Err(e) = ctx.parse_input(&field.ty, value) {
+					errors.push(FieldError {
+						field: name.as_str().into(),
+						message: e.to_string(),
+					});
+				}
+			}
+
+			if !errors.is_empty() {
+				return Err(MutationError::Invalid(errors));
+			}
+		}
+
+		let selection = ctx.selection(type_name, fields);
+		let mutation = Mutation {
+			type_name: type_name.clone(),
+			action: *action,
+			id,
+			data,
+			columns: selection.names().map(Into::into).collect(),
+			selection,
+		};
+
+		let object = resolver.mutate(&mutation).await?;
+
+		Ok(object.map_or(JsonValue::Null, JsonValue::Object))
+	}
+}
+
+/// Signals that a non-null field is null, the parent needs to be null as well
+#[derive(Debug)]
+struct PropagateNull;
+
+type Path = Vec<ResponseDataPathElement>;
+
+struct Context<'a> {
+	schema: &'a Schema,
+	document: &'a ExecutableDocument,
+	variables: &'a JsonMap,
+	scalars: &'a Scalars,
+	errors: Vec<GraphQLError>,
+	/// The cursors of the lists which have more objects
+	next: JsonMap,
+}
+
+impl<'a> Context<'a> {
+	/// Adds an error, which can then be extended
+	fn error(
+		&mut self,
+		message: impl Into<String>,
+		field: &Node<Field>,
+		path: &Path,
+	) -> &mut GraphQLError {
+		let mut error = GraphQLError::new(
+			message,
+			field.location(),
+			&self.document.sources,
+		);
+		error.path = path.clone();
+		self.errors.push(error);
+		self.errors.last_mut().unwrap()
+	}
+
+	/// Parses the custom scalars of an input value in place
+	fn parse_input(
+		&self,
+		ty: &ApolloType,
+		value: &mut JsonValue,
+	) -> Result<(), ScalarError> {
+		let type_name = ty.inner_named_type();
+		match (ty, value) {
+			(_, JsonValue::Null) => Ok(()),
+			(
+				ApolloType::List(item) | ApolloType::NonNullList(item),
+				JsonValue::Array(items),
+			) => items.iter_mut().try_for_each(|v| self.parse_input(item, v)),
+			(_, value) => {
+				let object = self.schema.get_input_object(type_name);
+				match (self.scalars.get(type_name), object, value) {
+					(Some(scalar), _, value) => *value = (scalar.parse)(value)?,
+					(None, Some(object), JsonValue::Object(fields)) => {
+						for (name, value) in fields.iter_mut() {
+							let Some(field) = object.fields.get(name.as_str())
+							else {
+								continue;
+							};
+							self.parse_input(&field.ty, value)?;
+						}
+					}
+					// built-in scalars and enums are coerced by the validation
+					_ => {}
+				}
+
+				Ok(())
+			}
+		}
+	}
+
+	/// Returns the fields selected on the objects, `__typename` is left out
+	fn selection(
+		&self,
+		type_name: &str,
+		fields: &[&'a Node<Field>],
+	) -> Selection {
+		let mut selected = IndexMap::new();
+		for field in fields {
+			self.collect_fields(type_name, &field.selection_set, &mut selected);
+		}
+
+		let mut selection = Selection::default();
+		for fields in selected.values() {
+			let field = fields[0];
+			if field.name == "__typename" {
+				continue;
+			}
+
+			let ty = field.definition.ty.inner_named_type();
+			let mut objects = Selection::default();
+			for object in self.object_types(ty) {
+				objects.merge(self.selection(object, fields));
+			}
+
+			selection
+				.fields
+				.entry(field.name.to_string())
+				.or_default()
+				.merge(objects);
+		}
+
+		selection
+	}
+
+	/// Returns the object types a value of the type can have, none for
+	/// scalars and enums
+	fn object_types(&self, type_name: &str) -> Vec<&'a str> {
+		let schema = self.schema;
+		match schema.types.get(type_name) {
+			Some(ExtendedType::Object(object)) => vec![object.name.as_str()],
+			Some(ExtendedType::Interface(_) | ExtendedType::Union(_)) => schema
+				.types
+				.iter()
+				.filter(|(name, ty)| {
+					ty.is_object() && schema.is_subtype(type_name, name)
+				})
+				.map(|(name, _)| name.as_str())
+				.collect(),
+			_ => vec![],
+		}
+	}
+
+	/// Groups the fields by their response key, fields of fragments are only
+	/// added if the type condition applies
+	fn collect_fields(
+		&self,
+		type_name: &str,
+		selection_set: &'a SelectionSet,
+		fields: &mut IndexMap<Name, Vec<&'a Node<Field>>>,
+	) {
+		for selection in &selection_set.selections {
+			match selection {
+				executable::Selection::Field(field) => {
+					if self.include(&field.directives) {
+						let key = field.response_key().clone();
+						fields.entry(key).or_default().push(field);
+					}
+				}
+				executable::Selection::FragmentSpread(spread) => {
+					if !self.include(&spread.directives) {
+						continue;
+					}
+
+					// cycles are rejected by the validation
+					let fragment =
+						self.document.fragments.get(&spread.fragment_name);
+					if let Some(fragment) = fragment {
+						let set = &fragment.selection_set;
+						if self.applies(set.ty.as_str(), type_name) {
+							self.collect_fields(type_name, set, fields);
+						}
+					}
+				}
+				executable::Selection::InlineFragment(inline) => {
+					let applies = inline
+						.type_condition
+						.as_ref()
+						.is_none_or(|c| self.applies(c.as_str(), type_name));
+
+					if applies && self.include(&inline.directives) {
+						let set = &inline.selection_set;
+						self.collect_fields(type_name, set, fields);
+					}
+				}
+			}
+		}
+	}
+
+	fn applies(&self, condition: &str, type_name: &str) -> bool {
+		condition == type_name || self.schema.is_subtype(condition, type_name)
+	}
+
+	/// Evaluates the `@skip` and `@include` directives
+	fn include(&self, directives: &DirectiveList) -> bool {
+		let condition = |name: &str| {
+			let value = directives.get(name)?.argument_by_name("if")?;
+
+			Some(match value.as_ref() {
+				ApolloValue::Boolean(b) => *b,
+				ApolloValue::Variable(v) => self
+					.variables
+					.get(v.as_str())
+					.and_then(JsonValue::as_bool)
+					.unwrap_or(false),
+				_ => false,
+			})
+		};
+
+		!condition("skip").unwrap_or(false)
+			&& condition("include").unwrap_or(true)
+	}
+
+	/// Returns an error if a non-null value is null
+	fn complete_value(
+		&mut self,
+		ty: &ApolloType,
+		fields: &[&'a Node<Field>],
+		value: JsonValue,
+		path: &mut Path,
+	) -> Result<JsonValue, PropagateNull> {
+		if !ty.is_non_null() {
+			// the error was already reported
+			return Ok(self
+				.complete_nullable(ty, fields, value, path)
+				.unwrap_or(JsonValue::Null));
+		}
+
+		let value = self.complete_nullable(ty, fields, value, path)?;
+		if value.is_null() {
+			let message =
+				format!("the non-null field {} is null", fields[0].name);
+			self.error(message, fields[0], path);
+			return Err(PropagateNull);
+		}
+
+		Ok(value)
+	}
+
+	fn complete_nullable(
+		&mut self,
+		ty: &ApolloType,
+		fields: &[&'a Node<Field>],
+		value: JsonValue,
+		path: &mut Path,
+	) -> Result<JsonValue, PropagateNull> {
+		if value.is_null() {
+			return Ok(value);
+		}
+
+		match (ty, value) {
+			(
+				ApolloType::List(item) | ApolloType::NonNullList(item),
+				JsonValue::Array(values),
+			) => {
+				let mut list = Vec::with_capacity(values.len());
+				for (i, value) in values.into_iter().enumerate() {
+					path.push(ResponseDataPathElement::ListIndex(i));
+					let value = self.complete_value(item, fields, value, path);
+					path.pop();
+					list.push(value?);
+				}
+
+				Ok(JsonValue::Array(list))
+			}
+			(ApolloType::List(_) | ApolloType::NonNullList(_), _) => {
+				self.error("expected a list", fields[0], path);
+				Err(PropagateNull)
+			}
+			(ty, value) => {
+				let type_name = ty.inner_named_type();
+				let (is_object, is_abstract) =
+					match self.schema.types.get(type_name) {
+						Some(ExtendedType::Object(_)) => (true, false),
+						Some(
+							ExtendedType::Interface(_) | ExtendedType::Union(_),
+						) => (true, true),
+						_ => (false, false),
+					};
+
+				match value {
+					JsonValue::Object(object) if is_abstract => {
+						let concrete = self.concrete_type(type_name, &object);
+						let Some(concrete) = concrete else {
+							self.error(
+								"could not resolve the type of the object",
+								fields[0],
+								path,
+							);
+							return Err(PropagateNull);
+						};
+
+						self.complete_object(&concrete, fields, &object, path)
+							.map(JsonValue::Object)
+					}
+					JsonValue::Object(object) if is_object => self
+						.complete_object(type_name, fields, &object, path)
+						.map(JsonValue::Object),
+					_ if is_object => {
+						self.error("expected an object", fields[0], path);
+						Err(PropagateNull)
+					}
+					value => match self.scalars.get(type_name) {
+						Some(scalar) => {
+							(scalar.serialize)(&value).map_err(|e| {
+								let message =
+									format!("invalid {type_name}: {e}");
+								self.error(message, fields[0], path);
+								PropagateNull
+							})
+						}
+						// built-in scalars and enums are returned as they are
+						None => Ok(value),
+					},
+				}
+			}
+		}
+	}
+
+	/// Returns the object type of a value of an interface or union, the
+	/// resolver returns it in the `__typename` field
+	fn concrete_type(
+		&self,
+		abstract_type: &str,
+		object: &JsonMap,
+	) -> Option<String> {
+		let type_name = object.get("__typename")?.as_str()?;
+
+		let is_object = matches!(
+			self.schema.types.get(type_name),
+			Some(ExtendedType::Object(_))
+		);
+		(is_object && self.schema.is_subtype(abstract_type, type_name))
+			.then(|| type_name.to_string())
+	}
+
+	fn complete_object(
+		&mut self,
+		type_name: &str,
+		fields: &[&'a Node<Field>],
+		object: &JsonMap,
+		path: &mut Path,
+	) -> Result<JsonMap, PropagateNull> {
+		let mut selected = IndexMap::new();
+		for field in fields {
+			self.collect_fields(type_name, &field.selection_set, &mut selected);
+		}
+
+		let mut result = JsonMap::new();
+		for (key, fields) in selected {
+			let field = fields[0];
+			if field.name == "__typename" {
+				result.insert(key.as_str(), type_name.into());
+				continue;
+			}
+
+			let value = object
+				.get(field.name.as_str())
+				.cloned()
+				.unwrap_or(JsonValue::Null);
+
+			path.push(ResponseDataPathElement::Field(key.clone()));
+			let value =
+				self.complete_value(&field.definition.ty, &fields, value, path);
+			path.pop();
+
+			result.insert(key.as_str(), value?);
+		}
+
+		Ok(result)
+	}
+}
+
+/// Returns the object of a list, ignoring non-null modifiers
+fn list_object(ty: &Type) -> Option<&Type> {
+	match ty {
+		Type::NonNull(ty) => list_object(ty),
+		Type::List(item) => match item.as_ref() {
+			Type::NonNull(item) => Some(item.as_ref()),
+			item => Some(item),
+		},
+		_ => None,
+	}
+	.filter(|ty| matches!(ty, Type::Object { .. }))
+}
+
+/// Returns the name of the object of a type, ignoring lists and non-null
+/// modifiers
+pub(crate) fn type_name(ty: &Type) -> &str {
+	match ty {
+		Type::List(ty) | Type::NonNull(ty) => type_name(ty),
+		Type::Object { type_name, .. }
+		| Type::InputObject { type_name, .. }
+		| Type::Enum { type_name, .. }
+		| Type::Interface { type_name, .. }
+		| Type::Union { type_name, .. }
+		| Type::Named(type_name) => type_name,
+		Type::Id => "ID",
+		Type::String => "String",
+		Type::Int => "Int",
+		Type::Float => "Float",
+		Type::Boolean => "Boolean",
+		Type::Scalar(scalar) => &scalar.name,
+	}
+}
+
+/// Converts an input value to json, enums become strings
+///
+/// Returns None if the variable was not provided, in which case the field is
+/// omitted.
+fn value_to_json(
+	value: &ApolloValue,
+	variables: &JsonMap,
+) -> Option<JsonValue> {
+	Some(match value {
+		ApolloValue::Null => JsonValue::Null,
+		ApolloValue::Variable(name) => variables.get(name.as_str())?.clone(),
+		ApolloValue::Enum(name) => name.as_str().into(),
+		ApolloValue::String(s) => s.as_str().into(),
+		ApolloValue::Boolean(b) => (*b).into(),
+		ApolloValue::Int(i) => match i.try_to_i32() {
+			Ok(i) => i.into(),
+			Err(_) => i.try_to_f64().unwrap_or_default().into(),
+		},
+		ApolloValue::Float(f) => f.try_to_f64().unwrap_or_default().into(),
+		ApolloValue::List(items) => JsonValue::Array(
+			items
+				.iter()
+				.map(|v| value_to_json(v, variables).unwrap_or(JsonValue::Null))
+				.collect(),
+		),
+		ApolloValue::Object(fields) => JsonValue::Object(
+			fields
+				.iter()
+				.filter_map(|(n, v)| {
+					Some((n.as_str().into(), value_to_json(v, variables)?))
+				})
+				.collect(),
+		),
+	})
+}
+
+/// Replaces the variables with their coerced values, returns None if the
+/// variable was not provided, in which case the field is omitted
+pub(crate) fn inline_variables(
+	value: &ApolloValue,
+	variables: &JsonMap,
+) -> Option<ApolloValue> {
+	Some(match value {
+		ApolloValue::Variable(name) => {
+			json_to_value(variables.get(name.as_str())?)
+		}
+		ApolloValue::List(items) => ApolloValue::List(
+			items
+				.iter()
+				.map(|v| {
+					let v = inline_variables(v, variables);
+					Node::new(v.unwrap_or(ApolloValue::Null))
+				})
+				.collect(),
+		),
+		ApolloValue::Object(fields) => ApolloValue::Object(
+			fields
+				.iter()
+				.filter_map(|(n, v)| {
+					Some((
+						n.clone(),
+						Node::new(inline_variables(v, variables)?),
+					))
+				})
+				.collect(),
+		),
+		value => value.clone(),
+	})
+}
+
+fn json_to_value(value: &JsonValue) -> ApolloValue {
+	match value {
+		JsonValue::Null => ApolloValue::Null,
+		JsonValue::Bool(b) => (*b).into(),
+		JsonValue::Number(n) => {
+			match n.as_i64().and_then(|i| i32::try_from(i).ok()) {
+				Some(i) => i.into(),
+				None => n.as_f64().unwrap_or_default().into(),
+			}
+		}
+		JsonValue::String(s) => s.as_str().into(),
+		JsonValue::Array(items) => ApolloValue::List(
+			items.iter().map(|v| Node::new(json_to_value(v))).collect(),
+		),
+		JsonValue::Object(fields) => ApolloValue::Object(
+			fields
+				.iter()
+				.filter_map(|(k, v)| {
+					Some((
+						Name::new(k.as_str()).ok()?,
+						Node::new(json_to_value(v)),
+					))
+				})
+				.collect(),
+		),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Mutex;
+
+	use database::{
+		id::Id,
+		query::{Cursor, Filter},
+	};
+
+	use crate::{mutation, scalar::Scalar};
+	use serde_json::json;
+
+	use super::*;
+
+	struct Events {
+		events: serde_json::Value,
+		plans: Mutex<Vec<Plan>>,
+		mutations: Mutex<Vec<Mutation>>,
+	}
+
+	#[async_trait::async_trait]
+	impl Resolver for Events {
+		async fn list(
+			&self,
+			plan: &Plan,
+		) -> Result<Page<JsonMap>, ResolveError> {
+			self.plans.lock().unwrap().push(plan.clone());
+
+			let JsonValue::Array(events) = JsonValue::from(self.events.clone())
+			else {
+				return Err(ResolveError(
+					"the events are not available".into(),
+				));
+			};
+			let mut items = events
+				.into_iter()
+				.filter_map(|e| e.as_object().cloned())
+				.collect::<Vec<_>>();
+
+			// the cursor does not need to be valid for the tests
+			let limit = plan.query.limit.map_or(usize::MAX, |l| l as usize);
+			let next = (items.len() > limit).then(|| {
+				items.truncate(limit);
+				Cursor::new(vec![], Id::from_bytes([1; 12]))
+			});
+
+			Ok(Page { items, next })
+		}
+
+		async fn mutate(
+			&self,
+			mutation: &Mutation,
+		) -> Result<Option<JsonMap>, MutationError> {
+			self.mutations.lock().unwrap().push(mutation.clone());
+
+			let title = mutation.data.get("title").and_then(|t| t.as_str());
+			if title == Some("") {
+				return Err(MutationError::Invalid(vec![FieldError {
+					field: "title".into(),
+					message: "cannot be empty".into(),
+				}]));
+			}
+
+			match mutation.id.as_deref() {
+				Some("404") => Ok(None),
+				id => {
+					let mut object = mutation.data.clone();
+					object.insert("id", id.unwrap_or("3").into());
+					Ok(Some(object))
+				}
+			}
+		}
+	}
+
+	fn executor() -> Executor {
+		let prop = |name: &str, ty: Type| Property {
+			name: name.into(),
+			arguments: vec![],
+			ty,
+		};
+
+		let contact = Type::Interface {
+			type_name: "Contact".into(),
+			fields: vec![prop("name", Type::String.non_null())],
+		};
+		let host = |name: &str, field: &str| Type::Object {
+			type_name: name.into(),
+			fields: vec![
+				prop("name", Type::String.non_null()),
+				prop(field, Type::String),
+			],
+			interfaces: vec![contact.clone()],
+		};
+		let host = Type::Union {
+			type_name: "Host".into(),
+			types: vec![host("Person", "email"), host("Company", "website")],
+		};
+
+		let event = Type::Object {
+			type_name: "Event".into(),
+			fields: vec![
+				prop("id", Type::Id.non_null()),
+				prop("title", Type::String),
+				prop("seats", Type::Int),
+				prop("starts", Type::Scalar(Scalar::date_time())),
+				prop("host", host),
+				prop("contacts", contact.non_null().list()),
+			],
+			interfaces: vec![],
+		};
+
+		let mutations = mutation::mutation_properties(&event);
+		Executor::new(
+			vec![filter::list_property("events", event).unwrap()],
+			mutations,
+		)
+		.unwrap()
+	}
+
+	async fn execute(
+		events: serde_json::Value,
+		query: &str,
+		variables: serde_json::Value,
+	) -> (serde_json::Value, Vec<Plan>) {
+		let (res, resolver) = execute_with(events, query, variables).await;
+
+		(res, resolver.plans.into_inner().unwrap())
+	}
+
+	async fn execute_with(
+		events: serde_json::Value,
+		query: &str,
+		variables: serde_json::Value,
+	) -> (serde_json::Value, Events) {
+		let resolver = Events {
+			events,
+			plans: Mutex::new(vec![]),
+			mutations: Mutex::new(vec![]),
+		};
+		let request = Request {
+			query: query.into(),
+			operation_name: None,
+			variables: serde_json::from_value(variables).unwrap(),
+		};
+
+		let res = executor().execute(&resolver, &request).await;
+		let res = serde_json::to_value(res).unwrap();
+
+		(res, resolver)
+	}
+
+	#[tokio::test]
+	async fn fragments() {
+		let events = json!([
+			{ "id": "1", "title": "Rust", "seats": 10 },
+			{ "id": "2", "title": null }
+		]);
+		let query = r#"
+		query Events($title: String, $skip: Boolean!) {
+			__typename
+			list: events(filter: { title: { contains: $title } }, limit: 2) {
+				...info
+				... on Event {
+					kind: __typename
+					seats @skip(if: $skip)
+				}
+			}
+		}
+
+		fragment info on Event {
+			id
+			title
+		}
+		"#;
+
+		let variables = json!({ "title": "Ru", "skip": false });
+		let (res, plans) = execute(events.clone(), query, variables).await;
+		assert_eq!(
+			res,
+			json!({
+				"data": {
+					"__typename": "Query",
+					"list": [
+						{ "id": "1", "title": "Rust", "kind": "Event", "seats": 10 },
+						{ "id": "2", "title": null, "kind": "Event", "seats": null }
+					]
+				}
+			})
+		);
+
+		let plan = &plans[0];
+		assert_eq!(plan.type_name, "Event");
+		assert_eq!(plan.columns, ["id", "title", "seats"]);
+		let expected = Query::new().limit(2).filter(Filter::compare(
+			"title",
+			database::query::Operator::Contains,
+			"Ru",
+		));
+		assert_eq!(plan.query, expected);
+
+		let variables = json!({ "skip": true });
+		let (res, plans) = execute(events, query, variables).await;
+		assert_eq!(
+			res["data"]["list"][0],
+			json!({ "id": "1", "title": "Rust", "kind": "Event" })
+		);
+		// an empty filter matches every event
+		let expected = Query::new().limit(2).filter(Filter::And(vec![]));
+		assert_eq!(plans[0].query, expected);
+	}
+
+	#[tokio::test]
+	async fn pages() {
+		let events = json!([{ "id": "1" }, { "id": "2" }, { "id": "3" }]);
+		let query = "{ first: events(limit: 2) { id } all: events { id } }";
+		let (res, _) = execute(events, query, json!({})).await;
+		assert_eq!(res["data"]["first"], json!([{ "id": "1" }, { "id": "2" }]));
+		assert_eq!(res["data"]["all"].as_array().unwrap().len(), 3);
+
+		// only the truncated list reports a cursor
+		let cursor = Cursor::new(vec![], Id::from_bytes([1; 12]));
+		assert_eq!(
+			res["extensions"],
+			json!({ "next": { "first": cursor.to_b64() } })
+		);
+
+		let query = "query ($after: String) { events(after: $after) { id } }";
+		let variables = json!({ "after": cursor.to_b64() });
+		let (res, plans) = execute(json!([]), query, variables).await;
+		assert!(res.get("extensions").is_none());
+		assert_eq!(plans[0].query, Query::new().after(Some(cursor)));
+	}
+
+	#[tokio::test]
+	async fn errors() {
+		// the id is non-null, the null propagates to the root
+		let events = json!([{ "id": "1" }, { "title": "Rust" }]);
+		let (res, _) =
+			execute(events, "{ events { id title } }", json!({})).await;
+		assert!(res["data"].is_null());
+		assert_eq!(res["errors"][0]["path"], json!(["events", 1, "id"]));
+		assert_eq!(
+			res["errors"][0]["locations"],
+			json!([{ "line": 1, "column": 12 }])
+		);
+
+		let (res, _) =
+			execute(json!(null), "{ events { id } }", json!({})).await;
+		assert!(res["data"].is_null());
+		assert_eq!(res["errors"][0]["message"], "the events are not available");
+		assert_eq!(res["errors"][0]["path"], json!(["events"]));
+
+		// request errors have no data
+		let (res, plans) =
+			execute(json!([]), "{ events { name } }", json!({})).await;
+		assert!(res.get("data").is_none());
+		assert!(!res["errors"].as_array().unwrap().is_empty());
+		assert!(plans.is_empty());
+
+		let query = "query ($limit: Int!) { events(limit: $limit) { id } }";
+		let (res, _) = execute(json!([]), query, json!({})).await;
+		assert!(res.get("data").is_none());
+	}
+
+	#[tokio::test]
+	async fn mutations() {
+		let query = r#"
+		mutation ($seats: Int) {
+			created: createEvent(data: { title: "Rust", seats: $seats }) {
+				id
+				title
+			}
+			updateEvent(id: "2", data: { title: "" }) {
+				id
+			}
+			deleteEvent(id: "404") {
+				__typename
+			}
+		}
+		"#;
+
+		let variables = json!({ "seats": 10 });
+		let (res, resolver) = execute_with(json!([]), query, variables).await;
+		assert_eq!(
+			res["data"],
+			json!({
+				"created": { "id": "3", "title": "Rust" },
+				"updateEvent": null,
+				"deleteEvent": null
+			})
+		);
+		assert_eq!(res["errors"][0]["path"], json!(["updateEvent"]));
+		assert_eq!(res["errors"][0]["extensions"], json!({ "field": "title" }));
+		assert_eq!(res["errors"].as_array().unwrap().len(), 1);
+
+		let mutations = resolver.mutations.into_inner().unwrap();
+		assert_eq!(mutations.len(), 3);
+		assert_eq!(mutations[0].action, Action::Create);
+		assert_eq!(mutations[0].columns, ["id", "title"]);
+		assert_eq!(
+			serde_json::to_value(&mutations[0].data).unwrap(),
+			json!({ "title": "Rust", "seats": 10 })
+		);
+		assert_eq!(mutations[1].id.as_deref(), Some("2"));
+		assert_eq!(mutations[2].action, Action::Delete);
+
+		// the data is required
+		let query = r#"mutation { createEvent { id } }"#;
+		let (res, resolver) = execute_with(json!([]), query, json!({})).await;
+		assert!(res.get("data").is_none());
+		assert!(resolver.mutations.into_inner().unwrap().is_empty());
+	}
+
+	#[tokio::test]
+	async fn abstract_types() {
+		let events = json!([{
+			"id": "1",
+			"host": { "__typename": "Person", "name": "Ada", "email": "ada@x" },
+			"contacts": [
+				{ "__typename": "Company", "name": "Zipp", "website": "zipp" },
+				{ "__typename": "Event", "name": "Rust" }
+			]
+		}]);
+		let query = r#"{
+			events {
+				host {
+					__typename
+					... on Person { email }
+					... on Company { website }
+				}
+				contacts {
+					name
+					... on Company { website }
+				}
+			}
+		}"#;
+
+		let (res, plans) = execute(events, query, json!({})).await;
+		assert_eq!(
+			res["data"],
+			json!({
+				"events": [{
+					"host": { "__typename": "Person", "email": "ada@x" },
+					"contacts": null
+				}]
+			})
+		);
+		// an event does not implement the contact
+		assert_eq!(
+			res["errors"][0]["path"],
+			json!(["events", 0, "contacts", 1])
+		);
+		assert_eq!(plans[0].columns, ["host", "contacts"]);
+
+		// the fields of every possible object are merged
+		let selection = &plans[0].selection;
+		let host = selection.field("host").unwrap();
+		assert_eq!(host.names().collect::<Vec<_>>(), ["email", "website"]);
+		let contacts = selection.field("contacts").unwrap();
+		assert_eq!(contacts.names().collect::<Vec<_>>(), ["name", "website"]);
+		assert!(contacts.field("name").unwrap().names().next().is_none());
+	}
+
+	#[tokio::test]
+	async fn scalars() {
+		let events = json!([
+			{ "id": "1", "starts": "2024-04-01T12:00:00+02:00" },
+			{ "id": "2", "starts": "tomorrow" }
+		]);
+		let query = r#"{
+			events(filter: { starts: { gte: "2024-04-01T00:00:00+02:00" } }) {
+				starts
+			}
+		}"#;
+		let (res, plans) = execute(events, query, json!({})).await;
+		assert_eq!(
+			res["data"]["events"],
+			json!([{ "starts": "2024-04-01T10:00:00Z" }, { "starts": null }])
+		);
+		assert_eq!(res["errors"][0]["path"], json!(["events", 1, "starts"]));
+		let starts = "2024-03-31T22:00:00Z".parse().unwrap();
+		assert_eq!(
+			plans[0].query,
+			Query::new().filter(Filter::compare(
+				"starts",
+				database::query::Operator::Gte,
+				database::query::Value::DateTime(starts)
+			))
+		);
+
+		let query = r#"mutation ($starts: DateTime) {
+			createEvent(data: { title: "Rust", starts: $starts }) { id }
+		}"#;
+		let variables = json!({ "starts": "2024-04-01T12:00:00+02:00" });
+		let (res, resolver) = execute_with(json!([]), query, variables).await;
+		assert_eq!(res["data"]["createEvent"]["id"], "3");
+		let mutations = resolver.mutations.into_inner().unwrap();
+		assert_eq!(mutations[0].data["starts"], "2024-04-01T10:00:00Z");
+
+		let variables = json!({ "starts": "tomorrow" });
+		let (res, _) = execute_with(json!([]), query, variables).await;
+		assert_eq!(res["errors"][0]["extensions"]["field"], "starts");
+
+		let schema = executor().schema().to_string();
+		assert!(schema.contains(
+			"scalar DateTime @specifiedBy(url: \"https://scalars.graphql.org/andimarek/date-time\")"
+		));
+	}
+
+	#[tokio::test]
+	async fn subscriptions() {
+		let events = json!([{ "id": "1", "title": "Rust" }]);
+		let query = r#"subscription { events(limit: 1) { title } }"#;
+		let (res, plans) = execute(events, query, json!({})).await;
+		assert_eq!(res["data"], json!({ "events": [{ "title": "Rust" }] }));
+		assert_eq!(plans[0].query, Query::new().limit(1));
+
+		let request = |query: &str| Request {
+			query: query.into(),
+			operation_name: None,
+			variables: JsonMap::new(),
+		};
+		let executor = executor();
+		assert_eq!(executor.subscribed_types(&request(query)), ["Event"]);
+		assert!(executor
+			.subscribed_types(&request("{ events { id } }"))
+			.is_empty());
+		assert!(executor
+			.subscribed_types(&request("subscription { events { name } }"))
+			.is_empty());
+	}
+}
@@ -0,0 +1,661 @@
+//! Filter and order arguments
+//!
+//! For an object `Event` the input `EventFilter` is generated, containing
+//! `and`, `or`, `not` and an operator input for every scalar field. The
+//! operators depend on the type of the field, for example a `String` supports
+//! `eq`, `ne`, `contains` and `in`.
+//!
+//! ```graphql
+//! events(
+//!   filter: { or: [{ id: { eq: "..." } }, { title: { contains: "rust" } }] }
+//!   order: [{ field: title, direction: DESC }]
+//!   limit: 10
+//!   after: "..."
+//! ) { title }
+//! ```
+//!
+//! `after` takes the cursor the previous page reported, see
+//! [`execute`](crate::execute).
+//!
+//! The arguments are translated into a database [`Query`] with
+//! [`query_from_arguments`], variables can be used anywhere inside of them.
+
+use apollo_compiler::{
+	ast::{Argument as ApolloArgument, Value as ApolloValue},
+	Node,
+};
+use database::query::{Cursor, Filter, Operator, Query, Value as DbValue};
+
+use crate::{
+	execute::{inline_variables, type_name, JsonMap, JsonValue},
+	scalar::ScalarFilter,
+	Argument, Property, Type, Value,
+};
+
+const DIRECTION: &str = "OrderDirection";
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum FilterError {
+	#[error("filters can only be created for objects, got {0}")]
+	NotAnObject(String),
+
+	#[error("the field {0} cannot be filtered or ordered")]
+	UnknownField(String),
+
+	#[error("the operator {0} is not supported")]
+	UnknownOperator(String),
+
+	#[error("the value of {0} is invalid")]
+	InvalidValue(String),
+}
+
+/// Returns a property listing the objects, with the arguments `filter`,
+/// `order`, `limit` and `after`
+///
+/// Returns an error if the type is not an object.
+pub fn list_property(
+	name: impl Into<String>,
+	object: Type,
+) -> Result<Property, FilterError> {
+	Ok(Property {
+		name: name.into(),
+		arguments: vec![
+			Argument::new("filter", filter_type(&object)?),
+			Argument::new("order", order_type(&object)?.non_null().list()),
+			Argument::new("limit", Type::Int),
+			Argument::new("after", Type::String),
+		],
+		ty: object.non_null().list().non_null(),
+	})
+}
+
+/// Returns the filter input of an object
+///
+/// Returns an error if the type is not an object.
+pub fn filter_type(object: &Type) -> Result<Type, FilterError> {
+	let type_name = format!("{}Filter", object_name(object)?);
+	let this = Type::Named(type_name.clone());
+
+	let mut fields = vec![
+		Argument::new("and", this.clone().non_null().list()),
+		Argument::new("or", this.clone().non_null().list()),
+		Argument::new("not", this),
+	];
+	fields.extend(
+		scalar_fields(object)?
+			.map(|(name, ty)| Argument::new(name, scalar_filter_type(ty))),
+	);
+
+	Ok(Type::InputObject { type_name, fields })
+}
+
+/// Returns the order input of an object
+///
+/// Returns an error if the type is not an object.
+pub fn order_type(object: &Type) -> Result<Type, FilterError> {
+	let name = object_name(object)?;
+
+	let field = Type::Enum {
+		type_name: format!("{name}OrderField"),
+		values: scalar_fields(object)?.map(|(n, _)| n.to_string()).collect(),
+	};
+	let direction = Type::Enum {
+		type_name: DIRECTION.into(),
+		values: vec!["ASC".into(), "DESC".into()],
+	};
+
+	Ok(Type::InputObject {
+		type_name: format!("{name}Order"),
+		fields: vec![
+			Argument::new("field", field.non_null()),
+			Argument::new("direction", direction.non_null())
+				.with_default(Value::Enum("ASC".into())),
+		],
+	})
+}
+
+/// Translates the `filter`, `order`, `limit` and `after` arguments of a
+/// property created by [`list_property`]
+///
+/// Variables are replaced by their values, fields with a variable which was
+/// not provided are omitted.
+pub fn query_from_arguments(
+	object: &Type,
+	arguments: &[Node<ApolloArgument>],
+	variables: &JsonMap,
+) -> Result<Query, FilterError> {
+	let mut query = Query::new();
+
+	for arg in arguments {
+		let Some(value) = inline_variables(&arg.value, variables) else {
+			continue;
+		};
+
+		match (arg.name.as_str(), &value) {
+			(_, ApolloValue::Null) => {}
+			("filter", value) => {
+				query = query.filter(object_filter(object, value)?);
+			}
+			("order", ApolloValue::List(orders)) => {
+				for order in orders {
+					query = order_by(object, query, order)?;
+				}
+			}
+			// a single value is coerced into a list
+			("order", value) => query = order_by(object, query, value)?,
+			("limit", ApolloValue::Int(i)) => {
+				let limit = i
+					.try_to_i32()
+					.ok()
+					.and_then(|l| u32::try_from(l).ok())
+					.ok_or_else(|| FilterError::InvalidValue("limit".into()))?;
+				query = query.limit(limit);
+			}
+			("after", ApolloValue::String(s)) => {
+				let cursor = Cursor::parse_b64(s.as_str())
+					.map_err(|_| FilterError::InvalidValue("after".into()))?;
+				query = query.after(Some(cursor));
+			}
+			(name, _) => return Err(FilterError::InvalidValue(name.into())),
+		}
+	}
+
+	Ok(query)
+}
+
+fn object_name(object: &Type) -> Result<&str, FilterError> {
+	match object {
+		Type::Object { type_name, .. } => Ok(type_name),
+		Type::NonNull(ty) => object_name(ty),
+		ty => Err(FilterError::NotAnObject(type_name(ty).into())),
+	}
+}
+
+fn object_fields(object: &Type) -> Result<&[Property], FilterError> {
+	match object {
+		Type::Object { fields, .. } => Ok(fields),
+		Type::NonNull(ty) => object_fields(ty),
+		ty => Err(FilterError::NotAnObject(type_name(ty).into())),
+	}
+}
+
+/// Returns the fields which can be filtered and ordered by, with their
+/// nullable type
+fn scalar_fields(
+	object: &Type,
+) -> Result<impl Iterator<Item = (&str, &Type)>, FilterError> {
+	let fields = object_fields(object)?;
+
+	Ok(fields.iter().filter_map(|f| {
+		let ty = match &f.ty {
+			Type::NonNull(ty) => ty,
+			ty => ty,
+		};
+
+		let filterable = match ty {
+			Type::Scalar(scalar) => scalar.filter.is_some(),
+			ty => matches!(
+				ty,
+				Type::Id
+					| Type::String | Type::Int
+					| Type::Float | Type::Boolean
+					| Type::Enum { .. }
+			),
+		};
+
+		filterable.then_some((f.name.as_str(), ty))
+	}))
+}
+
+fn field_type<'a>(
+	object: &'a Type,
+	name: &str,
+) -> Result<&'a Type, FilterError> {
+	scalar_fields(object)?
+		.find(|(n, _)| *n == name)
+		.map(|(_, ty)| ty)
+		.ok_or_else(|| FilterError::UnknownField(name.into()))
+}
+
+/// Returns the operators a scalar supports
+fn operators(ty: &Type) -> &'static [&'static str] {
+	match ty {
+		// ids are ordered by their creation
+		Type::Id => &["eq", "ne", "gt", "gte", "lt", "lte", "in"],
+		Type::Enum { .. } => &["eq", "ne", "in"],
+		Type::String => &["eq", "ne", "contains", "in"],
+		Type::Int | Type::Float => {
+			&["eq", "ne", "gt", "gte", "lt", "lte", "in"]
+		}
+		Type::Boolean => &["eq", "ne"],
+		Type::Scalar(scalar) => match scalar.filter {
+			Some(ScalarFilter::Id) => {
+				&["eq", "ne", "gt", "gte", "lt", "lte", "in"]
+			}
+			Some(ScalarFilter::Text) => &["eq", "ne", "contains", "in"],
+			Some(ScalarFilter::DateTime) => {
+				&["eq", "ne", "gt", "gte", "lt", "lte", "in"]
+			}
+			None => &[],
+		},
+		_ => &[],
+	}
+}
+
+fn scalar_filter_type(ty: &Type) -> Type {
+	let type_name = match ty {
+		Type::Id => "IDFilter".to_string(),
+		Type::String => "StringFilter".to_string(),
+		Type::Int => "IntFilter".to_string(),
+		Type::Float => "FloatFilter".to_string(),
+		Type::Boolean => "BooleanFilter".to_string(),
+		Type::Enum { type_name, .. } => format!("{type_name}Filter"),
+		Type::Scalar(scalar) => format!("{}Filter", scalar.name),
+		_ => unreachable!("only scalars can be filtered"),
+	};
+
+	let fields = operators(ty)
+		.iter()
+		.map(|op| match *op {
+			"in" => Argument::new(*op, ty.clone().non_null().list()),
+			op => Argument::new(op, ty.clone()),
+		})
+		.collect();
+
+	Type::InputObject { type_name, fields }
+}
+
+fn object_filter(
+	object: &Type,
+	value: &ApolloValue,
+) -> Result<Filter, FilterError> {
+	let fields = match value {
+		ApolloValue::Object(fields) => fields,
+		_ => return Err(FilterError::InvalidValue("filter".into())),
+	};
+
+	let mut filters = vec![];
+	for (name, value) in fields {
+		let filter = match (name.as_str(), value.as_ref()) {
+			(_, ApolloValue::Null) => continue,
+			// all variables of the operators were omitted
+			(_, ApolloValue::Object(ops)) if ops.is_empty() => continue,
+			(op @ ("and" | "or"), value) => {
+				// a single value is coerced into a list
+				let list = match value {
+					ApolloValue::List(list) => list
+						.iter()
+						.map(|v| object_filter(object, v))
+						.collect::<Result<_, _>>()?,
+					value => vec![object_filter(object, value)?],
+				};
+
+				if op == "and" {
+					Filter::And(list)
+				} else {
+					Filter::Or(list)
+				}
+			}
+			("not", value) => !object_filter(object, value)?,
+			(name, value) => {
+				field_filter(name, field_type(object, name)?, value)?
+			}
+		};
+
+		filters.push(filter);
+	}
+
+	Ok(match filters.len() {
+		1 => filters.pop().unwrap(),
+		_ => Filter::And(filters),
+	})
+}
+
+fn field_filter(
+	column: &str,
+	ty: &Type,
+	value: &ApolloValue,
+) -> Result<Filter, FilterError> {
+	let ops = match value {
+		ApolloValue::Object(ops) => ops,
+		_ => return Err(FilterError::InvalidValue(column.into())),
+	};
+
+	let mut filters = vec![];
+	for (op, value) in ops {
+		if !operators(ty).contains(&op.as_str()) {
+			return Err(FilterError::UnknownOperator(op.to_string()));
+		}
+
+		let op = match op.as_str() {
+			"in" => {
+				let values = match value.as_ref() {
+					ApolloValue::List(list) => list
+						.iter()
+						.map(|v| to_db_value(column, ty, v))
+						.collect::<Result<_, _>>()?,
+					value => vec![to_db_value(column, ty, value)?],
+				};
+				filters.push(Filter::is_in(column, values));
+				continue;
+			}
+			"eq" => Operator::Eq,
+			"ne" => Operator::Ne,
+			"gt" => Operator::Gt,
+			"gte" => Operator::Gte,
+			"lt" => Operator::Lt,
+			"lte" => Operator::Lte,
+			_ => Operator::Contains,
+		};
+
+		let value = to_db_value(column, ty, value)?;
+		filters.push(Filter::compare(column, op, value));
+	}
+
+	Ok(match filters.len() {
+		1 => filters.pop().unwrap(),
+		_ => Filter::And(filters),
+	})
+}
+
+fn to_db_value(
+	column: &str,
+	ty: &Type,
+	value: &ApolloValue,
+) -> Result<DbValue, FilterError> {
+	let invalid = || FilterError::InvalidValue(column.into());
+
+	Ok(match (ty, value) {
+		(_, ApolloValue::Null) => DbValue::Null,
+		(Type::Id, ApolloValue::String(s)) => {
+			DbValue::Id(s.parse().map_err(|_| invalid())?)
+		}
+		(Type::String, ApolloValue::String(s)) => DbValue::Text(s.to_string()),
+		(Type::Int, ApolloValue::Int(i)) => {
+			DbValue::Int(i.try_to_i32().map_err(|_| invalid())?.into())
+		}
+		(Type::Float, ApolloValue::Int(i)) => {
+			DbValue::Float(i.try_to_f64().map_err(|_| invalid())?)
+		}
+		(Type::Float, ApolloValue::Float(f)) => {
+			DbValue::Float(f.try_to_f64().map_err(|_| invalid())?)
+		}
+		(Type::Boolean, ApolloValue::Boolean(b)) => DbValue::Bool(*b),
+		(Type::Enum { values, .. }, value) => {
+			let e = enum_value(value).ok_or_else(invalid)?;
+			if !values.iter().any(|v| v == e) {
+				return Err(invalid());
+			}
+
+			DbValue::Text(e.to_string())
+		}
+		(Type::Scalar(scalar), value) => {
+			let value = match value {
+				ApolloValue::String(s) => JsonValue::from(s.as_str()),
+				ApolloValue::Boolean(b) => JsonValue::from(*b),
+				ApolloValue::Int(i) => {
+					JsonValue::from(i.try_to_i32().map_err(|_| invalid())?)
+				}
+				_ => return Err(invalid()),
+			};
+			let value = (scalar.parse)(&value).map_err(|_| invalid())?;
+			let value = value.as_str().ok_or_else(invalid)?;
+
+			match scalar.filter {
+				Some(ScalarFilter::Id) => {
+					DbValue::Id(value.parse().map_err(|_| invalid())?)
+				}
+				Some(ScalarFilter::Text) => DbValue::Text(value.to_string()),
+				Some(ScalarFilter::DateTime) => {
+					DbValue::DateTime(value.parse().map_err(|_| invalid())?)
+				}
+				None => return Err(invalid()),
+			}
+		}
+		_ => return Err(invalid()),
+	})
+}
+
+/// Returns the name of an enum value, the values of variables contain enums
+/// as strings
+fn enum_value(value: &ApolloValue) -> Option<&str> {
+	match value {
+		ApolloValue::Enum(e) => Some(e.as_str()),
+		ApolloValue::String(s) => Some(s.as_str()),
+		_ => None,
+	}
+}
+
+fn order_by(
+	object: &Type,
+	query: Query,
+	value: &ApolloValue,
+) -> Result<Query, FilterError> {
+	let fields = match value {
+		ApolloValue::Object(fields) => fields,
+		_ => return Err(FilterError::InvalidValue("order".into())),
+	};
+
+	let mut column = None;
+	let mut desc = false;
+	for (name, value) in fields {
+		match (name.as_str(), value.as_ref()) {
+			// the default is ascending
+			("direction", ApolloValue::Null) => {}
+			("field", value) => {
+				let field = enum_value(value)
+					.ok_or_else(|| FilterError::InvalidValue("field".into()))?;
+				field_type(object, field)?;
+				column = Some(field.to_string());
+			}
+			("direction", value) => {
+				desc = match enum_value(value) {
+					Some("ASC") => false,
+					Some("DESC") => true,
+					_ => {
+						return Err(FilterError::InvalidValue(DIRECTION.into()))
+					}
+				};
+			}
+			(name, _) => return Err(FilterError::InvalidValue(name.into())),
+		}
+	}
+
+	let column =
+		column.ok_or_else(|| FilterError::InvalidValue("order".into()))?;
+
+	Ok(if desc {
+		query.order_desc(column)
+	} else {
+		query.order_asc(column)
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use apollo_compiler::ExecutableDocument;
+	use database::id::{Id, Kind};
+
+	use super::*;
+	use crate::create_schema;
+
+	fn event() -> Type {
+		let prop = |name: &str, ty: Type| Property {
+			name: name.into(),
+			arguments: vec![],
+			ty,
+		};
+
+		Type::Object {
+			type_name: "Event".into(),
+			fields: vec![
+				prop("id", Type::Id.non_null()),
+				prop("title", Type::String),
+				prop("seats", Type::Int),
+				prop("public", Type::Boolean.non_null()),
+				prop(
+					"state",
+					Type::Enum {
+						type_name: "State".into(),
+						values: vec!["DRAFT".into(), "PUBLISHED".into()],
+					},
+				),
+				prop("tags", Type::String.list()),
+			],
+			interfaces: vec![],
+		}
+	}
+
+	fn arguments(query: &str) -> Result<Query, FilterError> {
+		variable_arguments(query, JsonMap::new())
+	}
+
+	fn variable_arguments(
+		query: &str,
+		variables: JsonMap,
+	) -> Result<Query, FilterError> {
+		let schema = create_schema(
+			vec![list_property("events", event()).unwrap()],
+			vec![],
+			vec![],
+		)
+		.unwrap();
+		let doc = ExecutableDocument::parse_and_validate(
+			&schema,
+			query,
+			"query.graphql",
+		)
+		.unwrap();
+		let op = doc.get_operation(None).unwrap();
+		let field = op.selection_set.fields().next().unwrap();
+
+		query_from_arguments(&event(), &field.arguments, &variables)
+	}
+
+	#[test]
+	fn schema() {
+		let schema = create_schema(
+			vec![list_property("events", event()).unwrap()],
+			vec![],
+			vec![],
+		)
+		.unwrap();
+
+		let events = schema.type_field("Query", "events").unwrap();
+		assert_eq!(events.ty.to_string(), "[Event!]!");
+		let arg =
+			|name: &str| events.argument_by_name(name).unwrap().ty.to_string();
+		assert_eq!(arg("filter"), "EventFilter");
+		assert_eq!(arg("order"), "[EventOrder!]");
+		assert_eq!(arg("limit"), "Int");
+		assert_eq!(arg("after"), "String");
+
+		let field = |ty: &str, name: &str| {
+			let input = schema.get_input_object(ty).unwrap();
+			input.fields.get(name).map(|f| f.ty.to_string())
+		};
+		assert_eq!(field("EventFilter", "or").unwrap(), "[EventFilter!]");
+		assert_eq!(field("EventFilter", "not").unwrap(), "EventFilter");
+		assert_eq!(field("EventFilter", "seats").unwrap(), "IntFilter");
+		assert_eq!(field("EventFilter", "state").unwrap(), "StateFilter");
+		// lists cannot be filtered
+		assert!(field("EventFilter", "tags").is_none());
+		assert_eq!(field("StringFilter", "in").unwrap(), "[String!]");
+		assert!(field("StringFilter", "gt").is_none());
+		assert!(field("BooleanFilter", "in").is_none());
+		assert_eq!(
+			field("EventOrder", "direction").unwrap(),
+			"OrderDirection!"
+		);
+
+		// operators not supported by the field kind are rejected
+		let query = r#"{ events(filter: { title: { gt: "a" } }) { id } }"#;
+		assert!(ExecutableDocument::parse_and_validate(
+			&schema,
+			query,
+			"query.graphql"
+		)
+		.is_err());
+	}
+
+	#[test]
+	fn translate() {
+		let id = Id::new(Kind::new(false, 1));
+		let query = format!(
+			r#"{{
+				events(
+					filter: {{
+						or: [{{ id: {{ eq: "{id}" }} }}, {{ title: {{ contains: "rust" }} }}]
+						not: {{ state: {{ in: [DRAFT] }} }}
+						seats: {{ gte: 2, lt: 10 }}
+					}}
+					order: [{{ field: seats, direction: DESC }}, {{ field: title }}]
+					limit: 5
+				) {{ id }}
+			}}"#
+		);
+
+		let query = arguments(&query).unwrap();
+		let expected = Query::new()
+			.order_desc("seats")
+			.order_asc("title")
+			.limit(5)
+			.filter(Filter::And(vec![
+				Filter::Or(vec![
+					Filter::eq("id", id),
+					Filter::compare("title", Operator::Contains, "rust"),
+				]),
+				!Filter::is_in("state", vec!["DRAFT".into()]),
+				Filter::And(vec![
+					Filter::compare("seats", Operator::Gte, 2i64),
+					Filter::compare("seats", Operator::Lt, 10i64),
+				]),
+			]));
+		assert_eq!(query, expected);
+
+		let query = arguments("{ events { id } }").unwrap();
+		assert_eq!(query, Query::new());
+
+		let cursor = Cursor::new(vec![2i64.into()], id);
+		let query =
+			arguments(&format!(r#"{{ events(after: "{cursor}") {{ id }} }}"#))
+				.unwrap();
+		assert_eq!(query, Query::new().after(Some(cursor)));
+
+		let err = arguments(r#"{ events(after: "a") { id } }"#).unwrap_err();
+		assert!(matches!(err, FilterError::InvalidValue(c) if c == "after"));
+
+		let err =
+			arguments(r#"{ events(filter: { id: { eq: "a" } }) { id } }"#)
+				.unwrap_err();
+		assert!(matches!(err, FilterError::InvalidValue(c) if c == "id"));
+
+		let err = list_property("ids", Type::Id).unwrap_err();
+		assert!(matches!(err, FilterError::NotAnObject(t) if t == "ID"));
+	}
+
+	#[test]
+	fn variables() {
+		let id = Id::new(Kind::new(false, 1));
+		let query = r#"query ($id: ID, $title: String, $limit: Int) {
+			events(
+				filter: { id: { gt: $id }, title: { eq: $title } }
+				limit: $limit
+			) { id }
+		}"#;
+
+		let variables = serde_json::json!({ "id": id, "limit": 2 });
+		let query = variable_arguments(
+			query,
+			serde_json::from_value(variables).unwrap(),
+		)
+		.unwrap();
+		// the title was not provided
+		let expected = Query::new().limit(2).filter(Filter::compare(
+			"id",
+			Operator::Gt,
+			id,
+		));
+		assert_eq!(query, expected);
+	}
+}
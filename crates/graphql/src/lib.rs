@@ -1,15 +1,27 @@
-use std::collections::BTreeMap;
-
 use apollo_compiler::{
 	ast::{
-		Definition, DirectiveList, Document, FieldDefinition, Name,
-		ObjectTypeDefinition, OperationType, SchemaDefinition,
-		Type as ApolloType,
+		Argument as ApolloArgument, Definition, Directive, DirectiveList,
+		Document, EnumTypeDefinition, EnumValueDefinition, FieldDefinition,
+		InputObjectTypeDefinition, InputValueDefinition,
+		InterfaceTypeDefinition, Name, ObjectTypeDefinition, OperationType,
+		ScalarTypeDefinition, SchemaDefinition, Type as ApolloType,
+		UnionTypeDefinition, Value as ApolloValue,
 	},
-	validation::Valid,
-	ExecutableDocument, Node, NodeStr, Schema,
+	Node, NodeStr,
 };
 
+pub mod execute;
+pub mod filter;
+pub mod mutation;
+pub mod names;
+pub mod scalar;
+
+pub use apollo_compiler::{validation::Valid, Schema};
+
+use apollo_compiler::validation::DiagnosticList;
+use names::NameError;
+use scalar::Scalar;
+
 // 1. schema (files)
 
 // api
@@ -30,19 +42,65 @@ events(id: "123", filters: { or: [{ id: { gt: "2" } }, { title: { contains: "tit
 
 */
 
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum SchemaError {
+	#[error(transparent)]
+	Name(#[from] NameError),
+
+	#[error(transparent)]
+	Filter(#[from] filter::FilterError),
+
+	/// The diagnostics of apollo-compiler, displayed as a report
+	#[error("the schema is invalid\n{0}")]
+	Invalid(DiagnosticList),
+}
+
 #[derive(Debug, Clone)]
 pub struct Property {
-	name: String,
-	arguments: Vec<Argument>,
-	ty: Type,
+	pub name: String,
+	pub arguments: Vec<Argument>,
+	pub ty: Type,
 }
 
+/// An argument of a field or a field of an input object
 #[derive(Debug, Clone)]
 pub struct Argument {
-	name: String,
-	ty: Type,
+	pub name: String,
+	pub ty: Type,
+	/// Used if the argument is not provided
+	pub default: Option<Value>,
 }
 
+impl Argument {
+	pub fn new(name: impl Into<String>, ty: Type) -> Self {
+		Self {
+			name: name.into(),
+			ty,
+			default: None,
+		}
+	}
+
+	pub fn with_default(mut self, default: Value) -> Self {
+		self.default = Some(default);
+		self
+	}
+}
+
+/// A constant input value, used as the default of an argument
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+	Null,
+	Int(i32),
+	Float(f64),
+	String(String),
+	Boolean(bool),
+	/// The name of an enum value
+	Enum(String),
+	List(Vec<Value>),
+	Object(Vec<(String, Value)>),
+}
+
+/// Every type is nullable unless it is wrapped in [`Type::NonNull`]
 #[derive(Debug, Clone)]
 pub enum Type {
 	Id,
@@ -50,12 +108,69 @@ pub enum Type {
 	Int,
 	Float,
 	Boolean,
-	Array(Vec<Type>),
+	/// A custom scalar, see [`scalar`]
+	Scalar(Scalar),
+	/// A list `[T]`, the items can be non-null as well
+	List(Box<Type>),
+	/// A type which is never null `T!`
+	NonNull(Box<Type>),
 	Object {
 		type_name: String,
 		fields: Vec<Property>,
-		fragments: Vec<Property>,
+		/// The [`Type::Interface`]s the object implements, the object needs
+		/// to have their fields as well
+		interfaces: Vec<Type>,
+	},
+	/// An abstract type, every object implementing it can be returned
+	Interface {
+		type_name: String,
+		fields: Vec<Property>,
+	},
+	/// An abstract type which is one of the objects, objects defined by other
+	/// fields can be referenced with [`Type::Named`]
+	Union {
+		type_name: String,
+		types: Vec<Type>,
 	},
+	/// Can only be used by arguments
+	InputObject {
+		type_name: String,
+		fields: Vec<Argument>,
+	},
+	Enum {
+		type_name: String,
+		values: Vec<String>,
+	},
+	/// A reference to a type which is defined by another field, needed for
+	/// recursive input objects
+	Named(String),
+}
+
+impl Type {
+	/// Returns a list of this type
+	pub fn list(self) -> Self {
+		Self::List(Box::new(self))
+	}
+
+	/// Returns this type as non-null, does nothing if it already is
+	pub fn non_null(self) -> Self {
+		match self {
+			Self::NonNull(_) => self,
+			ty => Self::NonNull(Box::new(ty)),
+		}
+	}
+
+	/// Returns the type without the non-null modifier
+	pub fn nullable(self) -> Self {
+		match self {
+			Self::NonNull(ty) => *ty,
+			ty => ty,
+		}
+	}
+
+	pub fn is_non_null(&self) -> bool {
+		matches!(self, Self::NonNull(_))
+	}
 }
 
 // https://docs.rs/apollo-compiler/1.0.0-beta.14/apollo_compiler/ast/struct.SchemaDefinition.html
@@ -66,30 +181,47 @@ const INT: Name = Name::new_unchecked(NodeStr::from_static(&"Int"));
 const FLOAT: Name = Name::new_unchecked(NodeStr::from_static(&"Float"));
 const BOOLEAN: Name = Name::new_unchecked(NodeStr::from_static(&"Boolean"));
 
-fn type_to_definitions(ty: &Type, defs: &mut Vec<Definition>) -> ApolloType {
-	match ty {
+fn type_to_definitions(
+	ty: &Type,
+	defs: &mut Vec<Definition>,
+) -> Result<ApolloType, NameError> {
+	let ty = match ty {
 		Type::Id => ApolloType::Named(ID),
 		Type::String => ApolloType::Named(STRING),
 		Type::Int => ApolloType::Named(INT),
 		Type::Float => ApolloType::Named(FLOAT),
 		Type::Boolean => ApolloType::Named(BOOLEAN),
+		Type::List(item) => type_to_definitions(item, defs)?.list(),
+		Type::NonNull(ty) => type_to_definitions(ty, defs)?.non_null(),
 		Type::Object {
 			type_name,
 			fields,
-			fragments,
+			interfaces,
 		} => {
-			let type_name = Name::new(type_name).unwrap();
+			let type_name = graphql_name(type_name)?;
+
+			// the same type can be used by multiple fields
+			if is_defined(defs, &type_name) {
+				return Ok(ApolloType::Named(type_name));
+			}
+
+			let implements_interfaces = interfaces
+				.iter()
+				.map(|i| {
+					Ok(type_to_definitions(i, defs)?.inner_named_type().clone())
+				})
+				.collect::<Result<_, NameError>>()?;
 
 			let fields = fields
 				.iter()
-				.map(|f| Node::new(property_to_definitions(f, defs)))
-				.collect();
+				.map(|f| property_to_definitions(f, defs).map(Node::new))
+				.collect::<Result<_, NameError>>()?;
 
 			// converts this property into a definition
 			let def = ObjectTypeDefinition {
 				description: None,
 				name: type_name.clone(),
-				implements_interfaces: vec![],
+				implements_interfaces,
 				directives: DirectiveList::new(),
 				fields,
 			};
@@ -98,93 +230,277 @@ fn type_to_definitions(ty: &Type, defs: &mut Vec<Definition>) -> ApolloType {
 
 			ApolloType::Named(type_name)
 		}
+		Type::Interface { type_name, fields } => {
+			let type_name = graphql_name(type_name)?;
 
-		_ => todo!(),
-	}
+			if is_defined(defs, &type_name) {
+				return Ok(ApolloType::Named(type_name));
+			}
+
+			let fields = fields
+				.iter()
+				.map(|f| property_to_definitions(f, defs).map(Node::new))
+				.collect::<Result<_, NameError>>()?;
+
+			let def = InterfaceTypeDefinition {
+				description: None,
+				name: type_name.clone(),
+				implements_interfaces: vec![],
+				directives: DirectiveList::new(),
+				fields,
+			};
+
+			defs.push(Definition::InterfaceTypeDefinition(Node::new(def)));
+
+			ApolloType::Named(type_name)
+		}
+		Type::Union { type_name, types } => {
+			let type_name = graphql_name(type_name)?;
+
+			if is_defined(defs, &type_name) {
+				return Ok(ApolloType::Named(type_name));
+			}
+
+			let def = UnionTypeDefinition {
+				description: None,
+				name: type_name.clone(),
+				directives: DirectiveList::new(),
+				members: types
+					.iter()
+					.map(|t| {
+						Ok(type_to_definitions(t, defs)?
+							.inner_named_type()
+							.clone())
+					})
+					.collect::<Result<_, NameError>>()?,
+			};
+
+			defs.push(Definition::UnionTypeDefinition(Node::new(def)));
+
+			ApolloType::Named(type_name)
+		}
+		Type::InputObject { type_name, fields } => {
+			let type_name = graphql_name(type_name)?;
+
+			if is_defined(defs, &type_name) {
+				return Ok(ApolloType::Named(type_name));
+			}
+
+			let fields = fields
+				.iter()
+				.map(|f| argument_to_definitions(f, defs).map(Node::new))
+				.collect::<Result<_, NameError>>()?;
+
+			let def = InputObjectTypeDefinition {
+				description: None,
+				name: type_name.clone(),
+				directives: DirectiveList::new(),
+				fields,
+			};
+
+			defs.push(Definition::InputObjectTypeDefinition(Node::new(def)));
+
+			ApolloType::Named(type_name)
+		}
+		Type::Enum { type_name, values } => {
+			let type_name = graphql_name(type_name)?;
+
+			if is_defined(defs, &type_name) {
+				return Ok(ApolloType::Named(type_name));
+			}
+
+			let values = values
+				.iter()
+				.map(|v| {
+					Ok(Node::new(EnumValueDefinition {
+						description: None,
+						value: graphql_name(v)?,
+						directives: DirectiveList::new(),
+					}))
+				})
+				.collect::<Result<_, NameError>>()?;
+
+			let def = EnumTypeDefinition {
+				description: None,
+				name: type_name.clone(),
+				directives: DirectiveList::new(),
+				values,
+			};
+
+			defs.push(Definition::EnumTypeDefinition(Node::new(def)));
+
+			ApolloType::Named(type_name)
+		}
+		Type::Scalar(scalar) => {
+			let type_name = graphql_name(&scalar.name)?;
+
+			if is_defined(defs, &type_name) {
+				return Ok(ApolloType::Named(type_name));
+			}
+
+			let mut directives = DirectiveList::new();
+			if let Some(url) = &scalar.specified_by {
+				directives.push(Node::new(Directive {
+					name: graphql_name("specifiedBy")?,
+					arguments: vec![Node::new(ApolloArgument {
+						name: graphql_name("url")?,
+						value: Node::new(url.into()),
+					})],
+				}));
+			}
+
+			let def = ScalarTypeDefinition {
+				description: None,
+				name: type_name.clone(),
+				directives,
+			};
+
+			defs.push(Definition::ScalarTypeDefinition(Node::new(def)));
+
+			ApolloType::Named(type_name)
+		}
+		Type::Named(type_name) => ApolloType::Named(graphql_name(type_name)?),
+	};
+
+	Ok(ty)
+}
+
+/// Returns the name or an error if it is not a valid graphql name
+fn graphql_name(name: &str) -> Result<Name, NameError> {
+	Name::new(name).map_err(|_| NameError::Invalid(name.into()))
+}
+
+/// Returns true if a type with this name was already added
+fn is_defined(defs: &[Definition], name: &Name) -> bool {
+	defs.iter().any(|def| match def {
+		Definition::ObjectTypeDefinition(d) => d.name == *name,
+		Definition::InputObjectTypeDefinition(d) => d.name == *name,
+		Definition::EnumTypeDefinition(d) => d.name == *name,
+		Definition::InterfaceTypeDefinition(d) => d.name == *name,
+		Definition::UnionTypeDefinition(d) => d.name == *name,
+		Definition::ScalarTypeDefinition(d) => d.name == *name,
+		_ => false,
+	})
 }
 
 fn property_to_definitions(
 	prop: &Property,
 	defs: &mut Vec<Definition>,
-) -> FieldDefinition {
-	let name = Name::new(&prop.name).unwrap();
-
-	for arg in &prop.arguments {
-		let name = Name::new(&arg.name).unwrap();
-
-		let arg_ty = type_to_definitions(&arg.ty, defs);
-
-		// defs.push(Definition::InputObjectTypeDefinition(Node::new(
-		// 	InputObjectTypeDefinition {
-		// 		description: None,
-		// 		name: Name::new("User").unwrap(),
-		// 		directives: DirectiveList::new(),
-		// 		fields: args,
-		// 	},
-		// )));
-	}
+) -> Result<FieldDefinition, NameError> {
+	let arguments = prop
+		.arguments
+		.iter()
+		.map(|arg| argument_to_definitions(arg, defs).map(Node::new))
+		.collect::<Result<_, NameError>>()?;
+
+	Ok(FieldDefinition {
+		description: None,
+		name: graphql_name(&prop.name)?,
+		arguments,
+		ty: type_to_definitions(&prop.ty, defs)?,
+		directives: DirectiveList::new(),
+	})
+}
 
-	FieldDefinition {
+fn argument_to_definitions(
+	arg: &Argument,
+	defs: &mut Vec<Definition>,
+) -> Result<InputValueDefinition, NameError> {
+	Ok(InputValueDefinition {
 		description: None,
-		name: name.clone(),
-		arguments: vec![],
-		ty: type_to_definitions(&prop.ty, defs),
+		name: graphql_name(&arg.name)?,
+		ty: Node::new(type_to_definitions(&arg.ty, defs)?),
+		default_value: match &arg.default {
+			Some(v) => Some(Node::new(to_value(v)?)),
+			None => None,
+		},
 		directives: DirectiveList::new(),
-	}
+	})
+}
+
+fn to_value(value: &Value) -> Result<ApolloValue, NameError> {
+	let value = match value {
+		Value::Null => ApolloValue::Null,
+		Value::Int(i) => (*i).into(),
+		Value::Float(f) => (*f).into(),
+		Value::String(s) => s.into(),
+		Value::Boolean(b) => (*b).into(),
+		Value::Enum(e) => ApolloValue::Enum(graphql_name(e)?),
+		Value::List(items) => ApolloValue::List(
+			items
+				.iter()
+				.map(|v| to_value(v).map(Node::new))
+				.collect::<Result<_, NameError>>()?,
+		),
+		Value::Object(fields) => ApolloValue::Object(
+			fields
+				.iter()
+				.map(|(n, v)| Ok((graphql_name(n)?, Node::new(to_value(v)?))))
+				.collect::<Result<_, NameError>>()?,
+		),
+	};
+
+	Ok(value)
 }
 
-fn create_schema(props: Vec<Property>) -> Valid<Schema> {
+/// Creates a schema with a query root, the mutation and subscription roots
+/// are only added if they have fields
+///
+/// The schema is validated, if it is invalid the error contains a report of
+/// every problem pointing into the generated sdl.
+pub fn create_schema(
+	query: Vec<Property>,
+	mutation: Vec<Property>,
+	subscription: Vec<Property>,
+) -> Result<Valid<Schema>, SchemaError> {
 	let mut document = Document::new();
 	let defs = &mut document.definitions;
 
-	let mut query_fields: Vec<Node<FieldDefinition>> = vec![];
+	let mut root_operations = vec![];
+	for (operation, name, props) in [
+		(OperationType::Query, "Query", query),
+		(OperationType::Mutation, "Mutation", mutation),
+		(OperationType::Subscription, "Subscription", subscription),
+	] {
+		if props.is_empty() && operation != OperationType::Query {
+			continue;
+		}
 
-	for prop in &props {
-		query_fields.push(Node::new(property_to_definitions(prop, defs)));
-	}
+		let fields = props
+			.iter()
+			.map(|prop| property_to_definitions(prop, defs).map(Node::new))
+			.collect::<Result<_, NameError>>()?;
 
-	defs.push(Definition::ObjectTypeDefinition(Node::new(
-		ObjectTypeDefinition {
-			description: None,
-			name: Name::new("Query").unwrap(),
-			implements_interfaces: vec![],
-			directives: DirectiveList::new(),
-			fields: query_fields,
-		},
-	)));
+		let name = graphql_name(name)?;
+		defs.push(Definition::ObjectTypeDefinition(Node::new(
+			ObjectTypeDefinition {
+				description: None,
+				name: name.clone(),
+				implements_interfaces: vec![],
+				directives: DirectiveList::new(),
+				fields,
+			},
+		)));
+		root_operations.push(Node::new((operation, name)));
+	}
 
 	defs.push(Definition::SchemaDefinition(Node::new(SchemaDefinition {
 		description: None,
 		directives: DirectiveList::new(),
-		root_operations: vec![Node::new((
-			OperationType::Query,
-			Name::new("Query").unwrap(),
-		))],
+		root_operations,
 	})));
 
-	let mut schema = Schema::builder().add_ast(&document).build().unwrap();
-
-	// let schema_input = r#"
-	// type User {
-	//   id: ID
-	//   name: String
-	//   profilePic(size: Int): URL
-	// }
-
-	// schema { query: User }
-
-	// scalar URL @specifiedBy(url: "https://tools.ietf.org/html/rfc3986")
-	// "#;
-	// let schema =
-	// 	Schema::parse_and_validate(schema_input, "schema.graphql").unwrap();
-
-	Valid::assume_valid(schema)
+	Schema::builder()
+		.add_ast(&document)
+		.build()
+		.and_then(Schema::validate)
+		.map_err(|e| SchemaError::Invalid(e.errors))
 }
 
 #[cfg(test)]
 mod tests {
 
-	use apollo_compiler::executable;
+	use apollo_compiler::{executable, ExecutableDocument};
 
 	use super::*;
 
@@ -201,48 +517,40 @@ mod tests {
     fragment vipCustomer on User {
       id
       name
-      profilePic(size: 50) {
-        url
-      }
+      profilePic(size: 50)
     }
     "#;
 
-		let schema = create_schema(vec![Property {
-			name: "user".to_string(),
-			arguments: vec![],
-			ty: Type::Object {
-				type_name: "User".into(),
-				fields: vec![
-					Property {
-						name: "id".to_string(),
-						arguments: vec![],
-						ty: Type::Id,
-					},
-					Property {
-						name: "name".to_string(),
-						arguments: vec![],
-						ty: Type::String,
-					},
-					Property {
-						name: "profilePic".to_string(),
-						arguments: vec![Argument {
-							name: "size".to_string(),
-							ty: Type::Int,
-						}],
-						ty: Type::Object {
-							type_name: "ProfilePic".into(),
-							fields: vec![Property {
-								name: "url".to_string(),
-								arguments: vec![],
-								ty: Type::String,
-							}],
-							fragments: vec![],
+		let schema = create_schema(
+			vec![Property {
+				name: "user".to_string(),
+				arguments: vec![],
+				ty: Type::Object {
+					type_name: "User".into(),
+					fields: vec![
+						Property {
+							name: "id".to_string(),
+							arguments: vec![],
+							ty: Type::Id,
 						},
-					},
-				],
-				fragments: vec![],
-			},
-		}]);
+						Property {
+							name: "name".to_string(),
+							arguments: vec![],
+							ty: Type::String,
+						},
+						Property {
+							name: "profilePic".to_string(),
+							arguments: vec![Argument::new("size", Type::Int)],
+							ty: Type::Scalar(Scalar::url()),
+						},
+					],
+					interfaces: vec![],
+				},
+			}],
+			vec![],
+			vec![],
+		)
+		.unwrap();
 		let document = ExecutableDocument::parse_and_validate(
 			&schema,
 			query_input,
@@ -253,10 +561,11 @@ mod tests {
 		let op = document
 			.get_operation(Some("getUser"))
 			.expect("getUser query does not exist");
+		// the fragment is spread inside of the user field
 		let fragment_in_op = op
 			.selection_set
-			.selections
-			.iter()
+			.fields()
+			.flat_map(|user| &user.selection_set.selections)
 			.filter_map(|sel| match sel {
 				executable::Selection::FragmentSpread(spread) => Some(
 					document.fragments.get(&spread.fragment_name)?.as_ref(),
@@ -274,4 +583,146 @@ mod tests {
 			.collect::<Vec<&str>>();
 		assert_eq!(field_ty, ["ID", "String", "URL"]);
 	}
+
+	fn prop(name: &str, ty: Type) -> Property {
+		Property {
+			name: name.to_string(),
+			arguments: vec![],
+			ty,
+		}
+	}
+
+	#[test]
+	fn lists_and_non_null() {
+		let tag = Type::Object {
+			type_name: "Tag".into(),
+			fields: vec![prop("name", Type::String.non_null())],
+			interfaces: vec![],
+		};
+
+		let schema = create_schema(
+			vec![
+				prop("tags", tag.clone().non_null().list().non_null()),
+				prop("tag", tag),
+				prop("matrix", Type::Int.list().non_null().list()),
+				prop("id", Type::Id.non_null().non_null()),
+			],
+			vec![],
+			vec![],
+		)
+		.unwrap();
+
+		let ty = |name: &str| {
+			schema.type_field("Query", name).unwrap().ty.to_string()
+		};
+		assert_eq!(ty("tags"), "[Tag!]!");
+		assert_eq!(ty("tag"), "Tag");
+		assert_eq!(ty("matrix"), "[[Int]!]");
+		assert_eq!(ty("id"), "ID!");
+		assert_eq!(
+			schema.type_field("Tag", "name").unwrap().ty.to_string(),
+			"String!"
+		);
+
+		assert!(Type::Int.non_null().is_non_null());
+		assert!(!Type::Int.non_null().nullable().is_non_null());
+	}
+
+	#[test]
+	fn arguments_and_inputs() {
+		let order = Type::Enum {
+			type_name: "Order".into(),
+			values: vec!["ASC".into(), "DESC".into()],
+		};
+		let filter = Type::InputObject {
+			type_name: "EventFilter".into(),
+			fields: vec![
+				Argument::new("title", Type::String),
+				Argument::new("order", order.clone())
+					.with_default(Value::Enum("ASC".into())),
+			],
+		};
+		let event = Type::Object {
+			type_name: "Event".into(),
+			fields: vec![prop("title", Type::String), prop("order", order)],
+			interfaces: vec![],
+		};
+
+		let schema = create_schema(
+			vec![Property {
+				name: "events".into(),
+				arguments: vec![
+					Argument::new("filter", filter),
+					Argument::new("limit", Type::Int.non_null())
+						.with_default(Value::Int(10)),
+					Argument::new("ids", Type::Id.non_null().list())
+						.with_default(Value::List(vec![])),
+				],
+				ty: event.list(),
+			}],
+			vec![],
+			vec![],
+		)
+		.unwrap();
+
+		let events = schema.type_field("Query", "events").unwrap();
+		let limit = events.argument_by_name("limit").unwrap();
+		assert_eq!(limit.ty.to_string(), "Int!");
+		assert_eq!(limit.default_value.as_ref().unwrap().to_string(), "10");
+		let ids = events.argument_by_name("ids").unwrap();
+		assert_eq!(ids.default_value.as_ref().unwrap().to_string(), "[]");
+
+		let query = r#"
+		query {
+			events(filter: { title: "rust", order: DESC }) {
+				title
+				order
+			}
+		}
+		"#;
+		ExecutableDocument::parse_and_validate(&schema, query, "query.graphql")
+			.unwrap();
+
+		let query = r#"{ events(filter: { order: UP }) { title } }"#;
+		assert!(ExecutableDocument::parse_and_validate(
+			&schema,
+			query,
+			"query.graphql"
+		)
+		.is_err());
+	}
+
+	#[test]
+	fn schema_errors() {
+		let err = create_schema(
+			vec![prop("link target", Type::String)],
+			vec![],
+			vec![],
+		)
+		.unwrap_err();
+		assert!(matches!(
+			err,
+			SchemaError::Name(NameError::Invalid(name)) if name == "link target"
+		));
+
+		// the object is missing the field of its interface
+		let node = Type::Interface {
+			type_name: "Node".into(),
+			fields: vec![prop("id", Type::Id.non_null())],
+		};
+		let tag = Type::Object {
+			type_name: "Tag".into(),
+			fields: vec![prop("name", Type::String)],
+			interfaces: vec![node],
+		};
+
+		let err =
+			create_schema(vec![prop("tag", tag)], vec![], vec![]).unwrap_err();
+		let SchemaError::Invalid(diagnostics) = &err else {
+			panic!("expected diagnostics, got {err:?}");
+		};
+		assert_eq!(diagnostics.len(), 1);
+		let report = err.to_string();
+		assert!(report.contains("does not satisfy interface"), "{report}");
+	}
 }
@@ -0,0 +1,198 @@
+//! Mutations
+//!
+//! Every object can be created, updated and deleted. The data of an object is
+//! passed as an input object with the same fields as the object, except the
+//! id. Fields returning other objects are set with a [`REFERENCE_INPUT`]
+//! naming the type and id of the object. Objects without fields have no data
+//! argument.
+//!
+//! ```graphql
+//! type Mutation {
+//!   createEvent(data: EventInput!): Event!
+//!   updateEvent(id: ID!, data: EventInput!): Event
+//!   deleteEvent(id: ID!): Event
+//! }
+//! ```
+//!
+//! The `id` argument has the type of the `id` field of the object.
+
+use crate::{scalar::Scalar, Argument, Property, Type};
+
+/// The input of a field which returns objects, `component` is the name of
+/// the object type
+///
+/// ```graphql
+/// input ReferenceInput {
+///   component: String!
+///   id: Id!
+/// }
+/// ```
+pub const REFERENCE_INPUT: &str = "ReferenceInput";
+
+/// What a mutation does with an object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+	Create,
+	/// Replaces the data of an object
+	Update,
+	Delete,
+}
+
+/// A root field of the mutation type
+#[derive(Debug, Clone)]
+pub struct MutationProperty {
+	pub action: Action,
+	pub property: Property,
+}
+
+/// Returns the create, update and delete mutations of an object
+///
+/// ## Panics
+/// If the type is not an object.
+pub fn mutation_properties(object: &Type) -> Vec<MutationProperty> {
+	let Type::Object {
+		type_name, fields, ..
+	} = object
+	else {
+		panic!("mutations need an object");
+	};
+
+	// an input object needs at least one field
+	let input = input_type(object);
+	let data = match &input {
+		Type::InputObject { fields, .. } if fields.is_empty() => vec![],
+		_ => vec![Argument::new("data", input.non_null())],
+	};
+	let id_type = fields
+		.iter()
+		.find(|f| f.name == "id")
+		.map_or(Type::Id, |f| f.ty.clone().nullable());
+	let id = Argument::new("id", id_type.non_null());
+
+	let mutation = |action, name: &str, arguments, ty| MutationProperty {
+		action,
+		property: Property {
+			name: format!("{name}{type_name}"),
+			arguments,
+			ty,
+		},
+	};
+
+	vec![
+		mutation(
+			Action::Create,
+			"create",
+			data.clone(),
+			object.clone().non_null(),
+		),
+		mutation(
+			Action::Update,
+			"update",
+			[id.clone()].into_iter().chain(data).collect(),
+			object.clone(),
+		),
+		mutation(Action::Delete, "delete", vec![id], object.clone()),
+	]
+}
+
+/// Returns the input type `{Object}Input` with the data of the object
+///
+/// Non-null fields are required by the input as well.
+pub fn input_type(object: &Type) -> Type {
+	let Type::Object {
+		type_name, fields, ..
+	} = object
+	else {
+		panic!("the input type needs an object");
+	};
+
+	Type::InputObject {
+		type_name: format!("{type_name}Input"),
+		fields: fields
+			.iter()
+			.filter(|f| f.name != "id")
+			.map(|f| Argument::new(&f.name, input_field(&f.ty)))
+			.collect(),
+	}
+}
+
+/// Returns the type of a field when used as an input, objects, interfaces,
+/// unions and types only referenced by name become a [`REFERENCE_INPUT`]
+fn input_field(ty: &Type) -> Type {
+	match ty {
+		Type::List(item) => input_field(item).list(),
+		Type::NonNull(ty) => input_field(ty).non_null(),
+		Type::Object { .. }
+		| Type::Interface { .. }
+		| Type::Union { .. }
+		| Type::Named(_) => Type::InputObject {
+			type_name: REFERENCE_INPUT.into(),
+			fields: vec![
+				Argument::new("component", Type::String.non_null()),
+				Argument::new("id", Type::Scalar(Scalar::id()).non_null()),
+			],
+		},
+		ty => ty.clone(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::create_schema;
+
+	#[test]
+	fn schema() {
+		let prop = |name: &str, ty: Type| Property {
+			name: name.into(),
+			arguments: vec![],
+			ty,
+		};
+		let tag = Type::Object {
+			type_name: "Tag".into(),
+			fields: vec![prop("name", Type::String)],
+			interfaces: vec![],
+		};
+		let event = Type::Object {
+			type_name: "Event".into(),
+			fields: vec![
+				prop("id", Type::Id.non_null()),
+				prop("title", Type::String.non_null()),
+				prop("seats", Type::Int.list()),
+				prop("tags", tag.list()),
+			],
+			interfaces: vec![],
+		};
+
+		let mutations = mutation_properties(&event)
+			.into_iter()
+			.map(|m| m.property)
+			.collect();
+		let schema =
+			create_schema(vec![prop("event", event)], mutations, vec![])
+				.unwrap();
+
+		let ty = |name: &str| {
+			schema.type_field("Mutation", name).unwrap().ty.to_string()
+		};
+		assert_eq!(ty("createEvent"), "Event!");
+		assert_eq!(ty("updateEvent"), "Event");
+		assert_eq!(ty("deleteEvent"), "Event");
+
+		let input = schema.get_input_object("EventInput").unwrap();
+		let fields = input
+			.fields
+			.iter()
+			.map(|(name, f)| format!("{name}: {}", f.ty))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			fields,
+			["title: String!", "seats: [Int]", "tags: [ReferenceInput]"]
+		);
+		let reference = schema.get_input_object(REFERENCE_INPUT).unwrap();
+		assert_eq!(reference.fields["id"].ty.to_string(), "Id!");
+		let update = schema.type_field("Mutation", "updateEvent").unwrap();
+		let id = update.argument_by_name("id").unwrap();
+		assert_eq!(id.ty.to_string(), "ID!");
+	}
+}
@@ -0,0 +1,159 @@
+//! Names
+//!
+//! Handles and field names can contain characters which are not allowed in
+//! graphql names. Every character which is not an ascii letter or digit
+//! separates words, types are then written in PascalCase and fields in
+//! camelCase: the handle `internal-link` becomes the type `InternalLink` and
+//! the field `link target` becomes `linkTarget`.
+//!
+//! Different names can map to the same graphql name, a [`NameMap`] reports
+//! such collisions instead of silently merging them.
+
+use indexmap::IndexMap;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum NameError {
+	#[error("{0:?} cannot be converted to a graphql name")]
+	Invalid(String),
+
+	#[error("{name:?} maps to {graphql_name} which is reserved")]
+	Reserved { name: String, graphql_name: String },
+
+	#[error("{first:?} and {second:?} both map to {graphql_name}")]
+	Collision {
+		first: String,
+		second: String,
+		graphql_name: String,
+	},
+}
+
+/// Returns the name of a type in PascalCase
+pub fn type_name(name: &str) -> Result<String, NameError> {
+	convert(name, true)
+}
+
+/// Returns the name of a field in camelCase
+pub fn field_name(name: &str) -> Result<String, NameError> {
+	convert(name, false)
+}
+
+fn convert(name: &str, upper_first: bool) -> Result<String, NameError> {
+	let converted = name
+		.split(|c: char| !c.is_ascii_alphanumeric())
+		.filter(|word| !word.is_empty())
+		.enumerate()
+		.map(|(i, word)| {
+			// an uppercase word like `URL` is lowercased as a whole
+			if i == 0 && !upper_first {
+				return match word.chars().any(|c| c.is_ascii_lowercase()) {
+					true => word[..1].to_ascii_lowercase() + &word[1..],
+					false => word.to_ascii_lowercase(),
+				};
+			}
+
+			word[..1].to_ascii_uppercase() + &word[1..]
+		})
+		.collect::<String>();
+
+	// a name cannot start with a digit
+	match converted.chars().next() {
+		Some(c) if c.is_ascii_alphabetic() => Ok(converted),
+		_ => Err(NameError::Invalid(name.into())),
+	}
+}
+
+/// The graphql names of names, no two names map to the same graphql name
+#[derive(Debug, Clone, Default)]
+pub struct NameMap {
+	/// The graphql name by the name
+	inner: IndexMap<String, String>,
+}
+
+impl NameMap {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Adds the name with it's graphql name, returns an error if another
+	/// name already maps to the graphql name
+	pub fn insert(
+		&mut self,
+		name: impl Into<String>,
+		graphql_name: impl Into<String>,
+	) -> Result<(), NameError> {
+		let name = name.into();
+		let graphql_name = graphql_name.into();
+
+		if let Some(first) = self.name(&graphql_name) {
+			if first != name {
+				return Err(NameError::Collision {
+					first: first.into(),
+					second: name,
+					graphql_name,
+				});
+			}
+		}
+
+		self.inner.insert(name, graphql_name);
+		Ok(())
+	}
+
+	pub fn graphql_name(&self, name: &str) -> Option<&str> {
+		self.inner.get(name).map(String::as_str)
+	}
+
+	/// Returns the name which maps to the graphql name
+	pub fn name(&self, graphql_name: &str) -> Option<&str> {
+		self.inner
+			.iter()
+			.find(|(_, g)| *g == graphql_name)
+			.map(|(n, _)| n.as_str())
+	}
+
+	/// Returns the names with their graphql name, in insertion order
+	pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+		self.inner.iter().map(|(n, g)| (n.as_str(), g.as_str()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn conversions() {
+		assert_eq!(type_name("internal-link").unwrap(), "InternalLink");
+		assert_eq!(type_name("counter").unwrap(), "Counter");
+		assert_eq!(type_name("_draft__post_").unwrap(), "DraftPost");
+		assert_eq!(field_name("link target").unwrap(), "linkTarget");
+		assert_eq!(field_name("Label").unwrap(), "label");
+		assert_eq!(field_name("maxLength").unwrap(), "maxLength");
+		assert_eq!(field_name("URL target").unwrap(), "urlTarget");
+
+		assert_eq!(
+			type_name("3d-model"),
+			Err(NameError::Invalid("3d-model".into()))
+		);
+		assert!(field_name("--").is_err());
+		assert!(field_name("").is_err());
+	}
+
+	#[test]
+	fn collisions() {
+		let mut names = NameMap::new();
+		names.insert("link target", "linkTarget").unwrap();
+		// the same name can be inserted again
+		names.insert("link target", "linkTarget").unwrap();
+
+		assert_eq!(
+			names.insert("link-target", "linkTarget"),
+			Err(NameError::Collision {
+				first: "link target".into(),
+				second: "link-target".into(),
+				graphql_name: "linkTarget".into()
+			})
+		);
+		assert_eq!(names.name("linkTarget"), Some("link target"));
+		assert_eq!(names.graphql_name("link target"), Some("linkTarget"));
+	}
+}
@@ -0,0 +1,220 @@
+//! Custom scalars
+//!
+//! Besides the built-in scalars a schema can contain custom scalars, which are
+//! declared with the url of their specification.
+//!
+//! ```graphql
+//! scalar DateTime @specifiedBy(url: "https://scalars.graphql.org/andimarek/date-time")
+//! ```
+//!
+//! A [`Scalar`] serializes the values returned by a resolver and parses the
+//! values of inputs, the executor looks them up in the [`Scalars`] of the
+//! schema.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, SecondsFormat};
+use database::id::Id;
+
+use crate::{execute::JsonValue, Property, Type};
+
+/// Converts a value, the error is reported to the client
+pub type ScalarFn = fn(&JsonValue) -> Result<JsonValue, ScalarError>;
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("{0}")]
+pub struct ScalarError(pub String);
+
+/// How the values of a scalar are compared by filters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalarFilter {
+	Id,
+	Text,
+	DateTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct Scalar {
+	pub name: String,
+	/// Added with the `@specifiedBy` directive
+	pub specified_by: Option<String>,
+	/// Converts a value of a resolver to the response
+	pub serialize: ScalarFn,
+	/// Converts a value of an input, the result is passed to the resolver
+	pub parse: ScalarFn,
+	/// None if the scalar cannot be filtered or ordered by
+	pub filter: Option<ScalarFilter>,
+}
+
+impl Scalar {
+	/// Creates a scalar which serializes and parses with the same function
+	pub fn new(name: impl Into<String>, convert: ScalarFn) -> Self {
+		Self {
+			name: name.into(),
+			specified_by: None,
+			serialize: convert,
+			parse: convert,
+			filter: None,
+		}
+	}
+
+	pub fn specified_by(mut self, url: impl Into<String>) -> Self {
+		self.specified_by = Some(url.into());
+		self
+	}
+
+	pub fn filter(mut self, filter: ScalarFilter) -> Self {
+		self.filter = Some(filter);
+		self
+	}
+
+	/// A timestamp as an RFC 3339 string, always returned in UTC
+	pub fn date_time() -> Self {
+		Self::new("DateTime", |value| {
+			let date_time = value
+				.as_str()
+				.and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+				.ok_or_else(|| {
+					ScalarError("expected an RFC 3339 date".into())
+				})?;
+
+			Ok(date_time
+				.to_utc()
+				.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+				.into())
+		})
+		.specified_by("https://scalars.graphql.org/andimarek/date-time")
+		.filter(ScalarFilter::DateTime)
+	}
+
+	/// Any json value, it is passed as it is
+	pub fn json() -> Self {
+		Self::new("JSON", |value| Ok(value.clone()))
+			.specified_by("https://www.rfc-editor.org/rfc/rfc8259")
+	}
+
+	/// An absolute url, it needs to start with a scheme like `https:`
+	pub fn url() -> Self {
+		Self::new("URL", |value| {
+			let url = value.as_str().filter(|url| is_absolute_url(url));
+
+			match url {
+				Some(url) => Ok(url.into()),
+				None => Err(ScalarError("expected an absolute url".into())),
+			}
+		})
+		.specified_by("https://www.rfc-editor.org/rfc/rfc3986")
+		.filter(ScalarFilter::Text)
+	}
+
+	/// A database id in its url safe base64 form without padding
+	pub fn id() -> Self {
+		Self::new("Id", |value| {
+			let id = value.as_str().and_then(|s| s.parse::<Id>().ok());
+
+			match id {
+				Some(id) => Ok(id.to_string().into()),
+				None => Err(ScalarError("expected a base64 id".into())),
+			}
+		})
+		.specified_by("https://www.rfc-editor.org/rfc/rfc4648#section-5")
+		.filter(ScalarFilter::Id)
+	}
+}
+
+/// Checks for a scheme followed by a non-empty part without whitespace
+fn is_absolute_url(url: &str) -> bool {
+	let Some((scheme, rest)) = url.split_once(':') else {
+		return false;
+	};
+
+	let mut chars = scheme.chars();
+	chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+		&& chars.all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+		&& !rest.is_empty()
+		&& !rest.contains(char::is_whitespace)
+}
+
+/// The custom scalars of a schema by their name
+#[derive(Debug, Clone, Default)]
+pub struct Scalars {
+	inner: HashMap<String, Scalar>,
+}
+
+impl Scalars {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn insert(&mut self, scalar: Scalar) {
+		self.inner.insert(scalar.name.clone(), scalar);
+	}
+
+	pub fn get(&self, name: &str) -> Option<&Scalar> {
+		self.inner.get(name)
+	}
+
+	/// Adds every scalar used by the properties, including their arguments
+	pub fn collect(&mut self, props: &[Property]) {
+		for prop in props {
+			self.collect_type(&prop.ty);
+			for arg in &prop.arguments {
+				self.collect_type(&arg.ty);
+			}
+		}
+	}
+
+	fn collect_type(&mut self, ty: &Type) {
+		match ty {
+			Type::Scalar(scalar) => self.insert(scalar.clone()),
+			Type::List(ty) | Type::NonNull(ty) => self.collect_type(ty),
+			Type::Object {
+				fields, interfaces, ..
+			} => {
+				self.collect(fields);
+				interfaces.iter().for_each(|i| self.collect_type(i));
+			}
+			Type::Interface { fields, .. } => self.collect(fields),
+			Type::Union { types, .. } => {
+				types.iter().for_each(|t| self.collect_type(t))
+			}
+			Type::InputObject { fields, .. } => {
+				fields.iter().for_each(|f| self.collect_type(&f.ty))
+			}
+			_ => {}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use database::id::Kind;
+
+	use super::*;
+
+	#[test]
+	fn conversions() {
+		let parse = |scalar: Scalar, value: &str| {
+			(scalar.parse)(&value.into())
+				.map(|v| v.as_str().unwrap().to_string())
+		};
+
+		assert_eq!(
+			parse(Scalar::date_time(), "2024-04-01T12:00:00+02:00").unwrap(),
+			"2024-04-01T10:00:00Z"
+		);
+		assert!(parse(Scalar::date_time(), "2024-04-01").is_err());
+
+		assert!(parse(Scalar::url(), "https://zipp.rs/docs").is_ok());
+		assert!(parse(Scalar::url(), "mailto:info@zipp.rs").is_ok());
+		assert!(parse(Scalar::url(), "/docs").is_err());
+		assert!(parse(Scalar::url(), "https://zipp.rs/a b").is_err());
+
+		let id = Id::new(Kind::new(false, 1)).to_string();
+		assert_eq!(parse(Scalar::id(), &id).unwrap(), id);
+		assert!(parse(Scalar::id(), "1").is_err());
+
+		let json = JsonValue::from(vec![1, 2]);
+		assert_eq!((Scalar::json().serialize)(&json).unwrap(), json);
+	}
+}